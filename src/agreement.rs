@@ -0,0 +1,146 @@
+use crate::{results, ExitType, ResultsRow};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+/// Results of one analyzer over the corpus, keyed by bytecode hash
+pub struct AnalyzerResults {
+    pub name: String,
+    pub rows: HashMap<String, ResultsRow>,
+}
+
+/// Parses a `name=path/to/results.csv` argument and reads the results file
+pub fn load_analyzer_results(arg: &str) -> Result<AnalyzerResults, String> {
+    let (name, path) = arg
+        .split_once('=')
+        .ok_or(format!("expected `name=path/to/results`, got `{}`", arg))?;
+    let rows = results::read_results_file(Path::new(path))
+        .map_err(|e| format!("could not read results file {}: {}", path, e))?;
+    Ok(AnalyzerResults {
+        name: name.to_string(),
+        rows: rows
+            .into_iter()
+            .map(|row| (row.bytecode_hash.clone(), row))
+            .collect(),
+    })
+}
+
+/// A set of contracts that the same analyzers failed on
+pub struct AgreementBucket {
+    /// Names of the analyzers that did not succeed, empty when all succeeded
+    pub failing: Vec<String>,
+    pub hashes: Vec<String>,
+}
+
+impl AgreementBucket {
+    pub fn label(&self, analyzer_count: usize) -> String {
+        match self.failing.len() {
+            0 => "all succeed".to_string(),
+            n if n == analyzer_count => "all fail".to_string(),
+            1 => format!("only {} fails", self.failing[0]),
+            _ => format!("only {} fail", self.failing.join(", ")),
+        }
+    }
+}
+
+/// Buckets every contract present in all results by which analyzers failed on it.
+/// Returns the buckets sorted by descending size and the number of contracts that were
+/// not analyzed by every analyzer.
+pub fn compute_agreement(analyzers: &[AnalyzerResults]) -> (Vec<AgreementBucket>, usize) {
+    let mut all_hashes: HashSet<&String> = HashSet::new();
+    for analyzer in analyzers {
+        all_hashes.extend(analyzer.rows.keys());
+    }
+
+    let mut buckets: BTreeMap<Vec<String>, Vec<String>> = BTreeMap::new();
+    let mut not_shared = 0;
+    for hash in all_hashes {
+        let outcomes = analyzers
+            .iter()
+            .map(|analyzer| analyzer.rows.get(hash).map(|row| &row.result))
+            .collect::<Vec<Option<&ExitType>>>();
+        if outcomes.iter().any(|outcome| outcome.is_none()) {
+            not_shared += 1;
+            continue;
+        }
+        let failing = analyzers
+            .iter()
+            .zip(outcomes)
+            .filter(|(_analyzer, outcome)| !matches!(outcome, Some(ExitType::Success)))
+            .map(|(analyzer, _outcome)| analyzer.name.clone())
+            .collect::<Vec<String>>();
+        buckets.entry(failing).or_default().push(hash.clone());
+    }
+
+    let mut buckets = buckets
+        .into_iter()
+        .map(|(failing, mut hashes)| {
+            hashes.sort();
+            AgreementBucket { failing, hashes }
+        })
+        .collect::<Vec<AgreementBucket>>();
+    buckets.sort_by_key(|bucket| std::cmp::Reverse(bucket.hashes.len()));
+    (buckets, not_shared)
+}
+
+/// Entry point of the `agreement` subcommand
+pub fn run_agreement(analyzer_args: &[String], examples: usize) {
+    let analyzers = analyzer_args
+        .iter()
+        .map(|arg| load_analyzer_results(arg))
+        .collect::<Result<Vec<AnalyzerResults>, String>>()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    let (buckets, not_shared) = compute_agreement(&analyzers);
+    let shared: usize = buckets.iter().map(|bucket| bucket.hashes.len()).sum();
+
+    println!(
+        "{} contracts analyzed by all of: {}",
+        shared,
+        analyzers
+            .iter()
+            .map(|analyzer| analyzer.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+    if not_shared > 0 {
+        println!(
+            "{} contracts were not analyzed by every analyzer and are excluded",
+            not_shared
+        );
+    }
+    // no percentage of nothing
+    if shared == 0 {
+        eprintln!("No contract was analyzed by every analyzer, there is nothing to compare");
+        std::process::exit(1);
+    }
+    println!();
+    println!("| Bucket | Count | Percent |");
+    println!("| ----- | ----- | ------- |");
+    for bucket in &buckets {
+        println!(
+            "|{} | {} | {:.2} |",
+            bucket.label(analyzers.len()),
+            bucket.hashes.len(),
+            bucket.hashes.len() as f64 / shared as f64 * 100.0
+        );
+    }
+
+    if examples > 0 {
+        for bucket in &buckets {
+            println!();
+            println!("{}:", bucket.label(analyzers.len()));
+            for hash in bucket.hashes.iter().take(examples) {
+                let outcomes = analyzers
+                    .iter()
+                    .map(|analyzer| format!("{}={}", analyzer.name, analyzer.rows[hash].result))
+                    .collect::<Vec<String>>();
+                println!("  {} ({})", hash, outcomes.join("; "));
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+/// A webhook that does not answer within these is given up on, the run does not wait for it
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct AlertState {
+    /// Whether each of the latest `window` results is a success, oldest first
+    recent: VecDeque<bool>,
+    /// The alert, once it fired
+    fired: Option<String>,
+}
+
+/// Tripwire on the success rate of the latest results, see `--alert-threshold`. It fires once,
+/// the first time the rate over a full window falls under the threshold.
+pub struct SuccessRateAlert {
+    /// Success rate (percent) under which the alert fires
+    threshold: f64,
+    /// What the threshold is derived from, for the alert message
+    reference: String,
+    window: usize,
+    /// Whether the run stops dispatching contracts once the alert fired
+    abort: bool,
+    state: Mutex<AlertState>,
+}
+
+impl SuccessRateAlert {
+    /// With a baseline the threshold is `threshold_percent` of its success rate, without one
+    /// it is the success rate itself
+    pub fn new(
+        threshold_percent: f64,
+        baseline_success_rate: Option<f64>,
+        window: usize,
+        abort: bool,
+    ) -> Self {
+        let (threshold, reference) = match baseline_success_rate {
+            Some(baseline_success_rate) => (
+                baseline_success_rate * threshold_percent / 100.0,
+                format!(
+                    "{}% of the baseline's {:.2}%",
+                    threshold_percent, baseline_success_rate
+                ),
+            ),
+            None => (threshold_percent, "the alert threshold".to_string()),
+        };
+        Self {
+            threshold,
+            reference,
+            window: window.max(1),
+            abort,
+            state: Mutex::new(AlertState {
+                recent: VecDeque::with_capacity(window),
+                fired: None,
+            }),
+        }
+    }
+
+    /// Records a result, returning the alert when this result made it fire
+    pub fn record(&self, success: bool) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        if state.recent.len() == self.window {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(success);
+        if state.fired.is_some() || state.recent.len() < self.window {
+            return None;
+        }
+        let successes = state.recent.iter().filter(|success| **success).count();
+        let success_rate = successes as f64 / self.window as f64 * 100.0;
+        if success_rate >= self.threshold {
+            return None;
+        }
+        let alert = format!(
+            "the success rate of the last {} results is {:.2}%, under {} ({:.2}%)",
+            self.window, success_rate, self.reference, self.threshold
+        );
+        state.fired = Some(alert.clone());
+        Some(alert)
+    }
+
+    /// Why the run is aborted, None until the alert fired or when it only warns
+    pub fn abort_reason(&self) -> Option<String> {
+        if !self.abort {
+            return None;
+        }
+        self.state
+            .lock()
+            .unwrap()
+            .fired
+            .as_ref()
+            .map(|alert| format!("the success rate alert fired: {}", alert))
+    }
+}
+
+/// Prints the alert so it stands out of the per result lines
+pub fn print_alert(alert: &str) {
+    let banner = "!".repeat(80);
+    eprintln!("{}\nALERT: {}\n{}", banner, alert, banner);
+}
+
+/// Posts the alert to a webhook as `{"text": ...}`, the payload Slack and Mattermost
+/// incoming webhooks accept
+pub async fn post_webhook(url: String, alert: String) {
+    let payload = json!({ "text": format!("omniscan: {}", alert) });
+    let posted = async {
+        reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+            .send()
+            .await?
+            .error_for_status()
+    }
+    .await;
+    if let Err(e) = posted {
+        eprintln!("Could not post the alert to {}: {}", url, e);
+    }
+}
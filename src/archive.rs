@@ -0,0 +1,199 @@
+use crate::{ContractSelection, FiestaMetadata};
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    ops::ControlFlow,
+    path::{Component, Path},
+};
+
+/// Whether `path` is a corpus archive omniscan can read contracts from
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(path).is_some()
+}
+
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?;
+    if !path.is_file() {
+        return None;
+    }
+    if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Some(ArchiveKind::TarZst)
+    } else if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else {
+        None
+    }
+}
+
+/// Calls `visit` with the path and contents of every file in the archive, in archive order,
+/// until it breaks
+fn for_each_file(
+    archive: &Path,
+    mut visit: impl FnMut(&str, Vec<u8>) -> ControlFlow<()>,
+) -> Result<(), String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let reader: Box<dyn Read> = match archive_kind(archive) {
+        Some(ArchiveKind::Tar) => Box::new(BufReader::new(file)),
+        Some(ArchiveKind::TarGz) => Box::new(flate2::read::GzDecoder::new(BufReader::new(file))),
+        Some(ArchiveKind::TarZst) => Box::new(zstd::Decoder::new(file).map_err(|e| e.to_string())?),
+        Some(ArchiveKind::Zip) => {
+            let mut zip = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+            for index in 0..zip.len() {
+                let mut entry = zip.by_index(index).map_err(|e| e.to_string())?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let mut contents = Vec::with_capacity(entry.size() as usize);
+                entry
+                    .read_to_end(&mut contents)
+                    .map_err(|e| e.to_string())?;
+                if visit(entry.name(), contents).is_break() {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+        None => return Err("not a .tar, .tar.gz, .tar.zst or .zip archive".to_string()),
+    };
+
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(|e| e.to_string())?;
+        let path = path.to_string_lossy().to_string();
+        let mut contents = Vec::with_capacity(entry.size() as usize);
+        entry
+            .read_to_end(&mut contents)
+            .map_err(|e| e.to_string())?;
+        if visit(&path, contents).is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Splits an archive path into the contract directory (`organized_contracts/XX/bytecodehash`)
+/// and the path of the file inside it. The archive may nest the corpus in a top directory.
+pub fn split_contract_path(path: &str) -> Option<(String, String)> {
+    let components = Path::new(path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<&str>>();
+    let start = components
+        .iter()
+        .position(|component| *component == "organized_contracts")?;
+    let contract_dir = components.get(start..start + 3)?.join("/");
+    let file = components.get(start + 3..)?.join("/");
+    (!file.is_empty()).then_some((contract_dir, file))
+}
+
+/// Files of the contract directory currently being read from the archive
+#[derive(Default)]
+struct PendingContract {
+    dir: String,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+/// Streams the archive and extracts the selected contracts to `corpus_root`, in the
+/// smart-contract-fiesta layout, so only they take up disk space. Files of a contract are
+/// expected next to each other in the archive, as tar and zip write directories.
+pub fn extract_contracts(
+    archive: &Path,
+    corpus_root: &Path,
+    selection: ContractSelection,
+) -> Result<Vec<FiestaMetadata>, String> {
+    let mut metadatas = Vec::new();
+    let mut skipped_count = 0;
+    let mut write_error = None;
+    let mut pending = PendingContract::default();
+
+    // extracts the pending contract if it is selected, breaks once the selection is complete
+    let mut flush = |pending: &mut PendingContract,
+                     metadatas: &mut Vec<FiestaMetadata>|
+     -> Result<ControlFlow<()>, String> {
+        let PendingContract { dir, files } = std::mem::take(pending);
+        let Some((_, metadata_json)) = files.iter().find(|(file, _)| file == "metadata.json")
+        else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        let Ok(mut metadata) = serde_json::from_slice::<FiestaMetadata>(metadata_json) else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        if !selection.select(&metadata, &mut skipped_count) {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        let path_to_dir = corpus_root.join(&dir);
+        for (file, contents) in &files {
+            let path = path_to_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+            fs::write(&path, contents).map_err(|e| e.to_string())?;
+        }
+        metadata
+            .update_path_to_dir(&path_to_dir, corpus_root)
+            .map_err(|e| e.to_string())?;
+        metadatas.push(metadata);
+        if metadatas.len().is_multiple_of(1000) {
+            println!(
+                "Total of {} contracts extracted from {}",
+                metadatas.len(),
+                archive.display()
+            );
+        }
+
+        Ok(if selection.is_complete(metadatas.len()) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        })
+    };
+
+    for_each_file(archive, |path, contents| {
+        let Some((dir, file)) = split_contract_path(path) else {
+            return ControlFlow::Continue(());
+        };
+        if dir != pending.dir {
+            match flush(&mut pending, &mut metadatas) {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(ControlFlow::Break(())) => return ControlFlow::Break(()),
+                Err(e) => {
+                    write_error = Some(e);
+                    return ControlFlow::Break(());
+                }
+            }
+            pending.dir = dir;
+        }
+        pending.files.push((file, contents));
+        ControlFlow::Continue(())
+    })?;
+    if let Some(e) = write_error {
+        return Err(format!(
+            "could not extract to {}: {}",
+            corpus_root.display(),
+            e
+        ));
+    }
+    // the last contract of the archive
+    if !selection.is_complete(metadatas.len()) && !pending.dir.is_empty() {
+        let _ = flush(&mut pending, &mut metadatas)
+            .map_err(|e| format!("could not extract to {}: {}", corpus_root.display(), e))?;
+    }
+    Ok(metadatas)
+}
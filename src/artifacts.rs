@@ -0,0 +1,97 @@
+use crate::error::OmniscanError;
+use serde::Serialize;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Directory holding the artifacts of a single contract
+pub fn contract_artifacts_dir(artifacts_dir: &Path, bytecode_hash: &str) -> PathBuf {
+    artifacts_dir.join(bytecode_hash)
+}
+
+/// Saves the captured pyrometer output of a contract as stdout.txt and stderr.txt
+pub fn write_captured_output(
+    artifacts_dir: &Path,
+    bytecode_hash: &str,
+    stdout: &str,
+    stderr: &str,
+) -> Result<(), OmniscanError> {
+    let contract_dir = contract_artifacts_dir(artifacts_dir, bytecode_hash);
+    let write_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| OmniscanError::Write { path, source }
+    };
+    fs::create_dir_all(&contract_dir).map_err(write_error(&contract_dir))?;
+    for (file_name, contents) in [("stdout.txt", stdout), ("stderr.txt", stderr)] {
+        let path = contract_dir.join(file_name);
+        fs::write(&path, contents).map_err(write_error(&path))?;
+    }
+    Ok(())
+}
+
+/// A file the analyzer wrote while analyzing a contract, see `--collect-outputs`
+#[derive(Serialize, Debug, Clone)]
+pub struct AnalyzerOutput {
+    /// Path relative to <ARTIFACTS>/<bytecode_hash>/outputs/
+    pub path: String,
+    pub size: u64,
+}
+
+/// Directory the analyzer of a single contract runs in
+pub fn run_dir(runs_dir: &Path, bytecode_hash: &str) -> PathBuf {
+    runs_dir.join(bytecode_hash)
+}
+
+/// Moves the files the analyzer wrote to its run directory to
+/// <ARTIFACTS>/<bytecode_hash>/outputs/ and removes the run directory
+pub fn collect_outputs(
+    run_dir: &Path,
+    artifacts_dir: &Path,
+    bytecode_hash: &str,
+) -> Result<Vec<AnalyzerOutput>, String> {
+    let outputs_dir = contract_artifacts_dir(artifacts_dir, bytecode_hash).join("outputs");
+    let mut outputs = Vec::new();
+    for entry in WalkDir::new(run_dir).sort_by_file_name() {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let size = entry.metadata().map_err(|e| e.to_string())?.len();
+        let rel_path = entry.path().strip_prefix(run_dir).unwrap();
+        let target = outputs_dir.join(rel_path);
+        fs::create_dir_all(target.parent().unwrap()).map_err(|e| e.to_string())?;
+        // the run directory is usually on another filesystem than the artifacts
+        if fs::rename(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target).map_err(|e| e.to_string())?;
+        }
+        outputs.push(AnalyzerOutput {
+            path: rel_path.to_string_lossy().to_string(),
+            size,
+        });
+    }
+    let _ = fs::remove_dir_all(run_dir);
+    Ok(outputs)
+}
+
+/// Appends the files collected for a contract to <ARTIFACTS>/outputs.jsonl
+pub fn record_outputs(
+    artifacts_dir: &Path,
+    bytecode_hash: &str,
+    outputs: &[AnalyzerOutput],
+) -> Result<(), OmniscanError> {
+    let line = serde_json::json!({
+        "bytecode_hash": bytecode_hash,
+        "total_size": outputs.iter().map(|output| output.size).sum::<u64>(),
+        "files": outputs,
+    });
+    let path = artifacts_dir.join("outputs.jsonl");
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut index| index.write_all(format!("{}\n", line).as_bytes()))
+        .map_err(|source| OmniscanError::Write { path, source })
+}
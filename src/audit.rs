@@ -0,0 +1,52 @@
+use crate::ResultsRow;
+use std::collections::HashSet;
+
+/// End of run check that every contract handed to the tx loop made it into the results
+pub struct PipelineAudit {
+    /// Contracts handed to the tx loop
+    pub dispatched: usize,
+    /// Results recorded by the rx loop during this run
+    pub recorded: usize,
+    /// Source paths of the dispatched contracts without a result
+    pub lost: Vec<String>,
+}
+
+impl PipelineAudit {
+    /// `dispatched` holds the bytecode hash and source path of every contract handed to the tx
+    /// loop
+    pub fn new(dispatched: &[(String, String)], recorded: usize, rows: &[ResultsRow]) -> Self {
+        let recorded_hashes = rows
+            .iter()
+            .map(|row| row.bytecode_hash.as_str())
+            .collect::<HashSet<&str>>();
+        let lost = dispatched
+            .iter()
+            .filter(|(bytecode_hash, _source_path)| {
+                !recorded_hashes.contains(bytecode_hash.as_str())
+            })
+            .map(|(_bytecode_hash, source_path)| source_path.clone())
+            .collect();
+        Self {
+            dispatched: dispatched.len(),
+            recorded,
+            lost,
+        }
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.dispatched == self.recorded && self.lost.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_consistent() {
+            return;
+        }
+        eprintln!(
+            "Pipeline audit failed: {} contracts dispatched, {} results recorded",
+            self.dispatched, self.recorded
+        );
+        for source_path in &self.lost {
+            eprintln!("  lost by the pipeline: {}", source_path);
+        }
+    }
+}
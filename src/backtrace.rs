@@ -0,0 +1,66 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Frames kept in a crash location, innermost first
+const CRASH_FRAMES: usize = 3;
+
+lazy_static! {
+    /// A frame of a `RUST_BACKTRACE=1` or `full` backtrace, e.g. `  12: graph::nodes::Node::ty`
+    /// or `  12:     0x55d1c2 - graph::nodes::Node::ty::h8f3a92c1d0b4e765`
+    static ref FRAME_REGEX: Regex =
+        Regex::new(r"^\s*\d+:\s+(?:0x[0-9a-fA-F]+ - )?(.+?)\s*$").unwrap();
+    /// The symbol hash rustc appends to legacy-mangled names
+    static ref SYMBOL_HASH_REGEX: Regex = Regex::new(r"::h[0-9a-f]{16}$").unwrap();
+}
+
+/// Frames of the panic machinery, the standard library and the process entry, which every
+/// panic goes through and which say nothing about where it comes from
+const RUNTIME_PREFIXES: [&str; 12] = [
+    "std::",
+    "core::",
+    "alloc::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+    "rust_begin_unwind",
+    "rust_panic",
+    "__rust",
+    "__libc_start",
+    "_start",
+    "<unknown>",
+];
+
+/// Sets `RUST_BACKTRACE=1` on the analyzer so its panics come with a backtrace, unless
+/// `--analyzer-env` or omniscan's own environment already decide it
+pub fn with_backtraces(mut env: Vec<(String, String)>) -> Vec<(String, String)> {
+    let decided = env.iter().any(|(key, _value)| key == "RUST_BACKTRACE")
+        || std::env::var_os("RUST_BACKTRACE").is_some();
+    if !decided {
+        env.push(("RUST_BACKTRACE".to_string(), "1".to_string()));
+    }
+    env
+}
+
+/// Where the first panic in `stderr` comes from: the innermost frames of its backtrace that
+/// belong to the analyzer, joined by ` <- `, e.g.
+/// `graph::nodes::Node::ty <- analyzers::func_call::call <- ...`. Names are kept without their
+/// symbol hash and without file and line, so the location survives unrelated edits to the
+/// analyzer. None when the panic printed no backtrace, or one without symbols.
+pub fn crash_location(stderr: &str) -> Option<String> {
+    let mut lines = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("stack backtrace:"));
+    lines.next()?;
+    let frames = lines
+        .filter(|line| !line.trim_start().starts_with("at "))
+        .map_while(|line| FRAME_REGEX.captures(line))
+        .map(|captures| SYMBOL_HASH_REGEX.replace(&captures[1], "").into_owned())
+        .filter(|name| {
+            !RUNTIME_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .take(CRASH_FRAMES)
+        .collect::<Vec<String>>();
+    (!frames.is_empty()).then(|| frames.join(" <- "))
+}
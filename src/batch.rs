@@ -0,0 +1,366 @@
+use crate::{
+    analyze_with_pyrometer, pyrometer_args, queue, retry, scratch, solc, spawn_pyrometer,
+    status::RunStatus, wait_for_analyzer, AnalyzerConfig, FiestaMetadata, ResultMessage,
+    SourceType, SpawnFailures,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// What the tx loop dispatches under one job permit
+pub enum DispatchUnit {
+    Contract(Box<FiestaMetadata>),
+    /// Small contracts analyzed by one pyrometer invocation, see `--batch-size`
+    Batch(Vec<FiestaMetadata>),
+}
+
+impl DispatchUnit {
+    pub fn bytecode_hashes(&self) -> Vec<String> {
+        match self {
+            DispatchUnit::Contract(metadata) => vec![metadata.bytecode_hash.clone()],
+            DispatchUnit::Batch(batch) => batch
+                .iter()
+                .map(|metadata| metadata.bytecode_hash.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Groups the contracts whose entrypoint is at most `max_bytes` into batches of `batch_size`
+/// contracts analyzed with the same flags. Other contracts are dispatched on their own, in
+/// queue order; a batch is dispatched once it is full, or at the end of the queue.
+pub fn plan_batches(
+    metadatas: Vec<FiestaMetadata>,
+    batch_size: usize,
+    max_bytes: u64,
+    analyzer: &AnalyzerConfig,
+) -> Vec<DispatchUnit> {
+    let mut units = Vec::new();
+    // open batches by the flags of their contracts
+    let mut open: HashMap<Vec<String>, Vec<FiestaMetadata>> = HashMap::new();
+    let mut open_order = Vec::new();
+    for metadata in metadatas {
+        let flags = match (&metadata.harness_error, &metadata.source_type) {
+            (
+                None,
+                Some(
+                    SourceType::SingleMain(_)
+                    | SourceType::Multiple(_)
+                    | SourceType::EtherscanMetadata(_),
+                ),
+            ) => pyrometer_args(&metadata, None, analyzer)
+                .ok()
+                .filter(|(_, size)| *size <= max_bytes)
+                .map(|(args, _)| args[1..].to_vec()),
+            _ => None,
+        };
+        let Some(flags) = flags else {
+            units.push(DispatchUnit::Contract(Box::new(metadata)));
+            continue;
+        };
+        let batch = open.entry(flags.clone()).or_insert_with(|| {
+            open_order.push(flags.clone());
+            Vec::new()
+        });
+        batch.push(metadata);
+        if batch.len() == batch_size {
+            units.push(DispatchUnit::Batch(open.remove(&flags).unwrap()));
+            open_order.retain(|open_flags| *open_flags != flags);
+        }
+    }
+    for flags in open_order {
+        let batch = open.remove(&flags).unwrap();
+        units.push(match batch.len() {
+            1 => DispatchUnit::Contract(Box::new(batch.into_iter().next().unwrap())),
+            _ => DispatchUnit::Batch(batch),
+        });
+    }
+    units
+}
+
+/// Splits the output of a batch into the output of each of its `paths`: a contract's part
+/// starts at the first line naming its path after the previous contract's part, anything
+/// before goes to the first contract. None for the contracts whose path is not named.
+pub fn split_output(output: &str, paths: &[String]) -> Vec<Option<String>> {
+    let lines = output.split_inclusive('\n').collect::<Vec<&str>>();
+    let mut starts = vec![None; paths.len()];
+    let mut line_idx = 0;
+    for (path_idx, path) in paths.iter().enumerate() {
+        if let Some(found) = lines[line_idx..]
+            .iter()
+            .position(|line| line.contains(path.as_str()))
+        {
+            starts[path_idx] = Some(line_idx + found);
+            line_idx += found + 1;
+        }
+    }
+    let found = starts.iter().flatten().copied().collect::<Vec<usize>>();
+    starts
+        .iter()
+        .map(|start| {
+            let start = (*start)?;
+            let end = found
+                .iter()
+                .find(|other| **other > start)
+                .copied()
+                .unwrap_or(lines.len());
+            let start = if Some(&start) == found.first() {
+                0
+            } else {
+                start
+            };
+            Some(lines[start..end].concat())
+        })
+        .collect()
+}
+
+/// Gives a stream naming none of the files, e.g. the panic of a crash, to the last contract
+/// the other stream reached
+fn attribute_unnamed(parts: &mut [Option<String>], other_parts: &[Option<String>], output: &str) {
+    if parts.iter().all(Option::is_none) && !output.trim().is_empty() {
+        if let Some(last) = other_parts.iter().rposition(Option::is_some) {
+            parts[last] = Some(output.to_string());
+        }
+    }
+}
+
+/// What a batch job needs from the tx loop
+pub struct BatchJob {
+    pub tx: mpsc::Sender<ResultMessage>,
+    pub journal: Option<Arc<queue::QueueJournal>>,
+    pub status: Arc<RunStatus>,
+    pub analyzer: Arc<AnalyzerConfig>,
+    pub scratch_dir: PathBuf,
+    pub analyzer_home: scratch::AnalyzerHome,
+    pub spawn_failures: Arc<SpawnFailures>,
+    pub solc_check: bool,
+    pub pyrometer_timeout: f64,
+    pub poll_interval: Duration,
+    pub spawn_retry: retry::SpawnRetry,
+    /// Whether the contracts of the batch warm the machine up, see `--warmup`
+    pub warmup: bool,
+    pub queued_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl BatchJob {
+    fn result_message(&self, metadata: &FiestaMetadata) -> ResultMessage {
+        ResultMessage {
+            warmup: self.warmup,
+            unhonored_settings: self
+                .analyzer
+                .compiler_settings
+                .as_ref()
+                .map(|settings_flags| settings_flags.flags(metadata).1)
+                .unwrap_or_default(),
+            ..ResultMessage::new(metadata, self.queued_at, self.started_at)
+        }
+    }
+
+    /// Whether the contract compiles with solc, and the settings of its metadata solc could
+    /// not honor, see `solc::solc_compiles`
+    async fn solc_compiles(&self, metadata: &FiestaMetadata) -> (Option<bool>, Vec<String>) {
+        if !self.solc_check {
+            return (None, Vec::new());
+        }
+        let metadata = metadata.clone();
+        let honor_settings = self.analyzer.compiler_settings.is_some();
+        tokio::task::spawn_blocking(move || solc::solc_compiles(&metadata, honor_settings))
+            .await
+            .unwrap_or((None, Vec::new()))
+    }
+
+    /// Analyzes the batch with one pyrometer invocation, with a timeout of the timeout of one
+    /// contract times the size of the batch. The time of the invocation is divided evenly
+    /// among the contracts whose output could be told apart, the others (the batch timed out,
+    /// or the analyzer stopped before reaching them) are analyzed again on their own.
+    pub async fn run(self, batch: Vec<FiestaMetadata>) {
+        for metadata in &batch {
+            if let Some(journal) = &self.journal {
+                // a journal that can't be written fails the run once the results are recorded
+                let _ = journal.record_dispatched(&metadata.bytecode_hash);
+            }
+        }
+        let slot = self.status.start_job(&batch[0].bytecode_hash);
+        let count = batch.len();
+        let invocations = batch
+            .iter()
+            .map(|metadata| pyrometer_args(metadata, None, &self.analyzer))
+            .collect::<Result<Vec<_>, _>>();
+        let mut unattributed = Vec::new();
+        match invocations {
+            Ok(invocations) => {
+                let paths = invocations
+                    .iter()
+                    .map(|(args, _)| args[0].clone())
+                    .collect::<Vec<String>>();
+                let args = paths
+                    .iter()
+                    .chain(&invocations[0].0[1..])
+                    .cloned()
+                    .collect::<Vec<String>>();
+                let scratch = scratch::JobScratch::create(
+                    &self.scratch_dir,
+                    &format!("batch-{}", batch[0].bytecode_hash),
+                    self.analyzer_home,
+                    None,
+                )
+                .ok();
+                let mut spawn_time = 0.0;
+                let (spawned, spawn_retries) = self
+                    .spawn_retry
+                    .spawn(|| {
+                        let spawn_start = Instant::now();
+                        let spawned =
+                            spawn_pyrometer(args.clone(), &self.analyzer, scratch.as_ref(), None);
+                        spawn_time = spawn_start.elapsed().as_secs_f64();
+                        spawned
+                    })
+                    .await;
+                self.spawn_failures.record(&spawned);
+                let run = match spawned {
+                    Ok(child) => {
+                        wait_for_analyzer(
+                            child,
+                            self.pyrometer_timeout * count as f64,
+                            self.poll_interval,
+                        )
+                        .await
+                    }
+                    Err(_) => None,
+                };
+                match run.and_then(|run| Some((run.output?, run.time, run.cpu_time))) {
+                    Some(((stdout, stderr), time, cpu_time)) => {
+                        let mut stdout_parts = split_output(&stdout, &paths);
+                        let mut stderr_parts = split_output(&stderr, &paths);
+                        attribute_unnamed(&mut stdout_parts, &stderr_parts, &stdout);
+                        attribute_unnamed(&mut stderr_parts, &stdout_parts, &stderr);
+                        for (idx, metadata) in batch.into_iter().enumerate() {
+                            if stdout_parts[idx].is_none() && stderr_parts[idx].is_none() {
+                                unattributed.push(metadata);
+                                continue;
+                            }
+                            let (solc_compiles, solc_unhonored) =
+                                self.solc_compiles(&metadata).await;
+                            let mut result_message = ResultMessage {
+                                output: Some((
+                                    stdout_parts[idx].take().unwrap_or_default(),
+                                    stderr_parts[idx].take().unwrap_or_default(),
+                                )),
+                                time: time / count as f64,
+                                cpu_time: cpu_time.map(|cpu_time| cpu_time / count as f64),
+                                harness_error: None,
+                                spawn_time: spawn_time / count as f64,
+                                size: invocations[idx].1,
+                                solc_compiles,
+                                batch_size: Some(count),
+                                spawn_retries,
+                                ..self.result_message(&metadata)
+                            };
+                            result_message.unhonored_settings.extend(solc_unhonored);
+                            let _ = self.tx.send(result_message);
+                        }
+                    }
+                    None => unattributed = batch,
+                }
+            }
+            Err(_) => unattributed = batch,
+        }
+        self.status.finish_job(slot);
+
+        for metadata in unattributed {
+            let slot = self.status.start_job(&metadata.bytecode_hash);
+            let scratch = scratch::JobScratch::create(
+                &self.scratch_dir,
+                &metadata.bytecode_hash,
+                self.analyzer_home,
+                None,
+            )
+            .ok();
+            let mut spawn_time = 0.0;
+            let (spawned, spawn_retries) = self
+                .spawn_retry
+                .spawn(|| {
+                    let spawn_start = Instant::now();
+                    let spawned = analyze_with_pyrometer(
+                        &metadata,
+                        None,
+                        &self.analyzer,
+                        scratch.as_ref(),
+                        None,
+                    );
+                    spawn_time = spawn_start.elapsed().as_secs_f64();
+                    spawned
+                })
+                .await;
+            self.spawn_failures.record(&spawned);
+            let result_message = match spawned {
+                Ok((child, size)) => {
+                    wait_for_analyzer(child, self.pyrometer_timeout, self.poll_interval)
+                        .await
+                        .map(|run| ResultMessage {
+                            output: run.output,
+                            time: run.time,
+                            cpu_time: run.cpu_time,
+                            harness_error: None,
+                            spawn_time,
+                            size,
+                            spawn_retries,
+                            ..self.result_message(&metadata)
+                        })
+                }
+                Err(e) => Some(ResultMessage {
+                    harness_error: Some(e.to_string()),
+                    spawn_retries,
+                    ..self.result_message(&metadata)
+                }),
+            };
+            if let Some(mut result_message) = result_message {
+                let (solc_compiles, solc_unhonored) = self.solc_compiles(&metadata).await;
+                result_message.solc_compiles = solc_compiles;
+                result_message.unhonored_settings.extend(solc_unhonored);
+                let _ = self.tx.send(result_message);
+            }
+            self.status.finish_job(slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_output_gives_each_contract_its_part() {
+        let paths = [
+            "a/main.sol".to_string(),
+            "b/main.sol".to_string(),
+            "c/main.sol".to_string(),
+        ];
+        let output = "loading\nanalyzing a/main.sol\nok\nanalyzing c/main.sol\nerror\n";
+        assert_eq!(
+            split_output(output, &paths),
+            [
+                Some("loading\nanalyzing a/main.sol\nok\n".to_string()),
+                None,
+                Some("analyzing c/main.sol\nerror\n".to_string()),
+            ]
+        );
+        assert_eq!(split_output("", &paths), [None, None, None]);
+    }
+
+    #[test]
+    fn unnamed_output_goes_to_the_last_contract_reached() {
+        let mut stderr_parts = vec![None, None];
+        let stdout_parts = [Some("analyzing a/main.sol\n".to_string()), None];
+        attribute_unnamed(&mut stderr_parts, &stdout_parts, "thread 'main' panicked\n");
+        assert_eq!(
+            stderr_parts,
+            [Some("thread 'main' panicked\n".to_string()), None]
+        );
+    }
+}
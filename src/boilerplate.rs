@@ -0,0 +1,106 @@
+use crate::complexity;
+use ethers::utils::keccak256;
+use std::{collections::HashSet, fmt};
+
+/// Runtime code of an EIP-1167 minimal proxy, up to the address of its implementation. Sources
+/// of such proxies hold it as a constant to deploy or to check against.
+const MINIMAL_PROXY_CODE: &str = "363d3d373d3d3d363d73";
+
+/// Contracts that add no distinct code to the measured corpus, see `--skip-boilerplate`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Boilerplate {
+    /// An EIP-1167 minimal proxy, whose code is the same few bytes whatever it delegates to
+    MinimalProxy,
+    /// The same sources as a contract found earlier, up to comments and whitespace, e.g. a
+    /// token deployed many times with different constructor arguments
+    Copy,
+}
+
+impl fmt::Display for Boilerplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Boilerplate::MinimalProxy => write!(f, "MinimalProxy"),
+            Boilerplate::Copy => write!(f, "Copy"),
+        }
+    }
+}
+
+/// Drops comments and collapses whitespace, leaving string literals as they are
+fn normalize(source: &str) -> String {
+    let mut normalized = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut pending_space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&next| next != '\n') {
+                    chars.next();
+                }
+                pending_space = true;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+                pending_space = true;
+            }
+            c if c.is_whitespace() => pending_space = true,
+            c => {
+                if pending_space && !normalized.is_empty() {
+                    normalized.push(' ');
+                }
+                pending_space = false;
+                normalized.push(c);
+                if c == '"' || c == '\'' {
+                    let mut escaped = false;
+                    for next in chars.by_ref() {
+                        normalized.push(next);
+                        if next == c && !escaped {
+                            break;
+                        }
+                        escaped = next == '\\' && !escaped;
+                    }
+                }
+            }
+        }
+    }
+    normalized
+}
+
+/// Identifies sources up to comments and whitespace, whatever the order of the files
+pub fn sources_key(sources: &[String]) -> [u8; 32] {
+    let mut normalized = sources
+        .iter()
+        .map(|source| normalize(source))
+        .collect::<Vec<String>>();
+    normalized.sort();
+    keccak256(normalized.join("\0").as_bytes())
+}
+
+/// Classifies contracts in discovery order, remembering the sources seen so far so that only
+/// the later copies of the same sources count as boilerplate
+#[derive(Default)]
+pub struct BoilerplateDetector {
+    seen: HashSet<[u8; 32]>,
+}
+
+impl BoilerplateDetector {
+    /// `sources` are the files of the contract's compilation unit
+    pub fn classify(&mut self, sources: &[String]) -> Option<Boilerplate> {
+        // clone factories hold the same code, but also the functions deploying the clones
+        let is_minimal_proxy = complexity::measure_sources(sources).functions <= 2
+            && sources
+                .iter()
+                .any(|source| source.to_ascii_lowercase().contains(MINIMAL_PROXY_CODE));
+        if is_minimal_proxy {
+            return Some(Boilerplate::MinimalProxy);
+        }
+
+        (!self.seen.insert(sources_key(sources))).then_some(Boilerplate::Copy)
+    }
+}
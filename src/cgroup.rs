@@ -0,0 +1,214 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+/// Period of the cpu.max quota (microseconds), the kernel default
+const CPU_PERIOD: u64 = 100_000;
+
+/// How long the processes of a cgroup that is being removed get to die after being killed
+const KILL_WAIT: Duration = Duration::from_millis(500);
+
+/// Mount point of the cgroup v2 hierarchy
+fn cgroup2_mount() -> Result<PathBuf, String> {
+    let mounts = fs::read_to_string("/proc/self/mounts").map_err(|e| e.to_string())?;
+    mounts
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|fields| fields.get(2) == Some(&"cgroup2"))
+        .and_then(|fields| fields.get(1).map(PathBuf::from))
+        .ok_or_else(|| "no cgroup v2 hierarchy is mounted".to_string())
+}
+
+/// Path of omniscan's own cgroup within the v2 hierarchy
+fn own_cgroup() -> Result<String, String> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").map_err(|e| e.to_string())?;
+    cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.trim_start_matches('/').to_string())
+        .ok_or_else(|| "omniscan is not in a cgroup v2 hierarchy".to_string())
+}
+
+fn write(path: &Path, value: &str) -> Result<(), String> {
+    fs::write(path, value).map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+/// Removes a cgroup, killing the processes still in it first: what the analyzer started and
+/// left running joined its cgroup with it. Left behind when they don't die in time.
+fn remove_cgroup(dir: &Path) {
+    if fs::remove_dir(dir).is_ok() {
+        return;
+    }
+    // cgroup.kill takes the whole tree at once, kernels before 5.14 lack it
+    if write(&dir.join("cgroup.kill"), "1").is_err() {
+        let procs = fs::read_to_string(dir.join("cgroup.procs")).unwrap_or_default();
+        for pid in procs
+            .lines()
+            .filter_map(|pid| pid.trim().parse::<i32>().ok())
+        {
+            // SAFETY: kill(2) has no memory safety requirements
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+            }
+        }
+    }
+    let step = Duration::from_millis(10);
+    for _ in 0..KILL_WAIT.as_millis() / step.as_millis() {
+        thread::sleep(step);
+        if fs::remove_dir(dir).is_ok() {
+            return;
+        }
+    }
+}
+
+/// Cgroups limiting each analyzer process to a share of the CPU, see `--cpu-quota`. Every
+/// contract gets a cgroup of its own under `<omniscan's cgroup>/omniscan-<pid>/`. The cgroups
+/// are removed once dropped, and omniscan moved back to the cgroup it started in.
+pub struct CpuQuota {
+    /// The cgroup omniscan started in
+    base: PathBuf,
+    /// Whether the run enabled the cpu controller for the children of `base`
+    enabled_base_cpu: bool,
+    run_dir: PathBuf,
+    /// Leaf of `run_dir` omniscan itself is moved to
+    omniscan_dir: PathBuf,
+    /// Value of cpu.max, the quota and the period in microseconds
+    cpu_max: String,
+}
+
+impl CpuQuota {
+    /// Sets up the cgroup of the run. omniscan must be started in a cgroup delegated to its
+    /// user with the cpu controller available, e.g. with
+    /// `systemd-run --user --scope -p Delegate=yes`.
+    pub fn setup(cpus: f64) -> Result<Self, String> {
+        let (mount, own_cgroup) = (cgroup2_mount()?, own_cgroup()?);
+        let base = match own_cgroup.is_empty() {
+            true => mount,
+            false => mount.join(own_cgroup),
+        };
+        let controllers = fs::read_to_string(base.join("cgroup.controllers")).map_err(|e| {
+            format!(
+                "could not read the controllers of {}: {}",
+                base.display(),
+                e
+            )
+        })?;
+        if !controllers
+            .split_whitespace()
+            .any(|controller| controller == "cpu")
+        {
+            return Err(format!(
+                "the cpu controller is not available in {}, start omniscan in a delegated \
+                 cgroup, e.g. with `systemd-run --user --scope -p Delegate=yes`",
+                base.display()
+            ));
+        }
+
+        // a cgroup that hands controllers to its children may not hold processes itself, so
+        // omniscan moves into a leaf of the run's cgroup first
+        let run_dir = base.join(format!("omniscan-{}", std::process::id()));
+        let omniscan_dir = run_dir.join("omniscan");
+        fs::create_dir_all(&omniscan_dir)
+            .map_err(|e| format!("could not create {}: {}", omniscan_dir.display(), e))?;
+        write(
+            &omniscan_dir.join("cgroup.procs"),
+            &std::process::id().to_string(),
+        )?;
+        let enabled_base_cpu = !fs::read_to_string(base.join("cgroup.subtree_control"))
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|controller| controller == "cpu");
+        write(&base.join("cgroup.subtree_control"), "+cpu")?;
+        write(&run_dir.join("cgroup.subtree_control"), "+cpu")?;
+
+        // the kernel rejects quotas under 1ms
+        let quota = ((cpus * CPU_PERIOD as f64).round() as u64).max(1000);
+        Ok(Self {
+            base,
+            enabled_base_cpu,
+            run_dir,
+            omniscan_dir,
+            cpu_max: format!("{} {}", quota, CPU_PERIOD),
+        })
+    }
+
+    /// Creates the cgroup of a contract, removed once dropped
+    pub fn job_cgroup(&self, bytecode_hash: &str) -> Result<JobCgroup, String> {
+        let dir = self.run_dir.join(bytecode_hash);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+        let job_cgroup = JobCgroup {
+            procs: OpenOptions::new()
+                .write(true)
+                .open(dir.join("cgroup.procs"))
+                .map_err(|e| format!("could not open {}: {}", dir.display(), e))?,
+            dir,
+        };
+        write(&job_cgroup.dir.join("cpu.max"), &self.cpu_max)?;
+        Ok(job_cgroup)
+    }
+}
+
+/// The cgroup of one contract's analyzer processes
+pub struct JobCgroup {
+    dir: PathBuf,
+    /// Opened beforehand, as the child may not allocate between fork and exec
+    procs: File,
+}
+
+impl JobCgroup {
+    /// Makes the command's process join the cgroup before it execs the analyzer, so none of
+    /// its threads run outside of the quota
+    pub fn apply(&self, command: &mut Command) {
+        let procs = self.procs.as_raw_fd();
+        // SAFETY: only calls write(2), which is async-signal-safe, on a descriptor that stays
+        // open in the child until exec. Writing 0 moves the writing process.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::write(procs, b"0".as_ptr().cast(), 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+impl Drop for JobCgroup {
+    fn drop(&mut self) {
+        remove_cgroup(&self.dir);
+    }
+}
+
+impl Drop for CpuQuota {
+    fn drop(&mut self) {
+        // the cgroups of contracts left behind
+        if let Ok(entries) = fs::read_dir(&self.run_dir) {
+            for dir in entries.flatten().map(|entry| entry.path()) {
+                if dir.is_dir() && dir != self.omniscan_dir {
+                    remove_cgroup(&dir);
+                }
+            }
+        }
+        // a cgroup handing the cpu controller to its children can't take omniscan back,
+        // unless it is the root
+        let _ = write(&self.run_dir.join("cgroup.subtree_control"), "-cpu");
+        if self.enabled_base_cpu {
+            let _ = write(&self.base.join("cgroup.subtree_control"), "-cpu");
+        }
+        let moved_back = write(
+            &self.base.join("cgroup.procs"),
+            &std::process::id().to_string(),
+        );
+        if moved_back.is_ok() {
+            let _ = fs::remove_dir(&self.omniscan_dir);
+            let _ = fs::remove_dir(&self.run_dir);
+        }
+    }
+}
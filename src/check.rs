@@ -0,0 +1,132 @@
+use crate::ResultsRow;
+use std::collections::HashMap;
+
+/// An expected contract whose result in this run does not match the expectations file
+pub struct Mismatch {
+    pub bytecode_hash: String,
+    pub expected: String,
+    /// None when the contract was not analyzed in this run
+    pub actual: Option<String>,
+}
+
+/// Compares every expected contract against the run's results, keyed by bytecode hash.
+/// Contracts that were analyzed but are not part of the expectations are ignored.
+pub fn compare_with_expectations(
+    expectations: &[ResultsRow],
+    result_rows: &[ResultsRow],
+) -> Vec<Mismatch> {
+    let actual_by_hash: HashMap<&str, &ResultsRow> = result_rows
+        .iter()
+        .map(|row| (row.bytecode_hash.as_str(), row))
+        .collect();
+
+    expectations
+        .iter()
+        .filter_map(|expected| {
+            let expected_label = expected.result.to_string();
+            match actual_by_hash.get(expected.bytecode_hash.as_str()) {
+                Some(actual) => {
+                    let actual_label = actual.result.to_string();
+                    if actual_label == expected_label {
+                        None
+                    } else {
+                        Some(Mismatch {
+                            bytecode_hash: expected.bytecode_hash.clone(),
+                            expected: expected_label,
+                            actual: Some(actual_label),
+                        })
+                    }
+                }
+                None => Some(Mismatch {
+                    bytecode_hash: expected.bytecode_hash.clone(),
+                    expected: expected_label,
+                    actual: None,
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Prints the mismatches, then the ones of known flaky contracts that do not fail the check
+/// (see `--flaky-gating`)
+pub fn print_mismatches(mismatches: &[Mismatch], flaky: &[Mismatch], total_expected: usize) {
+    if mismatches.is_empty() && flaky.is_empty() {
+        println!(
+            "Check passed: all {} expected contracts matched",
+            total_expected
+        );
+        return;
+    }
+
+    if mismatches.is_empty() {
+        println!(
+            "Check passed: {}/{} expected contracts matched",
+            total_expected - flaky.len(),
+            total_expected
+        );
+    } else {
+        println!(
+            "Check failed: {}/{} expected contracts differ",
+            mismatches.len(),
+            total_expected
+        );
+        print_mismatch_lines(mismatches);
+    }
+    if !flaky.is_empty() {
+        println!(
+            "{} known flaky contracts differ too, leaving them out of the check:",
+            flaky.len()
+        );
+        print_mismatch_lines(flaky);
+    }
+}
+
+fn print_mismatch_lines(mismatches: &[Mismatch]) {
+    for mismatch in mismatches {
+        match &mismatch.actual {
+            Some(actual) => println!(
+                "  {}: expected `{}`, got `{}`",
+                mismatch.bytecode_hash, mismatch.expected, actual
+            ),
+            None => println!(
+                "  {}: expected `{}`, but it was not analyzed",
+                mismatch.bytecode_hash, mismatch.expected
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::row;
+
+    #[test]
+    fn only_expected_contracts_are_compared() {
+        let expectations = [
+            row("0x1", "Success"),
+            row("0x2", "Error: unsupported"),
+            row("0x3", "Success"),
+        ];
+        let results = [
+            row("0x1", "Success"),
+            row("0x2", "PerformanceTimeout"),
+            row("0x4", "Error: unsupported"),
+        ];
+        let mismatches = compare_with_expectations(&expectations, &results)
+            .into_iter()
+            .map(|mismatch| (mismatch.bytecode_hash, mismatch.expected, mismatch.actual))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mismatches,
+            [
+                (
+                    "0x2".to_string(),
+                    "Error: unsupported".to_string(),
+                    Some("PerformanceTimeout".to_string())
+                ),
+                ("0x3".to_string(), "Success".to_string(), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{artifacts, diff, ExitType, ResultsRow};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Longest message shown per cluster, so the table stays readable
+const MESSAGE_WIDTH: usize = 120;
+/// Contracts listed per cluster
+const SAMPLES: usize = 3;
+
+lazy_static! {
+    /// Quoted names, e.g. the identifier or import path an error is about
+    static ref QUOTED_REGEX: Regex = Regex::new(r#"'[^']*'|"[^"]*"|`[^`]*`"#).unwrap();
+}
+
+/// How `report --clusters` groups and lists the failures
+pub struct ClusterOptions {
+    /// Number of clusters listed
+    pub limit: usize,
+    /// Share of words two messages need in common to be clustered together
+    pub min_similarity: f64,
+    /// Captured outputs of the run, where NonInterpreted messages are read from
+    pub artifacts_dir: Option<PathBuf>,
+}
+
+/// Failures whose messages share most of their words
+pub struct FailureCluster {
+    pub exit_type: &'static str,
+    /// Where the panics of the cluster come from, see `backtrace::crash_location`
+    pub crash_location: Option<String>,
+    /// Most common message of the cluster, as written in the results
+    pub representative: String,
+    /// Number of distinct normalized messages in the cluster
+    pub variants: usize,
+    pub rows: Vec<ResultsRow>,
+}
+
+/// Message a NonInterpreted failure is clustered by: the last line pyrometer wrote to stderr
+/// (stdout when stderr is empty), which the results file does not keep
+fn non_interpreted_message(row: &ResultsRow, artifacts_dir: Option<&Path>) -> String {
+    let contract_dir = artifacts_dir
+        .map(|artifacts_dir| artifacts::contract_artifacts_dir(artifacts_dir, &row.bytecode_hash));
+    let last_line = |file: &str| {
+        let contents = fs::read_to_string(contract_dir.as_ref()?.join(file)).ok()?;
+        let line = contents
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())?;
+        Some(line.trim().to_string())
+    };
+    last_line("stderr.txt")
+        .or_else(|| last_line("stdout.txt"))
+        .unwrap_or_else(|| "<no captured output>".to_string())
+}
+
+/// Words of a normalized message. Quoted names, paths, numbers and the placeholders of the
+/// normalization are left out: they tell failures of the same problem apart.
+fn tokens(normalized: &str) -> BTreeSet<String> {
+    QUOTED_REGEX
+        .replace_all(normalized, " ")
+        .split_whitespace()
+        .filter(|word| !word.contains(['/', '.', '<']))
+        .flat_map(|word| word.split(|c: char| !c.is_alphanumeric() && c != '_'))
+        .filter(|token| !token.is_empty() && !token.chars().all(|c| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Share of the words of the shorter message found in the other one, so a message and the
+/// same message with details appended are similar
+fn similarity(first: &BTreeSet<String>, second: &BTreeSet<String>) -> f64 {
+    let shorter = first.len().min(second.len());
+    if shorter == 0 {
+        return (first.len() == second.len()) as u8 as f64;
+    }
+    first.intersection(second).count() as f64 / shorter as f64
+}
+
+/// Groups the Error, ThreadPanic and NonInterpreted results into clusters, most frequent first.
+/// Messages are normalized like in `diff`, then each distinct message joins the first cluster
+/// of its result type and crash location whose most frequent message is similar enough (see
+/// `similarity`), or starts a cluster of its own.
+pub fn cluster_failures(
+    rows: &[ResultsRow],
+    artifacts_dir: Option<&Path>,
+    min_similarity: f64,
+) -> Vec<FailureCluster> {
+    // rows by result type, crash location and normalized message
+    type Key = (&'static str, Option<String>, String);
+    let mut by_message: HashMap<Key, (String, Vec<ResultsRow>)> = HashMap::new();
+    for row in rows {
+        let message = match &row.result {
+            ExitType::Error(message) | ExitType::ThreadPanic(message) => message.clone(),
+            ExitType::NonInterpreted(_, _) => non_interpreted_message(row, artifacts_dir),
+            _ => continue,
+        };
+        by_message
+            .entry((
+                row.result.name(),
+                row.crash_location.clone(),
+                diff::normalize_message(&message),
+            ))
+            .or_insert_with(|| (message, Vec::new()))
+            .1
+            .push(row.clone());
+    }
+    let mut messages = by_message.into_iter().collect::<Vec<_>>();
+    // most frequent first, so clusters are seeded by their most common message
+    messages.sort_by(|(first, (_, first_rows)), (second, (_, second_rows))| {
+        second_rows
+            .len()
+            .cmp(&first_rows.len())
+            .then(first.cmp(second))
+    });
+
+    let mut clusters: Vec<(BTreeSet<String>, FailureCluster)> = Vec::new();
+    for ((exit_type, crash_location, normalized), (message, rows)) in messages {
+        let message_tokens = tokens(&normalized);
+        let joined = clusters.iter_mut().find(|(seed_tokens, cluster)| {
+            cluster.exit_type == exit_type
+                && cluster.crash_location == crash_location
+                && similarity(seed_tokens, &message_tokens) >= min_similarity
+        });
+        match joined {
+            Some((_, cluster)) => {
+                cluster.variants += 1;
+                cluster.rows.extend(rows);
+            }
+            None => clusters.push((
+                message_tokens,
+                FailureCluster {
+                    exit_type,
+                    crash_location,
+                    representative: message,
+                    variants: 1,
+                    rows,
+                },
+            )),
+        }
+    }
+    let mut clusters = clusters
+        .into_iter()
+        .map(|(_, cluster)| cluster)
+        .collect::<Vec<FailureCluster>>();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.rows.len()));
+    clusters
+}
+
+fn shorten(message: &str) -> String {
+    let message = message.replace('\n', " ").replace('|', "\\|");
+    if message.chars().count() > MESSAGE_WIDTH {
+        message.chars().take(MESSAGE_WIDTH).collect::<String>() + "..."
+    } else {
+        message
+    }
+}
+
+/// Prints the `limit` largest clusters, with a few contracts of each to start the triage from
+pub fn print_clusters(rows: &[ResultsRow], options: &ClusterOptions) {
+    let clusters = cluster_failures(
+        rows,
+        options.artifacts_dir.as_deref(),
+        options.min_similarity,
+    );
+    let limit = options.limit;
+    let failures = clusters
+        .iter()
+        .map(|cluster| cluster.rows.len())
+        .sum::<usize>();
+    println!();
+    println!(
+        "{} failures in {} clusters of similar messages",
+        failures,
+        clusters.len()
+    );
+    if clusters.is_empty() {
+        return;
+    }
+    println!("| # | Result | Contracts | Share of failures | Variants | Message |");
+    println!("| ----- | ----- | ----- | ----- | ----- | ------- |");
+    for (idx, cluster) in clusters.iter().take(limit).enumerate() {
+        println!(
+            "|{} | {} | {} | {:.2} | {} | {} |",
+            idx + 1,
+            cluster.exit_type,
+            cluster.rows.len(),
+            cluster.rows.len() as f64 / failures as f64 * 100.0,
+            cluster.variants,
+            match &cluster.crash_location {
+                Some(crash_location) => {
+                    shorten(&format!(
+                        "{} (at {})",
+                        cluster.representative, crash_location
+                    ))
+                }
+                None => shorten(&cluster.representative),
+            }
+        );
+    }
+    if clusters.len() > limit {
+        let rest = clusters[limit..]
+            .iter()
+            .map(|cluster| cluster.rows.len())
+            .sum::<usize>();
+        println!(
+            "... and {} more clusters with {} failures",
+            clusters.len() - limit,
+            rest
+        );
+    }
+
+    println!();
+    println!("Samples:");
+    for (idx, cluster) in clusters.iter().take(limit).enumerate() {
+        for row in cluster.rows.iter().take(SAMPLES) {
+            println!("  #{} {} {}", idx + 1, row.bytecode_hash, row.source_path);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{
+    artifacts, collect_contract_sources,
+    diff::{ChangedResult, RunDiff},
+    junit::escape_xml,
+    load_contract_metadata, manifest, pyrometer_args,
+    score::ScoreWeights,
+    summary::RunSummary,
+    AnalyzerConfig, ResultsRow,
+};
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Lines of captured output shown inline per contract, the links lead to the whole files
+const OUTPUT_LINES: usize = 60;
+
+/// What `diff --html` needs besides the two runs
+pub struct HtmlReport {
+    pub path: PathBuf,
+    /// Artifacts directory of the new run (see `--artifacts`), for the captured output
+    pub artifacts_dir: Option<PathBuf>,
+    /// Corpus root, for the repro commands
+    pub corpus_root: PathBuf,
+    /// Flags of the repro commands, see `diff --analyzer-args`
+    pub analyzer: AnalyzerConfig,
+}
+
+/// The pyrometer invocation analyzing the contract like the run did, or the entrypoint with
+/// `--debug` when the contract can't be read from the corpus
+fn repro_command(row: &ResultsRow, report: &HtmlReport) -> String {
+    let entrypoint = report.corpus_root.join(&row.source_path);
+    let args = Path::new(&row.source_path)
+        .parent()
+        .and_then(|contract_dir| {
+            let mut metadata =
+                load_contract_metadata(&report.corpus_root.join(contract_dir), &report.corpus_root)
+                    .ok()?;
+            collect_contract_sources(&mut metadata).ok()?;
+            pyrometer_args(&metadata, None, &report.analyzer).ok()
+        })
+        .map(|(args, _)| args)
+        .unwrap_or_else(|| {
+            vec![
+                entrypoint.to_string_lossy().into_owned(),
+                "--debug".to_string(),
+            ]
+        });
+    shlex::try_join(std::iter::once("pyrometer").chain(args.iter().map(String::as_str)))
+        .unwrap_or_else(|_| format!("pyrometer {}", args.join(" ")))
+}
+
+/// Last lines of a captured output file, with the number of lines left out
+fn output_tail(path: &Path) -> Option<(String, usize)> {
+    let contents = fs::read_to_string(path).ok()?;
+    let lines = contents.lines().collect::<Vec<&str>>();
+    let skipped = lines.len().saturating_sub(OUTPUT_LINES);
+    Some((lines[skipped..].join("\n"), skipped))
+}
+
+/// `path` relative to the directory `base`, so the links of the page keep working when the page
+/// is moved along with the artifacts
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let base = std::path::absolute(base).unwrap_or_else(|_| base.to_path_buf());
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    base.components()
+        .skip(common)
+        .map(|_| Component::ParentDir.as_os_str())
+        .chain(path.components().skip(common).map(|c| c.as_os_str()))
+        .collect()
+}
+
+/// Captured stdout and stderr of the contract in the new run, linked and inline
+fn write_outputs(out: &mut String, row: &ResultsRow, report: &HtmlReport) {
+    let Some(artifacts_dir) = &report.artifacts_dir else {
+        writeln!(
+            out,
+            "<p class=\"muted\">No captured output, see <code>--artifacts</code></p>"
+        )
+        .unwrap();
+        return;
+    };
+    let contract_dir = artifacts::contract_artifacts_dir(artifacts_dir, &row.bytecode_hash);
+    let mut found = false;
+    for file in ["stderr.txt", "stdout.txt"] {
+        let path = contract_dir.join(file);
+        let Some((tail, skipped)) = output_tail(&path).filter(|(tail, _)| !tail.is_empty()) else {
+            continue;
+        };
+        found = true;
+        let report_dir = report
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let href = relative_to(&path, report_dir);
+        writeln!(
+            out,
+            "<p><a href=\"{}\">{}</a>{}</p><pre>{}</pre>",
+            escape_xml(&href.to_string_lossy()),
+            file,
+            if skipped > 0 {
+                format!(" (last {} lines, {} more before)", OUTPUT_LINES, skipped)
+            } else {
+                String::new()
+            },
+            escape_xml(&tail)
+        )
+        .unwrap();
+    }
+    if !found {
+        writeln!(
+            out,
+            "<p class=\"muted\">No captured output in {}</p>",
+            escape_xml(&contract_dir.to_string_lossy())
+        )
+        .unwrap();
+    }
+}
+
+/// A table row per contract, each with its captured output and repro command to unfold
+fn write_changes(
+    out: &mut String,
+    id: &str,
+    title: &str,
+    changes: &[ChangedResult],
+    report: &HtmlReport,
+) {
+    writeln!(out, "<h2 id=\"{}\">{} ({})</h2>", id, title, changes.len()).unwrap();
+    if changes.is_empty() {
+        writeln!(out, "<p class=\"muted\">None</p>").unwrap();
+        return;
+    }
+    writeln!(
+        out,
+        "<table><tr><th>Contract</th><th>Old result</th><th>New result</th><th>Time (sec)</th></tr>"
+    )
+    .unwrap();
+    for change in changes {
+        writeln!(
+            out,
+            "<tr><td><details><summary><code>{}</code><br><span class=\"muted\">{}</span></summary>",
+            escape_xml(&change.new.bytecode_hash),
+            escape_xml(&change.new.source_path)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "<p>Repro:</p><pre>{}</pre>",
+            escape_xml(&repro_command(&change.new, report))
+        )
+        .unwrap();
+        write_outputs(out, &change.new, report);
+        writeln!(
+            out,
+            "</details></td><td>{}</td><td>{}</td><td>{:.3} &rarr; {:.3}</td></tr>",
+            escape_xml(&change.old.result.to_string()),
+            escape_xml(&change.new.result.to_string()),
+            change.old.time,
+            change.new.time
+        )
+        .unwrap();
+    }
+    writeln!(out, "</table>").unwrap();
+}
+
+/// Renders the comparison of two runs as a standalone HTML page: the counts of both runs, then
+/// the regressions, changed failures and fixes, each contract unfolding into its repro command
+/// and its captured output in the new run
+pub fn format_html(
+    old_path: &Path,
+    new_path: &Path,
+    old_rows: &[ResultsRow],
+    new_rows: &[ResultsRow],
+    diff: &RunDiff,
+    score_weights: &ScoreWeights,
+    report: &HtmlReport,
+) -> String {
+    let old_summary =
+        RunSummary::from_rows(old_rows).with_full_corpus(manifest::results_full_corpus(old_path));
+    let new_summary =
+        RunSummary::from_rows(new_rows).with_full_corpus(manifest::results_full_corpus(new_path));
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>omniscan: {} vs {}</title>",
+        escape_xml(&old_path.to_string_lossy()),
+        escape_xml(&new_path.to_string_lossy())
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}}\
+         pre{{background:#f6f6f6;padding:8px;overflow-x:auto;max-width:100em}}\
+         .muted{{color:#777}}.worse{{color:#b00}}.better{{color:#080}}</style></head><body>"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<h1>Run comparison</h1><p>Old: <code>{}</code><br>New: <code>{}</code></p>",
+        escape_xml(&old_path.to_string_lossy()),
+        escape_xml(&new_path.to_string_lossy())
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<p><a href=\"#regressions\">{} regressions</a>, \
+         <a href=\"#changed-failures\">{} changed failures</a>, \
+         <a href=\"#fixes\">{} fixes</a>, {} unchanged. \
+         {} contracts only in the old run, {} only in the new run.</p>",
+        diff.regressions.len(),
+        diff.changed_failures.len(),
+        diff.fixes.len(),
+        diff.unchanged,
+        diff.only_old,
+        diff.only_new
+    )
+    .unwrap();
+
+    writeln!(
+        out,
+        "<table><tr><th>Result</th><th>Old</th><th>New</th><th>Change</th></tr>"
+    )
+    .unwrap();
+    let names = old_summary
+        .counts
+        .keys()
+        .chain(new_summary.counts.keys())
+        .collect::<BTreeSet<&String>>();
+    let (successes, failures): (Vec<&String>, Vec<&String>) =
+        names.into_iter().partition(|name| *name == "Success");
+    for name in successes.into_iter().chain(failures) {
+        let old_count = old_summary.counts.get(name).copied().unwrap_or(0);
+        let new_count = new_summary.counts.get(name).copied().unwrap_or(0);
+        let change = new_count as i64 - old_count as i64;
+        let class = match change {
+            0 => "",
+            change if (change > 0) == (name == "Success") => "better",
+            _ => "worse",
+        };
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{:+}</td></tr>",
+            name, old_count, new_count, class, change
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "<tr><td>Success rate</td><td>{}</td><td>{}</td><td>{:+.2}</td></tr>",
+        old_summary.format_success_rate(),
+        new_summary.format_success_rate(),
+        new_summary.success_rate - old_summary.success_rate
+    )
+    .unwrap();
+    let old_score = score_weights.score(&old_summary);
+    let new_score = score_weights.score(&new_summary);
+    writeln!(
+        out,
+        "<tr><td title=\"{}\">Score</td><td>{:.3}</td><td>{:.3}</td><td class=\"{}\">{:+.3}</td></tr></table>",
+        escape_xml(&score_weights.to_string()),
+        old_score,
+        new_score,
+        if new_score < old_score { "worse" } else if new_score > old_score { "better" } else { "" },
+        new_score - old_score
+    )
+    .unwrap();
+
+    write_changes(
+        &mut out,
+        "regressions",
+        "Regressions",
+        &diff.regressions,
+        report,
+    );
+    write_changes(
+        &mut out,
+        "changed-failures",
+        "Changed failures",
+        &diff.changed_failures,
+        report,
+    );
+    write_changes(&mut out, "fixes", "Fixes", &diff.fixes, report);
+    writeln!(out, "</body></html>").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_to_walks_up_to_the_common_directory() {
+        assert_eq!(
+            relative_to(
+                Path::new("/runs/a/artifacts/0x1/stdout.log"),
+                Path::new("/runs/a")
+            ),
+            PathBuf::from("artifacts/0x1/stdout.log")
+        );
+        assert_eq!(
+            relative_to(
+                Path::new("/runs/a/artifacts/0x1"),
+                Path::new("/runs/b/pages")
+            ),
+            PathBuf::from("../../a/artifacts/0x1")
+        );
+        // relative paths are taken from the working directory on both sides
+        assert_eq!(
+            relative_to(Path::new("artifacts/0x1"), Path::new("pages")),
+            PathBuf::from("../artifacts/0x1")
+        );
+    }
+}
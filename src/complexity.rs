@@ -0,0 +1,132 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref CONTRACT_DEFINITION: Regex =
+        Regex::new(r"\b(?:contract|interface|library)\s+[A-Za-z_$][\w$]*").unwrap();
+    /// Named functions plus the special functions, function types have no name and don't match.
+    /// The special functions are declared without `function`, calls like `x.receive(` are not
+    /// declarations, and receive never takes parameters.
+    static ref FUNCTION_DEFINITION: Regex = Regex::new(
+        r"\bfunction\s+[A-Za-z_$][\w$]*\s*\(|(?:^|[^.\w$\s])\s*(?:(?:constructor|fallback)\s*\(|receive\s*\(\s*\))"
+    )
+    .unwrap();
+    /// `assembly {`, optionally with a dialect string and flags, e.g. `assembly ("memory-safe") {`
+    static ref ASSEMBLY_BLOCK: Regex =
+        Regex::new(r#"\bassembly\s*(?:"[^"]*"\s*)?(?:\([^)]*\)\s*)?\{"#).unwrap();
+}
+
+/// Rough size of a contract's sources, from a lexical pass rather than a real parse
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceComplexity {
+    /// Lines holding code, blank and comment-only lines excluded
+    pub lines_of_code: usize,
+    /// Contracts, interfaces and libraries
+    pub contracts: usize,
+    /// Functions, constructors, fallback and receive functions
+    pub functions: usize,
+    pub assembly_blocks: usize,
+}
+
+/// Blanks out comments and the contents of string literals, keeping line breaks so lines can
+/// still be counted, and so that keywords inside them are not counted
+fn strip_comments_and_strings(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&next| next != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        stripped.push('\n');
+                    }
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            '"' | '\'' => {
+                stripped.push(c);
+                let mut escaped = false;
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        stripped.push('\n');
+                    }
+                    if next == c && !escaped {
+                        stripped.push(c);
+                        break;
+                    }
+                    escaped = next == '\\' && !escaped;
+                }
+            }
+            c => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+/// Measures a single source file
+pub fn measure(source: &str) -> SourceComplexity {
+    let code = strip_comments_and_strings(source);
+    SourceComplexity {
+        lines_of_code: code.lines().filter(|line| !line.trim().is_empty()).count(),
+        contracts: CONTRACT_DEFINITION.find_iter(&code).count(),
+        functions: FUNCTION_DEFINITION.find_iter(&code).count(),
+        assembly_blocks: ASSEMBLY_BLOCK.find_iter(&code).count(),
+    }
+}
+
+/// Measures every source of a compilation unit, summing the counts
+pub fn measure_sources(sources: &[String]) -> SourceComplexity {
+    sources.iter().map(|source| measure(source)).fold(
+        SourceComplexity::default(),
+        |total, complexity| SourceComplexity {
+            lines_of_code: total.lines_of_code + complexity.lines_of_code,
+            contracts: total.contracts + complexity.contracts,
+            functions: total.functions + complexity.functions,
+            assembly_blocks: total.assembly_blocks + complexity.assembly_blocks,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_counts_declarations_only() {
+        let source = r#"
+// contract Commented { function f() {} }
+library Math {
+    function add(uint a, uint b) internal pure returns (uint) { return a + b; }
+}
+contract Vault {
+    function(uint) external callback;
+    string constant NOTE = "function g() and assembly { }";
+    constructor() {}
+    fallback() external {}
+    receive() external payable {
+        token.receive();
+        other.fallback(1);
+    }
+    function pay() public {
+        assembly ("memory-safe") { let x := 1 }
+    }
+}
+"#;
+        let complexity = measure(source);
+        assert_eq!(complexity.contracts, 2);
+        // add, constructor, fallback, receive and pay
+        assert_eq!(complexity.functions, 5);
+        assert_eq!(complexity.assembly_blocks, 1);
+        assert_eq!(complexity.lines_of_code, 16);
+    }
+}
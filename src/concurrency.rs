@@ -0,0 +1,155 @@
+use crate::status::RunStatus;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Semaphore,
+};
+
+/// How often the jobs file is checked for a new number of jobs
+const JOBS_FILE_POLL: Duration = Duration::from_secs(1);
+/// Most jobs a run takes, far beyond what any machine runs but low enough to catch a typo
+pub const MAX_JOBS: i64 = 65_536;
+/// Jobs per physical core past which the run is warned about, analyzers are single threaded
+/// but mostly busy
+const JOBS_PER_CORE_WARNING: usize = 4;
+
+/// Warns when `jobs` greatly exceeds the physical cores: fine for lightweight analyses on big
+/// machines, but the timings then include waiting for a CPU
+pub fn warn_oversubscription(jobs: usize) {
+    let cores = num_cpus::get_physical().max(1);
+    if jobs > cores * JOBS_PER_CORE_WARNING {
+        eprintln!(
+            "Running {} jobs on {} physical cores: analyses will wait for a CPU and their times \
+             will not be comparable to runs with fewer jobs",
+            jobs, cores
+        );
+    }
+}
+
+/// Number of analyzer jobs running at once, which can change during the run, see
+/// `--jobs-file`. Jobs hold a permit of the semaphore while they run.
+pub struct JobLimit {
+    semaphore: Arc<Semaphore>,
+    jobs: Mutex<usize>,
+}
+
+impl JobLimit {
+    pub fn new(jobs: usize) -> Arc<Self> {
+        Arc::new(Self {
+            semaphore: Arc::new(Semaphore::new(jobs)),
+            jobs: Mutex::new(jobs),
+        })
+    }
+
+    pub fn semaphore(&self) -> Arc<Semaphore> {
+        self.semaphore.clone()
+    }
+
+    pub fn jobs(&self) -> usize {
+        *self.jobs.lock().unwrap()
+    }
+
+    /// Changes the number of jobs, from 1 to `MAX_JOBS`. Running jobs are never interrupted:
+    /// when there are fewer jobs, the permits in excess are taken out as running jobs finish,
+    /// before any new job starts.
+    pub fn resize(&self, jobs: usize) {
+        let jobs = jobs.clamp(1, MAX_JOBS as usize);
+        let mut current = self.jobs.lock().unwrap();
+        if jobs == *current {
+            return;
+        }
+        warn_oversubscription(jobs);
+        if jobs > *current {
+            self.semaphore.add_permits(jobs - *current);
+        } else {
+            // the semaphore is fair, so this waits ahead of the jobs that are not started yet
+            let excess = (*current - jobs) as u32;
+            let semaphore = self.semaphore.clone();
+            tokio::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many_owned(excess).await {
+                    permits.forget();
+                }
+            });
+        }
+        println!("Concurrent jobs: {} -> {}", *current, jobs);
+        *current = jobs;
+    }
+
+    /// Changes the number of jobs on SIGUSR1 (doubled) and SIGUSR2 (halved), and whenever the
+    /// number in `jobs_file` changes. The jobs file is created with the current number when it
+    /// does not exist. Runs until the run ends.
+    pub fn spawn_controls(self: &Arc<Self>, jobs_file: Option<PathBuf>) {
+        let job_limit = self.clone();
+        tokio::spawn(async move {
+            let (Ok(mut more), Ok(mut fewer)) = (
+                signal(SignalKind::user_defined1()),
+                signal(SignalKind::user_defined2()),
+            ) else {
+                eprintln!("Could not listen for SIGUSR1 and SIGUSR2, the jobs won't change");
+                return;
+            };
+            loop {
+                tokio::select! {
+                    _ = more.recv() => job_limit.resize(job_limit.jobs() * 2),
+                    _ = fewer.recv() => job_limit.resize(job_limit.jobs() / 2),
+                }
+            }
+        });
+
+        let Some(jobs_file) = jobs_file else {
+            return;
+        };
+        if !jobs_file.exists() {
+            if let Err(e) = fs::write(&jobs_file, format!("{}\n", self.jobs())) {
+                eprintln!("Could not create {}: {}", jobs_file.display(), e);
+            }
+        }
+        let job_limit = self.clone();
+        tokio::spawn(async move {
+            let mut last_contents = None;
+            loop {
+                if let Ok(contents) = fs::read_to_string(&jobs_file) {
+                    if last_contents.as_ref() != Some(&contents) {
+                        match contents.trim().parse::<usize>() {
+                            Ok(jobs) if jobs > 0 && jobs as i64 <= MAX_JOBS => {
+                                job_limit.resize(jobs)
+                            }
+                            _ => eprintln!(
+                                "Ignoring {}, it should hold a number of jobs from 1 to {}",
+                                jobs_file.display(),
+                                MAX_JOBS
+                            ),
+                        }
+                        last_contents = Some(contents);
+                    }
+                }
+                tokio::time::sleep(JOBS_FILE_POLL).await;
+            }
+        });
+    }
+}
+
+/// Pauses the run on SIGTSTP and resumes it on SIGCONT, like POST on `/pause` and `/resume`
+/// of the status server
+pub fn spawn_pause_signals(status: Arc<RunStatus>) {
+    tokio::spawn(async move {
+        let (Ok(mut pause), Ok(mut resume)) = (
+            signal(SignalKind::from_raw(libc::SIGTSTP)),
+            signal(SignalKind::from_raw(libc::SIGCONT)),
+        ) else {
+            eprintln!("Could not listen for SIGTSTP and SIGCONT, the run can't be paused");
+            return;
+        };
+        loop {
+            tokio::select! {
+                _ = pause.recv() => status.set_paused(true),
+                _ = resume.recv() => status.set_paused(false),
+            };
+        }
+    });
+}
@@ -0,0 +1,218 @@
+use crate::{artifacts, environment, solc};
+use std::{
+    fs, io,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+/// Set to 1, the kernel appends .PID to the name of a core whose pattern has no %p
+const CORE_USES_PID: &str = "/proc/sys/kernel/core_uses_pid";
+/// coredumpctl that does not answer within this leaves the crash without a core
+const COREDUMPCTL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the kernel writes the core of a crashed process, from kernel.core_pattern. The name
+/// of the core holds the pid of the process, so concurrent crashes are told apart
+#[derive(Debug)]
+enum CoreLocation {
+    /// A relative pattern: the working directory of the process, in a file whose name starts
+    /// with the pattern up to its first specifier
+    WorkingDir { prefix: String },
+    /// An absolute pattern: the directory
+    Dir { dir: PathBuf },
+    /// Piped to systemd-coredump, retrieved with coredumpctl
+    Systemd,
+}
+
+/// Collection of the cores of analyzers killed by a signal, see `--core-dumps`
+#[derive(Debug)]
+pub struct CoreDumps {
+    location: CoreLocation,
+    /// The pyrometer that crashed, saved next to its core so the core can be loaded in gdb
+    analyzer: Option<PathBuf>,
+}
+
+impl CoreDumps {
+    /// Finds out where the cores go. Fails when they go to a handler omniscan can't retrieve
+    /// them from, or nowhere.
+    pub fn setup() -> Result<Self, String> {
+        let pattern = fs::read_to_string(CORE_PATTERN)
+            .map_err(|e| format!("could not read {}: {}", CORE_PATTERN, e))?;
+        let pattern = pattern.trim();
+        let location = if let Some(handler) = pattern.strip_prefix('|') {
+            if !handler.contains("systemd-coredump") {
+                return Err(format!(
+                    "cores are piped to `{}`, only systemd-coredump is supported; set \
+                     kernel.core_pattern to a directory, e.g. /var/crash/core.%e.%p",
+                    handler
+                ));
+            }
+            CoreLocation::Systemd
+        } else if pattern.is_empty() {
+            return Err("kernel.core_pattern is empty, no cores are written".to_string());
+        } else if !pattern.contains("%p")
+            && fs::read_to_string(CORE_USES_PID).map_or(true, |uses_pid| uses_pid.trim() != "1")
+        {
+            return Err(format!(
+                "the cores of kernel.core_pattern `{}` do not name the crashed process, so the \
+                 cores of concurrent crashes can't be told apart; add %p to the pattern, e.g. \
+                 /var/crash/core.%e.%p, or set kernel.core_uses_pid to 1",
+                pattern
+            ));
+        } else if pattern.starts_with('/') {
+            CoreLocation::Dir {
+                dir: Path::new(pattern)
+                    .parent()
+                    .unwrap_or(Path::new("/"))
+                    .to_path_buf(),
+            }
+        } else {
+            CoreLocation::WorkingDir {
+                prefix: pattern.split('%').next().unwrap().to_string(),
+            }
+        };
+        Ok(Self {
+            location,
+            analyzer: environment::analyzer_path(),
+        })
+    }
+
+    /// Whether the kernel writes the core as the crashed process, which it can't from the user
+    /// namespace of the network isolation. systemd-coredump writes it as root.
+    pub fn written_as_process(&self) -> bool {
+        !matches!(self.location, CoreLocation::Systemd)
+    }
+
+    /// Lifts the core size limit of the analyzer, which is usually 0
+    pub fn apply(&self, command: &mut Command) {
+        // SAFETY: setrlimit(2) is a plain syscall, safe between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                let unlimited = libc::rlimit {
+                    rlim_cur: libc::RLIM_INFINITY,
+                    rlim_max: libc::RLIM_INFINITY,
+                };
+                if libc::setrlimit(libc::RLIMIT_CORE, &unlimited) != 0 {
+                    // an unprivileged process can only raise its soft limit up to the hard one
+                    let mut limit = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: 0,
+                    };
+                    libc::getrlimit(libc::RLIMIT_CORE, &mut limit);
+                    limit.rlim_cur = limit.rlim_max;
+                    if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// The core file a crashed process wrote since `since`, None when it wrote none
+    fn find_core(&self, pid: u32, cwd: Option<&Path>, since: SystemTime) -> Option<PathBuf> {
+        // file times come from a coarser clock, they can be a few millisecs behind `since`
+        let since = since - Duration::from_secs(1);
+        let written_since = |path: &Path| {
+            path.metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= since)
+        };
+        let newest = |dir: &Path, matches: &dyn Fn(&str) -> bool| {
+            fs::read_dir(dir)
+                .ok()?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.is_file()
+                        && path
+                            .file_name()
+                            .is_some_and(|name| matches(&name.to_string_lossy()))
+                        && written_since(path)
+                })
+                .max_by_key(|path| {
+                    path.metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                })
+        };
+        let pid = pid.to_string();
+        let names_pid = |name: &str| {
+            name.split(|c: char| !c.is_ascii_digit())
+                .any(|part| part == pid)
+        };
+        match &self.location {
+            CoreLocation::WorkingDir { prefix } => {
+                newest(cwd?, &|name| name.starts_with(prefix) && names_pid(name))
+            }
+            CoreLocation::Dir { dir } => newest(dir, &names_pid),
+            CoreLocation::Systemd => None,
+        }
+    }
+
+    /// Moves the core of the crashed analyzer with pid `pid`, started at `since` in `cwd`, to
+    /// <ARTIFACTS>/<bytecode_hash>/core, with the analyzer binary next to it. Returns the path
+    /// of the core relative to the artifacts directory, None when no core was found.
+    pub fn collect(
+        &self,
+        pid: u32,
+        cwd: Option<&Path>,
+        since: SystemTime,
+        artifacts_dir: &Path,
+        bytecode_hash: &str,
+    ) -> Result<Option<String>, String> {
+        let contract_dir = artifacts::contract_artifacts_dir(artifacts_dir, bytecode_hash);
+        fs::create_dir_all(&contract_dir).map_err(|e| e.to_string())?;
+        let target = contract_dir.join("core");
+        match &self.location {
+            CoreLocation::Systemd => {
+                // pids are reused, only a crash since the analyzer started is its own
+                let since = since
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_secs().saturating_sub(1));
+                // systemd-coredump may still be writing the core when the process is reaped
+                let dumped = (0..10).any(|attempt| {
+                    if attempt > 0 {
+                        thread::sleep(Duration::from_millis(300));
+                    }
+                    Command::new("coredumpctl")
+                        .arg("dump")
+                        .arg(pid.to_string())
+                        .arg(format!("--since=@{}", since))
+                        .arg("--output")
+                        .arg(&target)
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn()
+                        .ok()
+                        .and_then(|child| solc::wait_with_timeout(child, COREDUMPCTL_TIMEOUT))
+                        .is_some_and(|(success, _stdout, _stderr)| success)
+                });
+                if !dumped {
+                    return Ok(None);
+                }
+            }
+            _ => {
+                let Some(core) = self.find_core(pid, cwd, since) else {
+                    return Ok(None);
+                };
+                // the core is usually on another filesystem than the artifacts
+                if fs::rename(&core, &target).is_err() {
+                    fs::copy(&core, &target).map_err(|e| e.to_string())?;
+                    let _ = fs::remove_file(&core);
+                }
+            }
+        }
+        if let Some(analyzer) = &self.analyzer {
+            let analyzer_copy = contract_dir.join("pyrometer");
+            if !analyzer_copy.exists() && fs::hard_link(analyzer, &analyzer_copy).is_err() {
+                fs::copy(analyzer, &analyzer_copy).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(Some(format!("{}/core", bytecode_hash)))
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{ExitType, ResultsRow};
+use std::collections::BTreeMap;
+
+/// Failure counts of one group of contracts within a dimension
+pub struct FailureGroup {
+    pub key: String,
+    pub total: usize,
+    pub failures: usize,
+}
+
+impl FailureGroup {
+    pub fn failure_rate(&self) -> f64 {
+        self.failures as f64 / self.total as f64 * 100.0
+    }
+}
+
+/// Reduces "v0.8.17+commit.8df45f5f" to "v0.8.17" so nightly/commit builds group together
+pub fn normalize_compiler_version(compiler_version: &str) -> String {
+    let version = compiler_version
+        .split('+')
+        .next()
+        .unwrap_or(compiler_version);
+    let version = version.split('-').next().unwrap_or(version);
+    if version.is_empty() {
+        "unknown".to_string()
+    } else {
+        version.to_string()
+    }
+}
+
+/// Sort key so "v0.8.9" comes before "v0.8.10"
+fn compiler_version_sort_key(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().unwrap_or(u64::MAX))
+        .collect()
+}
+
+fn group_by<K: Ord>(
+    rows: &[ResultsRow],
+    key: impl Fn(&ResultsRow) -> (K, String),
+) -> Vec<FailureGroup> {
+    let mut groups: BTreeMap<K, FailureGroup> = BTreeMap::new();
+    for row in rows {
+        let (sort_key, label) = key(row);
+        let group = groups.entry(sort_key).or_insert(FailureGroup {
+            key: label,
+            total: 0,
+            failures: 0,
+        });
+        group.total += 1;
+        if !matches!(row.result, ExitType::Success) {
+            group.failures += 1;
+        }
+    }
+    groups.into_values().collect()
+}
+
+pub fn by_compiler_version(rows: &[ResultsRow]) -> Vec<FailureGroup> {
+    group_by(rows, |row| {
+        let version = normalize_compiler_version(&row.compiler_version);
+        (compiler_version_sort_key(&version), version)
+    })
+}
+
+pub fn by_optimization(rows: &[ResultsRow]) -> Vec<FailureGroup> {
+    group_by(rows, |row| {
+        let label = match row.optimization_used {
+            Some(true) => "enabled",
+            Some(false) => "disabled",
+            None => "unknown",
+        };
+        (label, label.to_string())
+    })
+}
+
+pub fn by_source_layout(rows: &[ResultsRow]) -> Vec<FailureGroup> {
+    group_by(rows, |row| {
+        let layout = row.source_layout_group();
+        (layout.clone(), layout)
+    })
+}
+
+/// Groups the rows into ten equally populated buckets by source size
+pub fn by_size_decile(rows: &[ResultsRow]) -> Vec<FailureGroup> {
+    let mut sizes = rows.iter().map(|row| row.size).collect::<Vec<u64>>();
+    sizes.sort_unstable();
+    if sizes.is_empty() {
+        return Vec::new();
+    }
+    // upper bound (inclusive) of each decile
+    let bounds = (1..=10)
+        .map(|decile| sizes[(sizes.len() * decile / 10).saturating_sub(1)])
+        .collect::<Vec<u64>>();
+
+    group_by(rows, |row| {
+        let decile = bounds
+            .iter()
+            .position(|bound| row.size <= *bound)
+            .unwrap_or(9);
+        let lower = if decile == 0 {
+            0
+        } else {
+            bounds[decile - 1] + 1
+        };
+        (
+            decile,
+            format!("D{} ({}-{} bytes)", decile + 1, lower, bounds[decile]),
+        )
+    })
+}
+
+fn print_groups(title: &str, groups: &[FailureGroup]) {
+    println!();
+    println!("| {} | Contracts | Failures | Failure rate |", title);
+    println!("| ----- | ----- | ----- | ------- |");
+    for group in groups {
+        println!(
+            "|{} | {} | {} | {:.2} |",
+            group.key,
+            group.total,
+            group.failures,
+            group.failure_rate()
+        );
+    }
+}
+
+/// Prints the failure rate broken down by compiler version, optimization setting,
+/// source layout and source size decile
+pub fn print_failure_correlation(rows: &[ResultsRow]) {
+    print_groups("Compiler version", &by_compiler_version(rows));
+    print_groups("Optimization", &by_optimization(rows));
+    print_groups("Source layout", &by_source_layout(rows));
+    print_groups("Source size", &by_size_decile(rows));
+}
@@ -0,0 +1,50 @@
+use std::{io, process::Child};
+
+fn seconds(time: libc::timeval) -> f64 {
+    time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0
+}
+
+/// A child that exited
+pub struct Reaped {
+    /// User and system CPU time (secs) it used, along with the descendants it waited for
+    pub cpu_time: f64,
+    /// Signal that killed it, None when it exited on its own
+    pub signal: Option<i32>,
+}
+
+/// Reaps the child with wait4. None when `nohang` and the child is still running.
+/// The child is reaped behind the back of `Child`, which must not be waited on afterwards.
+fn wait4(child: &Child, nohang: bool) -> io::Result<Option<Reaped>> {
+    let mut status = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    let options = if nohang { libc::WNOHANG } else { 0 };
+    loop {
+        let pid =
+            unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, options, &mut rusage) };
+        match pid {
+            0 => return Ok(None),
+            pid if pid > 0 => {
+                return Ok(Some(Reaped {
+                    cpu_time: seconds(rusage.ru_utime) + seconds(rusage.ru_stime),
+                    signal: libc::WIFSIGNALED(status).then(|| libc::WTERMSIG(status)),
+                }));
+            }
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+/// Like `Child::try_wait`, with the CPU time of the child once it exited
+pub fn try_reap(child: &Child) -> io::Result<Option<Reaped>> {
+    wait4(child, true)
+}
+
+/// Like `Child::wait`, returning the CPU time of the child
+pub fn reap(child: &Child) -> io::Result<f64> {
+    wait4(child, false).map(|reaped| reaped.map(|reaped| reaped.cpu_time).unwrap_or_default())
+}
@@ -0,0 +1,105 @@
+use crate::{solc, FiestaMetadata, SourceType};
+use serde_json::{json, Map, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// crytic-compile taking longer than this is killed, the contract is then analyzed from its
+/// original sources
+const CRYTIC_COMPILE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Scratch directory for the crytic-compile exports of one contract
+pub fn contract_work_dir(work_dir: &Path, bytecode_hash: &str) -> PathBuf {
+    work_dir.join(bytecode_hash)
+}
+
+/// Resolves the contract's compilation with crytic-compile and writes it as a solc
+/// standard-json input holding every resolved source, so the analyzer no longer has to resolve
+/// imports itself. Returns the path of that input.
+///
+/// Standard-json contracts are already a resolved compilation unit and are returned as is.
+pub fn prepare_compilation_unit(
+    metadata: &FiestaMetadata,
+    work_dir: &Path,
+) -> Result<PathBuf, String> {
+    let dir = Path::new(&metadata.abs_path_to_dir);
+    if let Some(SourceType::EtherscanMetadata(_)) = metadata.source_type {
+        return Ok(dir.join("contract.json"));
+    }
+
+    let export_dir = contract_work_dir(work_dir, &metadata.bytecode_hash);
+    fs::create_dir_all(&export_dir).map_err(|e| e.to_string())?;
+
+    let mut command = Command::new("crytic-compile");
+    command
+        .arg(metadata.entrypoint_file_name().map_err(|e| e.to_string())?)
+        .args(["--export-format", "solc", "--export-dir"])
+        .arg(&export_dir)
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    // prefer the exact compiler from the svm cache over whatever solc is on the PATH
+    if let Some(solc) = solc::cached_solc(&metadata.compiler_version) {
+        command.arg("--solc").arg(solc);
+    }
+    if let Some(SourceType::Project(project_source)) = &metadata.source_type {
+        let remappings = project_source.remapping_lines();
+        if !remappings.is_empty() {
+            command.arg("--solc-remaps").arg(remappings.join(" "));
+        }
+    }
+    let child = command
+        .spawn()
+        .map_err(|e| format!("could not run crytic-compile: {}", e))?;
+    let (success, _stdout, stderr) =
+        solc::wait_with_timeout(child, CRYTIC_COMPILE_TIMEOUT).ok_or(format!(
+            "crytic-compile timed out after {}s",
+            CRYTIC_COMPILE_TIMEOUT.as_secs()
+        ))?;
+    if !success {
+        return Err(format!(
+            "crytic-compile failed: {}",
+            stderr.lines().last().unwrap_or_default()
+        ));
+    }
+
+    let combined = fs::read_to_string(export_dir.join("combined_solc.json"))
+        .map_err(|e| format!("could not read the crytic-compile export: {}", e))?;
+    let combined: Value = serde_json::from_str(&combined).map_err(|e| e.to_string())?;
+    let source_paths: Vec<String> = match combined["sourceList"].as_array() {
+        Some(source_list) => source_list
+            .iter()
+            .filter_map(|path| path.as_str().map(str::to_string))
+            .collect(),
+        None => combined["sources"]
+            .as_object()
+            .map(|sources| sources.keys().cloned().collect())
+            .unwrap_or_default(),
+    };
+    if source_paths.is_empty() {
+        return Err("crytic-compile resolved no sources".to_string());
+    }
+
+    let mut sources = Map::new();
+    for source_path in source_paths {
+        let content = fs::read_to_string(dir.join(&source_path))
+            .map_err(|e| format!("could not read resolved source {}: {}", source_path, e))?;
+        sources.insert(source_path, json!({ "content": content }));
+    }
+    let input = json!({
+        "language": "Solidity",
+        "sources": sources,
+        "settings": {
+            "optimizer": {
+                "enabled": metadata.optimization_used,
+                "runs": metadata.runs,
+            }
+        }
+    });
+    let input_path = export_dir.join("input.json");
+    fs::write(&input_path, serde_json::to_string(&input).unwrap()).map_err(|e| e.to_string())?;
+    Ok(input_path)
+}
@@ -0,0 +1,52 @@
+use crate::ExitType;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref ANSI_REGEX: Regex = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap();
+    static ref DURATION_REGEX: Regex = Regex::new(r"\b\d+(\.\d+)?\s?(ns|us|µs|ms|s)\b").unwrap();
+    static ref ADDRESS_REGEX: Regex = Regex::new(r"\b0x[0-9a-fA-F]{8,16}\b").unwrap();
+}
+
+/// Removes what legitimately differs between two runs of the same contract: colors, timings
+/// and memory addresses (in panic messages)
+pub fn normalize_output(output: &str) -> String {
+    let output = ANSI_REGEX.replace_all(output, "");
+    let output = DURATION_REGEX.replace_all(&output, "<duration>");
+    ADDRESS_REGEX.replace_all(&output, "<address>").into_owned()
+}
+
+/// First line on which the normalized outputs differ, 1-based
+fn first_differing_line(first: &str, rerun: &str) -> Option<usize> {
+    let (first, rerun) = (normalize_output(first), normalize_output(rerun));
+    if first == rerun {
+        return None;
+    }
+    let mut first_lines = first.lines();
+    let mut rerun_lines = rerun.lines();
+    (1..).find(|_| first_lines.next() != rerun_lines.next())
+}
+
+/// Compares the two runs of a contract made with `--determinism-check`. Returns how they
+/// differ, None if the analyzer behaved the same. Outputs are (stdout, stderr), None when the
+/// run timed out.
+pub fn compare_runs(
+    first: &ExitType,
+    first_output: Option<(&str, &str)>,
+    rerun: &ExitType,
+    rerun_output: Option<(&str, &str)>,
+) -> Option<String> {
+    if first.name() != rerun.name() {
+        return Some(format!("{} then {}", first.name(), rerun.name()));
+    }
+    if first.to_string() != rerun.to_string() {
+        return Some(format!("{} message differs", first.name()));
+    }
+    let ((first_stdout, first_stderr), (rerun_stdout, rerun_stderr)) =
+        (first_output?, rerun_output?);
+    if let Some(line) = first_differing_line(first_stdout, rerun_stdout) {
+        return Some(format!("{} stdout differs at line {}", first.name(), line));
+    }
+    first_differing_line(first_stderr, rerun_stderr)
+        .map(|line| format!("{} stderr differs at line {}", first.name(), line))
+}
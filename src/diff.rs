@@ -0,0 +1,326 @@
+use crate::{
+    comparison, determinism, fingerprint, flakes, manifest, results, score, summary::RunSummary,
+    ExitType, ResultsRow,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::HashMap, fmt::Write, fs, path::Path};
+
+lazy_static! {
+    static ref LOCATION_REGEX: Regex = Regex::new(r":\d+(:\d+)?\b").unwrap();
+    static ref LINE_REGEX: Regex = Regex::new(r"(?i)\b(line|column|col)\s+\d+").unwrap();
+    static ref HASH_REGEX: Regex = Regex::new(r"\b(0x)?[0-9a-fA-F]{16,}\b").unwrap();
+}
+
+/// Strips what moves around between analyzer versions without the failure changing: line and
+/// column numbers (`src/x.rs:12:5`, `line 12`), hashes and addresses, on top of what differs
+/// between two runs of the same analyzer
+pub fn normalize_message(message: &str) -> String {
+    let message = determinism::normalize_output(message);
+    let message = LOCATION_REGEX.replace_all(&message, ":<line>");
+    let message = LINE_REGEX.replace_all(&message, "$1 <n>");
+    HASH_REGEX.replace_all(&message, "<hash>").into_owned()
+}
+
+/// Whether two failures are the same, comparing their messages as they are or normalized.
+/// Panics with the same message are different failures when their backtraces show they come
+/// from different places; a run without the crash location matches any.
+fn same_failure(old: &ResultsRow, new: &ResultsRow, exact_messages: bool) -> bool {
+    let same_location = match (&old.crash_location, &new.crash_location) {
+        (Some(old_location), Some(new_location)) => old_location == new_location,
+        _ => true,
+    };
+    if exact_messages {
+        return old.result.to_string() == new.result.to_string() && same_location;
+    }
+    old.result.name() == new.result.name()
+        && old.message().map(normalize_message) == new.message().map(normalize_message)
+        && same_location
+}
+
+/// A contract present in both runs, with its old and new result
+pub struct ChangedResult {
+    pub old: ResultsRow,
+    pub new: ResultsRow,
+}
+
+/// Differences between two runs over (part of) the same corpus, keyed by bytecode hash
+#[derive(Default)]
+pub struct RunDiff {
+    /// Succeeded in the old run, failed in the new one
+    pub regressions: Vec<ChangedResult>,
+    /// Failed in the old run, succeeded in the new one
+    pub fixes: Vec<ChangedResult>,
+    /// Failed in both runs, but with a different result
+    pub changed_failures: Vec<ChangedResult>,
+    pub unchanged: usize,
+    /// Unchanged failures whose messages only match once normalized, see `normalize_message`
+    pub normalized_matches: usize,
+    /// Contracts that were only analyzed by one of the runs
+    pub only_old: usize,
+    pub only_new: usize,
+    /// Changes of contracts known to be flaky, left out of the others, see
+    /// `flakes::exclude_from_diff`
+    pub flaky: Vec<ChangedResult>,
+}
+
+/// Failures in both runs are the same when their normalized messages match, unless
+/// `exact_messages` is set
+pub fn compute_diff(
+    old_rows: &[ResultsRow],
+    new_rows: &[ResultsRow],
+    exact_messages: bool,
+) -> RunDiff {
+    let old_by_hash: HashMap<&str, &ResultsRow> = old_rows
+        .iter()
+        .map(|row| (row.bytecode_hash.as_str(), row))
+        .collect();
+    let new_hashes: HashMap<&str, ()> = new_rows
+        .iter()
+        .map(|row| (row.bytecode_hash.as_str(), ()))
+        .collect();
+
+    let mut diff = RunDiff {
+        only_old: old_rows
+            .iter()
+            .filter(|row| !new_hashes.contains_key(row.bytecode_hash.as_str()))
+            .count(),
+        ..Default::default()
+    };
+
+    for new in new_rows {
+        let Some(old) = old_by_hash.get(new.bytecode_hash.as_str()) else {
+            diff.only_new += 1;
+            continue;
+        };
+        let changed = ChangedResult {
+            old: (*old).clone(),
+            new: new.clone(),
+        };
+        let old_success = matches!(old.result, ExitType::Success);
+        let new_success = matches!(new.result, ExitType::Success);
+        match (old_success, new_success) {
+            (true, true) => diff.unchanged += 1,
+            (true, false) => diff.regressions.push(changed),
+            (false, true) => diff.fixes.push(changed),
+            (false, false) => {
+                if same_failure(old, new, exact_messages) {
+                    diff.unchanged += 1;
+                    if old.result.to_string() != new.result.to_string() {
+                        diff.normalized_matches += 1;
+                    }
+                } else {
+                    diff.changed_failures.push(changed);
+                }
+            }
+        }
+    }
+
+    diff
+}
+
+fn format_changes(out: &mut String, title: &str, changes: &[ChangedResult], limit: usize) {
+    if changes.is_empty() {
+        return;
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "{} ({}):", title, changes.len()).unwrap();
+    for change in changes.iter().take(limit) {
+        writeln!(
+            out,
+            "  {}: `{}` -> `{}`",
+            change.new.bytecode_hash, change.old.result, change.new.result
+        )
+        .unwrap();
+    }
+    if changes.len() > limit {
+        writeln!(out, "  ... and {} more", changes.len() - limit).unwrap();
+    }
+}
+
+/// Renders the diff as text, listing at most `limit` contracts per section
+pub fn format_diff(diff: &RunDiff, limit: usize) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{} regressions, {} fixes, {} changed failures, {} unchanged",
+        diff.regressions.len(),
+        diff.fixes.len(),
+        diff.changed_failures.len(),
+        diff.unchanged
+    )
+    .unwrap();
+    if diff.normalized_matches > 0 {
+        writeln!(
+            out,
+            "{} unchanged failures only differ in line numbers or hashes (see --exact-messages)",
+            diff.normalized_matches
+        )
+        .unwrap();
+    }
+    if diff.only_old > 0 || diff.only_new > 0 {
+        writeln!(
+            out,
+            "{} contracts only in the old run, {} only in the new run",
+            diff.only_old, diff.only_new
+        )
+        .unwrap();
+    }
+    format_changes(&mut out, "Regressions", &diff.regressions, limit);
+    format_changes(&mut out, "Fixes", &diff.fixes, limit);
+    format_changes(&mut out, "Changed failures", &diff.changed_failures, limit);
+    format_changes(
+        &mut out,
+        "Changes of known flaky contracts, left out",
+        &diff.flaky,
+        limit,
+    );
+    out
+}
+
+pub fn print_diff(diff: &RunDiff, limit: usize) {
+    print!("{}", format_diff(diff, limit));
+}
+
+/// Reads the results of a run to compare against another. A run that only recorded its
+/// failures is refused, as its successes cannot be told from contracts it did not analyze
+pub fn read_comparable_results(path: &Path) -> Result<Vec<ResultsRow>, String> {
+    if let Ok(run_manifest) = manifest::read_manifest(&manifest::manifest_path(path)) {
+        if run_manifest.record == results::RecordMode::Failures {
+            return Err(
+                "only the failures of the run were recorded (--record failures), its successes \
+                 cannot be told apart from contracts it did not analyze"
+                    .to_string(),
+            );
+        }
+    }
+    results::read_results_file(path)
+}
+
+fn read_or_exit(path: &Path) -> Vec<ResultsRow> {
+    read_comparable_results(path).unwrap_or_else(|e| {
+        eprintln!("Could not read results file {}: {}", path.display(), e);
+        std::process::exit(1);
+    })
+}
+
+/// Entry point of the `diff` subcommand
+pub fn run_diff(
+    old_path: &Path,
+    new_path: &Path,
+    limit: usize,
+    exact_messages: bool,
+    score_weights: &score::ScoreWeights,
+    html: Option<&comparison::HtmlReport>,
+    flakes: Option<(&Path, flakes::FlakyGating)>,
+) -> RunDiff {
+    let old_rows = read_or_exit(old_path);
+    let new_rows = read_or_exit(new_path);
+    // the flakes of the pyrometer that produced the new results, of any when unknown
+    let known_flaky = flakes.map(|(flakes_db, gating)| {
+        let flake_db = flakes::FlakeDb::load(flakes_db).unwrap_or_else(|e| {
+            eprintln!(
+                "Could not read flake database {}: {}",
+                flakes_db.display(),
+                e
+            );
+            std::process::exit(1);
+        });
+        let analyzer = flakes::results_analyzer_key(new_path);
+        (flake_db.flaky(analyzer.as_deref()), gating)
+    });
+    fingerprint::warn_if_changed(
+        "the old run",
+        manifest::results_fingerprint(old_path).as_deref(),
+        manifest::results_fingerprint(new_path).as_deref(),
+    );
+    let mut diff = compute_diff(&old_rows, &new_rows, exact_messages);
+    if let Some((known_flaky, gating)) = &known_flaky {
+        flakes::exclude_from_diff(&mut diff, known_flaky, *gating);
+    }
+    print_diff(&diff, limit);
+    println!();
+    let old_summary =
+        RunSummary::from_rows(&old_rows).with_full_corpus(manifest::results_full_corpus(old_path));
+    let new_summary =
+        RunSummary::from_rows(&new_rows).with_full_corpus(manifest::results_full_corpus(new_path));
+    println!(
+        "{}",
+        new_summary.format_success_rate_change(&old_summary, &diff)
+    );
+    println!(
+        "{}",
+        score_weights.format_score(&new_summary, Some(&old_summary))
+    );
+    if let Some(html) = html {
+        let page = comparison::format_html(
+            old_path,
+            new_path,
+            &old_rows,
+            &new_rows,
+            &diff,
+            score_weights,
+            html,
+        );
+        if let Err(e) = fs::write(&html.path, page) {
+            eprintln!("Could not write {}: {}", html.path.display(), e);
+            std::process::exit(1);
+        }
+        println!("Wrote the comparison report to {}", html.path.display());
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::row;
+
+    #[test]
+    fn compute_diff_sorts_contracts_by_change() {
+        let old_rows = [
+            row("0x1", "Success"),
+            row("0x2", "Error: unsupported"),
+            row("0x3", "Error: bad token at src/parse.rs:12:5"),
+            row("0x4", "Error: unsupported"),
+            row("0x5", "Success"),
+            row("0x6", "Success"),
+        ];
+        let new_rows = [
+            row("0x1", "Error: unsupported"),
+            row("0x2", "Success"),
+            row("0x3", "Error: bad token at src/parse.rs:14:9"),
+            row("0x4", "PerformanceTimeout"),
+            row("0x5", "Success"),
+            row("0x7", "Success"),
+        ];
+        let diff = compute_diff(&old_rows, &new_rows, false);
+        let hashes = |changes: &[ChangedResult]| {
+            changes
+                .iter()
+                .map(|change| change.new.bytecode_hash.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hashes(&diff.regressions), ["0x1"]);
+        assert_eq!(hashes(&diff.fixes), ["0x2"]);
+        assert_eq!(hashes(&diff.changed_failures), ["0x4"]);
+        assert_eq!(diff.unchanged, 2);
+        assert_eq!(diff.normalized_matches, 1);
+        assert_eq!((diff.only_old, diff.only_new), (1, 1));
+
+        let diff = compute_diff(&old_rows, &new_rows, true);
+        assert_eq!(hashes(&diff.changed_failures), ["0x3", "0x4"]);
+        assert_eq!(diff.normalized_matches, 0);
+    }
+
+    #[test]
+    fn panics_from_different_places_are_different_failures() {
+        let mut old = row("0x1", "ThreadPanic: index out of bounds");
+        let mut new = old.clone();
+        assert!(same_failure(&old, &new, false));
+        old.crash_location = Some("pyrometer::a".to_string());
+        assert!(same_failure(&old, &new, false));
+        new.crash_location = Some("pyrometer::b".to_string());
+        assert!(!same_failure(&old, &new, false));
+    }
+}
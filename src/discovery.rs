@@ -0,0 +1,419 @@
+use crate::{fingerprint, io_errors::IoErrorBudget, read_metadata_file, FiestaMetadata};
+use ethers::utils::hex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+};
+use walkdir::WalkDir;
+
+/// Supported contracts of one completed organized_contracts/XX bucket, see
+/// `--discovery-checkpoint`
+#[derive(Serialize, Deserialize)]
+struct BucketCheckpoint {
+    /// The corpus the bucket belongs to
+    corpus_root: PathBuf,
+    /// sha256 over the path, size and mtime of every metadata.json of the bucket when it was
+    /// scanned, see `bucket_signature`. It changes when contracts are added, removed or their
+    /// metadata edited, the bucket is then scanned again
+    signature: String,
+    /// Directories of the contracts relative to the corpus root, with their metadata, in walk
+    /// order
+    contracts: Vec<(String, FiestaMetadata)>,
+}
+
+/// Stat signature of the metadata.json files of a bucket, which only lists the directories of
+/// the bucket rather than reading every file. None when the bucket can't be walked.
+fn bucket_signature(bucket_dir: &Path) -> Option<String> {
+    let mut hasher = Sha256::new();
+    for entry in WalkDir::new(bucket_dir).sort_by_file_name() {
+        let entry = entry.ok()?;
+        if !entry.file_type().is_file() || entry.file_name() != "metadata.json" {
+            continue;
+        }
+        let rel_path = entry
+            .path()
+            .strip_prefix(bucket_dir)
+            .unwrap_or(entry.path());
+        fingerprint::update_stat(&mut hasher, rel_path, &entry.metadata().ok()?);
+    }
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// Contracts of a bucket and the IO errors met while reading it
+struct BucketScan {
+    contracts: Vec<FiestaMetadata>,
+    io_errors: IoErrorBudget,
+    resumed: bool,
+}
+
+/// Walks a bucket for the metadata.json of supported contracts, stopping at `limit` of them.
+/// The scan is complete when it went through the whole bucket without IO errors.
+fn scan_bucket(
+    bucket_dir: &Path,
+    organized_contracts: &Path,
+    abs_fiesta_path: &Path,
+    max_io_errors: usize,
+    limit: usize,
+) -> (Vec<FiestaMetadata>, IoErrorBudget, bool) {
+    let mut contracts = Vec::new();
+    let mut io_errors = IoErrorBudget::new(max_io_errors);
+    let mut complete = true;
+    let mut walk = WalkDir::new(bucket_dir).into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let bucket = e
+                    .path()
+                    .and_then(|path| IoErrorBudget::bucket_of(organized_contracts, path));
+                io_errors.record_error(bucket, &e.to_string());
+                complete = false;
+                continue;
+            }
+        };
+        let path = entry.path();
+        let bucket = IoErrorBudget::bucket_of(organized_contracts, path);
+        if io_errors.is_skipped(bucket) {
+            if entry.file_type().is_dir() {
+                walk.skip_current_dir();
+            }
+            continue;
+        }
+        if !(path.is_file() && path.file_name().unwrap() == "metadata.json") {
+            continue;
+        }
+        // a truncated or unreadable file counts as an IO error
+        let mut metadata = match read_metadata_file(path) {
+            Ok(metadata) => {
+                io_errors.record_success(bucket);
+                metadata
+            }
+            Err(e) => {
+                io_errors.record_error(bucket, &e.to_string());
+                complete = false;
+                continue;
+            }
+        };
+        if !metadata.compiler_is_supported() {
+            continue;
+        }
+        // the directory of the contract, without the metadata.json file
+        let path_to_dir = path.parent().unwrap_or(abs_fiesta_path);
+        if let Err(e) = metadata.update_path_to_dir(path_to_dir, abs_fiesta_path) {
+            eprintln!("Skipping {}: {}", path_to_dir.display(), e);
+            continue;
+        }
+        contracts.push(metadata);
+        // the contracts after these would not be selected
+        if contracts.len() >= limit {
+            complete = false;
+            break;
+        }
+    }
+    (contracts, io_errors, complete)
+}
+
+/// Scans a bucket up to `limit` contracts, or loads its checkpoint when it is unchanged since.
+/// A bucket scanned completely is checkpointed.
+fn scan_or_resume(
+    bucket_dir: &Path,
+    organized_contracts: &Path,
+    abs_fiesta_path: &Path,
+    max_io_errors: usize,
+    checkpoint_dir: Option<&Path>,
+    limit: usize,
+) -> BucketScan {
+    let checkpoint_path = checkpoint_dir
+        .zip(bucket_dir.file_name())
+        .map(|(dir, bucket)| dir.join(bucket).with_extension("json"));
+    let signature = checkpoint_path
+        .as_ref()
+        .and_then(|_| bucket_signature(bucket_dir));
+
+    let checkpoint = checkpoint_path
+        .as_ref()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|contents| serde_json::from_slice::<BucketCheckpoint>(&contents).ok())
+        .filter(|checkpoint| {
+            checkpoint.corpus_root == abs_fiesta_path
+                && Some(&checkpoint.signature) == signature.as_ref()
+        });
+    if let Some(checkpoint) = checkpoint {
+        let contracts = checkpoint
+            .contracts
+            .into_iter()
+            .filter_map(|(rel_path_to_dir, mut metadata)| {
+                metadata
+                    .update_path_to_dir(&abs_fiesta_path.join(rel_path_to_dir), abs_fiesta_path)
+                    .ok()?;
+                Some(metadata)
+            })
+            .collect();
+        return BucketScan {
+            contracts,
+            io_errors: IoErrorBudget::new(max_io_errors),
+            resumed: true,
+        };
+    }
+
+    let (contracts, io_errors, complete) = scan_bucket(
+        bucket_dir,
+        organized_contracts,
+        abs_fiesta_path,
+        max_io_errors,
+        limit,
+    );
+    if let (Some(checkpoint_path), Some(signature), true) = (&checkpoint_path, signature, complete)
+    {
+        let checkpoint = BucketCheckpoint {
+            corpus_root: abs_fiesta_path.to_path_buf(),
+            signature,
+            contracts: contracts
+                .iter()
+                .map(|metadata| (metadata.rel_path_to_dir.clone(), metadata.clone()))
+                .collect(),
+        };
+        if let Err(e) = fs::write(checkpoint_path, serde_json::to_vec(&checkpoint).unwrap()) {
+            eprintln!(
+                "Could not write the discovery checkpoint {}: {}",
+                checkpoint_path.display(),
+                e
+            );
+        }
+    }
+    BucketScan {
+        contracts,
+        io_errors,
+        resumed: false,
+    }
+}
+
+/// Collects the supported contracts of a smart-contract-fiesta corpus in walk order: up to
+/// `num_contracts` of them after skipping the first `skip_contracts`. The buckets are walked
+/// in parallel, as many at once as there are cores, until enough contracts are found. No
+/// bucket is walked further than the contracts still wanted.
+pub fn discover_buckets(
+    abs_fiesta_path: &Path,
+    num_contracts: usize,
+    skip_contracts: usize,
+    max_io_errors: usize,
+    checkpoint_dir: Option<&Path>,
+) -> Vec<FiestaMetadata> {
+    BucketCursor::new(abs_fiesta_path, max_io_errors, checkpoint_dir)
+        .next(num_contracts, skip_contracts)
+}
+
+/// A walk of the buckets of a smart-contract-fiesta corpus taken batch after batch, each batch
+/// continuing where the previous one stopped rather than walking the corpus from its start
+pub struct BucketCursor<'a> {
+    abs_fiesta_path: &'a Path,
+    max_io_errors: usize,
+    checkpoint_dir: Option<&'a Path>,
+    /// The bucket directories in walk order, listed on the first batch
+    buckets: Option<Vec<PathBuf>>,
+    /// The bucket the next batch starts in, and how many of its contracts earlier batches took
+    next_bucket: usize,
+    taken_from_next: usize,
+}
+
+impl<'a> BucketCursor<'a> {
+    pub fn new(
+        abs_fiesta_path: &'a Path,
+        max_io_errors: usize,
+        checkpoint_dir: Option<&'a Path>,
+    ) -> Self {
+        Self {
+            abs_fiesta_path,
+            max_io_errors,
+            checkpoint_dir,
+            buckets: None,
+            next_bucket: 0,
+            taken_from_next: 0,
+        }
+    }
+
+    fn list_buckets(&self, io_errors: &mut IoErrorBudget) -> Vec<PathBuf> {
+        let organized_contracts = self.abs_fiesta_path.join("organized_contracts");
+        if let Some(checkpoint_dir) = self.checkpoint_dir {
+            if let Err(e) = fs::create_dir_all(checkpoint_dir) {
+                eprintln!(
+                    "Could not create the discovery checkpoint {}: {}",
+                    checkpoint_dir.display(),
+                    e
+                );
+            }
+        }
+        match fs::read_dir(&organized_contracts) {
+            Ok(entries) => entries
+                .filter_map(|entry| match entry {
+                    Ok(entry) => Some(entry.path()),
+                    Err(e) => {
+                        io_errors.record_error(None, &e.to_string());
+                        None
+                    }
+                })
+                .filter(|path| path.is_dir())
+                .collect::<Vec<PathBuf>>(),
+            Err(e) => {
+                io_errors.record_error(
+                    None,
+                    &format!("could not read {}: {}", organized_contracts.display(), e),
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    /// The next `num_contracts` contracts after skipping `skip_contracts`, see
+    /// `discover_buckets`
+    pub fn next(&mut self, num_contracts: usize, skip_contracts: usize) -> Vec<FiestaMetadata> {
+        let organized_contracts = self.abs_fiesta_path.join("organized_contracts");
+        let mut io_errors = IoErrorBudget::new(self.max_io_errors);
+        if self.buckets.is_none() {
+            self.buckets = Some(self.list_buckets(&mut io_errors));
+        }
+        let buckets = self.buckets.as_deref().unwrap_or_default();
+        let (abs_fiesta_path, max_io_errors, checkpoint_dir) = (
+            self.abs_fiesta_path,
+            self.max_io_errors,
+            self.checkpoint_dir,
+        );
+
+        let wanted = skip_contracts.saturating_add(num_contracts);
+        let mut found = Vec::new();
+        let mut resumed_buckets = 0;
+        while found.len() < wanted && self.next_bucket < buckets.len() {
+            let wave_start = self.next_bucket;
+            let wave_end = (wave_start + num_cpus::get().max(1)).min(buckets.len());
+            let wave = &buckets[wave_start..wave_end];
+            // a bucket holding the rest of the contracts wanted ends the walk by itself
+            let limit = wanted - found.len();
+            let taken_before = self.taken_from_next;
+            let scans = thread::scope(|scope| {
+                let handles = wave
+                    .iter()
+                    .enumerate()
+                    .map(|(i, bucket_dir)| {
+                        let organized_contracts = &organized_contracts;
+                        // the contracts earlier batches took are walked past again
+                        let limit = match i {
+                            0 => limit.saturating_add(taken_before),
+                            _ => limit,
+                        };
+                        scope.spawn(move || {
+                            scan_or_resume(
+                                bucket_dir,
+                                organized_contracts,
+                                abs_fiesta_path,
+                                max_io_errors,
+                                checkpoint_dir,
+                                limit,
+                            )
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Vec<BucketScan>>()
+            });
+
+            let previously_selected = found.len().saturating_sub(skip_contracts);
+            self.next_bucket = wave_end;
+            self.taken_from_next = 0;
+            for (i, scan) in scans.into_iter().enumerate() {
+                resumed_buckets += scan.resumed as usize;
+                io_errors.absorb(scan.io_errors);
+                let previously_taken = if i == 0 { taken_before } else { 0 };
+                let contracts = scan
+                    .contracts
+                    .into_iter()
+                    .skip(previously_taken)
+                    .collect::<Vec<FiestaMetadata>>();
+                let taken = contracts.len().min(wanted - found.len());
+                found.extend(contracts.into_iter().take(taken));
+                if found.len() >= wanted {
+                    // the next batch continues in this bucket, after the contracts taken
+                    self.next_bucket = wave_start + i;
+                    self.taken_from_next = previously_taken + taken;
+                    break;
+                }
+            }
+            let selected = found
+                .len()
+                .saturating_sub(skip_contracts)
+                .min(num_contracts);
+            if selected / 1000 > previously_selected.min(num_contracts) / 1000 {
+                println!("Total of {} contracts added to analysis queue", selected);
+            }
+        }
+        if resumed_buckets > 0 {
+            println!(
+                "Resumed {} bucket directories from the discovery checkpoint",
+                resumed_buckets
+            );
+        }
+        io_errors.print_summary();
+
+        found.into_iter().skip(skip_contracts).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::test_dir;
+
+    /// A smart-contract-fiesta corpus of `buckets` buckets of `per_bucket` contracts each
+    fn fiesta_corpus(name: &str, buckets: usize, per_bucket: usize) -> PathBuf {
+        let root = test_dir(name);
+        for bucket in 0..buckets {
+            for contract in 0..per_bucket {
+                let bytecode_hash = format!("{:02x}{:062x}", bucket, contract);
+                let dir = root
+                    .join("organized_contracts")
+                    .join(&bytecode_hash[..2])
+                    .join(&bytecode_hash);
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("main.sol"), "contract Token {}").unwrap();
+                let metadata = FiestaMetadata::for_test(&bytecode_hash);
+                fs::write(
+                    dir.join("metadata.json"),
+                    serde_json::to_string(&metadata).unwrap(),
+                )
+                .unwrap();
+            }
+        }
+        root
+    }
+
+    fn hashes(metadatas: &[FiestaMetadata]) -> Vec<String> {
+        metadatas
+            .iter()
+            .map(|metadata| metadata.bytecode_hash.clone())
+            .collect()
+    }
+
+    #[test]
+    fn bucket_cursor_continues_where_the_previous_batch_stopped() {
+        let root = fiesta_corpus("cursor", 3, 3);
+        let walk = hashes(&discover_buckets(&root, 9, 0, 0, None));
+        assert_eq!(walk.len(), 9);
+
+        let mut cursor = BucketCursor::new(&root, 0, None);
+        let mut batches = Vec::new();
+        for _ in 0..3 {
+            batches.extend(hashes(&cursor.next(4, 0)));
+        }
+        assert_eq!(batches, walk);
+        assert!(cursor.next(4, 0).is_empty());
+
+        let mut cursor = BucketCursor::new(&root, 0, None);
+        assert_eq!(hashes(&cursor.next(2, 3)), walk[3..5]);
+        assert_eq!(hashes(&cursor.next(2, 0)), walk[5..7]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{diff, diff::RunDiff, summary::RunSummary};
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport,
+    Transport,
+};
+use std::{env, path::Path};
+
+/// Number of contracts listed per section of the baseline diff in the email
+const EMAIL_DIFF_LIMIT: usize = 20;
+
+/// How the connection to the SMTP server is secured
+enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS, usually port 587
+    StartTls,
+    /// TLS from the start, usually port 465
+    Tls,
+    /// No encryption, only meant for a relay on the local network
+    None,
+}
+
+/// SMTP settings, read from the environment so credentials stay out of shell history
+pub struct SmtpConfig {
+    host: String,
+    port: Option<u16>,
+    tls: SmtpTls,
+    credentials: Option<Credentials>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl SmtpConfig {
+    /// Reads OMNISCAN_SMTP_HOST (required), OMNISCAN_SMTP_PORT, OMNISCAN_SMTP_TLS
+    /// (starttls, tls or none), OMNISCAN_SMTP_USERNAME, OMNISCAN_SMTP_PASSWORD and
+    /// OMNISCAN_EMAIL_FROM
+    pub fn from_env(to: &[String]) -> Result<Self, String> {
+        let host = env::var("OMNISCAN_SMTP_HOST")
+            .map_err(|_| "OMNISCAN_SMTP_HOST is not set".to_string())?;
+        let port = match env::var("OMNISCAN_SMTP_PORT") {
+            Ok(port) => Some(
+                port.parse::<u16>()
+                    .map_err(|e| format!("invalid OMNISCAN_SMTP_PORT `{}`: {}", port, e))?,
+            ),
+            Err(_) => None,
+        };
+        let tls = match env::var("OMNISCAN_SMTP_TLS").as_deref() {
+            Err(_) | Ok("starttls") => SmtpTls::StartTls,
+            Ok("tls") => SmtpTls::Tls,
+            Ok("none") => SmtpTls::None,
+            Ok(other) => {
+                return Err(format!(
+                    "invalid OMNISCAN_SMTP_TLS `{}`, expected starttls, tls or none",
+                    other
+                ))
+            }
+        };
+        let credentials = match (
+            env::var("OMNISCAN_SMTP_USERNAME"),
+            env::var("OMNISCAN_SMTP_PASSWORD"),
+        ) {
+            (Ok(username), Ok(password)) => Some(Credentials::new(username, password)),
+            (Err(_), Err(_)) => None,
+            _ => {
+                return Err(
+                    "OMNISCAN_SMTP_USERNAME and OMNISCAN_SMTP_PASSWORD must be set together"
+                        .to_string(),
+                )
+            }
+        };
+        let from = env::var("OMNISCAN_EMAIL_FROM")
+            .unwrap_or_else(|_| "omniscan <omniscan@localhost>".to_string());
+        let from = from
+            .parse::<Mailbox>()
+            .map_err(|e| format!("invalid OMNISCAN_EMAIL_FROM `{}`: {}", from, e))?;
+        let to = to
+            .iter()
+            .map(|address| {
+                address
+                    .parse::<Mailbox>()
+                    .map_err(|e| format!("invalid recipient `{}`: {}", address, e))
+            })
+            .collect::<Result<Vec<Mailbox>, String>>()?;
+
+        Ok(Self {
+            host,
+            port,
+            tls,
+            credentials,
+            from,
+            to,
+        })
+    }
+
+    fn transport(&self) -> Result<SmtpTransport, String> {
+        let mut builder = match self.tls {
+            SmtpTls::StartTls => {
+                SmtpTransport::starttls_relay(&self.host).map_err(|e| e.to_string())?
+            }
+            SmtpTls::Tls => SmtpTransport::relay(&self.host).map_err(|e| e.to_string())?,
+            SmtpTls::None => SmtpTransport::builder_dangerous(&self.host),
+        };
+        if let Some(port) = self.port {
+            builder = builder.port(port);
+        }
+        if let Some(credentials) = &self.credentials {
+            builder = builder.credentials(credentials.clone());
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Subject and plain text body of the end of run email
+pub fn format_run_email(
+    summary: &RunSummary,
+    output_path: &Path,
+    baseline_diff: Option<&RunDiff>,
+) -> (String, String) {
+    let successes = summary.counts.get("Success").copied().unwrap_or(0);
+    let mut subject = format!(
+        "omniscan: {}/{} succeeded ({:.2}%)",
+        successes, summary.total, summary.success_rate
+    );
+    // runs of several experiments land in the same inbox
+    let run_tags = summary.run_tags.describe();
+    if !run_tags.is_empty() {
+        subject = format!("{} [{}]", subject, run_tags);
+    }
+    if let Some(baseline_diff) = baseline_diff {
+        subject.push_str(&format!(
+            ", {} regressions, {} fixes",
+            baseline_diff.regressions.len(),
+            baseline_diff.fixes.len()
+        ));
+    }
+
+    let mut body = format!(
+        "Run finished, results written to {}\n\n| Type | Count | Percent |\n| ---- | ----- | ------- |\n",
+        output_path.display()
+    );
+    for (name, count) in &summary.counts {
+        body.push_str(&format!(
+            "| {} | {} | {:.2} |\n",
+            name,
+            count,
+            *count as f64 / summary.total as f64 * 100.0
+        ));
+    }
+    body.push_str(&format!(
+        "\nTotal analysis time: {:.0}s\n",
+        summary.total_time
+    ));
+    if let (Some(compiling), Some(success_rate)) =
+        (summary.solc_compiles, summary.solc_success_rate)
+    {
+        body.push_str(&format!(
+            "Success rate on the {} contracts that compile with solc: {:.2}%\n",
+            compiling, success_rate
+        ));
+    }
+    if let Some(baseline_diff) = baseline_diff {
+        body.push_str("\nCompared to the baseline:\n");
+        body.push_str(&diff::format_diff(baseline_diff, EMAIL_DIFF_LIMIT));
+    }
+
+    (subject, body)
+}
+
+pub fn send_email(config: &SmtpConfig, subject: &str, body: String) -> Result<(), String> {
+    let mut builder = Message::builder()
+        .from(config.from.clone())
+        .subject(subject);
+    for to in &config.to {
+        builder = builder.to(to.clone());
+    }
+    let message = builder.body(body).map_err(|e| e.to_string())?;
+    config
+        .transport()?
+        .send(&message)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,174 @@
+use crate::{fingerprint, solc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// A `--version` taking longer than this is killed, the version is then unknown
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the timings of a run depend on besides the corpus, recorded in its manifest so
+/// discrepancies between machines can be diagnosed after the fact
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EnvironmentSnapshot {
+    /// The pyrometer found on PATH, None if there is none
+    pub analyzer_path: Option<String>,
+    pub analyzer_sha256: Option<String>,
+    /// What `pyrometer --version` printed, None when it failed
+    pub analyzer_version: Option<String>,
+    /// Every solc* executable on PATH, with the version it reports
+    pub solc_versions: BTreeMap<String, String>,
+    /// RUST_* variables pyrometer runs with, including `--analyzer-env`
+    pub rust_env: BTreeMap<String, String>,
+    pub kernel: Option<String>,
+    pub cpu_model: Option<String>,
+    pub cpu_count: usize,
+}
+
+/// Executables on PATH whose name satisfies `matches`, the first of each name
+fn executables_on_path(matches: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    let Some(path) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    let mut executables = Vec::new();
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries = entries.flatten().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !matches(&name) || names.contains(&name) {
+                continue;
+            }
+            let is_executable = entry.path().metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            });
+            if is_executable {
+                names.push(name);
+                executables.push(entry.path());
+            }
+        }
+    }
+    executables
+}
+
+/// Last line of `solc --version`, e.g. "Version: 0.8.17+commit.8df45f5f.Linux.g++"
+fn solc_version(solc: &Path) -> String {
+    Command::new(solc)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()
+        .and_then(|child| solc::wait_with_timeout(child, VERSION_TIMEOUT))
+        .and_then(|(_success, stdout, _stderr)| {
+            stdout.lines().last().map(|line| line.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// First line of `pyrometer --version`, e.g. "pyrometer 0.2.0". None without a pyrometer on
+/// PATH, when it does not know the flag or does not answer in time.
+pub fn analyzer_version() -> Option<String> {
+    let child = Command::new(analyzer_path()?)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let (success, stdout, _stderr) = solc::wait_with_timeout(child, VERSION_TIMEOUT)?;
+    if !success {
+        return None;
+    }
+    stdout
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+}
+
+fn cpu_model() -> Option<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        // "model name" on x86, "Model" on ARM
+        matches!(key.trim(), "model name" | "Model").then(|| value.trim().to_string())
+    })
+}
+
+/// The pyrometer children are started with, the first on PATH
+pub fn analyzer_path() -> Option<PathBuf> {
+    executables_on_path(|name| name == "pyrometer")
+        .into_iter()
+        .next()
+}
+
+impl EnvironmentSnapshot {
+    pub fn capture(analyzer_env: &[(String, String)]) -> Self {
+        let analyzer = analyzer_path();
+        let solc_versions = executables_on_path(|name| name.starts_with("solc"))
+            .into_iter()
+            .map(|solc| (solc.display().to_string(), solc_version(&solc)))
+            .collect();
+        let mut rust_env = env::vars()
+            .filter(|(key, _value)| key.starts_with("RUST_"))
+            .collect::<BTreeMap<String, String>>();
+        rust_env.extend(
+            analyzer_env
+                .iter()
+                .filter(|(key, _value)| key.starts_with("RUST_"))
+                .cloned(),
+        );
+
+        Self {
+            analyzer_sha256: analyzer.as_deref().and_then(fingerprint::file_hash),
+            analyzer_path: analyzer.map(|analyzer| analyzer.display().to_string()),
+            analyzer_version: analyzer_version(),
+            solc_versions,
+            rust_env,
+            kernel: fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|release| release.trim().to_string()),
+            cpu_model: cpu_model(),
+            cpu_count: num_cpus::get(),
+        }
+    }
+
+    /// What differs in `new`, one line per changed field, empty when nothing changed. The path
+    /// of pyrometer is left out, the same binary can be installed elsewhere
+    pub fn changes(&self, new: &Self) -> Vec<String> {
+        fn change<T: PartialEq + std::fmt::Debug>(name: &str, old: &T, new: &T) -> Option<String> {
+            (old != new).then(|| format!("{}: {:?} -> {:?}", name, old, new))
+        }
+        [
+            change(
+                "pyrometer sha256",
+                &self.analyzer_sha256,
+                &new.analyzer_sha256,
+            ),
+            change(
+                "pyrometer version",
+                &self.analyzer_version,
+                &new.analyzer_version,
+            ),
+            change("solc versions", &self.solc_versions, &new.solc_versions),
+            change("RUST_* variables", &self.rust_env, &new.rust_env),
+            change("kernel", &self.kernel, &new.kernel),
+            change("CPU model", &self.cpu_model, &new.cpu_model),
+            change("CPU count", &self.cpu_count, &new.cpu_count),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
@@ -0,0 +1,44 @@
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+/// Errors of discovering the corpus, running the analyzer and recording the results. Each one
+/// names the file or the contract it is about, so it can be reported without a backtrace.
+#[derive(Debug, Error)]
+pub enum OmniscanError {
+    #[error("could not read {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("could not walk the corpus: {0}")]
+    Walk(#[from] walkdir::Error),
+    #[error("invalid {}: {source}", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("the sources of {0} were not collected")]
+    SourcesNotCollected(String),
+    #[error("no file of {bytecode_hash} defines contract {contract_name}")]
+    EntrypointNotFound {
+        bytecode_hash: String,
+        contract_name: String,
+    },
+    #[error("could not start pyrometer: {0}")]
+    Spawn(#[source] io::Error),
+    #[error("{source} (transient, still failing after {retries} retries)")]
+    TransientSpawn {
+        retries: u32,
+        source: Box<OmniscanError>,
+    },
+    #[error("could not write {}: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+    #[error("{} is not valid UTF-8", .0.display())]
+    NonUtf8Path(PathBuf),
+    #[error("could not read results file {}: {message}", path.display())]
+    Results { path: PathBuf, message: String },
+    #[error("could not export the failures to {}: {message}", path.display())]
+    Export { path: PathBuf, message: String },
+    #[error("{bytecode_hash}: {source}")]
+    Contract {
+        bytecode_hash: String,
+        source: Box<OmniscanError>,
+    },
+}
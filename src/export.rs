@@ -0,0 +1,130 @@
+use crate::{artifacts, error::OmniscanError, results, ExitType, ResultsRow, ResultsWriter};
+use std::{fs, path::Path};
+use walkdir::WalkDir;
+
+/// Recursively copies the contents of `from` into `to`
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<u64> {
+    let mut copied = 0;
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(from).unwrap();
+        let destination = to.join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&destination)?;
+        } else {
+            fs::copy(entry.path(), &destination)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+/// Copies one failing contract into `<out>/<exit type>/<bytecode_hash>/`, with its sources and
+/// metadata under `contract/`, the captured output under `output/` and the result in result.txt
+fn export_failure(
+    row: &ResultsRow,
+    corpus_root: &Path,
+    out: &Path,
+    artifacts_dir: Option<&Path>,
+) -> std::io::Result<()> {
+    let contract_out = out.join(row.result.name()).join(&row.bytecode_hash);
+    fs::create_dir_all(&contract_out)?;
+
+    let source_dir = Path::new(&row.source_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "results row has no source_path",
+            )
+        })?;
+    copy_dir(
+        &corpus_root.join(source_dir),
+        &contract_out.join("contract"),
+    )?;
+
+    if let Some(artifacts_dir) = artifacts_dir {
+        let captured = artifacts::contract_artifacts_dir(artifacts_dir, &row.bytecode_hash);
+        if captured.exists() {
+            copy_dir(&captured, &contract_out.join("output"))?;
+        }
+    }
+
+    let entrypoint = Path::new(&row.source_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fs::write(
+        contract_out.join("result.txt"),
+        format!(
+            "result: {}\ntime (sec): {:.3}\nsource_type: {}\nentrypoint: contract/{}\ncompiler_version: {}\n",
+            row.result, row.time, row.source_type, entrypoint, row.compiler_version
+        ),
+    )?;
+    Ok(())
+}
+
+/// Entry point of the `export-failures` subcommand
+pub fn export_failures(
+    results_path: &Path,
+    corpus_root: &Path,
+    out: &Path,
+    artifacts_dir: Option<&Path>,
+) -> Result<(), OmniscanError> {
+    let rows =
+        results::read_results_file(results_path).map_err(|message| OmniscanError::Results {
+            path: results_path.to_path_buf(),
+            message,
+        })?;
+    let failures = rows
+        .iter()
+        .filter(|row| !matches!(row.result, ExitType::Success))
+        .count();
+    let exported =
+        export_failure_rows(&rows, corpus_root, out, artifacts_dir).map_err(|message| {
+            OmniscanError::Export {
+                path: out.to_path_buf(),
+                message,
+            }
+        })?;
+    println!(
+        "Exported {}/{} failing contracts to {}",
+        exported,
+        failures,
+        out.display()
+    );
+    Ok(())
+}
+
+/// Exports the failing contracts of `rows` to `out`, see `export_failure`, returning how many
+/// were. Contracts that can't be exported are logged and left out.
+pub fn export_failure_rows(
+    rows: &[ResultsRow],
+    corpus_root: &Path,
+    out: &Path,
+    artifacts_dir: Option<&Path>,
+) -> Result<usize, String> {
+    fs::create_dir_all(out).map_err(|e| e.to_string())?;
+    // index of the exported contracts, readable by every other omniscan subcommand
+    let mut index_writer = ResultsWriter::new(out.join("failures.csv"));
+    index_writer
+        .initiate_headers_for_results_csv()
+        .map_err(|e| e.to_string())?;
+
+    let mut exported = 0;
+    for row in rows
+        .iter()
+        .filter(|row| !matches!(row.result, ExitType::Success))
+    {
+        if let Err(e) = export_failure(row, corpus_root, out, artifacts_dir) {
+            eprintln!("Could not export {}: {}", row.bytecode_hash, e);
+            continue;
+        }
+        match index_writer.append_to_results_file(row) {
+            Ok(()) => exported += 1,
+            Err(e) => eprintln!("Could not export {}: {}", row.bytecode_hash, e),
+        }
+    }
+    Ok(exported)
+}
@@ -0,0 +1,96 @@
+use std::{
+    fs,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// File descriptors a single job can hold at once: the stdout/stderr pipes of pyrometer, the
+/// pipe of the solc check, the sources being read and the short-lived descriptors of spawning
+pub const FDS_PER_JOB: usize = 8;
+/// Descriptors kept free for the rest of omniscan: the results file, the journal, the status
+/// server, the runtime
+const RESERVED_FDS: usize = 64;
+
+/// Whether a run already reported waiting for descriptors, so it is only reported once
+static REPORTED_WAIT: AtomicBool = AtomicBool::new(false);
+
+/// Number of file descriptors omniscan may open, used to size and gate the jobs so a run with a
+/// high `--jobs` waits for descriptors instead of failing with EMFILE
+#[derive(Clone, Copy, Debug)]
+pub struct FdBudget {
+    /// Soft RLIMIT_NOFILE after raising it
+    pub limit: usize,
+}
+
+/// Raises the soft RLIMIT_NOFILE to the hard limit, returning the resulting soft limit
+#[cfg(unix)]
+fn raise_nofile_limit() -> Option<usize> {
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit/setrlimit only read and write the rlimit struct passed to them
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) } != 0 {
+        return None;
+    }
+    // macOS rejects soft limits above OPEN_MAX, even with an unlimited hard limit
+    let target = if cfg!(target_os = "macos") {
+        rlimit.rlim_max.min(10240)
+    } else {
+        rlimit.rlim_max
+    };
+    if rlimit.rlim_cur < target {
+        let raised = libc::rlimit {
+            rlim_cur: target,
+            rlim_max: rlimit.rlim_max,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } == 0 {
+            rlimit.rlim_cur = target;
+        }
+    }
+    Some(usize::try_from(rlimit.rlim_cur).unwrap_or(usize::MAX))
+}
+
+#[cfg(not(unix))]
+fn raise_nofile_limit() -> Option<usize> {
+    None
+}
+
+/// Number of file descriptors currently open by omniscan, None where it cannot be counted
+pub fn open_fds() -> Option<usize> {
+    ["/proc/self/fd", "/dev/fd"]
+        .iter()
+        .find_map(|dir| fs::read_dir(dir).ok())
+        .map(|entries| entries.count())
+}
+
+impl FdBudget {
+    /// Raises the descriptor limit as far as allowed. None when the limit is unknown, in which
+    /// case jobs are not gated.
+    pub fn new() -> Option<Self> {
+        raise_nofile_limit().map(|limit| Self { limit })
+    }
+
+    /// The most jobs that fit in the descriptor limit
+    pub fn max_jobs(&self) -> usize {
+        let open = open_fds().unwrap_or(0);
+        (self.limit.saturating_sub(open + RESERVED_FDS) / FDS_PER_JOB).max(1)
+    }
+
+    /// Waits until there are enough free descriptors to launch one more job. Only waits while
+    /// `jobs_running` says other jobs hold descriptors that will be released.
+    pub async fn wait_for_descriptors(&self, jobs_running: impl Fn() -> bool) {
+        while let Some(open) = open_fds() {
+            if open + FDS_PER_JOB + RESERVED_FDS <= self.limit || !jobs_running() {
+                break;
+            }
+            if !REPORTED_WAIT.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "{} of {} file descriptors in use, holding back new jobs until some are released",
+                    open, self.limit
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -0,0 +1,744 @@
+use crate::FiestaMetadata;
+use clap::ValueEnum;
+use ethers::utils::{hex, keccak256};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::Semaphore, task::JoinSet, time::Instant};
+
+/// Addresses fetched at once, the key pool keeps them under the rate limit
+const FETCH_CONCURRENCY: usize = 8;
+const MAX_ATTEMPTS: u32 = 6;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Name of the log of processed addresses in the output corpus, read back to resume
+const FETCH_LOG: &str = "fetch-bulk.log";
+/// An explorer that does not answer within these is retried like one answering with an error
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Block explorer API the verified sources are fetched from
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Explorer {
+    /// The Etherscan API, also served by Arbiscan, Polygonscan, Basescan, BscScan...
+    Etherscan,
+    /// The API v2 of a Blockscout instance
+    Blockscout,
+}
+
+impl fmt::Display for Explorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Explorer::Etherscan => write!(f, "Etherscan"),
+            Explorer::Blockscout => write!(f, "Blockscout"),
+        }
+    }
+}
+
+/// Chains with a known block explorer
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    /// Ethereum mainnet, etherscan.io
+    Ethereum,
+    /// Arbitrum One, arbiscan.io
+    Arbitrum,
+    /// OP Mainnet, optimistic.etherscan.io
+    Optimism,
+    /// Polygon PoS, polygonscan.com
+    Polygon,
+    /// Base, basescan.org
+    Base,
+    /// BNB Smart Chain, bscscan.com
+    Bsc,
+    /// Gnosis, gnosis.blockscout.com
+    Gnosis,
+    /// Zora, explorer.zora.energy (Blockscout)
+    Zora,
+}
+
+impl Chain {
+    /// Name recorded in metadata.json and the results
+    pub fn name(self) -> &'static str {
+        match self {
+            Chain::Ethereum => "ethereum",
+            Chain::Arbitrum => "arbitrum",
+            Chain::Optimism => "optimism",
+            Chain::Polygon => "polygon",
+            Chain::Base => "base",
+            Chain::Bsc => "bsc",
+            Chain::Gnosis => "gnosis",
+            Chain::Zora => "zora",
+        }
+    }
+
+    /// The chain's Etherscan-family explorer, Blockscout for chains without one
+    pub fn default_explorer(self) -> Explorer {
+        match self {
+            Chain::Gnosis | Chain::Zora => Explorer::Blockscout,
+            _ => Explorer::Etherscan,
+        }
+    }
+
+    /// API endpoint of the chain's explorer of the given kind, None when there is no well-known
+    /// instance
+    pub fn api_url(self, explorer: Explorer) -> Option<&'static str> {
+        match (explorer, self) {
+            (Explorer::Etherscan, Chain::Ethereum) => Some("https://api.etherscan.io/api"),
+            (Explorer::Etherscan, Chain::Arbitrum) => Some("https://api.arbiscan.io/api"),
+            (Explorer::Etherscan, Chain::Optimism) => {
+                Some("https://api-optimistic.etherscan.io/api")
+            }
+            (Explorer::Etherscan, Chain::Polygon) => Some("https://api.polygonscan.com/api"),
+            (Explorer::Etherscan, Chain::Base) => Some("https://api.basescan.org/api"),
+            (Explorer::Etherscan, Chain::Bsc) => Some("https://api.bscscan.com/api"),
+            (Explorer::Blockscout, Chain::Ethereum) => Some("https://eth.blockscout.com"),
+            (Explorer::Blockscout, Chain::Arbitrum) => Some("https://arbitrum.blockscout.com"),
+            (Explorer::Blockscout, Chain::Optimism) => Some("https://optimism.blockscout.com"),
+            (Explorer::Blockscout, Chain::Polygon) => Some("https://polygon.blockscout.com"),
+            (Explorer::Blockscout, Chain::Base) => Some("https://base.blockscout.com"),
+            (Explorer::Blockscout, Chain::Gnosis) => Some("https://gnosis.blockscout.com"),
+            (Explorer::Blockscout, Chain::Zora) => Some("https://explorer.zora.energy"),
+            _ => None,
+        }
+    }
+
+    /// The same address is a different contract on another chain, so each chain fetched into a
+    /// corpus keeps its own log
+    fn fetch_log(self) -> String {
+        match self {
+            Chain::Ethereum => FETCH_LOG.to_string(),
+            chain => format!("fetch-bulk-{}.log", chain.name()),
+        }
+    }
+}
+
+/// Everything `omniscan fetch-bulk` needs
+pub struct FetchBulkConfig {
+    pub addresses: PathBuf,
+    pub out: PathBuf,
+    pub chain: Chain,
+    pub explorer: Explorer,
+    /// API keys of the explorer, optional for Blockscout
+    pub api_keys: Vec<String>,
+    /// Etherscan API endpoint, or the root URL of a Blockscout instance
+    pub api_url: String,
+    /// Requests per second allowed for each API key
+    pub rate: f64,
+}
+
+/// API keys handed out round robin, each no more often than the rate limit allows
+struct KeyPool {
+    /// Key and the earliest time it may be used again
+    keys: Mutex<Vec<(String, Instant)>>,
+    interval: Duration,
+}
+
+impl KeyPool {
+    /// Without keys, requests are sent without one and rate limited like a single key
+    fn new(api_keys: &[String], rate: f64) -> Self {
+        let now = Instant::now();
+        let keys = if api_keys.is_empty() {
+            vec![(String::new(), now)]
+        } else {
+            api_keys.iter().map(|key| (key.clone(), now)).collect()
+        };
+        Self {
+            keys: Mutex::new(keys),
+            interval: Duration::from_secs_f64(1.0 / rate),
+        }
+    }
+
+    /// Waits for the key that is available first and reserves its next slot
+    async fn acquire(&self) -> String {
+        let (key, available_at) = {
+            let mut keys = self.keys.lock().unwrap();
+            let (key, available_at) = keys
+                .iter_mut()
+                .min_by_key(|(_key, available_at)| *available_at)
+                .unwrap();
+            let slot = (*available_at).max(Instant::now());
+            *available_at = slot + self.interval;
+            (key.clone(), slot)
+        };
+        tokio::time::sleep_until(available_at).await;
+        key
+    }
+}
+
+/// What happened to an address, as recorded in the fetch log
+enum FetchOutcome {
+    /// Written to the corpus, or already in it under this bytecode hash
+    Fetched(String),
+    /// Etherscan has no verified source for it
+    Unverified,
+    /// No code is deployed at the address
+    NoCode,
+}
+
+impl FetchOutcome {
+    fn log_line(&self, address: &str) -> String {
+        match self {
+            FetchOutcome::Fetched(bytecode_hash) => {
+                format!("{},fetched,{}\n", address, bytecode_hash)
+            }
+            FetchOutcome::Unverified => format!("{},unverified\n", address),
+            FetchOutcome::NoCode => format!("{},no_code\n", address),
+        }
+    }
+}
+
+/// Calls the Etherscan API, retrying with exponential backoff on network errors, server errors
+/// and rate limit responses. Returns the `result` of the response.
+async fn etherscan_call(
+    client: &reqwest::Client,
+    key_pool: &KeyPool,
+    api_url: &str,
+    query: &[(&str, &str)],
+) -> Result<Value, String> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_error = String::new();
+    for _attempt in 0..MAX_ATTEMPTS {
+        let api_key = key_pool.acquire().await;
+        let response = client
+            .get(api_url)
+            .query(query)
+            .query(&[("apikey", api_key.as_str())])
+            .send()
+            .await;
+        let retry = match response {
+            Ok(response) if response.status().is_success() => {
+                let body = response.json::<Value>().await.map_err(|e| e.to_string())?;
+                // the proxy module answers like a JSON-RPC node
+                if let Some(error) = body.get("error") {
+                    return Err(error["message"].as_str().unwrap_or_default().to_string());
+                }
+                let result = body["result"].clone();
+                // errors come back as status 0 with the message in result
+                if body["status"] != "0" {
+                    return Ok(result);
+                }
+                let message = result.as_str().unwrap_or_default().to_string();
+                if !message.to_lowercase().contains("rate limit") {
+                    return Err(message);
+                }
+                message
+            }
+            Ok(response) if response.status().is_server_error() || response.status() == 429 => {
+                response.status().to_string()
+            }
+            Ok(response) => return Err(format!("Etherscan returned {}", response.status())),
+            Err(e) => e.to_string(),
+        };
+        last_error = retry;
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+    Err(format!(
+        "giving up after {} attempts: {}",
+        MAX_ATTEMPTS, last_error
+    ))
+}
+
+/// The path of a source inside the contract directory: relative, without the `.` and `..`
+/// components that would take it out of the directory. None when nothing is left
+fn contract_relative_path(path: &str) -> Option<String> {
+    let components = Path::new(path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(component) => component.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<&str>>();
+    (!components.is_empty()).then(|| components.join("/"))
+}
+
+/// Name of the file of a single-file contract
+fn single_file_name(vyper: bool) -> &'static str {
+    if vyper {
+        "main.vy"
+    } else {
+        "main.sol"
+    }
+}
+
+/// Source files of a verified contract in the smart-contract-fiesta layout: main.sol (main.vy
+/// for Vyper) for a single file, contract.json for a standard-json input, one file per source
+/// otherwise, at its path so that files of the same name in different directories are all kept
+fn fiesta_source_files(source_code: &str, vyper: bool) -> Vec<(String, String)> {
+    // standard-json inputs are wrapped in an extra pair of braces
+    let unwrapped = source_code
+        .strip_prefix("{{")
+        .and_then(|source| source.strip_suffix("}}"))
+        .map(|source| format!("{{{}}}", source));
+    let json = unwrapped
+        .as_deref()
+        .unwrap_or(source_code)
+        .parse::<Value>()
+        .ok()
+        .filter(Value::is_object);
+    match json {
+        Some(json) if json.get("language").is_some() => {
+            vec![("contract.json".to_string(), json.to_string())]
+        }
+        Some(Value::Object(sources)) => sources
+            .iter()
+            .filter_map(|(path, source)| {
+                Some((
+                    contract_relative_path(path)?,
+                    source["content"].as_str()?.to_string(),
+                ))
+            })
+            .collect(),
+        _ => vec![(single_file_name(vyper).to_string(), source_code.to_string())],
+    }
+}
+
+/// Calls an endpoint of the Blockscout API v2, retrying with exponential backoff on network
+/// errors, server errors and rate limit responses. Returns None when Blockscout answers 404.
+async fn blockscout_call(
+    client: &reqwest::Client,
+    key_pool: &KeyPool,
+    url: &str,
+) -> Result<Option<Value>, String> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_error = String::new();
+    for _attempt in 0..MAX_ATTEMPTS {
+        let api_key = key_pool.acquire().await;
+        let mut request = client.get(url);
+        if !api_key.is_empty() {
+            request = request.query(&[("apikey", api_key.as_str())]);
+        }
+        let retry = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                return response
+                    .json::<Value>()
+                    .await
+                    .map(Some)
+                    .map_err(|e| e.to_string());
+            }
+            Ok(response) if response.status() == 404 => return Ok(None),
+            Ok(response) if response.status().is_server_error() || response.status() == 429 => {
+                response.status().to_string()
+            }
+            Ok(response) => return Err(format!("Blockscout returned {}", response.status())),
+            Err(e) => e.to_string(),
+        };
+        last_error = retry;
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+    Err(format!(
+        "giving up after {} attempts: {}",
+        MAX_ATTEMPTS, last_error
+    ))
+}
+
+/// A verified contract as returned by an explorer, normalized to what the corpus stores
+struct VerifiedContract {
+    contract_name: String,
+    compiler_version: String,
+    runs: i64,
+    optimization_used: bool,
+    evm_version: Option<String>,
+    /// Source files in the smart-contract-fiesta layout, see `fiesta_source_files`
+    files: Vec<(String, String)>,
+    /// Deployed bytecode, empty when there is no code at the address
+    bytecode: Vec<u8>,
+}
+
+/// Fetches the verified sources and the deployed bytecode of an address from Etherscan, None
+/// when the address has no verified source
+async fn fetch_from_etherscan(
+    client: &reqwest::Client,
+    key_pool: &KeyPool,
+    api_url: &str,
+    address: &str,
+) -> Result<Option<VerifiedContract>, String> {
+    let source = etherscan_call(
+        client,
+        key_pool,
+        api_url,
+        &[
+            ("module", "contract"),
+            ("action", "getsourcecode"),
+            ("address", address),
+        ],
+    )
+    .await?;
+    let source = &source[0];
+    let source_code = source["SourceCode"].as_str().unwrap_or_default();
+    if source_code.is_empty() {
+        return Ok(None);
+    }
+
+    let code = etherscan_call(
+        client,
+        key_pool,
+        api_url,
+        &[
+            ("module", "proxy"),
+            ("action", "eth_getCode"),
+            ("address", address),
+            ("tag", "latest"),
+        ],
+    )
+    .await?;
+    let code = code.as_str().unwrap_or("0x");
+    let compiler_version = source["CompilerVersion"].as_str().unwrap_or_default();
+    Ok(Some(VerifiedContract {
+        contract_name: source["ContractName"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        compiler_version: compiler_version.to_string(),
+        runs: source["Runs"]
+            .as_str()
+            .and_then(|runs| runs.parse().ok())
+            .unwrap_or(0),
+        optimization_used: source["OptimizationUsed"] == "1",
+        evm_version: source["EVMVersion"].as_str().map(str::to_string),
+        files: fiesta_source_files(source_code, compiler_version.starts_with("vyper")),
+        bytecode: hex::decode(code.trim_start_matches("0x")).map_err(|e| e.to_string())?,
+    }))
+}
+
+/// Fetches the verified sources and the deployed bytecode of an address from a Blockscout
+/// instance, None when the address has no verified source (or no contract at all, Blockscout
+/// answers both with a 404)
+async fn fetch_from_blockscout(
+    client: &reqwest::Client,
+    key_pool: &KeyPool,
+    api_url: &str,
+    address: &str,
+) -> Result<Option<VerifiedContract>, String> {
+    let url = format!(
+        "{}/api/v2/smart-contracts/{}",
+        api_url.trim_end_matches('/'),
+        address
+    );
+    let Some(contract) = blockscout_call(client, key_pool, &url).await? else {
+        return Ok(None);
+    };
+    let source_code = contract["source_code"].as_str().unwrap_or_default();
+    if contract["is_verified"] == false || source_code.is_empty() {
+        return Ok(None);
+    }
+
+    let vyper = contract["language"].as_str() == Some("vyper");
+    // the entrypoint and the files it imports, like the sources map of an Etherscan response
+    let additional_sources = contract["additional_sources"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let files = if additional_sources.is_empty() {
+        vec![(single_file_name(vyper).to_string(), source_code.to_string())]
+    } else {
+        let entrypoint = contract["file_path"]
+            .as_str()
+            .unwrap_or(single_file_name(vyper));
+        std::iter::once((entrypoint, source_code))
+            .chain(additional_sources.iter().filter_map(|source| {
+                Some((
+                    source["file_path"].as_str()?,
+                    source["source_code"].as_str()?,
+                ))
+            }))
+            .filter_map(|(path, source)| Some((contract_relative_path(path)?, source.to_string())))
+            .collect()
+    };
+    // Etherscan prefixes vyper versions, which keeps them out of the analysis
+    let compiler_version = contract["compiler_version"].as_str().unwrap_or_default();
+    let compiler_version = match vyper {
+        true => format!("vyper:{}", compiler_version.trim_start_matches('v')),
+        false => compiler_version.to_string(),
+    };
+    let code = contract["deployed_bytecode"].as_str().unwrap_or("0x");
+    Ok(Some(VerifiedContract {
+        contract_name: contract["name"].as_str().unwrap_or_default().to_string(),
+        compiler_version,
+        runs: contract["optimization_runs"].as_i64().unwrap_or(0),
+        optimization_used: contract["optimization_enabled"] == true,
+        evm_version: contract["evm_version"].as_str().map(str::to_string),
+        files,
+        bytecode: hex::decode(code.trim_start_matches("0x")).map_err(|e| e.to_string())?,
+    }))
+}
+
+/// Fetches the verified sources and the deployed bytecode of one address and writes them to
+/// the corpus
+async fn fetch_address(
+    client: &reqwest::Client,
+    key_pool: &KeyPool,
+    config: &FetchBulkConfig,
+    address: &str,
+) -> Result<FetchOutcome, String> {
+    let contract = match config.explorer {
+        Explorer::Etherscan => {
+            fetch_from_etherscan(client, key_pool, &config.api_url, address).await?
+        }
+        Explorer::Blockscout => {
+            fetch_from_blockscout(client, key_pool, &config.api_url, address).await?
+        }
+    };
+    let Some(contract) = contract else {
+        return Ok(FetchOutcome::Unverified);
+    };
+    if contract.bytecode.is_empty() {
+        return Ok(FetchOutcome::NoCode);
+    }
+    let bytecode_hash = hex::encode(keccak256(&contract.bytecode));
+
+    let contract_dir = config
+        .out
+        .join("organized_contracts")
+        .join(&bytecode_hash[..2])
+        .join(&bytecode_hash);
+    // the same bytecode deployed at another address is already in the corpus
+    if contract_dir.join("metadata.json").is_file() {
+        return Ok(FetchOutcome::Fetched(bytecode_hash));
+    }
+    let metadata = FiestaMetadata {
+        contract_name: contract.contract_name,
+        compiler_version: contract.compiler_version,
+        runs: contract.runs,
+        optimization_used: contract.optimization_used,
+        bytecode_hash: bytecode_hash.clone(),
+        evm_version: contract.evm_version,
+        chain: Some(config.chain.name().to_string()),
+        abs_path_to_dir: String::new(),
+        rel_path_to_dir: String::new(),
+        source_type: None,
+        import_graph: None,
+        complexity: None,
+        addresses: Vec::new(),
+        harness_error: None,
+        compiler_available: None,
+        source_hash: None,
+    };
+    for (name, contents) in contract.files {
+        let path = contract_dir.join(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, contents).map_err(|e| e.to_string())?;
+    }
+    // metadata.json last, a contract without it is not picked up by a run
+    let metadata = serde_json::to_string(&metadata).map_err(|e| e.to_string())?;
+    fs::write(contract_dir.join("metadata.json"), metadata).map_err(|e| e.to_string())?;
+    Ok(FetchOutcome::Fetched(bytecode_hash))
+}
+
+/// Reads the addresses to fetch, one per line or in the first column of a csv. Blank lines,
+/// `#` comments and headers are skipped, duplicates are dropped.
+fn read_addresses(path: &Path) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut seen = HashSet::new();
+    Ok(contents
+        .lines()
+        .filter_map(|line| line.split(',').next())
+        .map(|address| address.trim().to_lowercase())
+        .filter(|address| {
+            address.len() == 42
+                && address.starts_with("0x")
+                && address[2..].chars().all(|c| c.is_ascii_hexdigit())
+        })
+        .filter(|address| seen.insert(address.clone()))
+        .collect())
+}
+
+/// Bytecode hashes of the contracts fetched into `out` for the addresses listed in
+/// `addresses_path`, each with the addresses it is deployed at. Addresses fetched into the same
+/// corpus for other lists are left out.
+pub fn fetched_contracts(
+    out: &Path,
+    addresses_path: &Path,
+    chain: Chain,
+) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let addresses = read_addresses(addresses_path)?
+        .into_iter()
+        .collect::<HashSet<String>>();
+    let mut contracts: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for line in fs::read_to_string(out.join(chain.fetch_log()))
+        .unwrap_or_default()
+        .lines()
+    {
+        let mut fields = line.split(',');
+        let (Some(address), Some("fetched"), Some(bytecode_hash)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if addresses.contains(address) {
+            contracts
+                .entry(bytecode_hash.to_string())
+                .or_default()
+                .push(address.to_string());
+        }
+    }
+    Ok(contracts)
+}
+
+/// Addresses a previous fetch into the same corpus already processed
+fn read_fetch_log(path: &Path) -> HashSet<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split(',').next())
+        .map(str::to_string)
+        .collect()
+}
+
+pub async fn fetch_bulk(config: FetchBulkConfig) {
+    if let Err(e) = try_fetch_bulk(config).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn try_fetch_bulk(config: FetchBulkConfig) -> Result<(), String> {
+    if config.api_keys.is_empty() && config.explorer == Explorer::Etherscan {
+        return Err(
+            "Fetching from Etherscan needs an API key, pass --api-key or set ETHERSCAN_API_KEY"
+                .to_string(),
+        );
+    }
+    let addresses = read_addresses(&config.addresses).map_err(|e| {
+        format!(
+            "Could not read addresses from {}: {}",
+            config.addresses.display(),
+            e
+        )
+    })?;
+    fs::create_dir_all(&config.out)
+        .map_err(|e| format!("Could not create {}: {}", config.out.display(), e))?;
+    let log_path = config.out.join(config.chain.fetch_log());
+    let done = read_fetch_log(&log_path);
+    let pending = addresses
+        .into_iter()
+        .filter(|address| !done.contains(address))
+        .collect::<Vec<String>>();
+    if !done.is_empty() {
+        println!(
+            "Resuming fetch: {} addresses already processed, {} left",
+            done.len(),
+            pending.len()
+        );
+    }
+
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Could not build the HTTP client: {}", e))?;
+    let key_pool = Arc::new(KeyPool::new(&config.api_keys, config.rate));
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| format!("Could not open {}: {}", log_path.display(), e))?;
+    let log = Arc::new(Mutex::new(log));
+    let config = Arc::new(config);
+    let semaphore = Arc::new(Semaphore::new(FETCH_CONCURRENCY));
+    let total = pending.len();
+    let mut fetches = JoinSet::new();
+    for address in pending {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let client = client.clone();
+        let key_pool = key_pool.clone();
+        let config = config.clone();
+        let log = log.clone();
+        fetches.spawn(async move {
+            let outcome = fetch_address(&client, &key_pool, &config, &address)
+                .await
+                .and_then(|outcome| {
+                    // failed addresses are not logged, so a rerun tries them again
+                    log.lock()
+                        .unwrap()
+                        .write_all(outcome.log_line(&address).as_bytes())
+                        .map_err(|e| format!("could not write the fetch log: {}", e))?;
+                    Ok(outcome)
+                });
+            drop(permit);
+            (address, outcome)
+        });
+    }
+
+    let (mut fetched, mut unverified, mut no_code, mut failed) = (0, 0, 0, 0);
+    while let Some(fetch) = fetches.join_next().await {
+        let Ok((address, outcome)) = fetch else {
+            failed += 1;
+            continue;
+        };
+        match outcome {
+            Ok(FetchOutcome::Fetched(_)) => fetched += 1,
+            Ok(FetchOutcome::Unverified) => unverified += 1,
+            Ok(FetchOutcome::NoCode) => no_code += 1,
+            Err(e) => {
+                eprintln!("{}: {}", address, e);
+                failed += 1;
+            }
+        }
+        let processed = fetched + unverified + no_code + failed;
+        if processed % 100 == 0 {
+            println!("{}/{} addresses processed", processed, total);
+        }
+    }
+    println!(
+        "Fetched {} contracts into {}: {} unverified, {} without code, {} failed (rerun to retry them)",
+        fetched,
+        config.out.display(),
+        unverified,
+        no_code,
+        failed
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contract_relative_path_stays_inside_the_contract() {
+        assert_eq!(
+            contract_relative_path("contracts/Token.sol").as_deref(),
+            Some("contracts/Token.sol")
+        );
+        assert_eq!(
+            contract_relative_path("/../../etc/passwd").as_deref(),
+            Some("etc/passwd")
+        );
+        assert_eq!(
+            contract_relative_path("./lib/../Math.sol").as_deref(),
+            Some("lib/Math.sol")
+        );
+        assert_eq!(contract_relative_path("/"), None);
+    }
+
+    #[test]
+    fn fiesta_source_files_keep_paths_and_vyper() {
+        assert_eq!(
+            fiesta_source_files("# @version 0.3.7", true),
+            [("main.vy".to_string(), "# @version 0.3.7".to_string())]
+        );
+        let sources = r#"{"a/Token.sol":{"content":"A"},"b/Token.sol":{"content":"B"}}"#;
+        assert_eq!(
+            fiesta_source_files(sources, false),
+            [
+                ("a/Token.sol".to_string(), "A".to_string()),
+                ("b/Token.sol".to_string(), "B".to_string())
+            ]
+        );
+        let standard_json = r#"{{"language":"Solidity","sources":{}}}"#;
+        assert_eq!(
+            fiesta_source_files(standard_json, false)[0].0,
+            "contract.json"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{project::CorpusKind, remote, solc, FetchedCorpus};
+use ethers::utils::hex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, UNIX_EPOCH},
+};
+use walkdir::WalkDir;
+
+/// A `git describe` taking longer than this is killed, e.g. on a slow network filesystem or
+/// waiting on a lock. The corpus is then fingerprinted by its contents.
+const GIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fingerprints computed by earlier runs, per corpus or archive, with the sizes and mtimes of
+/// the files they were computed from. Kept in the omniscan cache directory.
+#[derive(Serialize, Deserialize, Default)]
+struct FingerprintCache {
+    entries: BTreeMap<String, CachedFingerprint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFingerprint {
+    /// sha256 over the path, size and mtime of every file hashed
+    stat: String,
+    hash: String,
+}
+
+fn fingerprint_cache_path() -> std::path::PathBuf {
+    remote::cache_home().join("fingerprints.json")
+}
+
+/// The hash of `path` computed by `hash`, or the one of an earlier run when the files it was
+/// computed from have the same sizes and mtimes, as reading a whole corpus takes minutes
+fn cached_hash(path: &Path, stat: String, hash: impl FnOnce() -> Option<String>) -> Option<String> {
+    let cache_path = fingerprint_cache_path();
+    let mut cache = fs::read(&cache_path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<FingerprintCache>(&contents).ok())
+        .unwrap_or_default();
+    let key = std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+    if let Some(cached) = cache.entries.get(&key).filter(|cached| cached.stat == stat) {
+        return Some(cached.hash.clone());
+    }
+    let hash = hash()?;
+    cache.entries.insert(
+        key,
+        CachedFingerprint {
+            stat,
+            hash: hash.clone(),
+        },
+    );
+    // the cache only saves time, a run that can't write it fingerprints the corpus again
+    if let Some(dir) = cache_path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Ok(json) = serde_json::to_vec(&cache) {
+        let _ = fs::write(&cache_path, json);
+    }
+    Some(hash)
+}
+
+/// Adds the size and mtime of a file to a stat signature
+pub fn update_stat(hasher: &mut Sha256, rel_path: &Path, metadata: &fs::Metadata) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |mtime| mtime.as_nanos());
+    hasher.update(rel_path.to_string_lossy().as_bytes());
+    hasher.update([0]);
+    hasher.update(metadata.len().to_le_bytes());
+    hasher.update(mtime.to_le_bytes());
+}
+
+/// Release tag of the corpus: the contents of a VERSION file at its root, or the git tag of
+/// its checked out commit
+fn release_tag(corpus_root: &Path) -> Option<String> {
+    if let Ok(version) = fs::read_to_string(corpus_root.join("VERSION")) {
+        let version = version.trim();
+        if !version.is_empty() {
+            return Some(version.to_string());
+        }
+    }
+    let child = Command::new("git")
+        .arg("-C")
+        .arg(corpus_root)
+        .args(["describe", "--tags", "--exact-match", "HEAD"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let (success, stdout, _stderr) = solc::wait_with_timeout(child, GIT_TIMEOUT)?;
+    let tag = stdout.trim().to_string();
+    (success && !tag.is_empty()).then_some(tag)
+}
+
+/// The metadata.json files of a smart-contract-fiesta corpus with their paths relative to the
+/// corpus root, in path order
+fn metadata_files(corpus_root: &Path) -> Option<Vec<(std::path::PathBuf, fs::Metadata)>> {
+    let mut files = Vec::new();
+    let walk = WalkDir::new(corpus_root.join("organized_contracts")).sort_by_file_name();
+    for entry in walk {
+        let entry = entry.ok()?;
+        if !entry.file_type().is_file() || entry.file_name() != "metadata.json" {
+            continue;
+        }
+        let metadata = entry.metadata().ok()?;
+        files.push((entry.into_path(), metadata));
+    }
+    Some(files)
+}
+
+/// Hash over every metadata.json of a smart-contract-fiesta corpus and its path, in path order.
+/// Cached as long as none of the files changed size or mtime.
+fn metadata_index_hash(corpus_root: &Path) -> Option<String> {
+    let files = metadata_files(corpus_root)?;
+    let mut stat = Sha256::new();
+    for (path, metadata) in &files {
+        update_stat(
+            &mut stat,
+            path.strip_prefix(corpus_root).unwrap_or(path),
+            metadata,
+        );
+    }
+    cached_hash(corpus_root, hex::encode(stat.finalize()), || {
+        let mut hasher = Sha256::new();
+        for (path, _metadata) in &files {
+            let rel_path = path.strip_prefix(corpus_root).unwrap_or(path);
+            hasher.update(rel_path.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(fs::read(path).ok()?);
+            hasher.update([0]);
+        }
+        Some(hex::encode(hasher.finalize()))
+    })
+}
+
+/// Identifies a contract by its sources rather than by the bytecode hash of a corpus, to join
+/// results across corpus snapshots or with other datasets: sha256 of the contents of the
+/// sources, sorted so the names and order of the files don't matter, each followed by a NUL
+pub fn sources_hash(sources: &[String]) -> String {
+    let mut sorted = sources.iter().collect::<Vec<&String>>();
+    sorted.sort();
+    let mut hasher = Sha256::new();
+    for source in sorted {
+        hasher.update(source.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+pub fn file_hash(path: &Path) -> Option<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path).ok()?, &mut hasher).ok()?;
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// Identifies the dataset of a run, so comparisons can tell when it changed underneath them:
+/// "release:<tag>" when the corpus carries a release tag, else "sha256:<hash>" of the
+/// smart-contract-fiesta metadata index or of the archive. None for buckets and untagged
+/// project corpora, which cannot be hashed without reading all of them.
+pub fn corpus_fingerprint(
+    corpus: CorpusKind,
+    corpus_path: &Path,
+    fetched_corpus: Option<&FetchedCorpus>,
+) -> Option<String> {
+    match fetched_corpus {
+        Some(FetchedCorpus::Archive(archive_path)) => {
+            let mut stat = Sha256::new();
+            update_stat(&mut stat, archive_path, &fs::metadata(archive_path).ok()?);
+            return cached_hash(archive_path, hex::encode(stat.finalize()), || {
+                file_hash(archive_path)
+            })
+            .map(|hash| format!("sha256:{}", hash));
+        }
+        Some(FetchedCorpus::Remote(_)) => return None,
+        None => {}
+    }
+    if let Some(tag) = release_tag(corpus_path) {
+        return Some(format!("release:{}", tag));
+    }
+    match corpus {
+        CorpusKind::Fiesta => {
+            metadata_index_hash(corpus_path).map(|hash| format!("sha256:{}", hash))
+        }
+        CorpusKind::Foundry | CorpusKind::Hardhat | CorpusKind::Plain => None,
+    }
+}
+
+/// Warns when two runs being compared analyzed different versions of the dataset. Runs without
+/// a fingerprint are not compared.
+pub fn warn_if_changed(what: &str, old: Option<&str>, new: Option<&str>) {
+    if let (Some(old), Some(new)) = (old, new) {
+        if old != new {
+            println!(
+                "Warning: the corpus changed since {} ({} -> {}), differences may come from the dataset rather than the analyzer",
+                what, old, new
+            );
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{diff::ChangedResult, error::OmniscanError};
+use std::{fs, path::Path};
+
+/// pyrometer fixtures are named after the contract, so keep the names short but unique
+fn fixture_name(bytecode_hash: &str) -> String {
+    format!("fiesta_{}", &bytecode_hash[..bytecode_hash.len().min(12)])
+}
+
+/// Writes the newly fixed contracts in pyrometer's `test_data` layout: single file contracts
+/// become `<out>/fiesta_<hash>.sol`, multi file and standard-json contracts get their own
+/// `<out>/fiesta_<hash>/` directory. Returns the number of fixtures written, an error when
+/// `out` could not be created.
+pub fn emit_fixtures(
+    fixes: &[ChangedResult],
+    corpus_root: &Path,
+    out: &Path,
+) -> Result<usize, OmniscanError> {
+    fs::create_dir_all(out).map_err(|source| OmniscanError::Write {
+        path: out.to_path_buf(),
+        source,
+    })?;
+
+    let mut written = 0;
+    for fix in fixes {
+        let row = &fix.new;
+        let source_path = corpus_root.join(&row.source_path);
+        let Some(source_dir) = source_path.parent() else {
+            eprintln!("No source path recorded for {}", row.bytecode_hash);
+            continue;
+        };
+        let name = fixture_name(&row.bytecode_hash);
+
+        let copied = if row.source_type == "SingleFile" {
+            fs::copy(&source_path, out.join(format!("{}.sol", name))).map(|_| ())
+        } else {
+            let fixture_dir = out.join(&name);
+            fs::create_dir_all(&fixture_dir).and_then(|_| {
+                for entry in fs::read_dir(source_dir)? {
+                    let entry = entry?;
+                    let file_name = entry.file_name();
+                    // the fiesta metadata is not part of the compilation
+                    if entry.file_type()?.is_file() && file_name != "metadata.json" {
+                        fs::copy(entry.path(), fixture_dir.join(file_name))?;
+                    }
+                }
+                Ok(())
+            })
+        };
+
+        match copied {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!("Could not emit fixture for {}: {}", row.bytecode_hash, e),
+        }
+    }
+    Ok(written)
+}
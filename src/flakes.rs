@@ -0,0 +1,278 @@
+use crate::{
+    check::Mismatch,
+    diff::{self, RunDiff},
+    manifest, ExitType, ResultsRow,
+};
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+};
+
+/// Flake database of `--flakes-db` when no path is given
+pub const DEFAULT_FLAKES_DB: &str = "omniscan.flakes.json";
+
+/// Changes of outcome from which a contract counts as flaky. A single change may come from a
+/// change of the environment, e.g. solc; changing back as well does not.
+const FLAKY_CHANGES: u32 = 2;
+
+/// Flaky contracts listed at the end of a run or in a report
+const LISTED_FLAKES: usize = 10;
+
+/// Whether the `--check` mismatches of known flaky contracts fail the check
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlakyGating {
+    /// Flaky contracts are listed apart and do not fail the check
+    #[default]
+    Exclude,
+    /// Flaky contracts fail the check like any other
+    Include,
+}
+
+/// How the outcome of a contract changed across the runs of one analyzer binary
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OutcomeChanges {
+    /// Every outcome seen since the first change, see `outcome`
+    pub outcomes: BTreeSet<String>,
+    /// Times the outcome differed from the one of the previous run
+    pub changes: u32,
+    /// Whether the analyzer gave different outcomes within a run, see `--determinism-check`
+    #[serde(default)]
+    pub nondeterministic: bool,
+    /// RFC 3339 time of the last change
+    pub last_change: String,
+}
+
+impl OutcomeChanges {
+    pub fn is_flaky(&self) -> bool {
+        self.nondeterministic || self.changes >= FLAKY_CHANGES
+    }
+}
+
+/// Outcomes of one analyzer binary across runs
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AnalyzerHistory {
+    /// Number of runs recorded
+    pub runs: usize,
+    /// Outcome of every contract in the last run that analyzed it
+    pub last_outcomes: BTreeMap<String, String>,
+    /// Contracts whose outcome changed at least once, by bytecode hash
+    pub changed: BTreeMap<String, OutcomeChanges>,
+}
+
+/// Contracts whose outcome flip-flopped across runs, per analyzer binary by its sha256 (see
+/// `EnvironmentSnapshot::analyzer_sha256`), as builds of the same version can differ
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FlakeDb {
+    #[serde(default)]
+    pub analyzers: BTreeMap<String, AnalyzerHistory>,
+}
+
+/// What is compared between runs: the exit type, and the normalized message of failures (see
+/// `diff::normalize_message`). None for results that say nothing about the analyzer.
+fn outcome(row: &ResultsRow) -> Option<String> {
+    match &row.result {
+        ExitType::HarnessError(_) => None,
+        result => Some(match row.message() {
+            Some(message) => format!("{}: {}", result.name(), diff::normalize_message(message)),
+            None => result.name().to_string(),
+        }),
+    }
+}
+
+/// Key of an analyzer binary in the database
+pub fn analyzer_key(analyzer_sha256: Option<&str>) -> String {
+    analyzer_sha256.unwrap_or("unknown").to_string()
+}
+
+/// Key of the analyzer that produced a results file, from its manifest. None when unknown
+pub fn results_analyzer_key(results_path: &Path) -> Option<String> {
+    manifest::read_manifest(&manifest::manifest_path(results_path))
+        .ok()
+        .and_then(|run_manifest| run_manifest.environment)
+        .map(|environment| analyzer_key(environment.analyzer_sha256.as_deref()))
+}
+
+impl FlakeDb {
+    /// Reads the database, empty when it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Writes the database, replacing it only once it is complete
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        fs::write(&tmp_path, serde_json::to_string(self).unwrap())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Records the outcomes of a run of the analyzer, returning the contracts that became
+    /// flaky with it
+    pub fn record_run(&mut self, analyzer: &str, rows: &[ResultsRow]) -> Vec<String> {
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let history = self.analyzers.entry(analyzer.to_string()).or_default();
+        history.runs += 1;
+        let mut newly_flaky = Vec::new();
+        for row in rows {
+            let Some(outcome) = outcome(row) else {
+                continue;
+            };
+            let previous = history
+                .last_outcomes
+                .insert(row.bytecode_hash.clone(), outcome.clone());
+            let nondeterministic = matches!(row.result, ExitType::Nondeterministic(_));
+            let changed = previous
+                .as_ref()
+                .is_some_and(|previous| *previous != outcome);
+            if !changed && !nondeterministic {
+                continue;
+            }
+            let changes = history
+                .changed
+                .entry(row.bytecode_hash.clone())
+                .or_default();
+            let was_flaky = changes.is_flaky();
+            changes.outcomes.extend(previous);
+            changes.outcomes.insert(outcome);
+            changes.changes += changed as u32;
+            changes.nondeterministic |= nondeterministic;
+            changes.last_change = now.clone();
+            if !was_flaky && changes.is_flaky() {
+                newly_flaky.push(row.bytecode_hash.clone());
+            }
+        }
+        newly_flaky
+    }
+
+    /// The flaky contracts of the analyzer, or of every analyzer when it is not known, by
+    /// bytecode hash
+    pub fn flaky(&self, analyzer: Option<&str>) -> BTreeMap<String, OutcomeChanges> {
+        self.analyzers
+            .iter()
+            .filter(|(key, _)| analyzer.is_none_or(|analyzer| analyzer == key.as_str()))
+            .flat_map(|(_, history)| &history.changed)
+            .filter(|(_, changes)| changes.is_flaky())
+            .map(|(hash, changes)| (hash.clone(), changes.clone()))
+            .collect()
+    }
+}
+
+/// Splits the `--check` mismatches into the ones that fail the check and the ones of known
+/// flaky contracts, which do not with `FlakyGating::Exclude`
+pub fn partition_mismatches(
+    mismatches: Vec<Mismatch>,
+    flaky: &BTreeMap<String, OutcomeChanges>,
+    gating: FlakyGating,
+) -> (Vec<Mismatch>, Vec<Mismatch>) {
+    if gating == FlakyGating::Include {
+        return (mismatches, Vec::new());
+    }
+    mismatches
+        .into_iter()
+        .partition(|mismatch| !flaky.contains_key(mismatch.bytecode_hash.as_str()))
+}
+
+/// Moves the regressions, fixes and changed failures of known flaky contracts of the diff to
+/// its `flaky` changes, unless `gating` is `FlakyGating::Include`
+pub fn exclude_from_diff(
+    diff: &mut RunDiff,
+    flaky: &BTreeMap<String, OutcomeChanges>,
+    gating: FlakyGating,
+) {
+    if gating == FlakyGating::Include {
+        return;
+    }
+    for changes in [
+        &mut diff.regressions,
+        &mut diff.fixes,
+        &mut diff.changed_failures,
+    ] {
+        let (flaky_changes, kept) = std::mem::take(changes)
+            .into_iter()
+            .partition(|change| flaky.contains_key(change.new.bytecode_hash.as_str()));
+        *changes = kept;
+        diff.flaky.extend::<Vec<_>>(flaky_changes);
+    }
+}
+
+fn print_flaky_contract(hash: &str, changes: &OutcomeChanges) {
+    let outcomes = changes
+        .outcomes
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .join(" | ");
+    let nondeterministic = if changes.nondeterministic {
+        ", nondeterministic within a run"
+    } else {
+        ""
+    };
+    println!(
+        "  {}: changed {} times{}: {}",
+        hash, changes.changes, nondeterministic, outcomes
+    );
+}
+
+/// Prints the known flaky contracts among `rows`
+pub fn print_known_flakes(rows: &[ResultsRow], flaky: &BTreeMap<String, OutcomeChanges>) {
+    let known = rows
+        .iter()
+        .filter_map(|row| {
+            flaky
+                .get(row.bytecode_hash.as_str())
+                .map(|changes| (row.bytecode_hash.as_str(), changes))
+        })
+        .collect::<Vec<(&str, &OutcomeChanges)>>();
+    if known.is_empty() {
+        println!("None of the contracts are known to be flaky");
+        return;
+    }
+    let failing = rows
+        .iter()
+        .filter(|row| {
+            !matches!(row.result, ExitType::Success)
+                && flaky.contains_key(row.bytecode_hash.as_str())
+        })
+        .count();
+    println!(
+        "{} of the contracts are known to be flaky, {} of them failed:",
+        known.len(),
+        failing
+    );
+    for (hash, changes) in known.iter().take(LISTED_FLAKES) {
+        print_flaky_contract(hash, changes);
+    }
+    if known.len() > LISTED_FLAKES {
+        println!("  ... and {} more", known.len() - LISTED_FLAKES);
+    }
+}
+
+/// Prints the contracts that became flaky with the run
+pub fn print_newly_flaky(newly_flaky: &[String], db: &FlakeDb, analyzer: &str) {
+    if newly_flaky.is_empty() {
+        return;
+    }
+    println!(
+        "{} contracts flip-flopped across runs of the pyrometer binary with sha256 {}:",
+        newly_flaky.len(),
+        analyzer
+    );
+    let flaky = db.flaky(Some(analyzer));
+    for hash in newly_flaky.iter().take(LISTED_FLAKES) {
+        if let Some(changes) = flaky.get(hash.as_str()) {
+            print_flaky_contract(hash, changes);
+        }
+    }
+    if newly_flaky.len() > LISTED_FLAKES {
+        println!("  ... and {} more", newly_flaky.len() - LISTED_FLAKES);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    imports::{self, SourceSet, IMPORT_STATEMENT},
+    solc, FiestaMetadata, SourceType,
+};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// `forge flatten` taking longer than this is killed, the contract then counts as not flattened
+const FORGE_FLATTEN_TIMEOUT: Duration = Duration::from_secs(120);
+
+lazy_static! {
+    static ref PRAGMA: Regex = Regex::new(r"(?m)^\s*pragma\s+[^;]+;[^\n]*\n?").unwrap();
+    static ref SPDX: Regex = Regex::new(r"(?m)^\s*//\s*SPDX-License-Identifier:[^\n]*\n?").unwrap();
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FlattenMode {
+    /// omniscan's own flattener, resolving imports from the contract's files or standard-json
+    Builtin,
+    /// `forge flatten`, standard-json contracts still use the built-in flattener
+    Forge,
+}
+
+/// Appends `key` and everything it imports to `out`, dependencies first
+fn flatten_source(
+    sources: &SourceSet,
+    key: &str,
+    visited: &mut HashSet<String>,
+    out: &mut Vec<String>,
+) -> Result<(), String> {
+    if !visited.insert(key.to_string()) {
+        return Ok(());
+    }
+    let content = sources
+        .read(key)
+        .ok_or_else(|| format!("could not read {}", key))?;
+    for captures in IMPORT_STATEMENT.captures_iter(&content) {
+        let import_path = &captures[1];
+        let import_key = sources
+            .resolve(key, import_path)
+            .ok_or_else(|| format!("could not resolve import {} in {}", import_path, key))?;
+        flatten_source(sources, &import_key, visited, out)?;
+    }
+    out.push(format!(
+        "// File: {}\n{}",
+        key,
+        IMPORT_STATEMENT.replace_all(&content, "")
+    ));
+    Ok(())
+}
+
+/// Joins the sources into one file, keeping a single license identifier and each distinct
+/// pragma once, at the top
+fn join_sources(sources: Vec<String>) -> String {
+    let mut license = None;
+    let mut pragmas: Vec<String> = Vec::new();
+    let mut bodies = Vec::new();
+    for source in sources {
+        if license.is_none() {
+            license = SPDX
+                .find(&source)
+                .map(|spdx| spdx.as_str().trim().to_string());
+        }
+        for pragma in PRAGMA.find_iter(&source) {
+            let pragma = pragma.as_str().trim().to_string();
+            if !pragmas.contains(&pragma) {
+                pragmas.push(pragma);
+            }
+        }
+        let body = SPDX.replace_all(&source, "");
+        bodies.push(PRAGMA.replace_all(&body, "").to_string());
+    }
+
+    let mut flattened = String::new();
+    if let Some(license) = license {
+        flattened.push_str(&license);
+        flattened.push('\n');
+    }
+    for pragma in pragmas {
+        flattened.push_str(&pragma);
+        flattened.push('\n');
+    }
+    for body in bodies {
+        flattened.push('\n');
+        flattened.push_str(&body);
+    }
+    flattened
+}
+
+fn flatten_builtin(metadata: &FiestaMetadata) -> Result<String, String> {
+    let (entry, sources) = imports::contract_sources(metadata)?;
+    let mut visited = HashSet::new();
+    let mut flattened = Vec::new();
+    flatten_source(&sources, &entry, &mut visited, &mut flattened)?;
+    Ok(join_sources(flattened))
+}
+
+fn flatten_forge(metadata: &FiestaMetadata, dir: &Path, out: &Path) -> Result<(), String> {
+    let child = Command::new("forge")
+        .arg("flatten")
+        .arg(metadata.entrypoint_file_name().map_err(|e| e.to_string())?)
+        .arg("--output")
+        .arg(out)
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run forge: {}", e))?;
+    let (success, _stdout, stderr) =
+        solc::wait_with_timeout(child, FORGE_FLATTEN_TIMEOUT).ok_or(format!(
+            "forge flatten timed out after {}s",
+            FORGE_FLATTEN_TIMEOUT.as_secs()
+        ))?;
+    if success {
+        Ok(())
+    } else {
+        Err(format!(
+            "forge flatten failed: {}",
+            stderr.lines().last().unwrap_or_default()
+        ))
+    }
+}
+
+/// Whether the contract spans several files and so needs flattening
+pub fn is_multi_file(metadata: &FiestaMetadata) -> bool {
+    matches!(
+        metadata.source_type,
+        Some(SourceType::Multiple(_) | SourceType::EtherscanMetadata(_) | SourceType::Project(_))
+    )
+}
+
+/// Flattens a multi-file contract into `<work_dir>/flattened.sol`, returning its path
+pub fn flatten_contract(
+    metadata: &FiestaMetadata,
+    mode: FlattenMode,
+    work_dir: &Path,
+) -> Result<PathBuf, String> {
+    let dir = Path::new(&metadata.abs_path_to_dir);
+    fs::create_dir_all(work_dir).map_err(|e| e.to_string())?;
+    let out = work_dir.join("flattened.sol");
+
+    match (mode, &metadata.source_type) {
+        (FlattenMode::Forge, Some(SourceType::Multiple(_) | SourceType::Project(_))) => {
+            flatten_forge(metadata, dir, &out)?
+        }
+        _ => fs::write(&out, flatten_builtin(metadata)?).map_err(|e| e.to_string())?,
+    }
+    Ok(out)
+}
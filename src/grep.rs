@@ -0,0 +1,228 @@
+use crate::{
+    collect_contract_sources, discover_corpus, project::CorpusKind, results, FiestaMetadata,
+    SourceType,
+};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// Longest part of a matching line that is printed
+const MAX_LINE_CHARS: usize = 200;
+
+/// What `omniscan grep` searches and how it prints the matches
+pub struct GrepOptions {
+    pub pattern: Regex,
+    pub corpus: CorpusKind,
+    /// Results file restricting the search to its contracts, whose result types are then
+    /// joined to the matches
+    pub results: Option<PathBuf>,
+    /// Result types of the results file the search is restricted to, all when empty
+    pub result_types: Vec<String>,
+    /// Print the contracts with a match instead of the matching lines
+    pub files_with_matches: bool,
+}
+
+/// A line of a contract's sources matching the pattern
+struct Match {
+    file: String,
+    line_number: usize,
+    line: String,
+}
+
+/// Every source file of a contract whose sources were collected, by name: the files of its
+/// directory, the sources of its standard-json input, or the entrypoint of a project contract
+fn named_sources(metadata: &FiestaMetadata) -> Result<Vec<(String, String)>, String> {
+    Ok(match &metadata.source_type {
+        Some(SourceType::SingleMain(source)) => vec![("main.sol".to_string(), source.clone())],
+        Some(SourceType::Multiple(files)) => files.clone(),
+        Some(SourceType::EtherscanMetadata(source_metadata)) => source_metadata
+            .sources()
+            .into_iter()
+            .map(|(key, entry)| (key, entry.content))
+            .collect(),
+        Some(SourceType::Project(project_source)) => {
+            let path = Path::new(&metadata.abs_path_to_dir).join(&project_source.entrypoint);
+            let source = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            vec![(project_source.entrypoint.clone(), source)]
+        }
+        None => Vec::new(),
+    })
+}
+
+/// Whether a line of the collected sources of the contract matches the pattern, see
+/// `--source-contains`
+pub fn sources_match(metadata: &FiestaMetadata, pattern: &Regex) -> Result<bool, String> {
+    Ok(named_sources(metadata)?
+        .iter()
+        .any(|(_, source)| source.lines().any(|line| pattern.is_match(line))))
+}
+
+fn search_contract(metadata: &mut FiestaMetadata, pattern: &Regex) -> Result<Vec<Match>, String> {
+    let collected = metadata.source_type.is_none();
+    if collected {
+        collect_contract_sources(metadata).map_err(|e| e.to_string())?;
+    }
+    let mut matches = Vec::new();
+    for (file, source) in named_sources(metadata)? {
+        for (i, line) in source.lines().enumerate() {
+            if pattern.is_match(line) {
+                matches.push(Match {
+                    file: file.clone(),
+                    line_number: i + 1,
+                    line: line.trim().chars().take(MAX_LINE_CHARS).collect(),
+                });
+            }
+        }
+    }
+    // the sources of the whole corpus would not fit in memory
+    if collected {
+        metadata.source_type = None;
+    }
+    Ok(matches)
+}
+
+/// Searches the sources of the contracts, as many at once as there are cores, handing the
+/// matches of each contract to `found` in the order of `metadatas` as soon as its search and
+/// those of the contracts before it are done, so only the matches of the contracts still
+/// waiting on an earlier one are held. Returns how many contracts' sources could not be read,
+/// those are not searched.
+fn search_contracts(
+    metadatas: Vec<FiestaMetadata>,
+    pattern: &Regex,
+    mut found: impl FnMut(&FiestaMetadata, &[Match]),
+) -> usize {
+    let workers = num_cpus::get().max(1);
+    let queue = Mutex::new(metadatas.into_iter().enumerate());
+    let (sender, receiver) = mpsc::sync_channel(workers * 4);
+    let mut unreadable = 0;
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let (queue, sender) = (&queue, sender.clone());
+            scope.spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let Some((i, mut metadata)) = next else {
+                    break;
+                };
+                let matches = search_contract(&mut metadata, pattern);
+                if sender.send((i, metadata, matches)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        // searches done ahead of an earlier contract's, by index
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (i, metadata, matches) in receiver {
+            pending.insert(i, (metadata, matches));
+            while let Some((metadata, matches)) = pending.remove(&next) {
+                match matches {
+                    Ok(matches) => found(&metadata, &matches),
+                    Err(_) => unreadable += 1,
+                }
+                next += 1;
+            }
+        }
+    });
+    unreadable
+}
+
+/// Searches every eligible contract of the corpus for lines matching the pattern, printing
+/// them with the contract's result type when a results file is given, then how many contracts
+/// match per result type
+pub fn run_grep(corpus_root: &Path, options: GrepOptions) {
+    // result type of each contract of the results file, by bytecode hash
+    let result_types = options.results.as_ref().map(|path| {
+        results::read_results_file(path)
+            .unwrap_or_else(|e| {
+                eprintln!("Could not read results file {}: {}", path.display(), e);
+                std::process::exit(1);
+            })
+            .into_iter()
+            .map(|row| (row.bytecode_hash, row.result.name()))
+            .filter(|(_, name)| {
+                options.result_types.is_empty()
+                    || options.result_types.iter().any(|result| result == name)
+            })
+            .collect::<HashMap<String, &'static str>>()
+    });
+
+    let work_dir = std::env::temp_dir().join(format!("omniscan-grep-{}", std::process::id()));
+    let discovered = discover_corpus(
+        options.corpus,
+        corpus_root,
+        &work_dir,
+        usize::MAX,
+        0,
+        5,
+        None,
+    );
+    let _ = fs::remove_dir_all(&work_dir);
+    let mut metadatas = discovered.unwrap_or_else(|e| {
+        eprintln!("Could not read the corpus {}: {}", corpus_root.display(), e);
+        std::process::exit(1);
+    });
+    if let Some(result_types) = &result_types {
+        metadatas.retain(|metadata| result_types.contains_key(&metadata.bytecode_hash));
+    }
+    let total = metadatas.len();
+
+    // contracts and matching contracts per result type
+    let mut by_result: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    let mut matching = 0;
+    let unreadable = search_contracts(metadatas, &options.pattern, |metadata, matches| {
+        let result = result_types
+            .as_ref()
+            .and_then(|result_types| result_types.get(&metadata.bytecode_hash).copied());
+        let counts = by_result.entry(result.unwrap_or_default()).or_default();
+        counts.0 += 1;
+        if matches.is_empty() {
+            return;
+        }
+        counts.1 += 1;
+        matching += 1;
+        let prefix = result
+            .map(|result| format!("[{}] ", result))
+            .unwrap_or_default();
+        if options.files_with_matches {
+            println!("{}{}", prefix, metadata.rel_path_to_dir);
+            return;
+        }
+        for found in matches {
+            println!(
+                "{}{}/{}:{}: {}",
+                prefix, metadata.rel_path_to_dir, found.file, found.line_number, found.line
+            );
+        }
+    });
+
+    println!();
+    println!(
+        "{} of {} contracts match{}",
+        matching,
+        total,
+        if unreadable > 0 {
+            format!(", {} could not be read", unreadable)
+        } else {
+            String::new()
+        }
+    );
+    if result_types.is_some() {
+        println!("| Type | Contracts | Matching | Percent |");
+        println!("| ----- | ----- | ----- | ------- |");
+        for (result, (contracts, matching)) in by_result {
+            println!(
+                "|{} | {} | {} | {:.2} |",
+                result,
+                contracts,
+                matching,
+                matching as f64 / contracts as f64 * 100.0
+            );
+        }
+    }
+}
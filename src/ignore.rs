@@ -0,0 +1,194 @@
+use crate::FiestaMetadata;
+use chrono::{SecondsFormat, Utc};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Ignore file read by runs when `--ignore-file` is not given, and written by `omniscan ignore`
+pub const DEFAULT_IGNORE_FILE: &str = "omniscan.ignore.json";
+
+#[derive(Subcommand, Debug)]
+pub enum IgnoreAction {
+    /// Add contracts to the ignore file, or update the reason of contracts already in it
+    Add {
+        /// Bytecode hashes of the contracts
+        #[clap(required = true)]
+        hashes: Vec<String>,
+
+        /// Why the contracts are left out, e.g. a link to the analyzer issue
+        #[clap(long)]
+        reason: String,
+    },
+    /// Remove contracts from the ignore file
+    Remove {
+        /// Bytecode hashes of the contracts
+        #[clap(required = true)]
+        hashes: Vec<String>,
+    },
+    /// List the contracts in the ignore file, oldest first
+    List,
+}
+
+/// Why and since when a contract is left out of the runs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IgnoreEntry {
+    pub reason: String,
+    /// RFC 3339 time the contract was added
+    pub added_at: String,
+}
+
+/// Contracts left out of the runs, by bytecode hash
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct IgnoreFile {
+    pub contracts: BTreeMap<String, IgnoreEntry>,
+}
+
+impl IgnoreFile {
+    /// Reads the ignore file, empty when it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Writes the ignore file, replacing it only once it is complete
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        fs::write(&tmp_path, serde_json::to_string_pretty(self).unwrap())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Entries sorted by the time they were added, oldest first
+    fn by_age(&self) -> Vec<(&String, &IgnoreEntry)> {
+        let mut entries = self.contracts.iter().collect::<Vec<_>>();
+        entries.sort_by(|(_, first), (_, second)| first.added_at.cmp(&second.added_at));
+        entries
+    }
+}
+
+/// Bytecode hashes are compared without a 0x prefix and in lowercase, like the corpus stores them
+fn normalize_hash(hash: &str) -> String {
+    let hash = hash.trim();
+    hash.strip_prefix("0x").unwrap_or(hash).to_ascii_lowercase()
+}
+
+/// Day part of an RFC 3339 time, for the messages
+fn day(added_at: &str) -> &str {
+    added_at.split('T').next().unwrap_or(added_at)
+}
+
+pub fn run_ignore(action: IgnoreAction, path: &Path) {
+    let mut ignore_file = IgnoreFile::load(path).unwrap_or_else(|e| {
+        eprintln!("Could not read the ignore file {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    match action {
+        IgnoreAction::Add { hashes, reason } => {
+            let added_at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+            for hash in hashes {
+                let hash = normalize_hash(&hash);
+                match ignore_file.contracts.get_mut(&hash) {
+                    // keep when it was first ignored, so old entries still stand out
+                    Some(entry) => {
+                        entry.reason = reason.clone();
+                        println!("Updated {}", hash);
+                    }
+                    None => {
+                        ignore_file.contracts.insert(
+                            hash.clone(),
+                            IgnoreEntry {
+                                reason: reason.clone(),
+                                added_at: added_at.clone(),
+                            },
+                        );
+                        println!("Added {}", hash);
+                    }
+                }
+            }
+        }
+        IgnoreAction::Remove { hashes } => {
+            for hash in hashes {
+                let hash = normalize_hash(&hash);
+                if ignore_file.contracts.remove(&hash).is_some() {
+                    println!("Removed {}", hash);
+                } else {
+                    eprintln!("{} is not in {}", hash, path.display());
+                }
+            }
+        }
+        IgnoreAction::List => {
+            if ignore_file.contracts.is_empty() {
+                println!("No contracts are ignored in {}", path.display());
+                return;
+            }
+            println!("| Bytecode hash | Added | Reason |");
+            println!("| ----- | ----- | ------- |");
+            for (hash, entry) in ignore_file.by_age() {
+                println!(
+                    "| {} | {} | {} |",
+                    hash,
+                    day(&entry.added_at),
+                    entry.reason.replace('|', "\\|")
+                );
+            }
+            println!("{} contracts ignored", ignore_file.contracts.len());
+            return;
+        }
+    }
+    if let Err(e) = ignore_file.save(path) {
+        eprintln!("Could not write the ignore file {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// Ignore file of a run: the given `--ignore-file`, which has to exist, else the default one
+/// when there is one in the current directory
+pub fn run_ignore_file(ignore_file: Option<&str>) -> Option<PathBuf> {
+    match ignore_file {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            if !path.is_file() {
+                eprintln!("The ignore file {} does not exist", path.display());
+                std::process::exit(1);
+            }
+            Some(path)
+        }
+        None => Some(PathBuf::from(DEFAULT_IGNORE_FILE)).filter(|path| path.is_file()),
+    }
+}
+
+/// Leaves out the contracts of the ignore file at `path`, returning how many were excluded. The
+/// count and the oldest entry are always printed, so the file can't hide a contract unnoticed.
+pub fn exclude_ignored(metadatas: &mut Vec<FiestaMetadata>, path: &Path) -> usize {
+    let ignore_file = IgnoreFile::load(path).unwrap_or_else(|e| {
+        eprintln!("Could not read the ignore file {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    let before = metadatas.len();
+    metadatas.retain(|metadata| {
+        !ignore_file
+            .contracts
+            .contains_key(&normalize_hash(&metadata.bytecode_hash))
+    });
+    let excluded = before - metadatas.len();
+    if let Some((oldest_hash, oldest)) = ignore_file.by_age().first() {
+        println!(
+            "Excluded {} contracts listed in {} ({} entries, the oldest is {} from {}: {})",
+            excluded,
+            path.display(),
+            ignore_file.contracts.len(),
+            oldest_hash,
+            day(&oldest.added_at),
+            oldest.reason
+        );
+    }
+    excluded
+}
@@ -0,0 +1,252 @@
+use crate::{FiestaMetadata, SourceType};
+use ethers::etherscan::contract::SourceCodeMetadata;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+lazy_static! {
+    pub static ref IMPORT_STATEMENT: Regex =
+        Regex::new(r#"(?s)\bimport\s+(?:[^'";]*\s+from\s+)?["']([^"']+)["']\s*;"#).unwrap();
+}
+
+/// Lexically resolves `.` and `..`, source keys of a standard-json input are not on disk
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Where the sources of a contract come from
+pub enum SourceSet {
+    /// Files on disk. Imports that don't resolve are matched by file name against `by_name`,
+    /// as smart-contract-fiesta stores multi-file contracts without their directory layout
+    Files {
+        remappings: Vec<(String, String)>,
+        by_name: BTreeMap<String, PathBuf>,
+    },
+    /// The `sources` of a standard-json input
+    StandardJson {
+        remappings: Vec<(String, String)>,
+        sources: BTreeMap<String, String>,
+    },
+}
+
+fn parse_remappings(lines: &[String]) -> Vec<(String, String)> {
+    let mut remappings = lines
+        .iter()
+        .filter_map(|line| {
+            // context:prefix=target, the context is ignored
+            let line = line.rsplit(':').next()?;
+            let (prefix, target) = line.split_once('=')?;
+            Some((prefix.to_string(), target.to_string()))
+        })
+        .collect::<Vec<(String, String)>>();
+    // longest prefix wins
+    remappings.sort_by_key(|(prefix, _target)| std::cmp::Reverse(prefix.len()));
+    remappings
+}
+
+fn apply_remappings(remappings: &[(String, String)], import_path: &str) -> Option<String> {
+    remappings.iter().find_map(|(prefix, target)| {
+        import_path
+            .strip_prefix(prefix.as_str())
+            .map(|rest| format!("{}{}", target, rest))
+    })
+}
+
+impl SourceSet {
+    pub fn read(&self, key: &str) -> Option<String> {
+        match self {
+            SourceSet::Files { .. } => fs::read_to_string(key).ok(),
+            SourceSet::StandardJson { sources, .. } => sources.get(key).cloned(),
+        }
+    }
+
+    /// Key of the source `import_path` refers to when imported from `from`
+    pub fn resolve(&self, from: &str, import_path: &str) -> Option<String> {
+        let file_name = Path::new(import_path).file_name()?.to_str()?;
+        let relative = import_path.starts_with("./") || import_path.starts_with("../");
+        let from_dir = Path::new(from).parent().unwrap_or(Path::new(""));
+
+        match self {
+            SourceSet::Files {
+                remappings,
+                by_name,
+            } => {
+                let candidate = if relative {
+                    Some(normalize(&from_dir.join(import_path)))
+                } else {
+                    apply_remappings(remappings, import_path).map(PathBuf::from)
+                };
+                candidate
+                    .filter(|candidate| candidate.is_file())
+                    .or_else(|| by_name.get(file_name).cloned())
+                    .and_then(|path| path.to_str().map(str::to_string))
+            }
+            SourceSet::StandardJson {
+                remappings,
+                sources,
+            } => {
+                let candidate = if relative {
+                    normalize(&from_dir.join(import_path))
+                        .to_str()
+                        .map(str::to_string)
+                } else {
+                    apply_remappings(remappings, import_path)
+                        .or_else(|| Some(import_path.to_string()))
+                };
+                candidate
+                    .filter(|candidate| sources.contains_key(candidate))
+                    .or_else(|| {
+                        let mut matches = sources.keys().filter(|key| {
+                            Path::new(key.as_str()).file_name() == Some(file_name.as_ref())
+                        });
+                        // only trust a file name match if it is unambiguous
+                        match (matches.next(), matches.next()) {
+                            (Some(key), None) => Some(key.clone()),
+                            _ => None,
+                        }
+                    })
+            }
+        }
+    }
+}
+
+/// Entry source and sources of a standard-json contract
+fn standard_json_sources(
+    metadata: &FiestaMetadata,
+    source_metadata: &SourceCodeMetadata,
+) -> Result<(String, SourceSet), String> {
+    let sources = source_metadata
+        .sources()
+        .into_iter()
+        .map(|(key, entry)| (key, entry.content))
+        .collect::<BTreeMap<String, String>>();
+    let remapping_lines = match source_metadata {
+        SourceCodeMetadata::Metadata {
+            settings: Some(settings),
+            ..
+        } => settings["remappings"]
+            .as_array()
+            .map(|remappings| {
+                remappings
+                    .iter()
+                    .filter_map(|remapping| remapping.as_str().map(str::to_string))
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    let contract_declaration = format!("contract {} ", metadata.contract_name);
+    let entry = sources
+        .iter()
+        .find(|(_key, content)| content.contains(&contract_declaration))
+        .map(|(key, _content)| key.clone())
+        .ok_or_else(|| format!("no source declares contract {}", metadata.contract_name))?;
+    Ok((
+        entry,
+        SourceSet::StandardJson {
+            remappings: parse_remappings(&remapping_lines),
+            sources,
+        },
+    ))
+}
+
+/// Entry source of the contract and the set its imports are resolved against
+pub fn contract_sources(metadata: &FiestaMetadata) -> Result<(String, SourceSet), String> {
+    let dir = Path::new(&metadata.abs_path_to_dir);
+    let (entry, remappings, by_name) = match metadata.source_type.as_ref() {
+        Some(SourceType::EtherscanMetadata(source_metadata)) => {
+            return standard_json_sources(metadata, source_metadata)
+        }
+        Some(SourceType::SingleMain(_)) => (dir.join("main.sol"), Vec::new(), BTreeMap::new()),
+        Some(SourceType::Multiple(multiple_files)) => (
+            dir.join(metadata.entrypoint_file_name().map_err(|e| e.to_string())?),
+            Vec::new(),
+            multiple_files
+                .iter()
+                .map(|(name, _source)| {
+                    // imports are resolved by file name, whatever directory the file is in
+                    let file_name = Path::new(name)
+                        .file_name()
+                        .map_or(name.clone(), |file_name| {
+                            file_name.to_string_lossy().into_owned()
+                        });
+                    (file_name, dir.join(name))
+                })
+                .collect(),
+        ),
+        Some(SourceType::Project(project_source)) => (
+            dir.join(&project_source.entrypoint),
+            parse_remappings(&project_source.remapping_lines()),
+            BTreeMap::new(),
+        ),
+        None => return Err("sources were not collected".to_string()),
+    };
+    Ok((
+        entry.to_str().unwrap().to_string(),
+        SourceSet::Files {
+            remappings,
+            by_name,
+        },
+    ))
+}
+
+/// Shape of the import graph reachable from a contract's entrypoint
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportGraph {
+    /// Number of source files reachable from the entrypoint, the entrypoint included
+    pub files: usize,
+    /// Number of imports on the shortest path from the entrypoint to the furthest file,
+    /// 0 when the entrypoint imports nothing
+    pub depth: usize,
+    /// Number of distinct imports that could not be resolved to a source
+    pub unresolved: usize,
+}
+
+/// Walks the imports breadth first from the contract's entrypoint. Returns the shape of the
+/// graph and the contents of every reachable source.
+pub fn walk_imports(metadata: &FiestaMetadata) -> Result<(ImportGraph, Vec<String>), String> {
+    let (entry, sources) = contract_sources(metadata)?;
+    let mut graph = ImportGraph::default();
+    let mut contents = Vec::new();
+    let mut visited = HashSet::from([entry.clone()]);
+    let mut unresolved = HashSet::new();
+    let mut queue = VecDeque::from([(entry, 0)]);
+    while let Some((key, depth)) = queue.pop_front() {
+        graph.files += 1;
+        graph.depth = graph.depth.max(depth);
+        let Some(content) = sources.read(&key) else {
+            continue;
+        };
+        for captures in IMPORT_STATEMENT.captures_iter(&content) {
+            let import_path = &captures[1];
+            match sources.resolve(&key, import_path) {
+                Some(import_key) => {
+                    if visited.insert(import_key.clone()) {
+                        queue.push_back((import_key, depth + 1));
+                    }
+                }
+                None => {
+                    unresolved.insert(import_path.to_string());
+                }
+            }
+        }
+        contents.push(content);
+    }
+    graph.unresolved = unresolved.len();
+    Ok((graph, contents))
+}
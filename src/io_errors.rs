@@ -0,0 +1,87 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+/// Tracks the IO errors of the corpus walk per bucket directory (organized_contracts/XX). Once
+/// a bucket has `max_errors` errors in a row the rest of it is skipped, so a partially
+/// corrupted mirror costs a few lines per bad bucket instead of one per contract, or an abort.
+pub struct IoErrorBudget {
+    /// Errors in a row before a bucket is skipped, 0 to never skip
+    max_errors: usize,
+    /// Bucket the walk is in and its errors in a row
+    bucket: Option<OsString>,
+    consecutive: usize,
+    /// Buckets whose remaining contracts were skipped
+    skipped: Vec<OsString>,
+}
+
+impl IoErrorBudget {
+    pub fn new(max_errors: usize) -> Self {
+        Self {
+            max_errors,
+            bucket: None,
+            consecutive: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Bucket of a path under organized_contracts, None for organized_contracts itself
+    pub fn bucket_of<'a>(organized_contracts: &Path, path: &'a Path) -> Option<&'a OsStr> {
+        path.strip_prefix(organized_contracts).ok()?.iter().next()
+    }
+
+    fn enter(&mut self, bucket: Option<&OsStr>) {
+        if self.bucket.as_deref() != bucket {
+            self.bucket = bucket.map(OsStr::to_os_string);
+            self.consecutive = 0;
+        }
+    }
+
+    pub fn record_success(&mut self, bucket: Option<&OsStr>) {
+        self.enter(bucket);
+        self.consecutive = 0;
+    }
+
+    /// Logs the error, and the bucket being skipped once it runs out of budget
+    pub fn record_error(&mut self, bucket: Option<&OsStr>, error: &str) {
+        self.enter(bucket);
+        self.consecutive += 1;
+        eprintln!("Could not read the corpus: {}", error);
+        if let Some(bucket) = bucket {
+            if self.max_errors > 0 && self.consecutive == self.max_errors {
+                eprintln!(
+                    "Skipping the rest of organized_contracts/{} after {} IO errors in a row",
+                    bucket.to_string_lossy(),
+                    self.consecutive
+                );
+                self.skipped.push(bucket.to_os_string());
+            }
+        }
+    }
+
+    pub fn is_skipped(&self, bucket: Option<&OsStr>) -> bool {
+        bucket.is_some_and(|bucket| self.skipped.iter().any(|skipped| skipped == bucket))
+    }
+
+    /// Takes over the skipped buckets of the budget of a bucket walked on its own
+    pub fn absorb(&mut self, other: IoErrorBudget) {
+        self.skipped.extend(other.skipped);
+    }
+
+    /// Reports the skipped buckets at the end of the walk
+    pub fn print_summary(&self) {
+        if self.skipped.is_empty() {
+            return;
+        }
+        eprintln!(
+            "Skipped part of {} bucket directories of the corpus after IO errors: {}",
+            self.skipped.len(),
+            self.skipped
+                .iter()
+                .map(|bucket| bucket.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{ExitType, ResultsRow};
+use std::{fs, path::Path};
+
+/// Escapes the characters that are not allowed inside XML attributes and text nodes
+pub fn escape_xml(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // control characters (other than tab/newline/cr) are invalid in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builds a JUnit XML document with one test case per contract.
+/// Any non-success exit type is reported as a failure carrying the error/panic text, except
+/// for contracts with unresolved imports, whose failures are expected and reported as skipped.
+/// The contracts of the warmup (see `--warmup`) have no time, and are left out of the suite's.
+pub fn convert_rows_to_junit(rows: &[ResultsRow]) -> String {
+    let skipped = rows
+        .iter()
+        .filter(|row| !matches!(row.result, ExitType::Success) && row.has_unresolved_imports())
+        .count();
+    let failures = rows
+        .iter()
+        .filter(|row| !matches!(row.result, ExitType::Success))
+        .count()
+        - skipped;
+    let total_time: f64 = rows
+        .iter()
+        .filter(|row| row.warmup != Some(true))
+        .map(|row| row.time)
+        .sum();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"omniscan\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\">\n",
+        rows.len(),
+        failures,
+        skipped,
+        total_time
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"pyrometer\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\">\n",
+        rows.len(),
+        failures,
+        skipped,
+        total_time
+    ));
+
+    for row in rows {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\"",
+            escape_xml(&row.bytecode_hash),
+            escape_xml(&row.source_type),
+        ));
+        if row.warmup != Some(true) {
+            xml.push_str(&format!(" time=\"{:.3}\"", row.time));
+        }
+        match &row.result {
+            ExitType::Success => xml.push_str("/>\n"),
+            result if row.has_unresolved_imports() => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "      <skipped message=\"{} with {} unresolved imports\"/>\n",
+                    escape_xml(result.name()),
+                    row.unresolved_imports.unwrap_or(0)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            ExitType::PerformanceTimeout => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "      <failure type=\"PerformanceTimeout\" message=\"timed out after {:.3}s\"/>\n",
+                    row.time
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            ExitType::Error(message) => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "      <failure type=\"Error\" message=\"{}\"/>\n",
+                    escape_xml(message)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            ExitType::ThreadPanic(message) => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "      <failure type=\"ThreadPanic\" message=\"{}\"/>\n",
+                    escape_xml(message)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            ExitType::HarnessError(message) => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "      <error type=\"HarnessError\" message=\"{}\"/>\n",
+                    escape_xml(message)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            ExitType::Nondeterministic(difference) => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "      <failure type=\"Nondeterministic\" message=\"{}\"/>\n",
+                    escape_xml(difference)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            ExitType::NonInterpreted(stdout, stderr) => {
+                xml.push_str(">\n");
+                xml.push_str(
+                    "      <failure type=\"NonInterpreted\" message=\"could not interpret pyrometer output\"/>\n",
+                );
+                xml.push_str(&format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape_xml(stdout)
+                ));
+                xml.push_str(&format!(
+                    "      <system-err>{}</system-err>\n",
+                    escape_xml(stderr)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+        }
+    }
+
+    xml.push_str("  </testsuite>\n");
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+pub fn write_junit_report(path: &Path, rows: &[ResultsRow]) {
+    let xml = convert_rows_to_junit(rows);
+    fs::write(path, xml).unwrap();
+}
@@ -1,49 +1,145 @@
-use clap::{Parser, ValueHint};
+// the results schema is one json! literal, deeper than the default limit
+#![recursion_limit = "256"]
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use error::OmniscanError;
 use ethers::etherscan::contract::SourceCodeMetadata;
-use ethers::types::U256;
 use lazy_static::lazy_static;
+use project::{CorpusKind, Framework, ProjectSource};
 use regex::Regex;
+use report::{create_dir_or_exit, prepare_report_path, ReportPaths};
+use results::{ResultsRow, ResultsWriter};
 use serde::{Deserialize, Serialize};
+use status::RunStatus;
 use std::path::Path;
 use std::path::PathBuf;
 use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt,
-    fs::OpenOptions,
-    io::Write,
+    io::Read,
+    os::fd::AsRawFd,
     panic,
     process::Child,
-    sync::{mpsc, Arc},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
 };
 use std::{
     fs,
     process::{Command, Stdio},
 };
-use tokio::{
-    sync::{oneshot, Semaphore},
-    time::Instant,
-};
+use tokio::{sync::oneshot, time::Instant};
 use walkdir::WalkDir;
 
+mod agreement;
+mod alert;
+mod archive;
+mod artifacts;
+mod audit;
+mod backtrace;
+mod batch;
+mod boilerplate;
+mod cgroup;
+mod check;
+mod clusters;
+mod comparison;
+mod complexity;
+mod concurrency;
+mod coredump;
+mod correlation;
+mod cputime;
+mod crytic;
+mod determinism;
+mod diff;
+mod discovery;
+mod email;
+mod environment;
+mod error;
+mod export;
+mod fds;
+mod fetch;
+mod fingerprint;
+mod fixtures;
+mod flakes;
+mod flatten;
+mod grep;
+mod ignore;
+mod imports;
+mod io_errors;
+mod junit;
+mod manifest;
+mod mislabel;
+mod onchain;
+mod phases;
+mod preflight;
+mod profiles;
+mod progress;
+mod project;
+mod push;
+mod queue;
+mod remote;
+mod report;
+mod results;
+mod retry;
+mod rotation;
+mod rules;
+mod sampling;
+mod sandbox;
+mod sarif;
+mod schema;
+mod score;
+mod scratch;
+mod settings;
+mod solc;
+mod status;
+mod summary;
+mod sweep;
+mod table;
+mod tags;
+mod tail;
+mod target;
+mod triage;
+mod unknowns;
+mod upload;
+mod verify;
+mod workers;
+mod workspace;
+
 lazy_static! {
     static ref PANIC_REGEX: Regex = Regex::new(r"thread '.*?' panicked at (.+?)\n").unwrap();
     static ref DEBUG_PANIC_REGEX: Regex =
         Regex::new(r#"thread '.*?' panicked at .+?\nEncountered an error: (\w+)"#).unwrap();
-    static ref ERROR_REGEX: Regex = Regex::new(r"(?s)Error:.*?31m([a-zA-Z0-9` .]{5,})").unwrap();
-    static ref SUCCESS_REGEX: Regex =
-        Regex::new(r"DONE ANALYZING IN: \d+ms\. Writing to cli\.\.\.\n$").unwrap();
     static ref STACK_OVERFLOW_REGEX: Regex =
         Regex::new(r"thread '.*?' has overflowed its stack\n").unwrap();
 }
 
-const FIESTA_TOTAL_CONTRACTS: usize = 150_000;
+/// Version of the results/summary output schema, embedded in every output.
+/// Bumped whenever columns are added or change meaning, so consumers can tell the layouts apart.
+/// Files written before versioning was introduced are treated as version 1.
+pub const RESULTS_SCHEMA_VERSION: u32 = 3;
+
+/// Oldest results schema version read back. Later versions only added columns, which are read
+/// by name and left empty for older files
+pub const OLDEST_READABLE_SCHEMA_VERSION: u32 = 2;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Path to the smart-contract-fiesta root directory
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Path to the smart-contract-fiesta root directory, an archive of it (.tar, .tar.gz,
+    /// .tar.zst, .zip) or its URL in a bucket (s3://bucket/prefix, gs://bucket/prefix), or the
+    /// corpus directory of the `--corpus` kind
     #[clap(value_hint = ValueHint::FilePath, value_name = "PATH")]
-    pub path: String,
+    pub path: Option<String>,
+
+    /// Layout of the corpus at PATH
+    #[clap(long, value_enum, default_value_t = CorpusKind::Fiesta)]
+    pub corpus: CorpusKind,
 
     /// The number of contracts to run pyrometer on. Default is 5000
     /// If set to 0, all contracts will be analyzed
@@ -55,18 +151,1066 @@ struct Args {
     #[clap(long, short)]
     pub timeout: Option<f64>,
 
-    /// Where to save the results file, default is "./data/results_MM-DD_HH-MM.csv"
+    /// Abort the run once this many pyrometer processes in a row could not be started, as the
+    /// analyzer or the host is then broken. Single failures are recorded as HarnessError
+    /// results. If set to 0, the run is never aborted
+    #[clap(long, default_value_t = 10)]
+    pub max_spawn_failures: usize,
+
+    /// Start pyrometer again up to this many times when it could not be started for lack of
+    /// resources (EAGAIN, ENOMEM, out of file descriptors), which a loaded machine recovers
+    /// from. Contracts still failing are recorded as HarnessError marked transient
+    #[clap(long, default_value_t = 3)]
+    pub spawn_retries: u32,
+
+    /// Wait before the first retry of `--spawn-retries` (millisecs), doubled before each next
+    /// one and jittered by up to half of it
+    #[clap(long, default_value_t = 500)]
+    pub spawn_retry_backoff: u64,
+
+    /// How often each running pyrometer process is checked for completion (millisecs),
+    /// decimals supported. Also the resolution of the time column: raise it to spare CPU with
+    /// many jobs, lower it for precise timings of short contracts
+    #[clap(long, default_value_t = 2.0)]
+    pub poll_interval: f64,
+
+    /// Collect the core of every pyrometer process killed by a signal (SIGSEGV, SIGABRT...)
+    /// into the contract's artifacts directory, along with the pyrometer binary, so crashes
+    /// can be debugged. Lifts the core size limit of pyrometer, the cores are found with the
+    /// system's kernel.core_pattern
+    #[clap(
+        long,
+        requires = "artifacts",
+        conflicts_with_all = ["worker_pool", "batch_size"]
+    )]
+    pub core_dumps: bool,
+
+    /// Leave the first N dispatched contracts out of the timing statistics, while the page
+    /// cache and the CPU frequency settle. Their results are recorded, marked in the warmup
+    /// column
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    pub warmup: usize,
+
+    /// CPUs each pyrometer process may use, decimals supported, e.g. 1.0. Enforced with a
+    /// cgroup v2 cpu.max per contract, so one multithreaded analysis can't starve the others
+    /// and timings stay comparable. Needs a delegated cgroup with the cpu controller
+    #[clap(long, value_name = "CPUS")]
+    pub cpu_quota: Option<f64>,
+
+    /// Where to save the results file, default is "./data/results_MM-DD_HH-MM.csv".
+    /// Results are written as JSON lines instead of csv if the path ends in ".jsonl"
     #[clap(long, short)]
     pub output: Option<String>,
 
-    /// The number of concurrent proccesses to use for the analysis. Default is the number of cores
-    #[clap(long, short)]
-    pub jobs: Option<u8>,
+    /// The number of concurrent proccesses to use for the analysis. Default is the number of cores.
+    /// Lowered to what the file descriptor limit allows
+    #[clap(long, short, value_parser = clap::value_parser!(u32).range(1..=concurrency::MAX_JOBS))]
+    pub jobs: Option<u32>,
+
+    /// File holding the number of concurrent processes, created with the `--jobs` value if
+    /// missing. Editing it changes the number of processes during the run, e.g. to free the
+    /// machine for something else without stopping the run. SIGUSR1 and SIGUSR2 also double
+    /// and halve the number of processes
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub jobs_file: Option<String>,
+
+    /// Make the schedule of the run a function of SEED: the contracts are shuffled with it,
+    /// and the i-th contract runs in job slot i % jobs once the contract before it in that slot
+    /// finished. Two timing runs with the same seed and jobs then run each contract in the same
+    /// slot, after the same contracts
+    #[clap(
+        long,
+        value_name = "SEED",
+        conflicts_with_all = ["jobs_file", "worker_pool", "time_budget"]
+    )]
+    pub deterministic_schedule: Option<u64>,
 
     /// The number of contracts to initially skip over. Default is 0.
     /// This is intended for debugging purposes
     #[clap(long, short)]
     pub skip_contracts: Option<usize>,
+
+    /// Sample at most one contract per group of contracts with the same sources, up to
+    /// comments and whitespace, walking further into the corpus to still select `-n`
+    /// contracts. Makes small benchmark samples more diverse
+    #[clap(long)]
+    pub unique_sample: bool,
+
+    /// Analyze as many contracts as possible within this wall-clock budget (minutes) instead of
+    /// a fixed `-n`, decimals supported. The corpus is shuffled and contracts are dispatched
+    /// until the budget runs out, then the success rate of the sample is reported with its 95%
+    /// confidence interval. Contracts still running then are finished
+    #[clap(
+        long,
+        value_name = "MINUTES",
+        conflicts_with_all = ["num_contracts", "skip_contracts", "unique_sample", "replay", "queue"]
+    )]
+    pub time_budget: Option<f64>,
+
+    /// Seed of the shuffle of `--time-budget`, to draw the same sample again. Default is
+    /// random, the seed is printed at the start of the run
+    #[clap(long, requires = "time_budget")]
+    pub sample_seed: Option<u64>,
+
+    /// Dispatch the contracts holding the most value first, by their on-chain metadata in
+    /// `--onchain-data`. `-n` then selects the contracts of most value in the whole corpus
+    /// rather than the first ones
+    #[clap(
+        long,
+        value_enum,
+        requires = "onchain_data",
+        conflicts_with_all = ["time_budget", "deterministic_schedule", "replay", "skip_contracts", "unique_sample"]
+    )]
+    pub prioritize_by: Option<onchain::WeightBy>,
+
+    /// Results file enriched with `omniscan enrich` that `--prioritize-by` reads the on-chain
+    /// metadata of the contracts from
+    #[clap(long, value_hint = ValueHint::FilePath, requires = "prioritize_by")]
+    pub onchain_data: Option<String>,
+
+    /// IO errors in a row within one bucket directory of a smart-contract-fiesta corpus
+    /// (organized_contracts/XX) after which the rest of that directory is skipped, so a
+    /// partially corrupted copy of the corpus is still usable. If set to 0, nothing is skipped
+    #[clap(long, default_value_t = 5)]
+    pub max_io_errors: usize,
+
+    /// Directory where the scan of a smart-contract-fiesta corpus records each bucket
+    /// directory it completed, so a scan that was interrupted resumes from the last completed
+    /// buckets. Reused across runs for the buckets that did not change since
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub discovery_checkpoint: Option<String>,
+
+    /// Also write the results as a JUnit XML report to this path, one test case per contract
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub junit: Option<String>,
+
+    /// Also export the findings as a SARIF log to this path, keyed by contract source path
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub sarif: Option<String>,
+
+    /// Compare the results against an expectations results file and exit non-zero
+    /// if any expected contract's result differs
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub check: Option<String>,
+
+    /// Record the outcome of every contract in this flake database, to find the contracts
+    /// whose outcome flip-flops across runs of the same pyrometer binary
+    #[clap(long, value_hint = ValueHint::FilePath, value_name = "PATH", num_args = 0..=1,
+        default_missing_value = flakes::DEFAULT_FLAKES_DB)]
+    pub flakes_db: Option<String>,
+
+    /// Whether the `--check` mismatches and `--baseline` changes of contracts the flake
+    /// database knows to be flaky count like any other
+    #[clap(long, value_enum, default_value = "exclude", requires = "flakes_db")]
+    pub flaky_gating: flakes::FlakyGating,
+
+    /// Also write a JSON summary of the run (counts per result type) to this path
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub summary: Option<String>,
+
+    /// Weight of each result type in the score of the run, the mean weight of its contracts,
+    /// as comma separated ExitType=weight pairs. Result types left out weigh 0
+    #[clap(long, default_value = score::DEFAULT_SCORE_WEIGHTS)]
+    pub score_weights: score::ScoreWeights,
+
+    /// Tag of the run, e.g. the experiment it belongs to, recorded in its manifest and summary
+    /// so `omniscan runs` can filter runs by it. Can be repeated
+    #[clap(long)]
+    pub tag: Vec<String>,
+
+    /// Label of the run as KEY=VALUE, e.g. machine=bench01, recorded like `--tag` so
+    /// `omniscan runs` can also group runs by it. Can be repeated
+    #[clap(long, value_parser = parse_key_value)]
+    pub label: Vec<(String, String)>,
+
+    /// Rotate the results file every this many MiB: results are written to
+    /// <OUTPUT stem>.0001.<ext>, <OUTPUT stem>.0002.<ext>... listed in
+    /// <OUTPUT stem>.parts.json. The other subcommands given OUTPUT read all its parts
+    #[clap(long, value_name = "MIB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub rotate_results: Option<u64>,
+
+    /// Which results to write to the results file. `failures` leaves out the Success rows,
+    /// which shrinks the results of a full corpus run by about 90%; successes are still
+    /// counted in the summary and the table at the end of the run
+    #[clap(long, value_enum, default_value_t = results::RecordMode::All)]
+    pub record: results::RecordMode,
+
+    /// Directory to save the captured pyrometer stdout/stderr of every failing contract in,
+    /// as <ARTIFACTS>/<bytecode_hash>/{stdout,stderr}.txt. For a timeout, that is what
+    /// pyrometer wrote before it was killed
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub artifacts: Option<String>,
+
+    /// Persist the work queue to this journal file. If the file already exists the run resumes
+    /// from it, appending to the original results file and re-running contracts that were
+    /// still queued or in flight
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub queue: Option<String>,
+
+    /// Continue the run that wrote this results file (csv or jsonl): the contracts it has a
+    /// result for are skipped and the results of the others are appended to it. Pass the same
+    /// PATH, `-n` and `-s` as the interrupted run. Not for runs with `--record failures`
+    #[clap(
+        long,
+        value_name = "RESULTS",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["output", "queue", "replay", "time_budget", "distribute"]
+    )]
+    pub resume: Option<String>,
+
+    /// Serve a JSON status of the run (queue depth, completed counts, success rate, per-worker
+    /// state) on http://<STATUS_ADDR>/status, e.g. "127.0.0.1:8080", and a live dashboard of the
+    /// run on http://<STATUS_ADDR>/. POST on /pause and /resume pauses and resumes the run,
+    /// with the OMNISCAN_STATUS_TOKEN bearer token when it is set, else from local clients only
+    #[clap(long)]
+    pub status_addr: Option<String>,
+
+    /// Keep this file updated with the progress of the run as JSON (completed and queued
+    /// contracts, counts per result type, current and overall rates, ETA), replaced atomically
+    /// every `--progress-interval`, for orchestrators to watch the run by
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub progress_file: Option<String>,
+
+    /// Seconds between two updates of `--progress-file`, decimals supported
+    #[clap(long, default_value_t = 10.0, requires = "progress_file")]
+    pub progress_interval: f64,
+
+    /// Hand the contracts to `omniscan worker` processes through this shared queue instead of
+    /// analyzing them here, and record the results they push back: redis://[:PASSWORD@]HOST:PORT/NAME,
+    /// sqs://REGION/ACCOUNT/NAME or nats://[TOKEN@]HOST:PORT/NAME. The workers need the corpus at
+    /// their own PATH
+    #[clap(
+        long,
+        value_name = "QUEUE_URL",
+        conflicts_with_all = ["queue", "worker_pool", "batch_size", "time_budget", "deterministic_schedule"]
+    )]
+    pub distribute: Option<String>,
+
+    /// Contracts per batch pushed to the queue of `--distribute`
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 20,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "distribute"
+    )]
+    pub distribute_batch: u64,
+
+    /// Seconds without any result from the workers after which `--distribute` gives up on the
+    /// contracts still missing, which are then reported as lost
+    #[clap(long, default_value_t = 600, requires = "distribute")]
+    pub distribute_timeout: u64,
+
+    /// Email the summary of the run to this address when it finishes. Can be repeated. The SMTP
+    /// server is configured through the OMNISCAN_SMTP_* environment variables
+    #[clap(long)]
+    pub email_to: Vec<String>,
+
+    /// Upload the results file, manifest, summary.json and an archive of the failing contracts
+    /// (see `export-failures`) when the run finishes, to a bucket (s3://bucket/prefix,
+    /// gs://bucket/prefix) or an HTTP endpoint every file is PUT under
+    #[clap(long, value_name = "URL")]
+    pub upload: Option<String>,
+
+    /// Key of every uploaded file, relative to the `--upload` URL. {date}, {time}, {tag},
+    /// {pyrometer_rev} and {file} are replaced with the start of the run, its tags, the
+    /// pyrometer version and the file name
+    #[clap(long, default_value = upload::DEFAULT_UPLOAD_KEY, requires = "upload")]
+    pub upload_key: String,
+
+    /// POST the results to this collector as they come in, as batches of JSON lines, so a
+    /// central service can aggregate runs of many machines while they go. Every result is
+    /// pushed, whatever `--record`. OMNISCAN_PUSH_TOKEN is sent as bearer token when it is set
+    #[clap(long, value_name = "URL", value_hint = ValueHint::Url)]
+    pub push_results: Option<String>,
+
+    /// Results per batch POSTed to `--push-results`
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "push_results"
+    )]
+    pub push_batch: u64,
+
+    /// Seconds after which the results of an incomplete batch are pushed anyway, decimals
+    /// supported
+    #[clap(long, default_value_t = 5.0, requires = "push_results")]
+    pub push_interval: f64,
+
+    /// Directory keeping the batches the collector of `--push-results` did not take, pushed
+    /// again before the next batch and by the next run with the same spool. Default is
+    /// <OUTPUT>.spool
+    #[clap(long, value_hint = ValueHint::DirPath, requires = "push_results")]
+    pub push_spool: Option<String>,
+
+    /// Results file of a previous run to compare against when the run finishes, listing
+    /// regressions and fixes (also included in the email)
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub baseline: Option<String>,
+
+    /// Print the summary table at the end of the run without colors. Colors are also left out
+    /// when the NO_COLOR environment variable is set or the output is not a terminal
+    #[clap(long)]
+    pub no_color: bool,
+
+    /// Warn loudly once the success rate of the latest `--alert-window` results falls under
+    /// this percentage of the `--baseline` success rate, or under this success rate (percent)
+    /// without a baseline. An early tripwire for broken pyrometer builds
+    #[clap(long)]
+    pub alert_threshold: Option<f64>,
+
+    /// Number of latest results the success rate alert is computed over, no alert fires
+    /// before that many results are in
+    #[clap(long, default_value_t = 200, requires = "alert_threshold")]
+    pub alert_window: usize,
+
+    /// Stop dispatching contracts once the success rate alert fired, the results so far are
+    /// kept and reported
+    #[clap(long, requires = "alert_threshold")]
+    pub alert_abort: bool,
+
+    /// Also post the success rate alert to this URL, as the {"text": ...} JSON payload of
+    /// Slack and Mattermost incoming webhooks
+    #[clap(long, requires = "alert_threshold")]
+    pub alert_webhook: Option<String>,
+
+    /// Also compile every contract with the solc version from its metadata (installed with
+    /// `svm install`) in parallel with the analysis, recording the outcome in the
+    /// solc_compiles column
+    #[clap(long)]
+    pub solc_check: bool,
+
+    /// Pass the optimizer settings and EVM version of every contract's metadata on to solc
+    /// (see `--solc-check`) and to pyrometer (see `--analyzer-optimizer-runs-flag` and
+    /// `--analyzer-evm-version-flag`). Settings that could not be passed on are recorded in
+    /// the unhonored_settings column
+    #[clap(long)]
+    pub compiler_settings: bool,
+
+    /// pyrometer flag taking the optimizer runs of contracts compiled with the optimizer, e.g.
+    /// --optimizer-runs
+    #[clap(
+        long,
+        value_name = "FLAG",
+        requires = "compiler_settings",
+        allow_hyphen_values = true
+    )]
+    pub analyzer_optimizer_runs_flag: Option<String>,
+
+    /// pyrometer flag taking the EVM version of contracts compiled for another EVM version
+    /// than their compiler's default, e.g. --evm-version
+    #[clap(
+        long,
+        value_name = "FLAG",
+        requires = "compiler_settings",
+        allow_hyphen_values = true
+    )]
+    pub analyzer_evm_version_flag: Option<String>,
+
+    /// Pass the ContractName of every contract's metadata to pyrometer (see
+    /// `--analyzer-contract-flag`), so that the contract the metadata describes is analyzed
+    /// rather than whatever else its compilation unit declares. Whether it was is recorded in
+    /// the target_contract_analyzed column
+    #[clap(long)]
+    pub target_contract_from_metadata: bool,
+
+    /// pyrometer flag selecting the contract to analyze within the compilation unit
+    #[clap(
+        long,
+        value_name = "FLAG",
+        default_value = "--contract",
+        requires = "target_contract_from_metadata",
+        allow_hyphen_values = true
+    )]
+    pub analyzer_contract_flag: String,
+
+    /// Before the analysis, check that the exact compiler version of every contract's metadata
+    /// is a solc release svm can install (falling back to the svm cache when the release list
+    /// can't be fetched), recording it in the compiler_available column
+    #[clap(long)]
+    pub check_compiler: bool,
+
+    /// Analyze every contract twice and compare the results and the outputs (without timings
+    /// and colors). Contracts the analyzer does not handle the same way both times are
+    /// recorded as Nondeterministic
+    #[clap(long)]
+    pub determinism_check: bool,
+
+    /// Resolve each contract's compilation with crytic-compile first (imports, remappings,
+    /// frameworks) and analyze the resolved sources as a standard-json input. Contracts
+    /// crytic-compile cannot handle are analyzed from their original sources
+    #[clap(long)]
+    pub crytic_compile: bool,
+
+    /// Flatten multi-file contracts into a single file before the analysis, for analyzers that
+    /// only accept one file. Contracts that fail to flatten are analyzed from their original
+    /// sources
+    #[clap(long, value_enum, conflicts_with = "crytic_compile")]
+    pub flatten: Option<flatten::FlattenMode>,
+
+    /// Copy each contract's sources into this directory before analyzing it, e.g. a tmpfs like
+    /// "/dev/shm/omniscan", to keep the corpus disk out of the timings. Copies are removed once
+    /// the contract is analyzed
+    #[clap(long)]
+    pub workspace: Option<String>,
+
+    /// Move the files the analyzer of each contract writes in its working directory or TMPDIR
+    /// (e.g. the DOT graphs of pyrometer's `--dot` flags) to
+    /// <ARTIFACTS>/<bytecode_hash>/outputs/. Their sizes are listed in
+    /// <ARTIFACTS>/outputs.jsonl
+    #[clap(long, requires = "artifacts")]
+    pub collect_outputs: bool,
+
+    /// HOME of the analyzers, with the XDG base directories under it: `cold` gives each
+    /// contract a fresh one, `warm` shares one across the run, `inherit` keeps the user's.
+    /// Each analyzer always runs in a directory of its own, also set as its TMPDIR, removed
+    /// once the contract is analyzed
+    #[clap(long, value_enum, default_value = "cold")]
+    pub analyzer_home: scratch::AnalyzerHome,
+
+    /// Analyze the contracts on long-lived pyrometer processes started with `--worker-args`,
+    /// handing them one contract at a time as a JSON line on their stdin, instead of starting
+    /// a process per contract. For analyzers with a server mode, where process startup
+    /// dominates the time of small contracts. Workers share their HOME as with
+    /// `--analyzer-home warm`
+    #[clap(long, conflicts_with_all = ["cpu_quota", "collect_outputs"])]
+    pub worker_pool: bool,
+
+    /// Flags pyrometer is started with as a worker, split like a shell would
+    #[clap(
+        long,
+        default_value = "--server",
+        allow_hyphen_values = true,
+        requires = "worker_pool"
+    )]
+    pub worker_args: String,
+
+    /// Contracts a worker analyzes before it is replaced by a fresh process, so state or
+    /// memory piling up in a worker does not skew later contracts
+    #[clap(
+        long,
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "worker_pool"
+    )]
+    pub worker_recycle: u64,
+
+    /// Analyze up to N small contracts with the same flags in one pyrometer invocation, for
+    /// analyzers that accept several input paths. Results are attributed back to each contract
+    /// by the lines of the output naming its file
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(2..),
+        conflicts_with_all = [
+            "worker_pool",
+            "determinism_check",
+            "collect_outputs",
+            "flatten",
+            "crytic_compile",
+            "workspace",
+            "cpu_quota",
+            "target_contract_from_metadata",
+        ]
+    )]
+    pub batch_size: Option<u64>,
+
+    /// Largest analyzed file (bytes) of a contract batched with others, see `--batch-size`
+    #[clap(long, default_value_t = 4096, requires = "batch_size")]
+    pub batch_max_bytes: u64,
+
+    /// Directory caching the objects fetched from a corpus in a bucket. Default is
+    /// "$XDG_CACHE_HOME/omniscan/<bucket>/<prefix>"
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub remote_cache: Option<String>,
+
+    /// Fetch the verified sources of the addresses in this file from the block explorer into the
+    /// corpus at PATH (created if needed, see `fetch-bulk`) and analyze them, recording the addresses
+    /// of each contract in the results
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub addresses: Option<String>,
+
+    #[command(flatten)]
+    pub explorer_args: ExplorerArgs,
+
+    /// Analyze exactly the contracts listed in this manifest, in its order. Every run writes
+    /// the manifest of its contracts next to its results, as <OUTPUT>.manifest.json. PATH
+    /// defaults to the corpus of the manifest
+    #[clap(
+        long,
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["num_contracts", "skip_contracts", "addresses"]
+    )]
+    pub replay: Option<String>,
+
+    /// Flags passed to every pyrometer invocation instead of the default `--debug`, e.g.
+    /// "--debug --max-width 120". Split like a shell would. The flags can also be given after a
+    /// trailing `--`
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        conflicts_with = "trailing_analyzer_args"
+    )]
+    pub analyzer_args: Option<String>,
+
+    /// Flags passed to every pyrometer invocation, see `--analyzer-args`
+    #[clap(last = true)]
+    pub trailing_analyzer_args: Vec<String>,
+
+    /// Environment variable set on every pyrometer invocation, as KEY=VALUE, e.g.
+    /// RUST_BACKTRACE=full. Can be repeated
+    #[clap(long, value_parser = parse_key_value)]
+    pub analyzer_env: Vec<(String, String)>,
+
+    /// Let pyrometer use the network. By default every pyrometer process runs in a network
+    /// namespace of its own without network access, so fetching compilers or imports can
+    /// neither skew timings nor leak anything from untrusted sources
+    #[clap(long)]
+    pub allow_network: bool,
+
+    /// How pyrometer's success and error messages are recognized, which changes between
+    /// pyrometer versions: 0.1 or 0.2. Default is the profile of the version `pyrometer
+    /// --version` reports; an unknown version gets the baseline 0.1 profile and a warning
+    #[clap(long, value_name = "PROFILE")]
+    pub classification_profile: Option<String>,
+
+    /// Rules classifying the outputs the classification profile does not recognize, see
+    /// `omniscan classify-unknowns`. Default is omniscan.rules.json in the current directory,
+    /// when it exists
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub rules_file: Option<String>,
+
+    /// Leave out the contracts that add no distinct code to the corpus: EIP-1167 minimal
+    /// proxies, and contracts with the same sources as one found earlier up to comments and
+    /// whitespace. Applies to the contracts selected with `-n` and `--skip-contracts`
+    #[clap(long)]
+    pub skip_boilerplate: bool,
+
+    /// Repair the contracts whose sources are a mislabeled .vy file in place: a .vy file
+    /// holding Solidity is renamed to .sol and analyzed, and the metadata.json of Vyper sources
+    /// gets the Vyper CompilerVersion. Without it they are left out and listed in the summary
+    /// with the reason
+    #[clap(long)]
+    pub fix_corpus: bool,
+
+    /// Only analyze the contracts with a line of their sources matching this regex, e.g.
+    /// "assembly" or "create2\(". The corpus is walked until `-n` matching contracts are
+    /// selected. See `omniscan grep` to look before running
+    #[clap(long, value_name = "REGEX")]
+    pub source_contains: Option<String>,
+
+    /// Ignore file listing contracts to leave out of the run, see `omniscan ignore`. Default is
+    /// omniscan.ignore.json in the current directory, when it exists. The number of contracts
+    /// excluded is printed and recorded in the summary
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub ignore_file: Option<String>,
+}
+
+/// A request rate of at least one per second
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rate) if rate >= 1.0 => Ok(rate),
+        Ok(_) => Err("must be at least 1".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parses a KEY=VALUE pair of `--analyzer-env` or `--label`
+fn parse_key_value(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{}`", pair)),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Print the JSON Schema of the JSONL results or the summary output
+    Schema {
+        #[arg(value_enum)]
+        output: schema::SchemaKind,
+    },
+    /// Generate breakdowns and reports from an existing results file without re-running analysis
+    Report {
+        /// Path to a results file (csv or jsonl) written by a previous run
+        #[clap(value_hint = ValueHint::FilePath)]
+        results: String,
+
+        /// How to group the printed breakdown
+        #[clap(long, value_enum, default_value = "exit-type")]
+        group_by: report::GroupBy,
+
+        /// Also print the failure rate by compiler version, optimization setting,
+        /// source layout and source size decile
+        #[clap(long)]
+        failure_correlation: bool,
+
+        /// Also print the Error, ThreadPanic and NonInterpreted failures grouped into clusters
+        /// of similar messages, largest first, listing this many clusters
+        #[clap(long, value_name = "NUM", num_args = 0..=1, default_missing_value = "20")]
+        clusters: Option<usize>,
+
+        /// Share (0 to 1) of the words of the shorter of two messages the other one needs to
+        /// contain, once line numbers, hashes, paths and quoted names are stripped, for the
+        /// messages to be clustered together
+        #[clap(long, default_value_t = 0.6, requires = "clusters")]
+        cluster_similarity: f64,
+
+        /// Artifacts directory of the run (see `--artifacts`), NonInterpreted failures are
+        /// clustered by the last line of their captured stderr
+        #[clap(long, value_hint = ValueHint::DirPath, requires = "clusters")]
+        artifacts: Option<String>,
+
+        /// Rewrite the results file sorted this way and in canonical form (the column order and
+        /// number formats of this omniscan), so the results of two runs can be compared with
+        /// plain `diff`
+        #[clap(long, value_enum)]
+        sort: Option<report::SortBy>,
+
+        /// Leave out of the breakdowns and reports the contracts whose declared compiler can't
+        /// be installed (compiler_available is false, see `--check-compiler`), so a bogus
+        /// metadata is not blamed on the analyzer
+        #[clap(long)]
+        exclude_unavailable_compilers: bool,
+
+        /// Write a JUnit XML report to this path
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        junit: Option<String>,
+
+        /// Write a SARIF log to this path
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        sarif: Option<String>,
+
+        /// Write a JSON summary to this path
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        summary: Option<String>,
+
+        /// Weight of each result type in the score, see the option of runs
+        #[clap(long, default_value = score::DEFAULT_SCORE_WEIGHTS)]
+        score_weights: score::ScoreWeights,
+
+        /// Path to the smart-contract-fiesta root the results were produced from, used to
+        /// resolve source paths in the SARIF log. Default is the current directory
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        corpus: Option<String>,
+
+        /// Flake database (see `--flakes-db` of runs) to list the known flaky contracts of the
+        /// results from
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        flakes_db: Option<String>,
+
+        /// Also print the share of the on-chain balance or transaction count of the contracts
+        /// that each result type holds, for results enriched with `omniscan enrich`
+        #[clap(long, value_enum)]
+        weight_by: Option<onchain::WeightBy>,
+    },
+    /// Compare results of several analyzers on the same corpus, bucketing contracts by which
+    /// analyzers failed on them
+    Agreement {
+        /// Results of each analyzer as `name=path/to/results`, e.g. `pyrometer=a.csv slither=b.csv`
+        #[clap(required = true, num_args = 2.., value_name = "NAME=RESULTS")]
+        analyzers: Vec<String>,
+
+        /// The number of example bytecode hashes to print per bucket
+        #[clap(long, default_value_t = 5)]
+        examples: usize,
+    },
+    /// List the runs of summary files (see `--summary`) with the given tags and labels, or
+    /// group them by a label
+    Runs {
+        /// Summary files of the runs
+        #[clap(required = true, value_hint = ValueHint::FilePath)]
+        summaries: Vec<String>,
+
+        /// Only list runs with this tag. Can be repeated
+        #[clap(long)]
+        tag: Vec<String>,
+
+        /// Only list runs with this label, as KEY=VALUE. Can be repeated
+        #[clap(long, value_parser = parse_key_value)]
+        label: Vec<(String, String)>,
+
+        /// Print one line per value of this label instead, with the number of runs and their
+        /// mean success rate and score
+        #[clap(long, value_name = "KEY")]
+        group_by_label: Option<String>,
+    },
+    /// Copy the sources, metadata and captured output of every failing contract into a
+    /// standalone repro corpus
+    ExportFailures {
+        /// Path to a results file (csv or jsonl) written by a previous run
+        #[clap(value_hint = ValueHint::FilePath)]
+        results: String,
+
+        /// Path to the smart-contract-fiesta root the results were produced from
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        corpus: String,
+
+        /// Directory to write the repro corpus to
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        out: String,
+
+        /// The --artifacts directory of the run, to include the captured pyrometer output
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        artifacts: Option<String>,
+    },
+    /// Compare two results files, listing regressions, fixes and changed failures
+    Diff {
+        /// Results file (csv or jsonl) of the baseline run
+        #[clap(value_hint = ValueHint::FilePath)]
+        old: String,
+
+        /// Results file (csv or jsonl) of the new run
+        #[clap(value_hint = ValueHint::FilePath)]
+        new: String,
+
+        /// The maximum number of contracts to list per category
+        #[clap(long, default_value_t = 50)]
+        limit: usize,
+
+        /// Count failures with different messages as changed failures even when the messages
+        /// only differ in line and column numbers or hashes
+        #[clap(long)]
+        exact_messages: bool,
+
+        /// Write the contracts that flipped from failure to success to this directory,
+        /// in pyrometer's test_data fixture layout
+        #[clap(long, value_hint = ValueHint::DirPath, requires = "corpus")]
+        emit_fixtures: Option<String>,
+
+        /// Path to the smart-contract-fiesta root, needed to copy the fixture sources. Also
+        /// where the repro commands of `--html` point to, default is the corpus recorded in the
+        /// new run's manifest, else the current directory
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        corpus: Option<String>,
+
+        /// Also write the comparison as an HTML page to this path, where every contract that
+        /// changed unfolds into its repro command and its captured output in the new run
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        html: Option<String>,
+
+        /// Artifacts directory of the new run (see `--artifacts`), for the captured output
+        #[clap(long, value_hint = ValueHint::DirPath, requires = "html")]
+        artifacts: Option<String>,
+
+        /// Flags of the repro commands instead of those the new run's manifest records, or
+        /// pyrometer's defaults for runs without one. Split like a shell would
+        #[clap(long, allow_hyphen_values = true, requires = "html")]
+        analyzer_args: Option<String>,
+
+        /// Flake database (see `--flakes-db` of a run) whose known flaky contracts are listed
+        /// apart from the regressions, fixes and changed failures
+        #[clap(long, value_hint = ValueHint::FilePath, value_name = "PATH", num_args = 0..=1,
+            default_missing_value = flakes::DEFAULT_FLAKES_DB)]
+        flakes_db: Option<String>,
+
+        /// Whether the changes of known flaky contracts count like any other
+        #[clap(long, value_enum, default_value = "exclude", requires = "flakes_db")]
+        flaky_gating: flakes::FlakyGating,
+
+        /// Weight of each result type in the score, see the option of runs
+        #[clap(long, default_value = score::DEFAULT_SCORE_WEIGHTS)]
+        score_weights: score::ScoreWeights,
+    },
+    /// Follow a results file written by a run in progress, e.g. on a remote box, printing the
+    /// rolling success rate, the throughput and the most recent failures as results come in
+    Tail {
+        /// Path to the results file (csv or jsonl) of the run
+        #[clap(value_hint = ValueHint::FilePath)]
+        results: String,
+
+        /// Number of latest results the rolling success rate and throughput are computed over
+        #[clap(long, default_value_t = 200)]
+        window: usize,
+
+        /// The number of most recent failures to list
+        #[clap(long, default_value_t = 10)]
+        failures: usize,
+
+        /// How often the file is checked for new results (secs)
+        #[clap(long, default_value_t = 2.0)]
+        interval: f64,
+
+        /// Print the statistics of the file as it is and exit, instead of following it
+        #[clap(long)]
+        once: bool,
+    },
+    /// Go through the failures of a results file one by one, classifying each as a corpus
+    /// issue, an analyzer bug or a known failure. Decisions are saved to <RESULTS
+    /// stem>.triage.json, which `report` and runs with `--baseline` read
+    Triage {
+        /// Path to the results file (csv or jsonl)
+        #[clap(value_hint = ValueHint::FilePath)]
+        results: String,
+
+        /// Path to the smart-contract-fiesta root the results were produced from, for the
+        /// source previews and re-runs. Default is the current directory
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        corpus: Option<String>,
+
+        /// Artifacts directory of the run (see `--artifacts`), for the captured output
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        artifacts: Option<String>,
+
+        /// Triage file to read and write instead of <RESULTS stem>.triage.json
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        triage: Option<String>,
+
+        /// Results file of an earlier run: its triage decisions carry over to the contracts
+        /// that still fail the same way
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        baseline: Option<String>,
+
+        /// Also go through the failures that are already triaged
+        #[clap(long)]
+        all: bool,
+
+        /// Timeout of a re-run (secs)
+        #[clap(long, default_value_t = 2.0)]
+        timeout: f64,
+
+        /// Flags passed to pyrometer on a re-run instead of those the run's manifest records,
+        /// or the default `--debug`. Split like a shell would
+        #[clap(long, allow_hyphen_values = true)]
+        analyzer_args: Option<String>,
+
+        /// How pyrometer's output is classified on a re-run, see the option of the same name
+        /// of a run
+        #[clap(long, value_name = "PROFILE")]
+        classification_profile: Option<String>,
+
+        /// Let pyrometer use the network on a re-run, see the option of the same name of a run
+        #[clap(long)]
+        allow_network: bool,
+
+        /// Rules classifying the outputs of a re-run the classification profile does not
+        /// recognize. Default is the rules file the run recorded in its manifest, else
+        /// omniscan.rules.json in the current directory, when it exists
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        rules_file: Option<String>,
+    },
+    /// Go through the NonInterpreted results of a results file, grouped into clusters of
+    /// similar outputs, and write a rule (pattern and result) classifying each cluster. Rules
+    /// are appended to the rules file, which runs read, and applied to the results file at once
+    ClassifyUnknowns {
+        /// Path to the results file (csv or jsonl), rewritten with the new classifications
+        #[clap(value_hint = ValueHint::FilePath)]
+        results: String,
+
+        /// Artifacts directory of the run (see `--artifacts`), where the outputs of the
+        /// NonInterpreted results are read from
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        artifacts: String,
+
+        /// Rules file to append the rules to
+        #[clap(long, default_value = rules::DEFAULT_RULES_FILE, value_hint = ValueHint::FilePath)]
+        rules_file: String,
+
+        /// Share (0 to 1) of the words two outputs need in common to be clustered together, see
+        /// `report --cluster-similarity`
+        #[clap(long, default_value_t = 0.6)]
+        cluster_similarity: f64,
+    },
+    /// Download the verified sources of many addresses from Etherscan or Blockscout into a
+    /// corpus in the smart-contract-fiesta layout. Rerunning with the same output resumes the
+    /// download
+    FetchBulk {
+        /// File with one address per line, or a csv with the addresses in its first column
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        addresses: String,
+
+        /// Corpus root to write the contracts to, created if needed
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        out: String,
+
+        #[command(flatten)]
+        explorer_args: ExplorerArgs,
+    },
+    /// Add, remove or list the contracts left out of runs, with the reason and date each was
+    /// added
+    Ignore {
+        #[command(subcommand)]
+        action: ignore::IgnoreAction,
+
+        /// Ignore file to maintain
+        #[clap(long, default_value = ignore::DEFAULT_IGNORE_FILE, value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+    /// Fill the deployment block and date and the balance of the contracts of a results file
+    /// from an RPC node, and their transaction count from an indexer, for the contracts with
+    /// known addresses. The results file is rewritten in place, rerunning retries the contracts
+    /// that failed
+    Enrich {
+        /// Path to a results file (csv or jsonl) written by a previous run
+        #[clap(value_hint = ValueHint::FilePath)]
+        results: String,
+
+        /// JSON-RPC endpoint of a node of the chain of the contracts. Finding the deployment
+        /// block needs one that keeps the state of old blocks (an archive node)
+        #[clap(long, value_hint = ValueHint::Url)]
+        rpc_url: String,
+
+        /// Addresses of the contracts not fetched with `--addresses`, one
+        /// `address,bytecode_hash` per line
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        address_index: Option<String>,
+
+        /// Root URL of a Blockscout instance of the same chain, e.g. https://eth.blockscout.com,
+        /// counting the transactions to and from the addresses into the tx_count column. A
+        /// node can't tell how often a contract is called, so tx_count stays empty without it
+        #[clap(long, value_hint = ValueHint::Url)]
+        indexer_url: Option<String>,
+
+        /// Only look up the contracts that did not succeed
+        #[clap(long)]
+        failures_only: bool,
+    },
+    /// Check every contract of a smart-contract-fiesta corpus: its metadata.json parses, its
+    /// bytecode hash is well-formed and matches its directory, and its sources are present and
+    /// readable. Exits with status 1 when any is corrupted, e.g. after copying the corpus
+    /// between machines
+    VerifyCorpus {
+        /// Path to the smart-contract-fiesta root directory
+        #[clap(value_hint = ValueHint::DirPath)]
+        path: String,
+
+        /// Write every problem found to this file as JSON
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        report: Option<String>,
+    },
+    /// Analyze batches of contracts taken from the shared queue of a `--distribute` run and
+    /// push their results back, until the queue stays empty. Needs no access from the
+    /// coordinator, e.g. as the pods of a Kubernetes Job
+    Worker {
+        /// The queue URL the coordinator was started with, see `--distribute`
+        queue: String,
+
+        /// Path to the corpus on this machine
+        #[clap(value_hint = ValueHint::DirPath)]
+        path: String,
+
+        /// Seconds without any batch after which the worker exits
+        #[clap(long, default_value_t = 60)]
+        idle_exit: u64,
+
+        /// Flags every batch is analyzed with, as for a run, e.g. `-- -j 4 --timeout 60`
+        #[clap(last = true)]
+        run_args: Vec<String>,
+    },
+    /// Search the sources of every eligible contract of the corpus for a regex, e.g.
+    /// `"unchecked \{"`, optionally only the contracts of a results file with given result
+    /// types, printing how many of each result type match
+    Grep {
+        /// Regex searched for in each line of the sources
+        pattern: String,
+
+        /// Path to the corpus
+        #[clap(value_hint = ValueHint::DirPath)]
+        path: String,
+
+        /// Layout of the corpus at PATH
+        #[clap(long, value_enum, default_value_t = CorpusKind::Fiesta)]
+        corpus: CorpusKind,
+
+        /// Only search the contracts of this results file (csv or jsonl), joining their result
+        /// types to the matches
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        results: Option<String>,
+
+        /// Only search the contracts of `--results` with this result type, can be repeated
+        #[clap(long = "result", requires = "results", value_parser = schema::EXIT_TYPE_NAMES)]
+        result_types: Vec<String>,
+
+        /// Match case-insensitively
+        #[clap(short, long)]
+        ignore_case: bool,
+
+        /// Take the pattern as a literal string instead of a regex
+        #[clap(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Print the contracts with a match instead of the matching lines
+        #[clap(short = 'l', long)]
+        files_with_matches: bool,
+    },
+}
+
+/// How to reach the block explorer, shared by `fetch-bulk` and `--addresses`
+#[derive(clap::Args, Debug)]
+struct ExplorerArgs {
+    /// Chain the addresses are deployed on
+    #[clap(long, value_enum, default_value_t = fetch::Chain::Ethereum)]
+    pub chain: fetch::Chain,
+
+    /// Block explorer to fetch the verified sources from. Default is the chain's
+    /// Etherscan-family explorer, or Blockscout for chains without one
+    #[clap(long, value_enum)]
+    pub explorer: Option<fetch::Explorer>,
+
+    /// API key of the explorer. Can be repeated to rotate between several keys. Default is the
+    /// comma separated keys of the ETHERSCAN_API_KEY (or BLOCKSCOUT_API_KEY) environment
+    /// variable. Blockscout can be used without a key
+    #[clap(long)]
+    pub api_key: Vec<String>,
+
+    /// Requests per second allowed for each API key, at least 1
+    #[clap(long, default_value_t = 5.0, value_parser = parse_rate)]
+    pub rate: f64,
+
+    /// Etherscan API endpoint or root URL of the Blockscout instance, e.g.
+    /// "https://eth.blockscout.com". Default is the explorer's instance for `--chain`
+    #[clap(long)]
+    pub api_url: Option<String>,
+}
+
+impl ExplorerArgs {
+    /// Settings of a fetch of the addresses in `addresses` into the corpus at `out`, exits
+    /// when no explorer instance is known for the chain
+    fn fetch_config(&self, addresses: PathBuf, out: PathBuf) -> fetch::FetchBulkConfig {
+        let explorer = self
+            .explorer
+            .unwrap_or_else(|| self.chain.default_explorer());
+        let api_url = self
+            .api_url
+            .clone()
+            .unwrap_or_else(|| match self.chain.api_url(explorer) {
+                Some(api_url) => api_url.to_string(),
+                None => {
+                    eprintln!(
+                        "No {} instance is known for {}, pass its URL with --api-url",
+                        explorer,
+                        self.chain.name()
+                    );
+                    std::process::exit(1);
+                }
+            });
+        let api_key_var = match explorer {
+            fetch::Explorer::Etherscan => "ETHERSCAN_API_KEY",
+            fetch::Explorer::Blockscout => "BLOCKSCOUT_API_KEY",
+        };
+        let api_keys = if self.api_key.is_empty() {
+            std::env::var(api_key_var)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect()
+        } else {
+            self.api_key.clone()
+        };
+        fetch::FetchBulkConfig {
+            addresses,
+            out,
+            chain: self.chain,
+            explorer,
+            api_keys,
+            api_url,
+            rate: self.rate,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -77,6 +1221,8 @@ pub enum SourceType {
     Multiple(Vec<(String, String)>),
     /// File contents string from contract.json
     EtherscanMetadata(SourceCodeMetadata),
+    /// One source file of a framework project (foundry, ...)
+    Project(ProjectSource),
 }
 
 impl fmt::Display for SourceType {
@@ -85,6 +1231,7 @@ impl fmt::Display for SourceType {
             SourceType::SingleMain(_) => write!(f, "SingleFile"),
             SourceType::Multiple(_) => write!(f, "MultipleFiles"),
             SourceType::EtherscanMetadata(_) => write!(f, "JSON"),
+            SourceType::Project(project_source) => write!(f, "{}", project_source.framework),
         }
     }
 }
@@ -101,19 +1248,153 @@ pub struct FiestaMetadata {
     optimization_used: bool,
     #[serde(rename = "BytecodeHash")]
     bytecode_hash: String,
+    /// Target EVM version of the compilation, e.g. "london". Absent for smart-contract-fiesta,
+    /// "Default" when the compiler's default was used
+    #[serde(
+        rename = "EVMVersion",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    evm_version: Option<String>,
+    /// Chain the contract was fetched from with `fetch-bulk`, absent for smart-contract-fiesta,
+    /// which is all Ethereum mainnet
+    #[serde(rename = "Chain", default, skip_serializing_if = "Option::is_none")]
+    chain: Option<String>,
     #[serde(skip_serializing, skip_deserializing)]
     abs_path_to_dir: String,
+    /// Path to the contract's directory, relative to the corpus root
+    #[serde(skip_serializing, skip_deserializing)]
+    rel_path_to_dir: String,
     #[serde(skip_serializing, skip_deserializing)]
     source_type: Option<SourceType>,
+    /// Import graph of the collected sources, None when it could not be built
+    #[serde(skip_serializing, skip_deserializing)]
+    import_graph: Option<imports::ImportGraph>,
+    /// Size of the sources reachable from the entrypoint, None when they could not be read
+    #[serde(skip_serializing, skip_deserializing)]
+    complexity: Option<complexity::SourceComplexity>,
+    /// Addresses deployed with this bytecode, when the contracts were fetched with `--addresses`
+    #[serde(skip_serializing, skip_deserializing)]
+    addresses: Vec<String>,
+    /// Why omniscan could not prepare the contract for the analysis. It is then recorded as a
+    /// HarnessError without being analyzed
+    #[serde(skip_serializing, skip_deserializing)]
+    harness_error: Option<String>,
+    /// Whether the compiler of the metadata can be installed, see `--check-compiler`
+    #[serde(skip_serializing, skip_deserializing)]
+    compiler_available: Option<bool>,
+    /// Hash of the sources reachable from the entrypoint, see `fingerprint::sources_hash`
+    #[serde(skip_serializing, skip_deserializing)]
+    source_hash: Option<String>,
 }
 
 impl FiestaMetadata {
+    /// Metadata of a contract Token compiled by solc 0.8.17 with 200 optimizer runs, for tests
+    #[cfg(test)]
+    pub fn for_test(bytecode_hash: &str) -> Self {
+        serde_json::from_value(serde_json::json!({
+            "ContractName": "Token",
+            "CompilerVersion": "v0.8.17+commit.8df45f5f",
+            "Runs": 200,
+            "OptimizationUsed": true,
+            "BytecodeHash": bytecode_hash,
+        }))
+        .unwrap()
+    }
+
+    /// The EVM version the contract was compiled for, None when it was the compiler's default
+    pub fn target_evm_version(&self) -> Option<&str> {
+        self.evm_version
+            .as_deref()
+            .map(str::trim)
+            .filter(|evm_version| {
+                !evm_version.is_empty() && !evm_version.eq_ignore_ascii_case("default")
+            })
+    }
+
     pub fn compiler_is_supported(&self) -> bool {
         self.compiler_version.starts_with("v0.8.") && !self.compiler_version.contains("vyper")
     }
 
-    pub fn update_path_to_dir(&mut self, path_to_dir: &Path) {
-        self.abs_path_to_dir = path_to_dir.to_str().unwrap().to_string();
+    pub fn update_path_to_dir(
+        &mut self,
+        path_to_dir: &Path,
+        corpus_root: &Path,
+    ) -> Result<(), OmniscanError> {
+        let utf8 = |path: &Path| {
+            path.to_str()
+                .map(str::to_string)
+                .ok_or_else(|| OmniscanError::NonUtf8Path(path.to_path_buf()))
+        };
+        self.abs_path_to_dir = utf8(path_to_dir)?;
+        self.rel_path_to_dir = utf8(path_to_dir.strip_prefix(corpus_root).unwrap_or(path_to_dir))?;
+        Ok(())
+    }
+
+    /// The name of the file within the contract's directory that is handed to pyrometer.
+    /// Fails when the sources were not collected, or no file of a multi-file contract
+    /// defines the contract.
+    pub fn entrypoint_file_name(&self) -> Result<String, OmniscanError> {
+        match self.source_type.as_ref() {
+            Some(SourceType::SingleMain(_)) => Ok("main.sol".to_string()),
+            Some(SourceType::Multiple(multiple_files)) => {
+                let substr_to_find = format!("contract {} ", self.contract_name);
+                match multiple_files
+                    .iter()
+                    .find(|(_name, sol_string)| sol_string.contains(&substr_to_find))
+                {
+                    Some((name, _sol_string)) => Ok(name.clone()),
+                    None => Err(OmniscanError::EntrypointNotFound {
+                        bytecode_hash: self.bytecode_hash.clone(),
+                        contract_name: self.contract_name.clone(),
+                    }),
+                }
+            }
+            Some(SourceType::EtherscanMetadata(_)) => Ok("contract.json".to_string()),
+            Some(SourceType::Project(project_source)) => Ok(project_source.entrypoint.clone()),
+            None => Err(OmniscanError::SourcesNotCollected(
+                self.bytecode_hash.clone(),
+            )),
+        }
+    }
+
+    /// Path of the file handed to pyrometer, relative to the corpus root. The contract's
+    /// metadata.json when the entrypoint is unknown, so the path still leads to the contract.
+    /// Both parts are UTF-8, see `update_path_to_dir`, so nothing is lost converting it.
+    pub fn source_path(&self) -> String {
+        let file_name = self
+            .entrypoint_file_name()
+            .unwrap_or_else(|_| "metadata.json".to_string());
+        Path::new(&self.rel_path_to_dir)
+            .join(file_name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// `SourceType` with the detail that matters for the failure rates: the number of files of
+    /// a multi-file contract and the shape of a contract.json. "MultipleFiles:3",
+    /// "StandardJson:12" (standard-json input with 12 sources), "SourcesJson:4" (sources
+    /// without settings), "SingleSourceJson", "SingleFile" or the project framework.
+    pub fn source_layout(&self) -> String {
+        let Some(source_type) = self.source_type.as_ref() else {
+            return String::new();
+        };
+        match source_type {
+            SourceType::SingleMain(_) => "SingleFile".to_string(),
+            SourceType::Multiple(multiple_files) => {
+                format!("MultipleFiles:{}", multiple_files.len())
+            }
+            SourceType::EtherscanMetadata(SourceCodeMetadata::Metadata { sources, .. }) => {
+                format!("StandardJson:{}", sources.len())
+            }
+            SourceType::EtherscanMetadata(SourceCodeMetadata::Sources(sources)) => {
+                format!("SourcesJson:{}", sources.len())
+            }
+            SourceType::EtherscanMetadata(SourceCodeMetadata::SourceCode(_)) => {
+                "SingleSourceJson".to_string()
+            }
+            SourceType::Project(project_source) => project_source.framework.to_string(),
+        }
     }
 
     pub fn update_source_type(&mut self, source_type: SourceType) {
@@ -124,276 +1405,2852 @@ impl FiestaMetadata {
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+
+    if let Some(command) = args.command {
+        match command {
+            Commands::Schema { output } => schema::print_schema(output),
+            Commands::Report {
+                results,
+                group_by,
+                failure_correlation,
+                clusters,
+                cluster_similarity,
+                artifacts,
+                sort,
+                exclude_unavailable_compilers,
+                junit,
+                sarif,
+                summary,
+                score_weights,
+                corpus,
+                flakes_db,
+                weight_by,
+            } => report::run_report(
+                Path::new(&results),
+                group_by,
+                failure_correlation,
+                clusters.map(|limit| clusters::ClusterOptions {
+                    limit,
+                    min_similarity: cluster_similarity,
+                    artifacts_dir: artifacts.map(PathBuf::from),
+                }),
+                sort,
+                exclude_unavailable_compilers,
+                ReportPaths {
+                    junit: junit.map(prepare_report_path),
+                    sarif: sarif.map(prepare_report_path),
+                    summary: summary.map(prepare_report_path),
+                    corpus_root: PathBuf::from(corpus.unwrap_or_else(|| ".".to_string())),
+                    corpus_fingerprint: None,
+                    skipped_boilerplate: None,
+                    ignored: None,
+                    mislabeled: None,
+                    score_weights,
+                    // the tags of the run the results come from
+                    run_tags: manifest::read_manifest(&manifest::manifest_path(Path::new(
+                        &results,
+                    )))
+                    .map(|run_manifest| run_manifest.run_tags)
+                    .unwrap_or_default(),
+                    flakes_db: flakes_db.map(PathBuf::from),
+                    weight_by,
+                    full_corpus: false,
+                },
+            ),
+            Commands::Agreement {
+                analyzers,
+                examples,
+            } => agreement::run_agreement(&analyzers, examples),
+            Commands::Runs {
+                summaries,
+                tag,
+                label,
+                group_by_label,
+            } => tags::run_runs(&summaries, &tag, &label, group_by_label.as_deref()),
+            Commands::ExportFailures {
+                results,
+                corpus,
+                out,
+                artifacts,
+            } => {
+                if let Err(e) = export::export_failures(
+                    Path::new(&results),
+                    Path::new(&corpus),
+                    Path::new(&out),
+                    artifacts.as_deref().map(Path::new),
+                ) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            Commands::Diff {
+                old,
+                new,
+                limit,
+                exact_messages,
+                emit_fixtures,
+                corpus,
+                html,
+                artifacts,
+                analyzer_args,
+                score_weights,
+                flakes_db,
+                flaky_gating,
+            } => {
+                let html = html.map(|html| {
+                    let args = analyzer_args.map(|analyzer_args| {
+                        shlex::split(&analyzer_args).unwrap_or_else(|| {
+                            eprintln!("Could not split --analyzer-args `{}`", analyzer_args);
+                            std::process::exit(1);
+                        })
+                    });
+                    // the repro commands are those of the new run, as far as its manifest tells
+                    let new_manifest =
+                        manifest::read_manifest(&manifest::manifest_path(Path::new(&new))).ok();
+                    let corpus_root = PathBuf::from(
+                        corpus
+                            .clone()
+                            .or_else(|| new_manifest.as_ref().map(|m| m.corpus.clone()))
+                            .unwrap_or_else(|| ".".to_string()),
+                    );
+                    let analyzer = match new_manifest.and_then(|m| m.analyzer) {
+                        Some(invocation) if args.is_none() => invocation.config(),
+                        _ => AnalyzerConfig {
+                            args,
+                            ..Default::default()
+                        },
+                    };
+                    comparison::HtmlReport {
+                        path: PathBuf::from(html),
+                        artifacts_dir: artifacts.map(PathBuf::from),
+                        corpus_root: std::path::absolute(&corpus_root).unwrap_or(corpus_root),
+                        analyzer,
+                    }
+                });
+                let diff = diff::run_diff(
+                    Path::new(&old),
+                    Path::new(&new),
+                    limit,
+                    exact_messages,
+                    &score_weights,
+                    html.as_ref(),
+                    flakes_db
+                        .as_deref()
+                        .map(|flakes_db| (Path::new(flakes_db), flaky_gating)),
+                );
+                if let Some(fixtures_dir) = emit_fixtures {
+                    // clap guarantees the corpus is set along with emit_fixtures
+                    let corpus = corpus.unwrap();
+                    let written = fixtures::emit_fixtures(
+                        &diff.fixes,
+                        Path::new(&corpus),
+                        Path::new(&fixtures_dir),
+                    )
+                    .unwrap_or_else(|e| {
+                        eprintln!("Could not emit the fixtures: {}", e);
+                        std::process::exit(1);
+                    });
+                    println!(
+                        "Wrote {} pyrometer test fixtures to {}",
+                        written, fixtures_dir
+                    );
+                }
+            }
+            Commands::Tail {
+                results,
+                window,
+                failures,
+                interval,
+                once,
+            } => tail::run_tail(Path::new(&results), window, failures, interval, once),
+            Commands::Triage {
+                results,
+                corpus,
+                artifacts,
+                triage,
+                baseline,
+                all,
+                timeout,
+                analyzer_args,
+                classification_profile,
+                allow_network,
+                rules_file,
+            } => {
+                let args = analyzer_args.map(|analyzer_args| {
+                    shlex::split(&analyzer_args).unwrap_or_else(|| {
+                        eprintln!("Could not split --analyzer-args `{}`", analyzer_args);
+                        std::process::exit(1);
+                    })
+                });
+                let (profile, profile_warning) = profiles::select(
+                    classification_profile.as_deref(),
+                    environment::analyzer_version().as_deref(),
+                )
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+                if let Some(profile_warning) = profile_warning {
+                    eprintln!("WARNING: {}", profile_warning);
+                }
+                let results = PathBuf::from(results);
+                // pyrometer is invoked like the run did, as far as its manifest tells
+                let analyzer = match manifest::read_manifest(&manifest::manifest_path(&results))
+                    .ok()
+                    .and_then(|run_manifest| run_manifest.analyzer)
+                {
+                    Some(invocation) if args.is_none() => invocation.config(),
+                    _ => AnalyzerConfig {
+                        args,
+                        ..Default::default()
+                    },
+                };
+                let rules = rules::triage_rules_file(&results, rules_file.as_deref())
+                    .map(|path| {
+                        rules::Rules::load(&path).unwrap_or_else(|e| {
+                            eprintln!("Could not read the rules file {}: {}", path.display(), e);
+                            std::process::exit(1);
+                        })
+                    })
+                    .unwrap_or_default();
+                let corpus_root = PathBuf::from(corpus.unwrap_or_else(|| ".".to_string()));
+                triage::run_triage(
+                    &results,
+                    triage::TriageOptions {
+                        triage_path: triage
+                            .map(PathBuf::from)
+                            .unwrap_or_else(|| triage::triage_path(&results)),
+                        corpus_root: std::path::absolute(&corpus_root).unwrap_or(corpus_root),
+                        artifacts_dir: artifacts.map(PathBuf::from),
+                        baseline: baseline.map(PathBuf::from),
+                        all,
+                        timeout,
+                        classifier: profiles::Classifier { profile, rules },
+                        analyzer: AnalyzerConfig {
+                            env: backtrace::with_backtraces(Vec::new()),
+                            network_isolation: network_isolation(allow_network),
+                            ..analyzer
+                        },
+                    },
+                )
+                .await
+            }
+            Commands::ClassifyUnknowns {
+                results,
+                artifacts,
+                rules_file,
+                cluster_similarity,
+            } => unknowns::run_classify_unknowns(
+                Path::new(&results),
+                unknowns::UnknownsOptions {
+                    artifacts_dir: PathBuf::from(artifacts),
+                    rules_path: PathBuf::from(rules_file),
+                    min_similarity: cluster_similarity,
+                },
+            ),
+            Commands::FetchBulk {
+                addresses,
+                out,
+                explorer_args,
+            } => {
+                fetch::fetch_bulk(
+                    explorer_args.fetch_config(PathBuf::from(addresses), PathBuf::from(out)),
+                )
+                .await
+            }
+            Commands::Ignore { action, file } => ignore::run_ignore(action, Path::new(&file)),
+            Commands::Enrich {
+                results,
+                rpc_url,
+                address_index,
+                indexer_url,
+                failures_only,
+            } => {
+                onchain::enrich(
+                    Path::new(&results),
+                    &rpc_url,
+                    address_index.as_deref().map(Path::new),
+                    indexer_url.as_deref(),
+                    failures_only,
+                )
+                .await
+            }
+            Commands::VerifyCorpus { path, report } => {
+                verify::run_verify_corpus(Path::new(&path), report.as_deref().map(Path::new))
+            }
+            Commands::Worker {
+                queue,
+                path,
+                idle_exit,
+                run_args,
+            } => {
+                if let Err(e) = sweep::SharedQueue::check_url(&queue) {
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                }
+                sweep::run_worker(&queue, &path, Duration::from_secs(idle_exit), &run_args).await
+            }
+            Commands::Grep {
+                pattern,
+                path,
+                corpus,
+                results,
+                result_types,
+                ignore_case,
+                fixed_strings,
+                files_with_matches,
+            } => {
+                let pattern = if fixed_strings {
+                    regex::escape(&pattern)
+                } else {
+                    pattern
+                };
+                let pattern = regex::RegexBuilder::new(&pattern)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .unwrap_or_else(|e| {
+                        eprintln!("Invalid pattern: {}", e);
+                        std::process::exit(2);
+                    });
+                grep::run_grep(
+                    Path::new(&path),
+                    grep::GrepOptions {
+                        pattern,
+                        corpus,
+                        results: results.map(PathBuf::from),
+                        result_types,
+                        files_with_matches,
+                    },
+                )
+            }
+        }
+        return;
+    }
+
+    let replay_manifest = args.replay.as_ref().map(|path| {
+        manifest::read_manifest(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Could not read the manifest {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    if let Some(replay_manifest) = &replay_manifest {
+        if replay_manifest.corpus_kind != args.corpus {
+            eprintln!(
+                "The manifest was recorded for a --corpus {} run",
+                replay_manifest
+                    .corpus_kind
+                    .to_possible_value()
+                    .unwrap()
+                    .get_name()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let fiesta_path = args
+        .path
+        .clone()
+        .or_else(|| {
+            replay_manifest
+                .as_ref()
+                .map(|replay_manifest| replay_manifest.corpus.clone())
+        })
+        .unwrap_or_else(|| {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "the smart-contract-fiesta <PATH> is required when no subcommand is given",
+                )
+                .exit()
+        });
     // convert path to PathBuf
-    let abs_fiesta_path = std::path::PathBuf::from(args.path.clone());
+    let abs_fiesta_path = std::path::PathBuf::from(fiesta_path.clone());
+
+    // contracts are fetched from a bucket or an archive of the corpus instead of read from disk
+    let fetched_corpus = match remote::RemoteCorpus::parse(&fiesta_path) {
+        Some(Ok(remote_corpus)) => Some(FetchedCorpus::Remote(remote_corpus)),
+        Some(Err(e)) => {
+            eprintln!("Invalid corpus URL: {}", e);
+            std::process::exit(1);
+        }
+        None => archive::is_archive(&abs_fiesta_path)
+            .then(|| FetchedCorpus::Archive(abs_fiesta_path.clone())),
+    };
+
+    if args.addresses.is_some()
+        && (fetched_corpus.is_some() || !matches!(args.corpus, CorpusKind::Fiesta))
+    {
+        eprintln!("--addresses fetches into a smart-contract-fiesta corpus directory at PATH");
+        std::process::exit(1);
+    }
+    // bytecode hash -> addresses deployed with it, of the addresses fetched into the corpus
+    let fetched_addresses = match &args.addresses {
+        Some(addresses) => {
+            let addresses = PathBuf::from(addresses);
+            fetch::fetch_bulk(
+                args.explorer_args
+                    .fetch_config(addresses.clone(), abs_fiesta_path.clone()),
+            )
+            .await;
+            Some(
+                fetch::fetched_contracts(&abs_fiesta_path, &addresses, args.explorer_args.chain)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Could not read the fetched contracts: {}", e);
+                        std::process::exit(1);
+                    }),
+            )
+        }
+        None => None,
+    };
 
     // check if path exists and is a directory
-    if !abs_fiesta_path.exists() && !abs_fiesta_path.is_dir() {
-        eprintln!("The path {} does not exist or is not a dir", args.path);
+    if fetched_corpus.is_none() && !abs_fiesta_path.exists() && !abs_fiesta_path.is_dir() {
+        eprintln!("The path {} does not exist or is not a dir", fiesta_path);
+        std::process::exit(1);
+    }
+
+    if fetched_corpus.is_some() && !matches!(args.corpus, CorpusKind::Fiesta) {
+        eprintln!("Only smart-contract-fiesta corpora can be read from an archive or a bucket");
         std::process::exit(1);
     }
+    // a corpus fetched from an address list may hold nothing yet when no address is verified
+    if fetched_corpus.is_none() && fetched_addresses.is_none() {
+        if let Err(e) = preflight::check_corpus_layout(args.corpus, &abs_fiesta_path) {
+            eprintln!("Unexpected corpus layout: {}", e);
+            std::process::exit(1);
+        }
+    }
 
     // check if output path exists, otherwise use default.
-    let output_path = match args.output {
-        Some(path) => {
-            // check if path exists, otherwise create needed parent directories
-            let path = std::path::PathBuf::from(path);
-            let path_parent = path.parent().unwrap();
-            if !path_parent.exists() {
-                std::fs::create_dir_all(path_parent).unwrap();
-            }
+    let output_path = match args.output.clone().or_else(|| args.resume.clone()) {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from("./data").join(format!(
+            "results_{}.csv",
+            chrono::Local::now().format("%m-%d_%H-%M")
+        )),
+    };
+    // create the parent directories of the results if needed
+    if let Some(path_parent) = output_path.parent() {
+        create_dir_or_exit(path_parent);
+    }
+
+    // create the parent directories of the junit and sarif reports if needed
+    let junit_path = args.junit.map(prepare_report_path);
+    let sarif_path = args.sarif.map(prepare_report_path);
+    let summary_path = args.summary.map(prepare_report_path);
+    let artifacts_dir = args.artifacts.map(|path| {
+        let path = PathBuf::from(path);
+        create_dir_or_exit(&path);
+        path
+    });
+
+    // load the expectations up front so a bad file fails before the analysis starts
+    let expectations = args.check.map(|path| {
+        results::read_results_file(Path::new(&path)).unwrap_or_else(|e| {
+            eprintln!("Could not read expectations file {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+
+    let source_pattern = args.source_contains.as_ref().map(|pattern| {
+        Regex::new(pattern).unwrap_or_else(|e| {
+            eprintln!("Invalid --source-contains pattern: {}", e);
+            std::process::exit(2);
+        })
+    });
+
+    // a flake database that can't be read would not be written back either
+    let flake_db = args.flakes_db.as_ref().map(|path| {
+        flakes::FlakeDb::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Could not read flake database {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
 
-            path
+    let baseline = args.baseline.as_ref().map(|path| {
+        diff::read_comparable_results(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Could not read baseline file {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    if args.crytic_compile
+        && Command::new("crytic-compile")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_err()
+    {
+        eprintln!("--crytic-compile needs crytic-compile on the PATH (pip install crytic-compile)");
+        std::process::exit(1);
+    }
+    let analyzer_args = match &args.analyzer_args {
+        Some(analyzer_args) => Some(shlex::split(analyzer_args).unwrap_or_else(|| {
+            eprintln!("Could not split --analyzer-args `{}`", analyzer_args);
+            std::process::exit(1);
+        })),
+        None if !args.trailing_analyzer_args.is_empty() => {
+            Some(args.trailing_analyzer_args.clone())
         }
-        None => {
-            let mut path = std::path::PathBuf::from("./data");
-            path.push(format!(
-                "results_{}.csv",
-                chrono::Local::now().format("%m-%d_%H-%M")
-            ));
-            let path_parent = path.parent().unwrap();
-            if !path_parent.exists() {
-                std::fs::create_dir_all(path_parent).unwrap();
-            }
-            path
+        None => None,
+    };
+    let network_isolation = network_isolation(args.allow_network);
+    let core_dumps = args.core_dumps.then(|| {
+        let core_dumps = coredump::CoreDumps::setup().unwrap_or_else(|e| {
+            eprintln!("Could not set up --core-dumps: {}", e);
+            std::process::exit(1);
+        });
+        if core_dumps.written_as_process() && network_isolation.is_some() {
+            eprintln!(
+                "pyrometer is cut off from the network in a user namespace the kernel can't \
+                 write its cores from, pass --allow-network to collect them"
+            );
+        }
+        core_dumps
+    });
+    let analyzer_version = environment::analyzer_version();
+    let (profile, profile_warning) = profiles::select(
+        args.classification_profile.as_deref(),
+        analyzer_version.as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    if let Some(profile_warning) = &profile_warning {
+        eprintln!("WARNING: {}", profile_warning);
+    }
+    let rules_path = rules::run_rules_file(args.rules_file.as_deref())
+        .map(|path| std::path::absolute(&path).unwrap_or(path));
+    let rules = rules_path
+        .as_ref()
+        .map(|path| {
+            let rules = rules::Rules::load(path).unwrap_or_else(|e| {
+                eprintln!("Could not read the rules file {}: {}", path.display(), e);
+                std::process::exit(1);
+            });
+            println!(
+                "Classifying the outputs pyrometer's own messages don't with the {} rules of {}",
+                rules.len(),
+                path.display()
+            );
+            rules
+        })
+        .unwrap_or_default();
+    let analyzer = Arc::new(AnalyzerConfig {
+        args: analyzer_args,
+        env: backtrace::with_backtraces(args.analyzer_env.clone()),
+        network_isolation,
+        core_dumps,
+        compiler_settings: args
+            .compiler_settings
+            .then(|| settings::AnalyzerSettingsFlags {
+                optimizer_runs: args.analyzer_optimizer_runs_flag.clone(),
+                evm_version: args.analyzer_evm_version_flag.clone(),
+            }),
+        target_contract_flag: args
+            .target_contract_from_metadata
+            .then(|| args.analyzer_contract_flag.clone()),
+    });
+    // check the smtp settings now rather than after a run of several hours
+    let smtp_config = if args.email_to.is_empty() {
+        None
+    } else {
+        Some(
+            email::SmtpConfig::from_env(&args.email_to).unwrap_or_else(|e| {
+                eprintln!("Could not configure email: {}", e);
+                std::process::exit(1);
+            }),
+        )
+    };
+
+    // a misspelled destination would only show once the run is over
+    let upload_destination = args.upload.as_ref().map(|url| {
+        upload::UploadDestination::parse(url).unwrap_or_else(|e| {
+            eprintln!("Could not configure the upload: {}", e);
+            std::process::exit(1);
+        })
+    });
+    if let Some(url) = &args.push_results {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            eprintln!("--push-results must be an HTTP endpoint, got {}", url);
+            std::process::exit(1);
+        }
+        if !(args.push_interval > 0.0 && args.push_interval.is_finite()) {
+            eprintln!("--push-interval must be a positive number of seconds");
+            std::process::exit(1);
+        }
+    }
+    let run_started_at = chrono::Utc::now();
+
+    // check if jobs is set, otherwise use number of cores
+    let jobs = match args.jobs {
+        Some(jobs) => jobs as usize,
+        None => num_cpus::get(),
+    };
+    // every job holds pipes, fit the jobs in the descriptor limit rather than hitting EMFILE
+    let fd_budget = fds::FdBudget::new();
+    let jobs = match fd_budget {
+        Some(fd_budget) if fd_budget.max_jobs() < jobs => {
+            let max_jobs = fd_budget.max_jobs();
+            eprintln!(
+                "Lowering --jobs from {} to {}: the file descriptor limit is {} (ulimit -n) and each job needs up to {}",
+                jobs,
+                max_jobs,
+                fd_budget.limit,
+                fds::FDS_PER_JOB
+            );
+            max_jobs
+        }
+        _ => jobs,
+    };
+    concurrency::warn_oversubscription(jobs);
+
+    // live progress of the run, optionally served over http
+    let run_status = Arc::new(RunStatus::new(jobs));
+    if let Some(status_addr) = &args.status_addr {
+        if let Err(e) = status::serve_status(status_addr, run_status.clone()) {
+            eprintln!("Could not serve status on {}: {}", status_addr, e);
+            std::process::exit(1);
+        }
+        println!("Serving run status on http://{}/status", status_addr);
+    }
+
+    if !(args.progress_interval > 0.0 && args.progress_interval.is_finite()) {
+        eprintln!("--progress-interval must be a positive number of seconds");
+        std::process::exit(1);
+    }
+    if !(args.poll_interval > 0.0 && args.poll_interval.is_finite()) {
+        eprintln!("--poll-interval must be a positive number of millisecs");
+        std::process::exit(1);
+    }
+    if let Some(queue_url) = &args.distribute {
+        if let Err(e) = sweep::SharedQueue::check_url(queue_url) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    let alert = args.alert_threshold.map(|threshold| {
+        if !(threshold > 0.0 && threshold <= 100.0) {
+            eprintln!("--alert-threshold must be a percentage between 0 and 100");
+            std::process::exit(1);
+        }
+        let baseline_success_rate = baseline
+            .as_ref()
+            .map(|baseline| summary::RunSummary::from_rows(baseline).success_rate);
+        Arc::new(alert::SuccessRateAlert::new(
+            threshold,
+            baseline_success_rate,
+            args.alert_window,
+            args.alert_abort,
+        ))
+    });
+    let time_budget = args.time_budget.map(|minutes| {
+        if !(minutes > 0.0 && minutes.is_finite()) {
+            eprintln!("--time-budget must be a positive number of minutes");
+            std::process::exit(1);
+        }
+        Duration::from_secs_f64(minutes * 60.0)
+    });
+    let cpu_quota = args.cpu_quota.map(|cpus| {
+        if !(cpus > 0.0 && cpus.is_finite()) {
+            eprintln!("--cpu-quota must be a positive number of CPUs");
+            std::process::exit(1);
         }
+        let cpu_quota = cgroup::CpuQuota::setup(cpus).unwrap_or_else(|e| {
+            eprintln!("Could not set up --cpu-quota: {}", e);
+            std::process::exit(1);
+        });
+        Arc::new(cpu_quota)
+    });
+
+    // check if timeout is set, otherwise use default
+    let pyrometer_timeout = match args.timeout {
+        Some(timeout) => {
+            if timeout == 0.0 {
+                1_000_000.0 // inf
+            } else {
+                timeout
+            }
+        }
+        None => 2.0,
+    };
+
+    // check if num_contracts is set, otherwise use default, a time budget samples the whole corpus
+    let num_contracts = match args.num_contracts {
+        _ if time_budget.is_some() => usize::MAX,
+        Some(num_contracts) => {
+            if num_contracts == 0 {
+                usize::MAX
+            } else {
+                num_contracts
+            }
+        }
+        None => 5000,
+    };
+    // prioritized runs select the contracts of most value once the whole corpus is discovered
+    let priorities = args.onchain_data.as_ref().map(|path| {
+        onchain::read_priorities(Path::new(path), args.prioritize_by.unwrap()).unwrap_or_else(|e| {
+            eprintln!("Could not read the on-chain data {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    let (num_contracts, prioritized_contracts) = match priorities {
+        Some(_) => (usize::MAX, num_contracts),
+        None => (num_contracts, usize::MAX),
+    };
+
+    // check if skip_contracts is set, otherwise use default
+    let skip_contracts = args.skip_contracts.unwrap_or(0);
+    // a run over every contract measures the success rate of the corpus, not of a sample of it
+    let full_corpus = num_contracts == usize::MAX
+        && skip_contracts == 0
+        && time_budget.is_none()
+        && priorities.is_none()
+        && args.replay.is_none()
+        && !args.unique_sample;
+
+    // scratch space for files generated for the run, like project remappings
+    let workspace = args.workspace.as_ref().map(|workspace| {
+        let workspace = PathBuf::from(workspace);
+        if let Err(e) = fs::create_dir_all(&workspace) {
+            eprintln!("Could not create workspace {}: {}", workspace.display(), e);
+            std::process::exit(1);
+        }
+        workspace
+    });
+    if workspace.is_some() && !matches!(args.corpus, CorpusKind::Fiesta) {
+        println!("Project contracts import files outside of their directory and are not copied to the workspace");
+    }
+    let work_dir = workspace
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("omniscan-{}", std::process::id()));
+    // paths handed to the analyzer are absolute, it runs in its own directory with
+    // --collect-outputs
+    let work_dir = std::path::absolute(&work_dir).unwrap_or(work_dir);
+    // the selected contracts of an archive are extracted here, the rest stays compressed
+    let corpus_root = match &fetched_corpus {
+        Some(FetchedCorpus::Archive(_)) => work_dir.join("corpus"),
+        Some(FetchedCorpus::Remote(remote_corpus)) => args
+            .remote_cache
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| remote_corpus.default_cache_dir()),
+        None => abs_fiesta_path.clone(),
+    };
+    let corpus_root = std::path::absolute(&corpus_root).unwrap_or(corpus_root);
+
+    // identifies the dataset in the manifest and summary, so comparisons notice when it changed
+    let corpus_fingerprint =
+        fingerprint::corpus_fingerprint(args.corpus, &abs_fiesta_path, fetched_corpus.as_ref());
+    if let Some(replay_manifest) = &replay_manifest {
+        fingerprint::warn_if_changed(
+            "the manifest was recorded",
+            replay_manifest.corpus_fingerprint.as_deref(),
+            corpus_fingerprint.as_deref(),
+        );
+    }
+    if let Some(baseline_path) = &args.baseline {
+        fingerprint::warn_if_changed(
+            "the baseline",
+            manifest::results_fingerprint(Path::new(baseline_path)).as_deref(),
+            corpus_fingerprint.as_deref(),
+        );
+    }
+
+    // check if we are resuming from an existing queue journal
+    let queue_path = args.queue.map(PathBuf::from);
+    let resume_results = args.resume.is_some();
+    // the successes of a run recording only its failures are nowhere in its results file
+    if resume_results
+        && manifest::read_manifest(&manifest::manifest_path(&output_path))
+            .is_ok_and(|run_manifest| run_manifest.record == results::RecordMode::Failures)
+    {
+        eprintln!(
+            "{} was written with --record failures, it does not tell which contracts succeeded \
+             and can't be resumed with --resume. Runs started with --queue can be resumed \
+             whatever they record",
+            output_path.display()
+        );
+        std::process::exit(1);
+    }
+    let resume_state = queue_path
+        .as_ref()
+        .filter(|queue_path| queue_path.exists())
+        .map(|queue_path| {
+            queue::load_resume_state(queue_path).unwrap_or_else(|e| {
+                eprintln!("Could not resume from {}: {}", queue_path.display(), e);
+                std::process::exit(1);
+            })
+        });
+
+    let discover = |num_contracts, skip_contracts| {
+        discover_corpus(
+            args.corpus,
+            &corpus_root,
+            &work_dir,
+            num_contracts,
+            skip_contracts,
+            args.max_io_errors,
+            args.discovery_checkpoint.as_deref().map(Path::new),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Could not discover the corpus, {}", e);
+            std::process::exit(1);
+        })
     };
+    // the top-up rounds of a filtered sample continue the walk where the previous round
+    // stopped, the walk of a smart-contract-fiesta corpus is resumed rather than walked again
+    let mut bucket_cursor = discovery::BucketCursor::new(
+        &corpus_root,
+        args.max_io_errors,
+        args.discovery_checkpoint.as_deref().map(Path::new),
+    );
+    let mut walked = 0;
+    let discover_next = |num_contracts: usize, skip_contracts: usize| match args.corpus {
+        CorpusKind::Fiesta => bucket_cursor.next(num_contracts, skip_contracts),
+        _ => {
+            let batch = discover(num_contracts, walked + skip_contracts);
+            walked += skip_contracts + batch.len();
+            batch
+        }
+    };
+    // whether the contracts are selected by walking the corpus, rather than from a list
+    let walked_corpus = resume_state.is_none()
+        && replay_manifest.is_none()
+        && fetched_corpus.is_none()
+        && fetched_addresses.is_none();
+    let mut fiesta_metadatas = match &resume_state {
+        Some(resume_state) => {
+            println!(
+                "Resuming run: {} contracts completed, {} were in flight, {} left to analyze",
+                resume_state.completed,
+                resume_state.in_flight,
+                resume_state.pending_dirs.len()
+            );
+            if resume_state.pending_dirs.is_empty() {
+                println!("Nothing left to analyze");
+                return;
+            }
+            match args.corpus {
+                // the contracts extracted by the interrupted run may be gone, fetch them again
+                CorpusKind::Fiesta if fetched_corpus.is_some() => {
+                    fetch_corpus(
+                        fetched_corpus.as_ref().unwrap(),
+                        &corpus_root,
+                        ContractSelection::Hashes(&resume_state.pending_hashes),
+                    )
+                    .await
+                }
+                CorpusKind::Fiesta => resume_state
+                    .pending_dirs
+                    .iter()
+                    .filter_map(|dir| {
+                        load_contract_metadata(dir, &corpus_root)
+                            .map_err(|e| eprintln!("Skipping a pending contract, {}", e))
+                            .ok()
+                    })
+                    .collect(),
+                // project contracts have no metadata.json to reload, discover them again
+                _ => {
+                    let mut metadatas = discover(num_contracts, skip_contracts);
+                    metadatas.retain(|metadata| {
+                        resume_state
+                            .pending_hashes
+                            .contains(&metadata.bytecode_hash)
+                    });
+                    metadatas
+                }
+            }
+        }
+        None if replay_manifest.is_some() => {
+            let replay_manifest = replay_manifest.as_ref().unwrap();
+            let metadatas = match args.corpus {
+                CorpusKind::Fiesta if fetched_corpus.is_some() => {
+                    let hashes = replay_manifest
+                        .contracts
+                        .iter()
+                        .map(|entry| entry.bytecode_hash.clone())
+                        .collect::<HashSet<String>>();
+                    fetch_corpus(
+                        fetched_corpus.as_ref().unwrap(),
+                        &corpus_root,
+                        ContractSelection::Hashes(&hashes),
+                    )
+                    .await
+                }
+                CorpusKind::Fiesta => replay_manifest
+                    .contract_dirs()
+                    .map(|dir| corpus_root.join(dir))
+                    .filter(|dir| dir.join("metadata.json").is_file())
+                    .filter_map(|dir| {
+                        load_contract_metadata(&dir, &corpus_root)
+                            .map_err(|e| eprintln!("Skipping a replayed contract, {}", e))
+                            .ok()
+                    })
+                    .collect(),
+                _ => discover(usize::MAX, 0),
+            };
+            replay_manifest.order(metadatas).unwrap_or_else(|e| {
+                eprintln!("Could not replay {}: {}", args.replay.as_ref().unwrap(), e);
+                std::process::exit(1);
+            })
+        }
+        None if fetched_corpus.is_some() => {
+            let mut metadatas = fetch_corpus(
+                fetched_corpus.as_ref().unwrap(),
+                &corpus_root,
+                ContractSelection::First {
+                    num_contracts,
+                    skip_contracts,
+                },
+            )
+            .await;
+            if args.unique_sample {
+                sampling::dedup_sample(&mut metadatas);
+            }
+            if metadatas.is_empty() {
+                eprintln!(
+                    "{} holds no supported organized_contracts/XX/<bytecode_hash>/metadata.json",
+                    fiesta_path
+                );
+                std::process::exit(1);
+            }
+            metadatas
+        }
+        None => match &fetched_addresses {
+            Some(fetched_addresses) => discover_fetched_contracts(
+                fetched_addresses,
+                &corpus_root,
+                num_contracts,
+                skip_contracts,
+            ),
+            None if args.unique_sample || source_pattern.is_some() => sampling::filtered_sample(
+                discover_next,
+                num_contracts,
+                skip_contracts,
+                args.unique_sample,
+                source_pattern.as_ref(),
+            ),
+            None => discover(num_contracts, skip_contracts),
+        },
+    };
+    let ignored = ignore::run_ignore_file(args.ignore_file.as_deref())
+        .map(|ignore_file| ignore::exclude_ignored(&mut fiesta_metadatas, &ignore_file));
+    // the contracts the interrupted run analyzed are left out before their sources are read
+    if resume_results {
+        let analyzed = results::analyzed_hashes(&output_path).unwrap_or_else(|e| {
+            eprintln!("Could not resume from {}: {}", output_path.display(), e);
+            std::process::exit(1);
+        });
+        let selected = fiesta_metadatas.len();
+        fiesta_metadatas.retain(|metadata| !analyzed.contains(&metadata.bytecode_hash));
+        println!(
+            "Resuming {}: {} of the {} selected contracts were analyzed, {} left to analyze",
+            output_path.display(),
+            selected - fiesta_metadatas.len(),
+            selected,
+            fiesta_metadatas.len()
+        );
+        if fiesta_metadatas.is_empty() {
+            println!("Nothing left to analyze");
+            return;
+        }
+    }
+    let sample_seed = time_budget.map(|_| {
+        let seed = args.sample_seed.unwrap_or_else(|| fastrand::u64(..));
+        sampling::shuffle(&mut fiesta_metadatas, seed);
+        println!(
+            "Sampling the corpus in random order for {} minutes, seed {} (see --sample-seed)",
+            args.time_budget.unwrap(),
+            seed
+        );
+        seed
+    });
+    if let Some(seed) = args.deterministic_schedule {
+        sampling::shuffle(&mut fiesta_metadatas, seed);
+    }
+    if let Some(priorities) = &priorities {
+        let discovered = fiesta_metadatas.len();
+        let known = onchain::prioritize(&mut fiesta_metadatas, priorities);
+        fiesta_metadatas.truncate(prioritized_contracts);
+        println!(
+            "Dispatching {} contracts by descending {}, {} of the {} discovered have on-chain data",
+            fiesta_metadatas.len(),
+            args.prioritize_by.unwrap().name(),
+            known,
+            discovered
+        );
+    }
+    if let Some(fetched_addresses) = &fetched_addresses {
+        fiesta_metadatas.iter_mut().for_each(|metadata| {
+            if let Some(addresses) = fetched_addresses.get(&metadata.bytecode_hash) {
+                metadata.addresses = addresses.clone();
+                // the bytecode may have been fetched for another chain first
+                metadata.chain = Some(args.explorer_args.chain.name().to_string());
+            }
+        });
+    }
+
+    // project corpora know their sources from discovery already
+    fiesta_metadatas
+        .iter_mut()
+        .filter(|metadata| metadata.source_type.is_none())
+        .for_each(|metadata| {
+            match catch_harness_panic("collecting the sources", || {
+                collect_contract_sources(metadata)
+            }) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => metadata.harness_error = Some(e.to_string()),
+                Err(e) => metadata.harness_error = Some(e),
+            }
+        });
+    let mislabeled = fiesta_metadatas
+        .iter_mut()
+        .filter(|metadata| metadata.source_type.is_none() && metadata.harness_error.is_none())
+        .filter_map(|metadata| mislabel::handle(metadata, &corpus_root, args.fix_corpus))
+        .collect::<Vec<mislabel::MislabeledContract>>();
+    if !mislabeled.is_empty() {
+        let repaired = mislabeled
+            .iter()
+            .filter(|contract| contract.repaired)
+            .count();
+        println!(
+            "{} contracts have a mislabeled .vy file as sources, {} repaired{}",
+            mislabeled.len(),
+            repaired,
+            if args.fix_corpus {
+                ""
+            } else {
+                " (see --fix-corpus)"
+            }
+        );
+    }
+    fiesta_metadatas
+        .retain(|metadata| metadata.source_type.is_some() || metadata.harness_error.is_some());
+    // the contracts of a walk of the corpus were selected by their sources already, the others
+    // are narrowed down
+    if let Some(source_pattern) = source_pattern.as_ref().filter(|_| !walked_corpus) {
+        let selected = fiesta_metadatas.len();
+        // contracts whose sources could not be read can't be told to match
+        fiesta_metadatas.retain(|metadata| {
+            grep::sources_match(metadata, source_pattern).unwrap_or_else(|e| {
+                eprintln!(
+                    "Leaving out {}, its sources could not be read: {}",
+                    metadata.rel_path_to_dir, e
+                );
+                false
+            })
+        });
+        println!(
+            "{} of the {} selected contracts have sources matching `{}`",
+            fiesta_metadatas.len(),
+            selected,
+            source_pattern
+        );
+    }
+    let mut boilerplate_detector = boilerplate::BoilerplateDetector::default();
+    let mut boilerplate = HashMap::new();
+    fiesta_metadatas
+        .iter_mut()
+        .filter(|metadata| metadata.harness_error.is_none())
+        .for_each(|metadata| {
+            if let Err(e) = metadata.entrypoint_file_name() {
+                metadata.harness_error = Some(e.to_string());
+                return;
+            }
+            match catch_harness_panic("reading the imports", || imports::walk_imports(metadata)) {
+                Ok(Ok((import_graph, sources))) => {
+                    metadata.import_graph = Some(import_graph);
+                    metadata.complexity = Some(complexity::measure_sources(&sources));
+                    // the hash of no sources at all would join unrelated contracts
+                    if !sources.is_empty() {
+                        metadata.source_hash = Some(fingerprint::sources_hash(&sources));
+                    }
+                    if let Some(kind) = boilerplate_detector.classify(&sources) {
+                        boilerplate.insert(metadata.bytecode_hash.clone(), kind);
+                    }
+                }
+                Ok(Err(_)) => {}
+                Err(e) => metadata.harness_error = Some(e),
+            }
+        });
+    let mut boilerplate_counts = BTreeMap::new();
+    for kind in boilerplate.values() {
+        *boilerplate_counts.entry(kind.to_string()).or_insert(0) += 1;
+    }
+    if !boilerplate.is_empty() {
+        let minimal_proxies = boilerplate_counts.get("MinimalProxy").copied().unwrap_or(0);
+        let copies = boilerplate_counts.get("Copy").copied().unwrap_or(0);
+        if args.skip_boilerplate {
+            println!(
+                "Skipping {} boilerplate contracts: {} minimal proxies, {} copies of other contracts",
+                boilerplate.len(),
+                minimal_proxies,
+                copies
+            );
+            fiesta_metadatas.retain(|metadata| !boilerplate.contains_key(&metadata.bytecode_hash));
+        } else {
+            println!(
+                "{} contracts are boilerplate ({} minimal proxies, {} copies of other contracts), \
+                 --skip-boilerplate leaves them out",
+                boilerplate.len(),
+                minimal_proxies,
+                copies
+            );
+        }
+    }
+    let skipped_boilerplate = args.skip_boilerplate.then_some(boilerplate_counts);
+    if args.check_compiler {
+        check_compilers(&mut fiesta_metadatas).await;
+    }
+
+    // when resuming, keep appending to the results file of the interrupted run
+    let output_path = match &resume_state {
+        Some(resume_state) => resume_state.output_path.clone(),
+        None => output_path,
+    };
+
+    let preparation = match args.flatten {
+        Some(flatten_mode) => Some(SourcePreparation::Flatten(flatten_mode)),
+        None => args
+            .crytic_compile
+            .then_some(SourcePreparation::CryticCompile),
+    };
+    let prepare_work_dir = work_dir.join("prepared");
+
+    let workspace_dir = args.workspace.is_some().then(|| work_dir.clone());
+    let collect_outputs = args
+        .collect_outputs
+        .then(|| (work_dir.join("runs"), artifacts_dir.clone().unwrap()));
+    let core_dumps_dir = args.core_dumps.then(|| artifacts_dir.clone().unwrap());
+    let scratch_dir = work_dir.join("scratch");
+    let worker_pool = args.worker_pool.then(|| {
+        let worker_args = shlex::split(&args.worker_args).unwrap_or_else(|| {
+            eprintln!("Could not split --worker-args `{}`", args.worker_args);
+            std::process::exit(1);
+        });
+        let worker_pool = workers::WorkerPool::new(
+            worker_args,
+            args.worker_recycle as usize,
+            analyzer.clone(),
+            &scratch_dir,
+            args.analyzer_home,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Could not set up the worker pool: {}", e);
+            std::process::exit(1);
+        });
+        Arc::new(worker_pool)
+    });
+
+    // fail now rather than hours into the run when the outputs won't fit
+    let run_outputs = preflight::RunOutputs {
+        results: &output_path,
+        reports: [&junit_path, &sarif_path]
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+            .collect(),
+        journal: queue_path.as_deref(),
+        artifacts_dir: artifacts_dir.as_deref(),
+        work_dir: &prepare_work_dir,
+        preparation,
+    };
+    if let Err(e) = preflight::check_disk_space(&run_outputs, &fiesta_metadatas) {
+        eprintln!("Not enough disk space for the run: {}", e);
+        std::process::exit(1);
+    }
+
+    let run_tags = tags::RunTags::new(&args.tag, &args.label);
+    // a resumed run keeps the manifest of the run it resumes, and records like that run did
+    let manifest_path = manifest::manifest_path(&output_path);
+    let resumed_manifest = (resume_state.is_some() || resume_results)
+        .then(|| manifest::read_manifest(&manifest_path).ok())
+        .flatten();
+    if let Some(resumed_manifest) = &resumed_manifest {
+        fingerprint::warn_if_changed(
+            "the resumed run started",
+            resumed_manifest.corpus_fingerprint.as_deref(),
+            corpus_fingerprint.as_deref(),
+        );
+        if let Some(environment) = &resumed_manifest.environment {
+            let changes =
+                environment.changes(&environment::EnvironmentSnapshot::capture(&analyzer.env));
+            if !changes.is_empty() {
+                println!(
+                    "Warning: the environment changed since the resumed run started, its results \
+                     and the ones appended now may not be comparable:"
+                );
+                for change in changes {
+                    println!("  {}", change);
+                }
+            }
+        }
+    }
+    let record = resumed_manifest
+        .as_ref()
+        .map_or(args.record, |run_manifest| run_manifest.record);
+    if resume_state.is_none() && !(resume_results && manifest_path.exists()) {
+        let run_manifest = manifest::RunManifest {
+            run_tags: run_tags.clone(),
+            full_corpus,
+            rules_file: rules_path.as_ref().map(|path| path.display().to_string()),
+            rules_sha256: rules_path.as_deref().and_then(fingerprint::file_hash),
+            analyzer: Some(manifest::AnalyzerInvocation::new(&analyzer)),
+            ..manifest::RunManifest::new(
+                &fiesta_path,
+                args.corpus,
+                corpus_fingerprint.clone(),
+                environment::EnvironmentSnapshot::capture(&analyzer.env),
+                profile.name,
+                record,
+                &fiesta_metadatas,
+            )
+        };
+        if let Err(e) = manifest::write_manifest(&manifest_path, &run_manifest) {
+            eprintln!(
+                "Could not write the manifest {}: {}",
+                manifest_path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let journal = queue_path.map(|queue_path| {
+        let journal = match &resume_state {
+            Some(_) => queue::QueueJournal::reopen(&queue_path),
+            None => queue::QueueJournal::create(&queue_path, &output_path, &fiesta_metadatas),
+        };
+        Arc::new(journal.unwrap_or_else(|e| {
+            eprintln!("Could not record the queue journal: {}", e);
+            std::process::exit(1);
+        }))
+    });
+
+    run_status.set_total(fiesta_metadatas.len());
+    let progress_file = args.progress_file.as_ref().map(|path| {
+        progress::ProgressFile::start(
+            prepare_report_path(path.clone()),
+            &output_path,
+            run_status.clone(),
+            Duration::from_secs_f64(args.progress_interval),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Could not write the progress file {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    println!("Beginning analysis of {} contracts", fiesta_metadatas.len());
+    let job_limit = concurrency::JobLimit::new(jobs);
+    job_limit.spawn_controls(args.jobs_file.as_ref().map(PathBuf::from));
+    concurrency::spawn_pause_signals(run_status.clone());
+
+    // Create a channel for threads to send their results
+    let (tx, rx) = mpsc::channel();
+
+    // Create a oneshot to signal the rx loop to stop
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+
+    // Create a thread that runs the rx loop
+    let rx_journal = journal.clone();
+    let rx_status = run_status.clone();
+    let rx_output_path = output_path.clone();
+    let rx_alert = alert.clone();
+    let report_paths = ReportPaths {
+        junit: junit_path,
+        sarif: sarif_path,
+        summary: summary_path,
+        corpus_root: corpus_root.clone(),
+        corpus_fingerprint: corpus_fingerprint.clone(),
+        skipped_boilerplate,
+        ignored,
+        mislabeled: Some(mislabeled),
+        score_weights: args.score_weights.clone(),
+        run_tags: run_tags.clone(),
+        flakes_db: None,
+        weight_by: None,
+        full_corpus,
+    };
+    let rx_report_paths = report_paths.clone();
+    let upload_artifacts_dir = artifacts_dir.clone();
+    let rx_target_contract_flag = analyzer.target_contract_flag.clone();
+    let (pusher, push_task) = match &args.push_results {
+        Some(url) => {
+            let spool_dir = match &args.push_spool {
+                Some(spool_dir) => PathBuf::from(spool_dir),
+                None => {
+                    let mut spool_name = output_path.file_name().unwrap_or_default().to_os_string();
+                    spool_name.push(".spool");
+                    output_path.with_file_name(spool_name)
+                }
+            };
+            let (pusher, push_task) = push::start(push::PushConfig {
+                url: url.clone(),
+                spool_dir,
+                batch_size: args.push_batch as usize,
+                interval: Duration::from_secs_f64(args.push_interval),
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Could not push the results, {}", e);
+                std::process::exit(1);
+            });
+            (Some(pusher), Some(push_task))
+        }
+        None => (None, None),
+    };
+    // with --distribute the contracts go to the shared queue and their results come back from it
+    let sweep_id = format!("{:016x}", fastrand::u64(..));
+    let (undispatched_tx, undispatched_rx) = oneshot::channel();
+    let expected = fiesta_metadatas
+        .iter()
+        .map(|metadata| metadata.bytecode_hash.clone())
+        .collect::<HashSet<String>>();
+    let collect_queue = args.distribute.clone();
+    let collect_sweep = sweep_id.clone();
+    let rx_handle = tokio::spawn(async move {
+        if let Some(queue_url) = collect_queue {
+            return sweep::collect(
+                &queue_url,
+                &collect_sweep,
+                expected,
+                undispatched_rx,
+                sweep::CollectConfig {
+                    output_path: rx_output_path,
+                    report_paths: rx_report_paths,
+                    record,
+                    rotate_results: args.rotate_results.map(|mib| mib * 1024 * 1024),
+                    status: rx_status,
+                    pusher,
+                    idle_timeout: Duration::from_secs(args.distribute_timeout),
+                },
+            )
+            .await;
+        }
+        rx_loop(
+            rx,
+            stop_rx,
+            RxLoopConfig {
+                output_path: rx_output_path,
+                report_paths: rx_report_paths,
+                artifacts_dir,
+                append_results: resume_state.is_some() || resume_results,
+                rotate_results: args.rotate_results.map(|mib| mib * 1024 * 1024),
+                record,
+                journal: rx_journal,
+                status: rx_status,
+                alert: rx_alert,
+                alert_webhook: args.alert_webhook.clone(),
+                classifier: profiles::Classifier { profile, rules },
+                target_contract_flag: rx_target_contract_flag,
+                pusher,
+            },
+        )
+        .await
+    });
+
+    let dispatched = fiesta_metadatas
+        .iter()
+        .map(|metadata| (metadata.bytecode_hash.clone(), metadata.source_path()))
+        .collect::<Vec<(String, String)>>();
+    let audit_status = run_status.clone();
+    // the budget runs from the first dispatched contract, discovery is not part of the sample
+    let deadline = time_budget.map(|time_budget| Instant::now() + time_budget);
+    let distribute = args.distribute.clone();
+    let tx_handle = tokio::spawn(async move {
+        if let Some(queue_url) = distribute {
+            return sweep::dispatch(
+                &queue_url,
+                &sweep_id,
+                args.corpus,
+                fiesta_metadatas,
+                args.distribute_batch as usize,
+                undispatched_tx,
+            )
+            .await;
+        }
+        tx_loop(
+            fiesta_metadatas,
+            tx,
+            stop_tx,
+            TxLoopConfig {
+                job_limit,
+                max_spawn_failures: args.max_spawn_failures,
+                pyrometer_timeout,
+                journal,
+                status: run_status,
+                solc_check: args.solc_check,
+                determinism_check: args.determinism_check,
+                poll_interval: Duration::from_secs_f64(args.poll_interval / 1000.0),
+                warmup: args.warmup,
+                slots: args.deterministic_schedule.map(|_| jobs),
+                spawn_retry: retry::SpawnRetry {
+                    retries: args.spawn_retries,
+                    backoff: Duration::from_millis(args.spawn_retry_backoff),
+                },
+                cpu_quota,
+                preparation,
+                prepare_work_dir,
+                analyzer,
+                fd_budget,
+                workspace_dir,
+                collect_outputs,
+                core_dumps_dir,
+                scratch_dir,
+                analyzer_home: args.analyzer_home,
+                worker_pool,
+                batching: args
+                    .batch_size
+                    .map(|batch_size| (batch_size as usize, args.batch_max_bytes)),
+                alert,
+                deadline,
+            },
+        )
+        .await
+    });
+
+    let (tx_result, rx_result) = tokio::join!(tx_handle, rx_handle);
+    let (aborted, undispatched) = match tx_result.unwrap() {
+        Ok(undispatched) => (None, undispatched),
+        Err((reason, undispatched)) => (Some(reason), undispatched),
+    };
+    // the contracts left when the time budget ran out or the run was aborted were never
+    // handed to a job
+    let corpus_size = dispatched.len();
+    let dispatched = dispatched
+        .into_iter()
+        .filter(|(bytecode_hash, _)| !undispatched.contains(bytecode_hash))
+        .collect::<Vec<(String, String)>>();
+    let _ = fs::remove_dir_all(&work_dir);
+    let result_rows = match rx_result.unwrap() {
+        Ok(result_rows) => result_rows,
+        Err(e) => {
+            eprintln!("Could not record the results, {}", e);
+            std::process::exit(1);
+        }
+    };
+    // an aborted run did not dispatch every contract, they are not lost
+    let audit = match &aborted {
+        Some(reason) => {
+            eprintln!(
+                "Run aborted after {} of {} contracts, {} dispatched, {}",
+                result_rows.len(),
+                corpus_size,
+                dispatched.len(),
+                reason
+            );
+            None
+        }
+        None => {
+            let audit = audit::PipelineAudit::new(
+                &dispatched,
+                audit_status.snapshot().completed,
+                &result_rows,
+            );
+            audit.print();
+            Some(audit)
+        }
+    };
+    if let Some(push_task) = push_task {
+        push_task.finish().await;
+    }
+    if let Some(progress_file) = &progress_file {
+        progress_file.finish(if aborted.is_some() {
+            "aborted"
+        } else {
+            "finished"
+        });
+    }
+
+    let summary = summary::RunSummary {
+        run_tags,
+        ..summary::RunSummary::from_rows(&result_rows).with_full_corpus(full_corpus)
+    };
+    let baseline_full_corpus = args
+        .baseline
+        .as_ref()
+        .is_some_and(|path| manifest::results_full_corpus(Path::new(path)));
+    // gate on the flakes known before this run, a contract that changed in it may be a
+    // genuine regression
+    let flaky_analyzer = flakes::analyzer_key(
+        environment::analyzer_path()
+            .as_deref()
+            .and_then(fingerprint::file_hash)
+            .as_deref(),
+    );
+    let known_flaky = flake_db
+        .as_ref()
+        .map(|flake_db| flake_db.flaky(Some(&flaky_analyzer)))
+        .unwrap_or_default();
+    let baseline = baseline.map(|baseline| {
+        let mut baseline_diff = diff::compute_diff(&baseline, &result_rows, false);
+        flakes::exclude_from_diff(&mut baseline_diff, &known_flaky, args.flaky_gating);
+        (
+            summary::RunSummary::from_rows(&baseline).with_full_corpus(baseline_full_corpus),
+            baseline_diff,
+        )
+    });
+    println!(
+        "{}",
+        table::format_summary_table(
+            &summary,
+            baseline
+                .as_ref()
+                .map(|(baseline_summary, baseline_diff)| (baseline_summary, baseline_diff)),
+            table::use_color(args.no_color),
+        )
+    );
+    if let Some(timings) = table::format_timings_table(&summary, table::use_color(args.no_color)) {
+        println!("{}", timings);
+    }
+    println!(
+        "{}",
+        args.score_weights.format_score(
+            &summary,
+            baseline
+                .as_ref()
+                .map(|(baseline_summary, _)| baseline_summary)
+        )
+    );
+    match &baseline {
+        Some((baseline_summary, baseline_diff)) => println!(
+            "{}",
+            summary.format_success_rate_change(baseline_summary, baseline_diff)
+        ),
+        None => println!("Success rate: {}", summary.format_success_rate()),
+    }
+    if let Some(seed) = sample_seed {
+        println!(
+            "Sampled {} of {} contracts within the time budget (seed {})",
+            summary.total, corpus_size, seed
+        );
+    }
+    if let Some(ignored) = ignored.filter(|ignored| *ignored > 0) {
+        println!("{} contracts were excluded by the ignore file", ignored);
+    }
+    let non_interpreted = summary.counts.get("NonInterpreted").copied().unwrap_or(0);
+    if profile_warning.is_some() && non_interpreted > 0 {
+        println!(
+            "WARNING: {} NonInterpreted results with the fallback classification profile {}, \
+             see --classification-profile",
+            non_interpreted, profile.name
+        );
+    }
+    let retried = result_rows
+        .iter()
+        .filter(|row| row.spawn_retries.is_some())
+        .collect::<Vec<&ResultsRow>>();
+    if !retried.is_empty() {
+        println!(
+            "{} contracts were started again after transient failures, {} of them never started",
+            retried.len(),
+            retried
+                .iter()
+                .filter(|row| matches!(row.result, ExitType::HarnessError(_)))
+                .count()
+        );
+    }
+    let baseline_diff = baseline.map(|(_, baseline_diff)| {
+        println!("Compared to the baseline:");
+        diff::print_diff(&baseline_diff, 20);
+        baseline_diff
+    });
+    // failures triaged on the baseline that still fail the same way need no new look
+    if let Some(baseline_triage) = args
+        .baseline
+        .as_ref()
+        .and_then(|baseline| triage::load_for_results(Path::new(baseline)))
+    {
+        triage::print_triage_counts("Triaged in the baseline", &result_rows, &baseline_triage);
+    }
+    if let Some(smtp_config) = smtp_config {
+        let (subject, body) =
+            email::format_run_email(&summary, &output_path, baseline_diff.as_ref());
+        match email::send_email(&smtp_config, &subject, body) {
+            Ok(()) => println!("Emailed the run summary to {}", args.email_to.join(", ")),
+            Err(e) => eprintln!("Could not email the run summary: {}", e),
+        }
+    }
+    // CI relies on the uploaded files, a failed upload fails the run
+    let mut upload_failed = false;
+    if let Some(upload_destination) = &upload_destination {
+        let upload_key = upload::UploadKey {
+            template: args.upload_key.clone(),
+            started_at: run_started_at,
+            run_tags: report_paths.run_tags.clone(),
+            analyzer_version: analyzer_version.clone(),
+        };
+        let uploaded_run = upload::UploadedRun {
+            output_path: &output_path,
+            summary_json: serde_json::to_string_pretty(&report::run_summary(
+                &report_paths,
+                &result_rows,
+            ))
+            .unwrap(),
+            result_rows: &result_rows,
+            corpus_root: &corpus_root,
+            artifacts_dir: upload_artifacts_dir.as_deref(),
+        };
+        match upload::upload_run(upload_destination, &upload_key, &uploaded_run).await {
+            Ok(keys) => println!(
+                "Uploaded {} files to {}: {}",
+                keys.len(),
+                args.upload.as_deref().unwrap_or_default(),
+                keys.join(", ")
+            ),
+            Err(e) => {
+                eprintln!("Could not upload the run: {}", e);
+                upload_failed = true;
+            }
+        }
+    }
+
+    let mut check_failed = false;
+    if let Some(expectations) = expectations {
+        let (mismatches, flaky_mismatches) = flakes::partition_mismatches(
+            check::compare_with_expectations(&expectations, &result_rows),
+            &known_flaky,
+            args.flaky_gating,
+        );
+        check::print_mismatches(&mismatches, &flaky_mismatches, expectations.len());
+        check_failed = !mismatches.is_empty();
+    }
+    if let Some(mut flake_db) = flake_db {
+        if !known_flaky.is_empty() {
+            flakes::print_known_flakes(&result_rows, &known_flaky);
+        }
+        let newly_flaky = flake_db.record_run(&flaky_analyzer, &result_rows);
+        flakes::print_newly_flaky(&newly_flaky, &flake_db, &flaky_analyzer);
+        let path = args.flakes_db.as_deref().unwrap_or_default();
+        if let Err(e) = flake_db.save(Path::new(path)) {
+            eprintln!("Could not write flake database {}: {}", path, e);
+        }
+    }
+    if check_failed {
+        std::process::exit(1);
+    }
+    if !audit.is_some_and(|audit| audit.is_consistent()) || upload_failed {
+        std::process::exit(1);
+    }
+}
+
+/// Records whether the compiler of every contract can be installed, see `--check-compiler`,
+/// and prints how many can't
+async fn check_compilers(fiesta_metadatas: &mut [FiestaMetadata]) {
+    let releases = solc::solc_releases()
+        .await
+        .map_err(|e| {
+            eprintln!(
+                "Could not fetch the solc release list, only the svm cache is checked: {}",
+                e
+            )
+        })
+        .ok();
+    let mut unavailable = BTreeMap::new();
+    for metadata in fiesta_metadatas.iter_mut() {
+        metadata.compiler_available =
+            solc::compiler_available(&metadata.compiler_version, releases.as_ref());
+        if metadata.compiler_available == Some(false) {
+            *unavailable
+                .entry(metadata.compiler_version.clone())
+                .or_insert(0) += 1;
+        }
+    }
+    if unavailable.is_empty() {
+        return;
+    }
+    println!(
+        "{} contracts declare a compiler svm can't install: {}",
+        unavailable.values().sum::<usize>(),
+        unavailable
+            .iter()
+            .map(|(version, count)| format!("{} ({})", version, count))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+}
+
+/// Network namespace pyrometer runs in, see `--allow-network`. None with network access
+fn network_isolation(allow_network: bool) -> Option<sandbox::NetworkIsolation> {
+    match allow_network {
+        true => None,
+        false => sandbox::NetworkIsolation::detect()
+            .map_err(|e| {
+                eprintln!(
+                    "Could not cut pyrometer off from the network ({}), it runs with network \
+                     access. Pass --allow-network to run with network access on purpose",
+                    e
+                )
+            })
+            .ok(),
+    }
+}
+
+/// Walks the corpus and collects the metadata of up to `num_contracts` supported contracts,
+/// after skipping the first `skip_contracts` of them. Unreadable directories and metadata are
+/// logged and skipped, see `--max-io-errors`. The buckets already scanned are read from
+/// `checkpoint_dir` when set, see `--discovery-checkpoint`.
+pub fn discover_contracts(
+    abs_fiesta_path: &Path,
+    num_contracts: usize,
+    skip_contracts: usize,
+    max_io_errors: usize,
+    checkpoint_dir: Option<&Path>,
+) -> Vec<FiestaMetadata> {
+    /*
+    walk the directory and collect all bytecode hashes
+    path -> organized_contracts -> XX -> bytecodehash -> metadata.json
+    metadata ex: {"ContractName":"Vyper_contract","CompilerVersion":"vyper:0.3.1","Runs":0,"OptimizationUsed":false,"BytecodeHash":"832117d7cd8eb3c6a7677a71fd59bd258faf57c4434f57151d51950060922abd"}
+
+    find metadata.json files -> serde_json::from_str -> ContractMetadata
+    filter by CompilerVersion > v0.8.0 and doesnt contain "vyper"
+    */
+    discovery::discover_buckets(
+        abs_fiesta_path,
+        num_contracts,
+        skip_contracts,
+        max_io_errors,
+        checkpoint_dir,
+    )
+}
+
+/// Collects the metadata of the contracts fetched from an address list, like
+/// `discover_contracts` but only for the given bytecode hashes
+pub fn discover_fetched_contracts(
+    fetched_addresses: &BTreeMap<String, Vec<String>>,
+    corpus_root: &Path,
+    num_contracts: usize,
+    skip_contracts: usize,
+) -> Vec<FiestaMetadata> {
+    fetched_addresses
+        .keys()
+        .filter_map(|bytecode_hash| {
+            let path_to_dir = corpus_root
+                .join("organized_contracts")
+                .join(&bytecode_hash[..2])
+                .join(bytecode_hash);
+            load_contract_metadata(&path_to_dir, corpus_root)
+                .map_err(|e| eprintln!("Skipping a fetched contract, {}", e))
+                .ok()
+        })
+        .filter(FiestaMetadata::compiler_is_supported)
+        .skip(skip_contracts)
+        .take(num_contracts)
+        .collect()
+}
+
+/// A smart-contract-fiesta corpus whose contracts are fetched before the run instead of read in
+/// place
+pub enum FetchedCorpus {
+    /// Extracted from a .tar, .tar.gz, .tar.zst or .zip archive
+    Archive(PathBuf),
+    /// Downloaded from an S3 or GCS bucket into a local cache
+    Remote(remote::RemoteCorpus),
+}
+
+/// Fetches the selected contracts of the corpus into `corpus_root`, exiting when the corpus
+/// cannot be read
+pub async fn fetch_corpus(
+    fetched_corpus: &FetchedCorpus,
+    corpus_root: &Path,
+    selection: ContractSelection<'_>,
+) -> Vec<FiestaMetadata> {
+    let fetched = match fetched_corpus {
+        FetchedCorpus::Archive(archive_path) => {
+            archive::extract_contracts(archive_path, corpus_root, selection)
+        }
+        FetchedCorpus::Remote(remote_corpus) => {
+            remote::fetch_contracts(remote_corpus, corpus_root, selection).await
+        }
+    };
+    fetched.unwrap_or_else(|e| {
+        eprintln!("Could not fetch the corpus: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Which contracts of a smart-contract-fiesta corpus that is not on disk (an archive, a bucket)
+/// to fetch
+pub enum ContractSelection<'a> {
+    /// Like a corpus on disk: up to `num_contracts` supported contracts after skipping the
+    /// first `skip_contracts`
+    First {
+        num_contracts: usize,
+        skip_contracts: usize,
+    },
+    /// The contracts with these bytecode hashes, to resume a run
+    Hashes(&'a HashSet<String>),
+}
+
+impl ContractSelection<'_> {
+    /// Whether the contract is selected, in corpus order. `skipped_count` counts the contracts
+    /// skipped so far.
+    pub fn select(&self, metadata: &FiestaMetadata, skipped_count: &mut usize) -> bool {
+        match self {
+            ContractSelection::First { skip_contracts, .. } => {
+                if !metadata.compiler_is_supported() {
+                    return false;
+                }
+                if *skipped_count < *skip_contracts {
+                    *skipped_count += 1;
+                    return false;
+                }
+                true
+            }
+            ContractSelection::Hashes(hashes) => hashes.contains(&metadata.bytecode_hash),
+        }
+    }
+
+    pub fn is_complete(&self, selected: usize) -> bool {
+        match self {
+            ContractSelection::First { num_contracts, .. } => selected == *num_contracts,
+            ContractSelection::Hashes(hashes) => selected == hashes.len(),
+        }
+    }
+}
+
+/// Collects the contracts of a corpus of the given kind
+pub fn discover_corpus(
+    corpus: CorpusKind,
+    corpus_root: &Path,
+    work_dir: &Path,
+    num_contracts: usize,
+    skip_contracts: usize,
+    max_io_errors: usize,
+    discovery_checkpoint: Option<&Path>,
+) -> Result<Vec<FiestaMetadata>, OmniscanError> {
+    match corpus {
+        CorpusKind::Fiesta => Ok(discover_contracts(
+            corpus_root,
+            num_contracts,
+            skip_contracts,
+            max_io_errors,
+            discovery_checkpoint,
+        )),
+        CorpusKind::Foundry => project::discover_project_contracts(
+            Framework::Foundry,
+            corpus_root,
+            &work_dir.join("remappings"),
+            num_contracts,
+            skip_contracts,
+        ),
+        CorpusKind::Hardhat => project::discover_project_contracts(
+            Framework::Hardhat,
+            corpus_root,
+            &work_dir.join("remappings"),
+            num_contracts,
+            skip_contracts,
+        ),
+        CorpusKind::Plain => Ok(project::discover_plain_contracts(
+            corpus_root,
+            num_contracts,
+            skip_contracts,
+        )),
+    }
+}
+
+/// Parses a contract's metadata.json
+fn read_metadata_file(path: &Path) -> Result<FiestaMetadata, OmniscanError> {
+    let contents = fs::read(path).map_err(|source| OmniscanError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_slice(&contents).map_err(|source| OmniscanError::Json {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Reads the metadata.json of a single contract directory
+pub fn load_contract_metadata(
+    path_to_dir: &Path,
+    abs_fiesta_path: &Path,
+) -> Result<FiestaMetadata, OmniscanError> {
+    let mut metadata = read_metadata_file(&path_to_dir.join("metadata.json"))?;
+    metadata.update_path_to_dir(path_to_dir, abs_fiesta_path)?;
+    Ok(metadata)
+}
+
+/// Arguments of the pyrometer invocation on a contract, with the size of its entrypoint
+pub fn pyrometer_args(
+    metadata: &FiestaMetadata,
+    prepared_input: Option<&Path>,
+    analyzer: &AnalyzerConfig,
+) -> Result<(Vec<String>, u64), OmniscanError> {
+    let flags = |defaults: &[&str]| match &analyzer.args {
+        Some(analyzer_args) => analyzer_args.clone(),
+        None => defaults.iter().map(|flag| flag.to_string()).collect(),
+    };
+    let file_size = |path: &Path| {
+        fs::metadata(path)
+            .map(|file| file.len())
+            .map_err(|source| OmniscanError::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+    };
+    let path_to_dir = PathBuf::from(&metadata.abs_path_to_dir);
+
+    let mut args = Vec::new();
+    let size = if let Some(prepared_input) = prepared_input {
+        // keep the size of the original entrypoint so results stay comparable across modes
+        let size = file_size(&path_to_dir.join(metadata.entrypoint_file_name()?))?;
+        args.push(prepared_input.to_string_lossy().into_owned());
+        args.extend(flags(&["--debug"]));
+        size
+    } else {
+        let source_type = metadata
+            .source_type
+            .as_ref()
+            .ok_or_else(|| OmniscanError::SourcesNotCollected(metadata.bytecode_hash.clone()))?;
+        let path_to_file = path_to_dir.join(metadata.entrypoint_file_name()?);
+        let size = file_size(&path_to_file)?;
+        args.push(path_to_file.to_string_lossy().into_owned());
+        match source_type {
+            SourceType::SingleMain(_sol) => {
+                args.extend(flags(&["--debug", "--debug-panic"]));
+            }
+            SourceType::Multiple(_) | SourceType::EtherscanMetadata(_) => {
+                args.extend(flags(&["--debug"]));
+            }
+            SourceType::Project(project_source) => {
+                args.extend(flags(&["--debug"]));
+                if let Some(remappings) = &project_source.remappings {
+                    args.push("--remappings".to_string());
+                    args.push(remappings.to_string_lossy().into_owned());
+                }
+            }
+        }
+        size
+    };
+    if let Some(settings_flags) = &analyzer.compiler_settings {
+        args.extend(settings_flags.flags(metadata).0);
+    }
+    if let Some(flag) = &analyzer.target_contract_flag {
+        args.extend(target::target_flags(flag, metadata));
+    }
+    Ok((args, size))
+}
+
+/// Spawns pyrometer on the contract's entrypoint, or on `prepared_input` when the compilation
+/// unit was resolved or flattened beforehand (see `--crytic-compile` and `--flatten`).
+/// The flags of `analyzer` replace the default flags when set. pyrometer runs in the
+/// directories of `scratch` when set, see `--analyzer-home`, and in `cgroup` when set, see
+/// `--cpu-quota`. Failing to read the entrypoint or to start pyrometer is
+/// returned rather than panicking, it becomes the contract's HarnessError result.
+pub fn analyze_with_pyrometer(
+    metadata: &FiestaMetadata,
+    prepared_input: Option<&Path>,
+    analyzer: &AnalyzerConfig,
+    scratch: Option<&scratch::JobScratch>,
+    cgroup: Option<&cgroup::JobCgroup>,
+) -> Result<(Child, u64), OmniscanError> {
+    let (args, size) = pyrometer_args(metadata, prepared_input, analyzer)?;
+    Ok((spawn_pyrometer(args, analyzer, scratch, cgroup)?, size))
+}
+
+/// Starts pyrometer with `args`, in the scratch directories and cgroup of the job
+pub fn spawn_pyrometer(
+    args: Vec<String>,
+    analyzer: &AnalyzerConfig,
+    scratch: Option<&scratch::JobScratch>,
+    cgroup: Option<&cgroup::JobCgroup>,
+) -> Result<Child, OmniscanError> {
+    let mut command = Command::new("pyrometer");
+    command
+        .args(args)
+        .envs(analyzer.env.iter().map(|(key, value)| (key, value)));
+    // files written relative to the working directory, to the temp dir or to HOME stay
+    // with the contract
+    if let Some(scratch) = scratch {
+        scratch.apply(&mut command);
+    }
+    // the cgroup is joined first, a new user namespace would not be allowed to
+    if let Some(cgroup) = cgroup {
+        cgroup.apply(&mut command);
+    }
+    if let Some(network_isolation) = &analyzer.network_isolation {
+        network_isolation.apply(&mut command);
+    }
+    if let Some(core_dumps) = &analyzer.core_dumps {
+        core_dumps.apply(&mut command);
+    }
+    command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(OmniscanError::Spawn)
+}
+
+/// How pyrometer is invoked on every contract
+#[derive(Debug, Default)]
+pub struct AnalyzerConfig {
+    /// Flags replacing pyrometer's default flags, see `--analyzer-args`
+    pub args: Option<Vec<String>>,
+    /// Environment variables set on the child, see `--analyzer-env`
+    pub env: Vec<(String, String)>,
+    /// Network namespace of the child, None with `--allow-network` or when the host does not
+    /// allow it
+    pub network_isolation: Option<sandbox::NetworkIsolation>,
+    /// Where the cores of crashed children are collected from, see `--core-dumps`
+    pub core_dumps: Option<coredump::CoreDumps>,
+    /// Flags the compiler settings of the metadata are passed with, None unless
+    /// `--compiler-settings` is given
+    pub compiler_settings: Option<settings::AnalyzerSettingsFlags>,
+    /// Flag the ContractName of the metadata is passed with, None unless
+    /// `--target-contract-from-metadata` is given
+    pub target_contract_flag: Option<String>,
+}
+
+/// Kills a child and waits on it, so it does not linger as a zombie until omniscan exits.
+/// Returns the CPU time it used, None when it could not be reaped.
+async fn kill_and_reap(mut child: Child) -> Option<f64> {
+    let _ = child.kill();
+    tokio::task::spawn_blocking(move || cputime::reap(&child))
+        .await
+        .ok()?
+        .ok()
+}
+
+/// Text of a caught panic
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+/// Runs a step of a single contract's processing, turning a panic into the message of the
+/// contract's HarnessError so the rest of the run goes on
+fn catch_harness_panic<T>(step: &str, f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(panic::AssertUnwindSafe(f)).map_err(|payload| {
+        format!(
+            "omniscan panicked while {}: {}",
+            step,
+            panic_message(payload)
+        )
+    })
+}
+
+/// A finished analyzer process
+pub struct AnalyzerRun {
+    /// Captured stdout and stderr, None when it timed out or could not be started
+    output: Option<(String, String)>,
+    /// What the analyzer wrote to stdout and stderr before it was killed at its timeout, None
+    /// when it did not time out or ran on a worker
+    partial_output: Option<(String, String)>,
+    time: f64,
+    /// User and system CPU time of the analyzer (secs), None when it ran on a worker
+    cpu_time: Option<f64>,
+    /// Why the analyzer could not be started
+    harness_error: Option<String>,
+    /// Signal that killed the analyzer, None when it exited on its own, was killed by
+    /// omniscan at its timeout, or ran on a worker
+    signal: Option<i32>,
+}
 
-    // check if jobs is set, otherwise use number of cores
-    let jobs = match args.jobs {
-        Some(jobs) => jobs,
-        None => num_cpus::get() as u8,
-    };
+/// Spawn failures in a row across all jobs. A single failure is recorded as the contract's
+/// result, but once every spawn fails (missing binary, out of memory) the run is aborted
+/// rather than filled with failures.
+#[derive(Default)]
+pub struct SpawnFailures {
+    consecutive: AtomicUsize,
+    last_error: Mutex<String>,
+}
 
-    // check if timeout is set, otherwise use default
-    let (pyrometer_timeout, rx_loop_timeout) = match args.timeout {
-        Some(timeout) => {
-            if timeout == 0.0 {
-                (1_000_000.0, 1_000_000.0) // inf
-            } else {
-                (timeout, timeout + 1.0)
+impl SpawnFailures {
+    /// Only failures to start the process count, an unreadable entrypoint is the contract's
+    /// problem and not the run's
+    fn record<T>(&self, spawned: &Result<T, OmniscanError>) {
+        match spawned {
+            Ok(_) => self.consecutive.store(0, Ordering::SeqCst),
+            Err(OmniscanError::Spawn(e)) => {
+                *self.last_error.lock().unwrap() = e.to_string();
+                self.consecutive.fetch_add(1, Ordering::SeqCst);
             }
-        }
-        None => (2.0, 2.0 + 1.0),
-    };
-
-    // check if num_contracts is set, otherwise use default
-    let num_contracts = match args.num_contracts {
-        Some(num_contracts) => {
-            if num_contracts == 0 {
-                std::usize::MAX
-            } else {
-                num_contracts
+            Err(e @ OmniscanError::TransientSpawn { .. }) => {
+                *self.last_error.lock().unwrap() = e.to_string();
+                self.consecutive.fetch_add(1, Ordering::SeqCst);
             }
+            Err(_) => {}
         }
-        None => 5000,
-    };
-
-    // check if skip_contracts is set, otherwise use default
-    let skip_contracts = args.skip_contracts.unwrap_or(0);
+    }
 
-    let mut fiesta_metadatas: Vec<FiestaMetadata> = Vec::with_capacity(FIESTA_TOTAL_CONTRACTS);
+    /// Why the run is aborted, None while fewer than `limit` spawns failed in a row.
+    /// A limit of 0 never aborts.
+    fn systemic(&self, limit: usize) -> Option<String> {
+        let consecutive = self.consecutive.load(Ordering::SeqCst);
+        (limit > 0 && consecutive >= limit).then(|| {
+            format!(
+                "the last {} pyrometer processes could not be started: {}",
+                consecutive,
+                self.last_error.lock().unwrap()
+            )
+        })
+    }
+}
 
-    /*
-    walk the directory and collect all bytecode hashes
-    path -> organized_contracts -> XX -> bytecodehash -> metadata.json
-    metadata ex: {"ContractName":"Vyper_contract","CompilerVersion":"vyper:0.3.1","Runs":0,"OptimizationUsed":false,"BytecodeHash":"832117d7cd8eb3c6a7677a71fd59bd258faf57c4434f57151d51950060922abd"}
+/// How long the output of an analyzer is still read for once it exited or was killed at its
+/// timeout, as processes it started may keep its pipes open
+const SALVAGE_GRACE: Duration = Duration::from_secs(1);
 
-    find metadata.json files -> serde_json::from_str -> ContractMetadata
-    filter by CompilerVersion > v0.8.0 and doesnt contain "vyper"
-    */
-    let mut contract_count = 0;
-    let mut skipped_count = 0;
-    for entry in WalkDir::new(abs_fiesta_path.join("organized_contracts")) {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        // check if path is metadata.json
-        if path.is_file() && path.file_name().unwrap() == "metadata.json" {
-            // read the file
-            let file = std::fs::File::open(path).unwrap();
-            let mut metadata: FiestaMetadata = serde_json::from_reader(file).unwrap();
-            // filter by compiler version
-            if !metadata.compiler_is_supported() {
-                continue;
-            }
+/// How often the rx loop checks the stop signal while no result comes in
+const RX_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-            if skipped_count < skip_contracts {
-                skipped_count += 1;
-                continue;
+/// Polls the analyzer every `poll_interval` until it exits or the timeout is reached, killing
+/// it then. Returns None if it could not be polled.
+async fn wait_for_analyzer(
+    mut child: Child,
+    pyrometer_timeout: f64,
+    poll_interval: Duration,
+) -> Option<AnalyzerRun> {
+    // drain the pipes while the child runs, not only once it exited
+    let output_readers = OutputReaders::spawn(&mut child);
+    let pyrometer_timeout_duration = Duration::from_secs_f64(pyrometer_timeout);
+    let start_time = Instant::now();
+    // Poll the child process in a loop until timeout is reached
+    loop {
+        match cputime::try_reap(&child) {
+            Ok(Some(reaped)) => {
+                let time = start_time.elapsed().as_secs_f64();
+                let output =
+                    tokio::task::spawn_blocking(move || output_readers.salvage(SALVAGE_GRACE))
+                        .await
+                        .unwrap();
+                return Some(AnalyzerRun {
+                    output: Some(output),
+                    partial_output: None,
+                    time,
+                    cpu_time: Some(reaped.cpu_time),
+                    harness_error: None,
+                    signal: reaped.signal,
+                });
             }
-            // update the path to the directory (without the metadata.json file on the path)
-            let mut path_to_dir = path.to_path_buf();
-            path_to_dir.pop();
-            metadata.update_path_to_dir(&path_to_dir);
-            fiesta_metadatas.push(metadata);
-            contract_count += 1;
-            if contract_count % 1000 == 0 {
-                println!(
-                    "Total of {} contracts added to analysis queue",
-                    contract_count
-                );
+            Ok(None) => {
+                // Check if timeout is reached
+                if start_time.elapsed() > pyrometer_timeout_duration {
+                    let cpu_time = kill_and_reap(child).await;
+                    let partial_output =
+                        tokio::task::spawn_blocking(move || output_readers.salvage(SALVAGE_GRACE))
+                            .await
+                            .unwrap();
+                    return Some(AnalyzerRun {
+                        output: None,
+                        partial_output: Some(partial_output),
+                        time: pyrometer_timeout,
+                        cpu_time,
+                        harness_error: None,
+                        signal: None,
+                    });
+                }
+                // async sleep for a short duration to avoid busy waiting. this wait is also our resolution for pyro completion
+                tokio::time::sleep(poll_interval).await;
             }
-            if contract_count == num_contracts {
-                break;
+            Err(e) => {
+                println!("Error while polling child process: {:?}", e);
+                kill_and_reap(child).await;
+                return None;
             }
         }
     }
+}
 
-    fiesta_metadatas.iter_mut().for_each(|metadata| {
-        collect_contract_sources(metadata);
-    });
-    fiesta_metadatas.retain(|metadata| metadata.source_type.is_some());
-
-    println!("Beginning analysis of {} contracts", fiesta_metadatas.len());
-
-    // Create a channel for threads to send their results
-    let (tx, rx) = mpsc::channel();
+/// A started analysis: a pyrometer process of its own, or a worker of the pool with the
+/// arguments of the contract
+enum AnalyzerProcess {
+    Child(Child),
+    Worker(workers::Worker, Vec<String>),
+}
 
-    // Create a oneshot to signal the rx loop to stop
-    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+/// Starts the analysis of a contract, on a worker when there is a worker pool
+fn start_analyzer(
+    metadata: &FiestaMetadata,
+    prepared_input: Option<&Path>,
+    analyzer: &AnalyzerConfig,
+    scratch: Option<&scratch::JobScratch>,
+    cgroup: Option<&cgroup::JobCgroup>,
+    worker_pool: Option<&workers::WorkerPool>,
+) -> Result<(AnalyzerProcess, u64), OmniscanError> {
+    match worker_pool {
+        Some(worker_pool) => {
+            let (args, size) = pyrometer_args(metadata, prepared_input, analyzer)?;
+            Ok((AnalyzerProcess::Worker(worker_pool.checkout()?, args), size))
+        }
+        None => analyze_with_pyrometer(metadata, prepared_input, analyzer, scratch, cgroup)
+            .map(|(child, size)| (AnalyzerProcess::Child(child), size)),
+    }
+}
 
-    // Create a thread that runs the rx loop
-    let rx_handle = tokio::spawn(async move {
-        rx_loop(rx, stop_rx, output_path, rx_loop_timeout).await;
-    });
+/// Waits for the analysis started with `start_analyzer`, see `wait_for_analyzer`
+async fn finish_analyzer(
+    process: AnalyzerProcess,
+    worker_pool: Option<&Arc<workers::WorkerPool>>,
+    pyrometer_timeout: f64,
+    poll_interval: Duration,
+) -> Option<AnalyzerRun> {
+    match process {
+        AnalyzerProcess::Child(child) => {
+            wait_for_analyzer(child, pyrometer_timeout, poll_interval).await
+        }
+        AnalyzerProcess::Worker(worker, args) => Some(
+            worker_pool
+                .expect("workers come from the pool")
+                .analyze(worker, args, pyrometer_timeout)
+                .await,
+        ),
+    }
+}
 
-    let tx_handle = tokio::spawn(async move {
-        tx_loop(
-            fiesta_metadatas,
-            tx,
-            stop_tx,
-            jobs.into(),
-            pyrometer_timeout,
-        )
-        .await;
-    });
+/// Everything the tx loop needs to dispatch contracts
+pub struct TxLoopConfig {
+    /// Number of concurrent processes, adjustable during the run
+    pub job_limit: Arc<concurrency::JobLimit>,
+    /// Spawn failures in a row that abort the run, see `--max-spawn-failures`
+    pub max_spawn_failures: usize,
+    pub pyrometer_timeout: f64,
+    pub journal: Option<Arc<queue::QueueJournal>>,
+    pub status: Arc<RunStatus>,
+    /// Also compile every contract with its own solc version
+    pub solc_check: bool,
+    /// Analyze every contract a second time, see `--determinism-check`
+    pub determinism_check: bool,
+    /// How often running analyzers are checked for completion
+    pub poll_interval: Duration,
+    /// Number of first dispatched contracts whose timings are left out, see `--warmup`
+    pub warmup: usize,
+    /// Number of job slots the contracts are assigned to in turn, None to run each one in the
+    /// first slot free, see `--deterministic-schedule`
+    pub slots: Option<usize>,
+    /// Retries of analyzers that could not be started for lack of resources
+    pub spawn_retry: retry::SpawnRetry,
+    /// Cgroups limiting the CPU of each analyzer, see `--cpu-quota`
+    pub cpu_quota: Option<Arc<cgroup::CpuQuota>>,
+    /// How sources are prepared before the analysis, None to analyze them as they are
+    pub preparation: Option<SourcePreparation>,
+    /// Scratch directory for the prepared sources
+    pub prepare_work_dir: PathBuf,
+    /// Flags and environment of the pyrometer invocations
+    pub analyzer: Arc<AnalyzerConfig>,
+    /// Descriptor limit new jobs wait on, None when it is unknown
+    pub fd_budget: Option<fds::FdBudget>,
+    /// Directory the sources are copied to before the analysis, see `--workspace`
+    pub workspace_dir: Option<PathBuf>,
+    /// Scratch directory holding the directory each analyzer runs in, and the artifacts
+    /// directory their files are moved to, see `--collect-outputs`
+    pub collect_outputs: Option<(PathBuf, PathBuf)>,
+    /// Artifacts directory the cores of crashed analyzers are moved to, see `--core-dumps`
+    pub core_dumps_dir: Option<PathBuf>,
+    /// Scratch directory holding the working directory and HOME of each analyzer
+    pub scratch_dir: PathBuf,
+    /// Whether the analyzers get a HOME per contract, one for the run or the user's, see
+    /// `--analyzer-home`
+    pub analyzer_home: scratch::AnalyzerHome,
+    /// Long-lived analyzers the contracts are handed to, see `--worker-pool`
+    pub worker_pool: Option<Arc<workers::WorkerPool>>,
+    /// Number of contracts per batch and largest file batched, see `--batch-size`
+    pub batching: Option<(usize, u64)>,
+    /// Success rate alert aborting the run once it fired, see `--alert-abort`
+    pub alert: Option<Arc<alert::SuccessRateAlert>>,
+    /// When to stop dispatching contracts, see `--time-budget`
+    pub deadline: Option<Instant>,
+}
 
-    let _ = tokio::join!(tx_handle, rx_handle);
+/// Pre-processing applied to a contract's sources before it is handed to pyrometer
+#[derive(Clone, Copy, Debug)]
+pub enum SourcePreparation {
+    /// Resolve the compilation unit with crytic-compile, see `--crytic-compile`
+    CryticCompile,
+    /// Flatten multi-file contracts into one file, see `--flatten`
+    Flatten(flatten::FlattenMode),
 }
 
-pub fn analyze_with_pyrometer(metadata: &FiestaMetadata) -> (Child, u64) {
-    match metadata.clone().source_type.unwrap() {
-        SourceType::SingleMain(_sol) => {
-            let path_to_file = PathBuf::from(metadata.abs_path_to_dir.clone()).join("main.sol");
-            // reformat path_to_file as a string
-            let path_to_file = path_to_file.to_str().unwrap();
-            let size = fs::metadata(path_to_file).unwrap().len();
+/// Prepares the contract's sources in `work_dir`. Returns the input to analyze instead of the
+/// original entrypoint, None if the sources are analyzed as they are, and whether flattening
+/// succeeded, None if the contract was not flattened.
+fn prepare_sources(
+    metadata: &FiestaMetadata,
+    preparation: SourcePreparation,
+    work_dir: &Path,
+) -> (Option<PathBuf>, Option<bool>) {
+    let contract_work_dir = crytic::contract_work_dir(work_dir, &metadata.bytecode_hash);
+    let (prepared, flattened) = match preparation {
+        SourcePreparation::CryticCompile => {
+            (crytic::prepare_compilation_unit(metadata, work_dir), None)
+        }
+        SourcePreparation::Flatten(_) if !flatten::is_multi_file(metadata) => return (None, None),
+        SourcePreparation::Flatten(flatten_mode) => {
+            let prepared = flatten::flatten_contract(metadata, flatten_mode, &contract_work_dir);
+            let flattened = prepared.is_ok();
+            (prepared, Some(flattened))
+        }
+    };
+    match prepared {
+        Ok(prepared_input) => (Some(prepared_input), flattened),
+        Err(e) => {
+            eprintln!(
+                "{}: {}, analyzing the original sources",
+                metadata.bytecode_hash, e
+            );
+            (None, flattened)
+        }
+    }
+}
 
-            let child = Command::new("pyrometer")
-                .args(&[path_to_file, "--debug", "--debug-panic"])
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .expect("Failed to spawn process");
+/// Held by the job of a slot until it finished, frees the slot for its next unit when dropped,
+/// see `Schedule`
+struct SlotPermit {
+    slot: usize,
+    free_slots: tokio::sync::mpsc::UnboundedSender<usize>,
+}
 
-            (child, size)
-        }
-        SourceType::Multiple(multiple_files) => {
-            let substr_to_find = format!("contract {} ", metadata.contract_name);
-            for (name, sol_string) in multiple_files {
-                if sol_string.contains(&substr_to_find) {
-                    let path_to_file = PathBuf::from(metadata.abs_path_to_dir.clone()).join(name);
-                    let path_to_file = path_to_file.to_str().unwrap();
-                    let size = fs::metadata(path_to_file).unwrap().len();
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        let _ = self.free_slots.send(self.slot);
+    }
+}
 
-                    let child = Command::new("pyrometer")
-                        .args(&[path_to_file, "--debug"])
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .spawn()
-                        .expect("Failed to spawn process");
+/// Order the units are dispatched in: queue order, or with job slots (see
+/// `--deterministic-schedule`) the i-th unit in slot i % slots after the unit before it in that
+/// slot. Slots are served as they free up, so a long contract only holds up its own slot.
+struct Schedule {
+    units: std::vec::IntoIter<batch::DispatchUnit>,
+    slot_queues: Vec<VecDeque<batch::DispatchUnit>>,
+    free_slots: tokio::sync::mpsc::UnboundedSender<usize>,
+    freed_slots: tokio::sync::mpsc::UnboundedReceiver<usize>,
+}
 
-                    return (child, size);
+impl Schedule {
+    fn new(units: Vec<batch::DispatchUnit>, slots: Option<usize>) -> Self {
+        let (free_slots, freed_slots) = tokio::sync::mpsc::unbounded_channel();
+        let slots = slots.unwrap_or(0);
+        let mut slot_queues = (0..slots).map(|_| VecDeque::new()).collect::<Vec<_>>();
+        let units = match slots {
+            0 => units,
+            _ => {
+                for (idx, unit) in units.into_iter().enumerate() {
+                    slot_queues[idx % slots].push_back(unit);
+                }
+                for slot in 0..slots {
+                    let _ = free_slots.send(slot);
                 }
+                Vec::new()
             }
-            panic!(
-                "Could not find contract name {} in multiple_files",
-                metadata.contract_name
-            );
+        };
+        Self {
+            units: units.into_iter(),
+            slot_queues,
+            free_slots,
+            freed_slots,
         }
-        SourceType::EtherscanMetadata(_source_metadata) => {
-            let path_to_file =
-                PathBuf::from(metadata.abs_path_to_dir.clone()).join("contract.json");
-            let path_to_file = path_to_file.to_str().unwrap();
-            let size = fs::metadata(path_to_file).unwrap().len();
-            let child = Command::new("pyrometer")
-                .args(&[path_to_file, "--debug"])
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .expect("Failed to spawn process");
+    }
 
-            (child, size)
+    /// The next unit, with the permit of its slot when there are slots, once a slot is free
+    async fn next(&mut self) -> Option<(batch::DispatchUnit, Option<SlotPermit>)> {
+        if self.slot_queues.is_empty() {
+            return self.units.next().map(|unit| (unit, None));
+        }
+        while self.slot_queues.iter().any(|queue| !queue.is_empty()) {
+            let slot = self.freed_slots.recv().await?;
+            if let Some(unit) = self.slot_queues[slot].pop_front() {
+                let permit = SlotPermit {
+                    slot,
+                    free_slots: self.free_slots.clone(),
+                };
+                return Some((unit, Some(permit)));
+            }
         }
+        None
+    }
+
+    /// The units not handed out yet, without waiting for their slots
+    fn remaining(self) -> impl Iterator<Item = batch::DispatchUnit> {
+        self.units.chain(self.slot_queues.into_iter().flatten())
     }
 }
 
+/// Why a run was aborted, with the contracts it did not dispatch
+pub type TxAbort = (String, HashSet<String>);
+
+/// Dispatches every contract, returning the bytecode hashes of the contracts that were not:
+/// once the deadline passes, the contracts left are not dispatched but returned. When the run
+/// is aborted, returns why along with the contracts it did not dispatch.
 pub async fn tx_loop(
     fiesta_metadatas: Vec<FiestaMetadata>,
     tx_result: mpsc::Sender<ResultMessage>,
     tx_stop: oneshot::Sender<()>,
-    max_concurrent_processes: usize,
-    pyrometer_timeout: f64,
-) {
+    config: TxLoopConfig,
+) -> Result<HashSet<String>, TxAbort> {
+    let TxLoopConfig {
+        job_limit,
+        max_spawn_failures,
+        pyrometer_timeout,
+        journal,
+        status,
+        solc_check,
+        determinism_check,
+        poll_interval,
+        warmup,
+        slots,
+        spawn_retry,
+        cpu_quota,
+        preparation,
+        prepare_work_dir,
+        analyzer,
+        fd_budget,
+        workspace_dir,
+        collect_outputs,
+        core_dumps_dir,
+        scratch_dir,
+        analyzer_home,
+        worker_pool,
+        batching,
+        alert,
+        deadline,
+    } = config;
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_time()
         .build()
         .unwrap();
 
     // Semaphore for limiting the number of concurrent processes
-    let semaphore = Arc::new(Semaphore::new(max_concurrent_processes));
+    let semaphore = job_limit.semaphore();
 
-    let pyrometer_timeout_duration = Duration::from_secs_f64(pyrometer_timeout);
     let mut join_handles = Vec::new();
+    let spawn_failures = Arc::new(SpawnFailures::default());
+    let mut aborted = None;
+    let units = match batching {
+        Some((batch_size, max_bytes)) => {
+            batch::plan_batches(fiesta_metadatas, batch_size, max_bytes, &analyzer)
+        }
+        None => fiesta_metadatas
+            .into_iter()
+            .map(|metadata| batch::DispatchUnit::Contract(Box::new(metadata)))
+            .collect(),
+    };
+
+    let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    let mut undispatched = HashSet::new();
+    let mut dispatched_contracts = 0;
+    let mut schedule = Schedule::new(units, slots);
 
-    for metadata in fiesta_metadatas {
+    // with a deterministic schedule, a unit is handed out once its slot is free
+    while let Some((unit, slot_permit)) = schedule.next().await {
+        // the unit is next in line, it now waits for a job
+        let queued_at = chrono::Utc::now();
+        if out_of_time() {
+            undispatched.extend(unit.bytecode_hashes());
+            continue;
+        }
         let tx = tx_result.clone();
+        let journal = journal.clone();
+        let status = status.clone();
+        let prepare_work_dir = prepare_work_dir.clone();
+        let analyzer = analyzer.clone();
+        let workspace_dir = workspace_dir.clone();
+        let collect_outputs = collect_outputs.clone();
+        let core_dumps_dir = core_dumps_dir.clone();
+        let scratch_dir = scratch_dir.clone();
+        let cpu_quota = cpu_quota.clone();
+        let worker_pool = worker_pool.clone();
+        let spawn_failures = spawn_failures.clone();
         let semaphore = semaphore.clone();
-        let permit = semaphore.acquire_owned().await;
+        if let Some(fd_budget) = &fd_budget {
+            fd_budget
+                .wait_for_descriptors(|| semaphore.available_permits() < job_limit.jobs())
+                .await;
+        }
+        // contracts are dispatched in queue order: permits are only requested by this loop,
+        // one contract at a time, and when lowering the number of jobs, and tokio's semaphore
+        // hands them out first come first served
+        let permit = (semaphore.acquire_owned().await, slot_permit);
+        status.wait_while_paused().await;
+        // jobs that were running while this one waited may have found the analyzer broken
+        if let Some(reason) = spawn_failures.systemic(max_spawn_failures) {
+            undispatched.extend(unit.bytecode_hashes());
+            aborted = Some(reason);
+            break;
+        }
+        if let Some(reason) = alert.as_ref().and_then(|alert| alert.abort_reason()) {
+            undispatched.extend(unit.bytecode_hashes());
+            aborted = Some(reason);
+            break;
+        }
+        // the budget may have run out while waiting for a job
+        if out_of_time() {
+            undispatched.extend(unit.bytecode_hashes());
+            continue;
+        }
+        let started_at = chrono::Utc::now();
+        let warmup_job = dispatched_contracts < warmup;
+        dispatched_contracts += match &unit {
+            batch::DispatchUnit::Contract(_) => 1,
+            batch::DispatchUnit::Batch(batch) => batch.len(),
+        };
 
-        let join_handle = runtime.spawn(async move {
-            // Spawn the child process
-            let (mut child, size) = analyze_with_pyrometer(&metadata);
-
-            let start_time = Instant::now();
-            // Poll the child process in a loop until timeout is reached
-            loop {
-                match child.try_wait() {
-                    Ok(Some(_status)) => {
-                        let result_message = ResultMessage {
-                            metadata: metadata.clone(),
-                            child: Some(child),
-                            time: start_time.elapsed().as_secs_f64(),
-                            size,
-                        };
-                        let _ = tx.send(result_message);
-                        break;
+        let metadata = match unit {
+            batch::DispatchUnit::Contract(metadata) => *metadata,
+            batch::DispatchUnit::Batch(batch) => {
+                let batch_job = batch::BatchJob {
+                    tx: tx.clone(),
+                    journal,
+                    status: status.clone(),
+                    analyzer,
+                    scratch_dir,
+                    analyzer_home,
+                    spawn_failures,
+                    solc_check,
+                    pyrometer_timeout,
+                    poll_interval,
+                    spawn_retry,
+                    warmup: warmup_job,
+                    queued_at,
+                    started_at,
+                };
+                let job_batch = batch.clone();
+                let job = async move {
+                    batch_job.run(batch).await;
+                    drop(permit);
+                };
+                join_handles.push(runtime.spawn(async move {
+                    if let Err(e) = tokio::spawn(job).await {
+                        if e.is_panic() {
+                            let harness_error =
+                                format!("omniscan panicked: {}", panic_message(e.into_panic()));
+                            status.abandon_job(&job_batch[0].bytecode_hash);
+                            for metadata in &job_batch {
+                                let _ = tx.send(ResultMessage::harness_error(
+                                    metadata,
+                                    harness_error.clone(),
+                                    queued_at,
+                                    started_at,
+                                ));
+                            }
+                        }
                     }
-                    Ok(None) => {
-                        // Check if timeout is reached
-                        if start_time.elapsed() > pyrometer_timeout_duration {
-                            let _ = child.kill();
-                            let result_message = ResultMessage {
-                                metadata: metadata.clone(),
-                                child: None,
-                                time: pyrometer_timeout,
-                                size,
-                            };
-                            let _ = tx.send(result_message);
-                            break;
+                }));
+                continue;
+            }
+        };
+        let job_metadata = metadata.clone();
+        let job_tx = tx.clone();
+        let job_status = status.clone();
+        let job = async move {
+            if let Some(harness_error) = &metadata.harness_error {
+                let _ = tx.send(ResultMessage::harness_error(
+                    &metadata,
+                    harness_error.clone(),
+                    queued_at,
+                    started_at,
+                ));
+                return;
+            }
+
+            // the copy is analyzed, results keep referring to the corpus
+            let analyzed_metadata = match &workspace_dir {
+                Some(workspace_dir) => {
+                    let copy_metadata = metadata.clone();
+                    let workspace_dir = workspace_dir.clone();
+                    tokio::task::spawn_blocking(move || {
+                        workspace::copy_contract(&copy_metadata, &workspace_dir)
+                    })
+                    .await
+                    .unwrap()
+                    .unwrap_or_else(|e| {
+                        eprintln!(
+                            "{}: could not copy to the workspace: {}, analyzing in place",
+                            metadata.bytecode_hash, e
+                        );
+                        metadata.clone()
+                    })
+                }
+                None => metadata.clone(),
+            };
+            let (prepared_input, flattened) = match preparation {
+                Some(preparation) => {
+                    let prepare_metadata = analyzed_metadata.clone();
+                    let work_dir = prepare_work_dir.clone();
+                    tokio::task::spawn_blocking(move || {
+                        prepare_sources(&prepare_metadata, preparation, &work_dir)
+                    })
+                    .await
+                    .unwrap()
+                }
+                None => (None, None),
+            };
+
+            let run_dir = match &collect_outputs {
+                Some((runs_dir, _)) => {
+                    let run_dir = artifacts::run_dir(runs_dir, &metadata.bytecode_hash);
+                    match tokio::fs::create_dir_all(&run_dir).await {
+                        Ok(()) => Some(run_dir),
+                        Err(source) => {
+                            eprintln!(
+                                "{}: {}, its output is not collected",
+                                metadata.bytecode_hash,
+                                OmniscanError::Write {
+                                    path: run_dir,
+                                    source
+                                }
+                            );
+                            None
                         }
-                        // async sleep for a short duration to avoid busy waiting. this wait is also our resolution for pyro completion
-                        tokio::time::sleep(Duration::from_millis(2)).await;
                     }
-                    Err(e) => {
-                        println!("Error while polling child process: {:?}", e);
-                        break;
+                }
+                None => None,
+            };
+            // removed at the end of the job, with the rest of the analyzer's scratch files
+            let scratch = scratch::JobScratch::create(
+                &scratch_dir,
+                &metadata.bytecode_hash,
+                analyzer_home,
+                run_dir.as_deref(),
+            )
+            .map_err(|e| {
+                eprintln!(
+                    "{}: could not create the scratch directories: {}, analyzing in omniscan's",
+                    metadata.bytecode_hash, e
+                )
+            })
+            .ok();
+            // held until the analyzer processes are reaped, at the end of the job
+            let job_cgroup = cpu_quota.as_ref().and_then(|cpu_quota| {
+                cpu_quota
+                    .job_cgroup(&metadata.bytecode_hash)
+                    .map_err(|e| {
+                        eprintln!(
+                            "{}: {}, analyzing without a CPU quota",
+                            metadata.bytecode_hash, e
+                        )
+                    })
+                    .ok()
+            });
+
+            // Spawn the child process, timed apart from the analysis and from the waits
+            // between retries
+            let spawned_at = SystemTime::now();
+            let mut spawn_time = 0.0;
+            let (spawned, spawn_retries) = spawn_retry
+                .spawn(|| {
+                    let spawn_start = Instant::now();
+                    let spawned = start_analyzer(
+                        &analyzed_metadata,
+                        prepared_input.as_deref(),
+                        &analyzer,
+                        scratch.as_ref(),
+                        job_cgroup.as_ref(),
+                        worker_pool.as_deref(),
+                    );
+                    spawn_time = spawn_start.elapsed().as_secs_f64();
+                    spawned
+                })
+                .await;
+            spawn_failures.record(&spawned);
+            if let Some(journal) = &journal {
+                // a journal that can't be written fails the run once the result is recorded
+                let _ = journal.record_dispatched(&metadata.bytecode_hash);
+            }
+            let worker = status.start_job(&metadata.bytecode_hash);
+
+            // compile with solc alongside the analysis, not after it
+            let solc_handle = solc_check.then(|| {
+                let metadata = analyzed_metadata.clone();
+                let honor_settings = analyzer.compiler_settings.is_some();
+                tokio::task::spawn_blocking(move || solc::solc_compiles(&metadata, honor_settings))
+            });
+
+            let (run, size, pid) = match spawned {
+                Ok((process, size)) => {
+                    let pid = match &process {
+                        AnalyzerProcess::Child(child) => Some(child.id()),
+                        AnalyzerProcess::Worker(..) => None,
+                    };
+                    (
+                        finish_analyzer(
+                            process,
+                            worker_pool.as_ref(),
+                            pyrometer_timeout,
+                            poll_interval,
+                        )
+                        .await,
+                        size,
+                        pid,
+                    )
+                }
+                Err(e) => (
+                    Some(AnalyzerRun {
+                        output: None,
+                        partial_output: None,
+                        time: 0.0,
+                        cpu_time: None,
+                        harness_error: Some(e.to_string()),
+                        signal: None,
+                    }),
+                    0,
+                    None,
+                ),
+            };
+            // the core may be in the working directory of the analyzer, removed with the job
+            let crashed = run.as_ref().is_some_and(|run| run.signal.is_some());
+            let core_dump = match (core_dumps_dir.clone(), pid) {
+                (Some(core_dumps_dir), Some(pid)) if crashed => {
+                    let analyzer = analyzer.clone();
+                    let cwd = scratch
+                        .as_ref()
+                        .map(|scratch| scratch.cwd().to_path_buf())
+                        .or_else(|| std::env::current_dir().ok());
+                    let bytecode_hash = metadata.bytecode_hash.clone();
+                    tokio::task::spawn_blocking(move || {
+                        analyzer
+                            .core_dumps
+                            .as_ref()?
+                            .collect(
+                                pid,
+                                cwd.as_deref(),
+                                spawned_at,
+                                &core_dumps_dir,
+                                &bytecode_hash,
+                            )
+                            .map_err(|e| {
+                                eprintln!("{}: could not collect the core: {}", bytecode_hash, e)
+                            })
+                            .ok()
+                            .flatten()
+                    })
+                    .await
+                    .unwrap()
+                }
+                _ => None,
+            };
+            let result_message = run.map(|run| ResultMessage {
+                metadata: metadata.clone(),
+                output: run.output,
+                partial_output: run.partial_output,
+                time: run.time,
+                cpu_time: run.cpu_time,
+                harness_error: run.harness_error,
+                spawn_time,
+                queued_at,
+                started_at,
+                size,
+                solc_compiles: None,
+                flattened,
+                analyzer_outputs: Vec::new(),
+                rerun_output: None,
+                batch_size: None,
+                spawn_retries,
+                warmup: warmup_job,
+                core_dump,
+                unhonored_settings: analyzer
+                    .compiler_settings
+                    .as_ref()
+                    .map(|settings_flags| settings_flags.flags(&analyzed_metadata).1)
+                    .unwrap_or_default(),
+            });
+            // the second run of --determinism-check, under the same permit
+            let rerun = match &result_message {
+                Some(result_message)
+                    if determinism_check && result_message.harness_error.is_none() =>
+                {
+                    let (spawned, _) = spawn_retry
+                        .spawn(|| {
+                            start_analyzer(
+                                &analyzed_metadata,
+                                prepared_input.as_deref(),
+                                &analyzer,
+                                scratch.as_ref(),
+                                job_cgroup.as_ref(),
+                                worker_pool.as_deref(),
+                            )
+                        })
+                        .await;
+                    spawn_failures.record(&spawned);
+                    match spawned {
+                        Ok((process, _)) => {
+                            finish_analyzer(
+                                process,
+                                worker_pool.as_ref(),
+                                pyrometer_timeout,
+                                poll_interval,
+                            )
+                            .await
+                        }
+                        Err(_) => None,
                     }
                 }
+                _ => None,
+            };
+
+            // removing it kills what the analyzer left running and waits for it to die
+            if let Some(job_cgroup) = job_cgroup {
+                let _ = tokio::task::spawn_blocking(move || drop(job_cgroup)).await;
+            }
+
+            let (solc_compiles, solc_unhonored) = match solc_handle {
+                Some(solc_handle) => solc_handle.await.unwrap_or((None, Vec::new())),
+                None => (None, Vec::new()),
+            };
+            // a killed analyzer may have written part of its files, keep them as well
+            let analyzer_outputs = match (run_dir, &collect_outputs) {
+                (Some(run_dir), Some((_, artifacts_dir))) => {
+                    let (artifacts_dir, bytecode_hash) =
+                        (artifacts_dir.clone(), metadata.bytecode_hash.clone());
+                    // walks and moves every file the analyzer wrote
+                    tokio::task::spawn_blocking(move || {
+                        artifacts::collect_outputs(&run_dir, &artifacts_dir, &bytecode_hash)
+                            .unwrap_or_else(|e| {
+                                eprintln!(
+                                    "{}: could not collect the analyzer outputs: {}",
+                                    bytecode_hash, e
+                                );
+                                Vec::new()
+                            })
+                    })
+                    .await
+                    .unwrap_or_default()
+                }
+                _ => Vec::new(),
+            };
+            if let Some(mut result_message) = result_message {
+                result_message.solc_compiles = solc_compiles;
+                result_message.unhonored_settings.extend(solc_unhonored);
+                result_message.analyzer_outputs = analyzer_outputs;
+                result_message.rerun_output = rerun.map(|run| run.output);
+                let _ = tx.send(result_message);
+            }
+            if preparation.is_some() {
+                let _ = fs::remove_dir_all(crytic::contract_work_dir(
+                    &prepare_work_dir,
+                    &metadata.bytecode_hash,
+                ));
+            }
+            if let Some(workspace_dir) = &workspace_dir {
+                let _ = fs::remove_dir_all(workspace::contract_copy_dir(
+                    workspace_dir,
+                    &metadata.bytecode_hash,
+                ));
             }
 
+            status.finish_job(worker);
             // Drop the semaphore permit
             drop(permit);
+        };
+        // a panicking job releases its permit as it unwinds, and is recorded as a HarnessError
+        let join_handle = runtime.spawn(async move {
+            if let Err(e) = tokio::spawn(job).await {
+                if e.is_panic() {
+                    job_status.abandon_job(&job_metadata.bytecode_hash);
+                    let _ = job_tx.send(ResultMessage::harness_error(
+                        &job_metadata,
+                        format!("omniscan panicked: {}", panic_message(e.into_panic())),
+                        queued_at,
+                        started_at,
+                    ));
+                }
+            }
         });
 
         join_handles.push(join_handle);
@@ -413,89 +4270,412 @@ pub async fn tx_loop(
     })
     .join()
     .unwrap();
+
+    match aborted {
+        Some(reason) => {
+            undispatched.extend(schedule.remaining().flat_map(|unit| unit.bytecode_hashes()));
+            Err((reason, undispatched))
+        }
+        None => Ok(undispatched),
+    }
+}
+
+/// Everything the rx loop needs to record results
+pub struct RxLoopConfig {
+    pub output_path: PathBuf,
+    pub report_paths: ReportPaths,
+    pub artifacts_dir: Option<PathBuf>,
+    /// Append to an existing results file instead of starting a new one
+    pub append_results: bool,
+    /// Size (bytes) of the parts the results are rotated into, see `--rotate-results`
+    pub rotate_results: Option<u64>,
+    /// Which results are written to the results file, see `--record`
+    pub record: results::RecordMode,
+    pub journal: Option<Arc<queue::QueueJournal>>,
+    pub status: Arc<RunStatus>,
+    /// Success rate alert fed with every result, see `--alert-threshold`
+    pub alert: Option<Arc<alert::SuccessRateAlert>>,
+    pub alert_webhook: Option<String>,
+    /// How the analyzer's output is classified, see `--classification-profile` and
+    /// `--rules-file`
+    pub classifier: profiles::Classifier,
+    /// Flag the analyzer was given the ContractName with, see `--target-contract-from-metadata`
+    pub target_contract_flag: Option<String>,
+    /// Streams every result to a collector, see `--push-results`
+    pub pusher: Option<push::ResultPusher>,
+}
+
+/// Keeps the first failure to record a contract's result or artifacts, see `rx_loop`
+fn keep_write_error(
+    write_error: &mut Option<OmniscanError>,
+    bytecode_hash: &str,
+    written: Result<(), OmniscanError>,
+) {
+    if let Err(e) = written {
+        write_error.get_or_insert(OmniscanError::Contract {
+            bytecode_hash: bytecode_hash.to_string(),
+            source: Box::new(e),
+        });
+    }
 }
 
 pub async fn rx_loop(
     rx_result: mpsc::Receiver<ResultMessage>,
     mut rx_stop: oneshot::Receiver<()>,
-    output_path: PathBuf,
-    rx_loop_timeout: f64,
-) {
-    let results_writer = ResultsWriter {
-        output_path: output_path.clone(),
-    };
-    results_writer.initiate_headers_for_results_csv();
+    config: RxLoopConfig,
+) -> Result<Vec<ResultsRow>, OmniscanError> {
+    let RxLoopConfig {
+        output_path,
+        report_paths,
+        artifacts_dir,
+        append_results,
+        rotate_results,
+        record,
+        journal,
+        status,
+        alert,
+        alert_webhook,
+        classifier,
+        target_contract_flag,
+        pusher,
+    } = config;
+    // the first failure to write a result, the run goes on so the reports still cover it
+    let mut write_error = None;
+    let mut results_writer = ResultsWriter::new(output_path.clone()).recording(record);
+    if append_results {
+        // a resumed run rotates its results only when the run it resumes did
+        results_writer = results_writer.resuming()?;
+    } else {
+        if let Some(max_part_bytes) = rotate_results {
+            results_writer = results_writer.rotating(max_part_bytes);
+        }
+        write_error = results_writer.initiate_headers_for_results_csv().err();
+    }
 
-    let rx_loop_timeout = Duration::from_secs_f64(rx_loop_timeout);
     let mut parse_count = 0;
     let mut total_parsable = 0;
+    let mut result_rows: Vec<ResultsRow> = Vec::new();
+    let mut webhook_posts = Vec::new();
+    let mut watch_success_rate = |result_row: &ResultsRow| {
+        let Some(alert) = alert
+            .as_ref()
+            .and_then(|alert| alert.record(matches!(result_row.result, ExitType::Success)))
+        else {
+            return;
+        };
+        alert::print_alert(&alert);
+        if let Some(url) = &alert_webhook {
+            webhook_posts.push(tokio::spawn(alert::post_webhook(url.clone(), alert)));
+        }
+    };
 
-    // keep looping over the rx_result channel until the rx_stop channel is closed
+    // keep looping over the rx_result channel until the tx loop signals it dispatched and
+    // finished every job, or dropped the channel. Results can take arbitrarily long to come in
+    // (compiles, spawn retries, paused runs), so waiting for one never ends the loop.
+    let mut stopping = false;
     loop {
-        match rx_stop.try_recv() {
-            Ok(_) => {
-                println!("Stopping rx_loop");
-                break;
-            }
-            Err(_) => {
-                // Use timeout to wait for the next message with a 5 seconds timeout
-                match rx_result.recv_timeout(rx_loop_timeout) {
-                    Ok(result_message) if result_message.child.is_some() => {
-                        // println!("Received some result message");
-                        let exit_type = check_child_exit(result_message.child.unwrap());
-                        assert!(
-                            !matches!(exit_type, ExitType::PerformanceTimeout),
-                            "PerformanceTimeout should not be possible here"
-                        );
-                        results_writer.append_to_results_file(
-                            &result_message.metadata,
-                            &exit_type,
-                            result_message.time,
-                            result_message.size,
+        if !stopping && rx_stop.try_recv().is_ok() {
+            println!("Stopping rx_loop");
+            // results sent right before the stop signal may still be queued, drain them first
+            stopping = true;
+        }
+        let received = if stopping {
+            match rx_result.try_recv() {
+                Ok(result_message) => Ok(result_message),
+                Err(_) => break,
+            }
+        } else {
+            // wake up regularly to check the stop signal. The wait blocks the thread, which
+            // must not hold up the tx loop on a runtime with a single worker
+            tokio::task::block_in_place(|| rx_result.recv_timeout(RX_POLL_INTERVAL))
+        };
+        if let (Ok(result_message), Some(artifacts_dir)) = (&received, &artifacts_dir) {
+            if !result_message.analyzer_outputs.is_empty() {
+                let (artifacts_dir, bytecode_hash, outputs) = (
+                    artifacts_dir.clone(),
+                    result_message.metadata.bytecode_hash.clone(),
+                    result_message.analyzer_outputs.clone(),
+                );
+                let recorded = tokio::task::spawn_blocking(move || {
+                    artifacts::record_outputs(&artifacts_dir, &bytecode_hash, &outputs)
+                })
+                .await
+                .unwrap();
+                keep_write_error(
+                    &mut write_error,
+                    &result_message.metadata.bytecode_hash,
+                    recorded,
+                );
+            }
+        }
+        match received {
+            Ok(mut result_message) if result_message.output.is_some() => {
+                // println!("Received some result message");
+                let (stdout, stderr) = result_message.output.take().unwrap();
+                let exit_type = catch_harness_panic("classifying the output", || {
+                    let exit_type = convert_pyrometer_output_to_exit_type(
+                        &classifier,
+                        stdout.clone(),
+                        stderr.clone(),
+                    );
+                    assert!(
+                        !matches!(exit_type, ExitType::PerformanceTimeout),
+                        "PerformanceTimeout should not be possible here"
+                    );
+                    result_message.check_determinism(
+                        &classifier,
+                        exit_type,
+                        Some((&stdout, &stderr)),
+                    )
+                })
+                .unwrap_or_else(ExitType::HarnessError);
+                let result_row = ResultsRow::from_result_message(&result_message, exit_type)
+                    .with_phase_times(phases::parse_phase_times(&stdout))
+                    .with_crash_location(backtrace::crash_location(&stderr))
+                    .with_target_contract_analyzed(target_contract_flag.as_deref().and_then(
+                        |flag| {
+                            target::target_analyzed(
+                                flag,
+                                &result_message.metadata,
+                                Some((&stdout, &stderr)),
+                            )
+                        },
+                    ));
+                let written = results_writer
+                    .append_to_results_file(&result_row)
+                    .and_then(|()| match &journal {
+                        Some(journal) => journal.record_completed(&result_row.bytecode_hash),
+                        None => Ok(()),
+                    });
+                keep_write_error(&mut write_error, &result_row.bytecode_hash, written);
+                status.record_result(&result_row);
+                if let Some(pusher) = &pusher {
+                    pusher.push(&result_row);
+                }
+                if let Some(artifacts_dir) = &artifacts_dir {
+                    if !matches!(result_row.result, ExitType::Success) {
+                        let written = artifacts::write_captured_output(
+                            artifacts_dir,
+                            &result_row.bytecode_hash,
+                            &stdout,
+                            &stderr,
                         );
-                        if let ExitType::Success = &exit_type {
-                            parse_count += 1;
-                        }
-                        total_parsable += 1;
+                        keep_write_error(&mut write_error, &result_row.bytecode_hash, written);
                     }
-                    Ok(result_message) => {
-                        // only here when child is None
-                        // Timeout hit on process, count as failure
-                        // println!("Received none result message");
-                        results_writer.append_to_results_file(
-                            &result_message.metadata,
-                            &ExitType::PerformanceTimeout,
-                            result_message.time,
-                            result_message.size,
+                }
+                if let ExitType::Success = &result_row.result {
+                    parse_count += 1;
+                }
+                total_parsable += 1;
+                watch_success_rate(&result_row);
+                result_rows.push(result_row);
+            }
+            Ok(result_message) => {
+                // only here when output is None
+                // Timeout hit on process or it could not be started, count as failure
+                // println!("Received none result message");
+                let exit_type = match &result_message.harness_error {
+                    Some(harness_error) => ExitType::HarnessError(harness_error.clone()),
+                    None => catch_harness_panic("classifying the output", || {
+                        result_message.check_determinism(
+                            &classifier,
+                            ExitType::PerformanceTimeout,
+                            None,
+                        )
+                    })
+                    .unwrap_or_else(ExitType::HarnessError),
+                };
+                // the analyzer never ran on contracts with a harness error
+                let target_contract_analyzed = target_contract_flag
+                    .as_deref()
+                    .filter(|_| result_message.harness_error.is_none())
+                    .and_then(|flag| target::target_analyzed(flag, &result_message.metadata, None));
+                let mut result_row = ResultsRow::from_result_message(&result_message, exit_type)
+                    .with_target_contract_analyzed(target_contract_analyzed);
+                // what a timed out analyzer wrote tells how far it got
+                if let Some((stdout, stderr)) = &result_message.partial_output {
+                    result_row = result_row
+                        .with_phase_times(phases::parse_phase_times(stdout))
+                        .with_last_phase(phases::last_phase(stdout));
+                    if let Some(artifacts_dir) = &artifacts_dir {
+                        let written = artifacts::write_captured_output(
+                            artifacts_dir,
+                            &result_row.bytecode_hash,
+                            stdout,
+                            stderr,
                         );
-                        total_parsable += 1;
+                        keep_write_error(&mut write_error, &result_row.bytecode_hash, written);
                     }
-                    Err(e) => match e {
-                        mpsc::RecvTimeoutError::Timeout => {
-                            println!("Timeout hit, quitting rx_loop");
-                            return;
-                        }
-                        _ => {
-                            println!("Error receiving from rx_result: {:?}", e);
-                        }
-                    },
                 }
+                let written = results_writer
+                    .append_to_results_file(&result_row)
+                    .and_then(|()| match &journal {
+                        Some(journal) => journal.record_completed(&result_row.bytecode_hash),
+                        None => Ok(()),
+                    });
+                keep_write_error(&mut write_error, &result_row.bytecode_hash, written);
+                status.record_result(&result_row);
+                if let Some(pusher) = &pusher {
+                    pusher.push(&result_row);
+                }
+                total_parsable += 1;
+                watch_success_rate(&result_row);
+                result_rows.push(result_row);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            // the tx loop and its jobs are gone and every result they sent was received
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        println!(
+            "{}/{}: {:.2}%, Parsable/Total Parsable",
+            parse_count,
+            total_parsable,
+            parse_count as f64 / total_parsable as f64 * 100.0
+        );
+    }
+
+    // a resumed run only saw part of the results, report on the whole file instead
+    if append_results {
+        match results::read_results_file(&output_path) {
+            // the successes of the run being resumed were not recorded, only this session's
+            Ok(mut all_rows) if record == results::RecordMode::Failures => {
                 println!(
-                    "{}/{}: {:.2}%, Parsable/Total Parsable",
-                    parse_count,
-                    total_parsable,
-                    parse_count as f64 / total_parsable as f64 * 100.0
+                    "Only failures were recorded, the reports count the successes since the \
+                     run was resumed"
                 );
+                all_rows.extend(
+                    result_rows
+                        .into_iter()
+                        .filter(|row| matches!(row.result, ExitType::Success)),
+                );
+                result_rows = all_rows;
             }
+            Ok(all_rows) => result_rows = all_rows,
+            Err(e) => eprintln!("Could not read back {}: {}", output_path.display(), e),
         }
     }
+
+    report::write_reports(&report_paths, &result_rows);
+    for webhook_post in webhook_posts {
+        let _ = webhook_post.await;
+    }
+
+    match write_error {
+        Some(e) => Err(e),
+        None => Ok(result_rows),
+    }
 }
 
 pub struct ResultMessage {
     metadata: FiestaMetadata,
-    child: Option<Child>,
+    /// Captured stdout and stderr of the analyzer, None when it timed out or could not be
+    /// started
+    output: Option<(String, String)>,
+    /// What the analyzer wrote before it was killed at its timeout, see `AnalyzerRun`
+    partial_output: Option<(String, String)>,
     time: f64,
+    /// User and system CPU time of the analyzer, None when it could not be told
+    cpu_time: Option<f64>,
+    /// Why omniscan could not run the analyzer on the contract, recorded as a HarnessError
+    harness_error: Option<String>,
+    /// Time taken to start the analyzer process, not included in `time`
+    spawn_time: f64,
+    /// When the contract was queued, and when it was given a job slot
+    queued_at: chrono::DateTime<chrono::Utc>,
+    started_at: chrono::DateTime<chrono::Utc>,
     size: u64,
+    /// Outcome of the solc compilation, None when not checked or unknown
+    solc_compiles: Option<bool>,
+    /// Whether flattening succeeded, None when the contract was not flattened
+    flattened: Option<bool>,
+    /// Files the analyzer wrote, moved to the artifacts directory, see `--collect-outputs`
+    analyzer_outputs: Vec<artifacts::AnalyzerOutput>,
+    /// Output of the second run, None when the contract was not analyzed twice (see
+    /// `--determinism-check`), Some(None) when the second run timed out
+    rerun_output: Option<Option<(String, String)>>,
+    /// Number of contracts analyzed by the same pyrometer invocation, see `--batch-size`
+    batch_size: Option<usize>,
+    /// Times the analyzer was started again after a transient failure, see `--spawn-retries`
+    spawn_retries: u32,
+    /// Whether the contract was among the first ones dispatched, see `--warmup`
+    warmup: bool,
+    /// Path of the core of the crashed analyzer in the artifacts directory, see `--core-dumps`
+    core_dump: Option<String>,
+    /// Compiler settings of the metadata the analyzer or solc could not be given, see
+    /// `--compiler-settings`
+    unhonored_settings: Vec<String>,
+}
+
+impl ResultMessage {
+    /// Result of a contract without any output yet, as when the analyzer timed out
+    fn new(
+        metadata: &FiestaMetadata,
+        queued_at: chrono::DateTime<chrono::Utc>,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            metadata: metadata.clone(),
+            output: None,
+            partial_output: None,
+            time: 0.0,
+            cpu_time: None,
+            harness_error: None,
+            spawn_time: 0.0,
+            queued_at,
+            started_at,
+            size: 0,
+            solc_compiles: None,
+            flattened: None,
+            analyzer_outputs: Vec::new(),
+            rerun_output: None,
+            batch_size: None,
+            spawn_retries: 0,
+            warmup: false,
+            core_dump: None,
+            unhonored_settings: Vec::new(),
+        }
+    }
+
+    /// Result of a contract omniscan failed to run the analyzer on
+    fn harness_error(
+        metadata: &FiestaMetadata,
+        harness_error: String,
+        queued_at: chrono::DateTime<chrono::Utc>,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            harness_error: Some(harness_error),
+            ..Self::new(metadata, queued_at, started_at)
+        }
+    }
+
+    /// Replaces `exit_type` with Nondeterministic when the second run of the contract differs
+    /// from the first one
+    fn check_determinism(
+        &self,
+        classifier: &profiles::Classifier,
+        exit_type: ExitType,
+        output: Option<(&str, &str)>,
+    ) -> ExitType {
+        let Some(rerun_output) = &self.rerun_output else {
+            return exit_type;
+        };
+        let rerun_output = rerun_output
+            .as_ref()
+            .map(|(stdout, stderr)| (stdout.as_str(), stderr.as_str()));
+        let rerun = match rerun_output {
+            Some((stdout, stderr)) => convert_pyrometer_output_to_exit_type(
+                classifier,
+                stdout.to_string(),
+                stderr.to_string(),
+            ),
+            None => ExitType::PerformanceTimeout,
+        };
+        match determinism::compare_runs(&exit_type, output, &rerun, rerun_output) {
+            Some(difference) => ExitType::Nondeterministic(difference),
+            None => exit_type,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -511,6 +4691,10 @@ pub enum ExitType {
     ThreadPanic(String),
     /// Failed to interpret the output of pyrometer. (stdout, stderr)
     NonInterpreted(String, String),
+    /// Two runs of the same contract differed, see `--determinism-check`. (how they differed)
+    Nondeterministic(String),
+    /// omniscan failed to run the analyzer on the contract, e.g. it could not be started
+    HarnessError(String),
 }
 
 impl fmt::Display for ExitType {
@@ -521,110 +4705,179 @@ impl fmt::Display for ExitType {
             ExitType::Error(s) => write!(f, "Error: {}", s.replace(',', ":")),
             ExitType::ThreadPanic(s) => write!(f, "ThreadPanic: {}", s.replace(',', ":")),
             ExitType::NonInterpreted(_stdout, _stderr) => write!(f, "NonInterpreted Error"),
+            ExitType::Nondeterministic(s) => write!(f, "Nondeterministic: {}", s.replace(',', ":")),
+            ExitType::HarnessError(s) => write!(f, "HarnessError: {}", s.replace(',', ":")),
         }
     }
 }
 
-pub struct ResultsWriter {
-    pub output_path: PathBuf,
-}
-
-impl ResultsWriter {
-    pub fn convert_fields_to_header() -> String {
-        "bytecode_hash,result,time (sec),source_type,source_size\n".to_string()
+impl ExitType {
+    /// Parses the label written to the results csv back into an ExitType.
+    /// NonInterpreted output is not stored in the csv, so it comes back empty.
+    pub fn from_label(label: &str) -> Option<ExitType> {
+        match label {
+            "Success" => Some(ExitType::Success),
+            "PerformanceTimeout" => Some(ExitType::PerformanceTimeout),
+            "NonInterpreted Error" => Some(ExitType::NonInterpreted(String::new(), String::new())),
+            _ => {
+                if let Some(message) = label.strip_prefix("Error:") {
+                    Some(ExitType::Error(message.trim_start().to_string()))
+                } else if let Some(message) = label.strip_prefix("Nondeterministic:") {
+                    Some(ExitType::Nondeterministic(message.trim_start().to_string()))
+                } else if let Some(message) = label.strip_prefix("HarnessError:") {
+                    Some(ExitType::HarnessError(message.trim_start().to_string()))
+                } else {
+                    label
+                        .strip_prefix("ThreadPanic:")
+                        .map(|message| ExitType::ThreadPanic(message.trim_start().to_string()))
+                }
+            }
+        }
     }
 
-    pub fn initiate_headers_for_results_csv(&self) {
-        println!("Initiating headers for results at: {:?}", &self.output_path);
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&self.output_path)
-            .unwrap();
-
-        let header_string = Self::convert_fields_to_header();
-        file.write_all(header_string.as_bytes()).unwrap();
+    /// Name of the variant, without any attached message
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExitType::Success => "Success",
+            ExitType::PerformanceTimeout => "PerformanceTimeout",
+            ExitType::Error(_) => "Error",
+            ExitType::ThreadPanic(_) => "ThreadPanic",
+            ExitType::NonInterpreted(_, _) => "NonInterpreted",
+            ExitType::Nondeterministic(_) => "Nondeterministic",
+            ExitType::HarnessError(_) => "HarnessError",
+        }
     }
+}
 
-    pub fn append_to_results_file(
-        &self,
-        metadata: &FiestaMetadata,
-        exit_type: &ExitType,
-        time: f64,
-        size: u64,
-    ) {
-        let mut file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&self.output_path)
-            .unwrap();
+/// Reads both pipes of a child on their own threads while it runs. Reading only after the
+/// child exited would let an analyzer that writes more than the pipe buffer (~64KB) block
+/// forever on a full pipe, and end up as a PerformanceTimeout. What was read so far stays
+/// available, for the output of a child killed at its timeout.
+pub struct OutputReaders {
+    stdout: Option<PipeReader>,
+    stderr: Option<PipeReader>,
+}
 
-        let bytecode_hash = metadata.bytecode_hash.clone();
-        let source_type = metadata.source_type.clone().unwrap();
+/// How much of the output of each pipe is kept. The end is kept, where panics and the
+/// analysis results are printed.
+const MAX_CAPTURED_OUTPUT: usize = 8 * 1024 * 1024;
 
-        let result_row =
-            ResultsRow::from(exit_type.clone(), bytecode_hash, source_type, time, size);
+/// How often a reader thread waiting for output checks whether it was told to stop, in ms
+const PIPE_POLL_INTERVAL_MS: i32 = 100;
 
-        let row_string = result_row.convert_to_csv_string();
+/// A reader thread and the bytes it read so far
+struct PipeReader {
+    thread: JoinHandle<()>,
+    output: Arc<Mutex<CapturedOutput>>,
+    stop: Arc<AtomicBool>,
+}
 
-        file.write_all(row_string.as_bytes()).unwrap();
-    }
+/// The last `MAX_CAPTURED_OUTPUT` bytes read from a pipe
+#[derive(Default)]
+struct CapturedOutput {
+    bytes: Vec<u8>,
+    dropped: usize,
 }
 
-pub struct ResultsRow {
-    pub bytecode_hash: String,
-    pub result: ExitType,
-    pub time: f64,
-    pub source_type: SourceType,
-    pub size: u64,
+impl CapturedOutput {
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+        // drop the start in bulk rather than on every chunk
+        if self.bytes.len() > 2 * MAX_CAPTURED_OUTPUT {
+            let excess = self.bytes.len() - MAX_CAPTURED_OUTPUT;
+            self.bytes.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    fn text(&self) -> String {
+        let text = String::from_utf8_lossy(&self.bytes);
+        match self.dropped {
+            0 => text.into_owned(),
+            dropped => format!("[first {} bytes of output dropped]\n{}", dropped, text),
+        }
+    }
 }
 
-impl ResultsRow {
-    pub fn from(
-        result: ExitType,
-        bytecode_hash: String,
-        source_type: SourceType,
-        time: f64,
-        size: u64,
-    ) -> Self {
+impl PipeReader {
+    fn spawn(mut pipe: impl Read + AsRawFd + Send + 'static) -> Self {
+        let output = Arc::new(Mutex::new(CapturedOutput::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_output, thread_stop) = (output.clone(), stop.clone());
+        let thread = thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            // returning drops the pipe, which closes its read end
+            while !thread_stop.load(Ordering::Relaxed) {
+                let mut pollfd = libc::pollfd {
+                    fd: pipe.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                match unsafe { libc::poll(&mut pollfd, 1, PIPE_POLL_INTERVAL_MS) } {
+                    0 => continue,
+                    n if n < 0 => match std::io::Error::last_os_error().kind() {
+                        std::io::ErrorKind::Interrupted => continue,
+                        _ => break,
+                    },
+                    _ => {}
+                }
+                match pipe.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => thread_output.lock().unwrap().push(&buf[..n]),
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+        });
         Self {
-            bytecode_hash,
-            result: result.clone(),
-            time,
-            source_type,
-            size,
+            thread,
+            output,
+            stop,
         }
     }
 
-    pub fn convert_to_csv_string(&self) -> String {
-        format!(
-            "{},{},{:.3},{},{}\n",
-            self.bytecode_hash, self.result, self.time, self.source_type, self.size
-        )
+    /// Stops reading and closes the pipe, returning what was read
+    fn close(self) -> String {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+
+    /// Waits for the pipe to be closed, returning all that was kept
+    fn join(self) -> String {
+        let _ = self.thread.join();
+        self.output.lock().unwrap().text()
     }
 }
 
-pub fn check_child_exit(child: Child) -> ExitType {
-    // determine if the exit status has panics, errors, etc.
-    if child.stdout.is_some() & child.stderr.is_some() {
-        let stdout = child.stdout.unwrap();
-        let mut stdout_reader = std::io::BufReader::new(stdout);
-        let mut stdout_string = String::new();
-        std::io::Read::read_to_string(&mut stdout_reader, &mut stdout_string).unwrap();
-        let mut stderr = child.stderr.unwrap();
-        let mut stderr_string = String::new();
-        std::io::Read::read_to_string(&mut stderr, &mut stderr_string).unwrap();
+impl OutputReaders {
+    pub fn spawn(child: &mut Child) -> Self {
+        Self {
+            stdout: child.stdout.take().map(PipeReader::spawn),
+            stderr: child.stderr.take().map(PipeReader::spawn),
+        }
+    }
 
-        // convert stdout into one of the ExitType variants
-        convert_pyrometer_output_to_exit_type(stdout_string, stderr_string)
-    } else {
-        dbg!(&child);
-        panic!("Child stdout is None")
+    /// What the child wrote before it exited or was killed. Waits up to `grace` for what is
+    /// still in the pipes, as processes the child started may keep them open, then closes them.
+    pub fn salvage(self, grace: Duration) -> (String, String) {
+        let deadline = Instant::now() + grace;
+        let readers = [&self.stdout, &self.stderr];
+        while Instant::now() < deadline
+            && readers.iter().any(|reader| {
+                reader
+                    .as_ref()
+                    .is_some_and(|reader| !reader.thread.is_finished())
+            })
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let close = |reader: Option<PipeReader>| reader.map(PipeReader::close).unwrap_or_default();
+        (close(self.stdout), close(self.stderr))
     }
 }
 
 pub fn convert_pyrometer_output_to_exit_type(
+    classifier: &profiles::Classifier,
     stdout_string: String,
     stderr_string: String,
 ) -> ExitType {
@@ -642,21 +4895,26 @@ pub fn convert_pyrometer_output_to_exit_type(
     }
 
     // Check if the output is from stdout and contains an error message
-    if let Some(captures) = ERROR_REGEX.captures(&stdout_string) {
+    if let Some(captures) = classifier.profile.error.captures(&stdout_string) {
         let error_message = captures[1].trim().to_string();
         return ExitType::Error(error_message);
     }
 
     // Check if the output is from stdout and contains a success message
-    if SUCCESS_REGEX.is_match(&stdout_string) {
+    if classifier.profile.success.is_match(&stdout_string) {
         return ExitType::Success;
     }
 
+    // Then the rules written for outputs like this one with `classify-unknowns`
+    if let Some(exit_type) = classifier.rules.classify(&stdout_string, &stderr_string) {
+        return exit_type;
+    }
+
     // If none of the above patterns are matched, return a NonInterpreted variant.
     ExitType::NonInterpreted(stdout_string, stderr_string)
 }
 
-pub fn collect_contract_sources(metadata: &mut FiestaMetadata) {
+pub fn collect_contract_sources(metadata: &mut FiestaMetadata) -> Result<(), OmniscanError> {
     /*
     There will either be a main.sol file, several .sol files of different names, or a contracts.json file
     - first look for contracts.json
@@ -664,17 +4922,27 @@ pub fn collect_contract_sources(metadata: &mut FiestaMetadata) {
     - then look for multiple .sol files
     - edgecase is a single main.vy file that has misconfigured metadata.json... there's about 10 of these, we can skip.
     */
+    let read_source = |path: &Path| {
+        fs::read_to_string(path).map_err(|source| OmniscanError::Read {
+            path: path.to_path_buf(),
+            source,
+        })
+    };
     let path_to_dir = std::path::PathBuf::from(&metadata.abs_path_to_dir);
     let mut path_to_contract = std::path::PathBuf::new();
     for entry in WalkDir::new(&path_to_dir) {
-        let entry = entry.unwrap();
+        let entry = entry?;
         let path = entry.path();
         // println!("Looking for contracts.json: {}", &path.display());
-        if path.is_file() && path.file_name().unwrap() == "contract.json" {
+        if path.is_file() && path.file_name().is_some_and(|name| name == "contract.json") {
             path_to_contract = path.to_path_buf();
-            let json_string = std::fs::read_to_string(path_to_contract.clone()).unwrap();
+            let json_string = read_source(path)?;
             // println!("{:#?}", &json_string);
-            let contract_metadata: SourceCodeMetadata = serde_json::from_str(&json_string).unwrap();
+            let contract_metadata: SourceCodeMetadata = serde_json::from_str(&json_string)
+                .map_err(|source| OmniscanError::Json {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
             metadata.update_source_type(SourceType::EtherscanMetadata(contract_metadata));
             break;
         }
@@ -683,9 +4951,9 @@ pub fn collect_contract_sources(metadata: &mut FiestaMetadata) {
     if path_to_contract == std::path::PathBuf::new() {
         let mut sol_files = Vec::new();
         for entry in WalkDir::new(&path_to_dir) {
-            let entry = entry.unwrap();
+            let entry = entry?;
             let path = entry.path();
-            if path.is_file() && path.extension().unwrap() == "sol" {
+            if path.is_file() && path.extension().is_some_and(|extension| extension == "sol") {
                 sol_files.push(path.to_path_buf());
             }
         }
@@ -693,24 +4961,135 @@ pub fn collect_contract_sources(metadata: &mut FiestaMetadata) {
 
         if sol_files.len() == 1 {
             path_to_contract = sol_files[0].to_path_buf();
-            metadata.update_source_type(SourceType::SingleMain(
-                std::fs::read_to_string(path_to_contract.clone()).unwrap(),
-            ));
+            metadata.update_source_type(SourceType::SingleMain(read_source(&path_to_contract)?));
         } else if sol_files.is_empty() {
-            println!("Found no .sol files: {}. this is likely a main.vy that should be a main.sol. needs changed", &path_to_dir.display())
-            // could go to path_to_contract and rename main.vy to main.sol
+            // likely a mislabeled main.vy, see `mislabel::handle`
         } else {
-            // if there are multiple .sol files, look for main.sol
+            // if there are multiple .sol files, look for main.sol. Named by their path in the
+            // contract directory, as fetch-bulk keeps same-named files of different directories
             let mut multiple_files = sol_files
                 .into_iter()
                 .map(|path| {
-                    let name = path.file_name().unwrap().to_str().unwrap().to_string();
-                    let string = std::fs::read_to_string(path).unwrap();
-                    (name, string)
+                    let name = path
+                        .strip_prefix(&path_to_dir)
+                        .unwrap_or(&path)
+                        .to_string_lossy()
+                        .into_owned();
+                    Ok((name, read_source(&path)?))
                 })
-                .collect::<Vec<(String, String)>>();
+                .collect::<Result<Vec<(String, String)>, OmniscanError>>()?;
             multiple_files.sort_by(|a, b| a.0.cmp(&b.0));
             metadata.update_source_type(SourceType::Multiple(multiple_files));
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(count: usize) -> Vec<batch::DispatchUnit> {
+        (0..count)
+            .map(|idx| {
+                batch::DispatchUnit::Contract(Box::new(FiestaMetadata::for_test(&idx.to_string())))
+            })
+            .collect()
+    }
+
+    fn hashes(unit: &batch::DispatchUnit) -> String {
+        unit.bytecode_hashes().join(" ")
+    }
+
+    #[tokio::test]
+    async fn schedule_serves_slots_as_they_free_up() {
+        let mut schedule = Schedule::new(units(5), Some(2));
+        let (first, first_permit) = schedule.next().await.unwrap();
+        let (second, second_permit) = schedule.next().await.unwrap();
+        assert_eq!((hashes(&first), hashes(&second)), ("0".into(), "1".into()));
+        // slot 1 frees up first, its next unit goes before the rest of slot 0
+        drop(second_permit);
+        let (third, _third_permit) = schedule.next().await.unwrap();
+        assert_eq!(hashes(&third), "3");
+        drop(first_permit);
+        let (fourth, _fourth_permit) = schedule.next().await.unwrap();
+        assert_eq!(hashes(&fourth), "2");
+    }
+
+    #[tokio::test]
+    async fn schedule_without_slots_keeps_queue_order() {
+        let mut schedule = Schedule::new(units(3), None);
+        let mut order = Vec::new();
+        while let Some((unit, permit)) = schedule.next().await {
+            assert!(permit.is_none());
+            order.push(hashes(&unit));
+        }
+        assert_eq!(order, ["0", "1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn remaining_units_include_the_queued_slots() {
+        let mut schedule = Schedule::new(units(5), Some(2));
+        let (first, _first_permit) = schedule.next().await.unwrap();
+        assert_eq!(hashes(&first), "0");
+        let remaining = schedule
+            .remaining()
+            .map(|unit| hashes(&unit))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, ["2", "4", "1", "3"]);
+
+        let mut schedule = Schedule::new(units(3), None);
+        schedule.next().await.unwrap();
+        let remaining = schedule
+            .remaining()
+            .map(|unit| hashes(&unit))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, ["1", "2"]);
+    }
+
+    #[test]
+    fn parse_rate_rejects_rates_below_one() {
+        assert_eq!(parse_rate("5"), Ok(5.0));
+        assert_eq!(parse_rate("1.5"), Ok(1.5));
+        assert!(parse_rate("0.5").is_err());
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn captured_output_keeps_the_end() {
+        let mut output = CapturedOutput::default();
+        let chunk = vec![b'a'; MAX_CAPTURED_OUTPUT];
+        for _ in 0..3 {
+            output.push(&chunk);
+        }
+        output.push(b"panicked");
+        let text = output.text();
+        assert!(text.starts_with(&format!(
+            "[first {} bytes of output dropped]\n",
+            output.dropped
+        )));
+        assert!(text.ends_with("panicked"));
+        assert_eq!(
+            output.dropped + output.bytes.len(),
+            3 * MAX_CAPTURED_OUTPUT + 8
+        );
+        assert!(output.bytes.len() <= 2 * MAX_CAPTURED_OUTPUT);
+    }
+
+    #[test]
+    fn salvage_closes_pipes_held_open_by_grandchildren() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "echo started; sleep 30 &"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let readers = OutputReaders::spawn(&mut child);
+        child.wait().unwrap();
+        let start = Instant::now();
+        let (stdout, _stderr) = readers.salvage(Duration::from_millis(200));
+        assert_eq!(stdout, "started\n");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
 }
@@ -0,0 +1,186 @@
+use crate::{
+    environment::EnvironmentSnapshot, project::CorpusKind, results::RecordMode,
+    settings::AnalyzerSettingsFlags, tags::RunTags, AnalyzerConfig, FiestaMetadata,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The ordered set of contracts a run analyzed, written next to its results so the exact same
+/// set can be analyzed again with `--replay`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RunManifest {
+    pub omniscan_version: String,
+    pub created_at: String,
+    /// PATH of the run, the default corpus of a replay
+    pub corpus: String,
+    pub corpus_kind: CorpusKind,
+    /// Version of the dataset, see `fingerprint::corpus_fingerprint`
+    #[serde(default)]
+    pub corpus_fingerprint: Option<String>,
+    /// Machine and tools the run was made with, None for manifests of older runs
+    #[serde(default)]
+    pub environment: Option<EnvironmentSnapshot>,
+    /// How the analyzer's output was classified, see `--classification-profile`. None for
+    /// manifests of older runs
+    #[serde(default)]
+    pub classification_profile: Option<String>,
+    /// Rules file the outputs the profile does not recognize were classified with, see
+    /// `--rules-file`, and the sha256 of its content. None when no rules file was read
+    #[serde(default)]
+    pub rules_file: Option<String>,
+    #[serde(default)]
+    pub rules_sha256: Option<String>,
+    /// How pyrometer was invoked, to rebuild the command analyzing a contract. None for
+    /// manifests of older runs
+    #[serde(default)]
+    pub analyzer: Option<AnalyzerInvocation>,
+    /// Which results the results file holds, see `--record`
+    #[serde(default)]
+    pub record: RecordMode,
+    /// See `--tag` and `--label`
+    #[serde(flatten)]
+    pub run_tags: RunTags,
+    /// Whether the run analyzed the whole corpus rather than a sample of it, its success rate
+    /// then has no sampling error. False for manifests of older runs
+    #[serde(default)]
+    pub full_corpus: bool,
+    pub contracts: Vec<ManifestEntry>,
+}
+
+/// The flags of `AnalyzerConfig` that make up the pyrometer command of a contract
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct AnalyzerInvocation {
+    /// See `--analyzer-args`, None for pyrometer's defaults
+    pub args: Option<Vec<String>>,
+    /// See `--compiler-settings`
+    pub compiler_settings: Option<AnalyzerSettingsFlags>,
+    /// See `--target-contract-from-metadata`
+    pub target_contract_flag: Option<String>,
+}
+
+impl AnalyzerInvocation {
+    pub fn new(analyzer: &AnalyzerConfig) -> Self {
+        Self {
+            args: analyzer.args.clone(),
+            compiler_settings: analyzer.compiler_settings.clone(),
+            target_contract_flag: analyzer.target_contract_flag.clone(),
+        }
+    }
+
+    /// An analyzer invoked the same way, without the sandboxing of the run
+    pub fn config(self) -> AnalyzerConfig {
+        AnalyzerConfig {
+            args: self.args,
+            compiler_settings: self.compiler_settings,
+            target_contract_flag: self.target_contract_flag,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    pub bytecode_hash: String,
+    /// Path of the analyzed file, relative to the corpus root
+    pub source_path: String,
+}
+
+impl RunManifest {
+    pub fn new(
+        corpus: &str,
+        corpus_kind: CorpusKind,
+        corpus_fingerprint: Option<String>,
+        environment: EnvironmentSnapshot,
+        classification_profile: &str,
+        record: RecordMode,
+        metadatas: &[FiestaMetadata],
+    ) -> Self {
+        Self {
+            omniscan_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            corpus: corpus.to_string(),
+            corpus_kind,
+            corpus_fingerprint,
+            environment: Some(environment),
+            classification_profile: Some(classification_profile.to_string()),
+            rules_file: None,
+            rules_sha256: None,
+            analyzer: None,
+            record,
+            run_tags: RunTags::default(),
+            full_corpus: false,
+            contracts: metadatas
+                .iter()
+                .map(|metadata| ManifestEntry {
+                    bytecode_hash: metadata.bytecode_hash.clone(),
+                    source_path: metadata.source_path(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Directories of the contracts, relative to the corpus root
+    pub fn contract_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.contracts
+            .iter()
+            .filter_map(|entry| Path::new(&entry.source_path).parent())
+    }
+
+    /// Puts the contracts found in the corpus in the order of the manifest, failing when any
+    /// contract of the manifest is missing, as the replay would not be comparable
+    pub fn order(&self, metadatas: Vec<FiestaMetadata>) -> Result<Vec<FiestaMetadata>, String> {
+        let mut by_hash = metadatas
+            .into_iter()
+            .map(|metadata| (metadata.bytecode_hash.clone(), metadata))
+            .collect::<HashMap<String, FiestaMetadata>>();
+        let mut ordered = Vec::with_capacity(self.contracts.len());
+        let mut missing = Vec::new();
+        for entry in &self.contracts {
+            match by_hash.remove(&entry.bytecode_hash) {
+                Some(metadata) => ordered.push(metadata),
+                None => missing.push(entry.source_path.as_str()),
+            }
+        }
+        if missing.is_empty() {
+            Ok(ordered)
+        } else {
+            Err(format!(
+                "{} of the {} contracts of the manifest are not in the corpus, e.g. {}",
+                missing.len(),
+                self.contracts.len(),
+                missing[..missing.len().min(3)].join(", ")
+            ))
+        }
+    }
+}
+
+/// The manifest of a results file: results.csv -> results.manifest.json
+pub fn manifest_path(output_path: &Path) -> PathBuf {
+    output_path.with_extension("manifest.json")
+}
+
+/// Corpus fingerprint recorded in the manifest next to a results file, if any
+pub fn results_fingerprint(output_path: &Path) -> Option<String> {
+    read_manifest(&manifest_path(output_path))
+        .ok()?
+        .corpus_fingerprint
+}
+
+/// Whether the manifest next to a results file says the run covered the whole corpus
+pub fn results_full_corpus(output_path: &Path) -> bool {
+    read_manifest(&manifest_path(output_path)).is_ok_and(|manifest| manifest.full_corpus)
+}
+
+pub fn write_manifest(path: &Path, manifest: &RunManifest) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+pub fn read_manifest(path: &Path) -> Result<RunManifest, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
@@ -0,0 +1,174 @@
+use crate::{collect_contract_sources, FiestaMetadata};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+lazy_static! {
+    /// A Solidity pragma, or a contract, library or interface declaration opening a body or
+    /// naming its bases. Vyper 0.1 declares interfaces as `contract Factory():`, which this
+    /// leaves out
+    static ref SOLIDITY_REGEX: Regex = Regex::new(
+        r"(?m)^\s*(pragma\s+solidity\b|(abstract\s+)?(contract|library|interface)\s+\w+\s*(\bis\b|\{))"
+    )
+    .unwrap();
+    /// The CompilerVersion field of a metadata.json, with its string value
+    static ref COMPILER_VERSION_REGEX: Regex =
+        Regex::new(r#""CompilerVersion"\s*:\s*("(?:[^"\\]|\\.)*")"#).unwrap();
+    /// `# @version 0.3.1` or `# pragma version ^0.3.10` of a Vyper source
+    static ref VYPER_VERSION_REGEX: Regex =
+        Regex::new(r"(?m)^\s*#\s*(?:@version|pragma\s+version)\s+[\^~>=<]*\s*(\d+\.\d+\.\d+)")
+            .unwrap();
+}
+
+/// How the sources of a contract without any .sol file or contract.json are mislabeled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MislabelReason {
+    /// A .vy file holding Solidity: renamed to .sol by `--fix-corpus`, then analyzed
+    SolidityInVyFile,
+    /// Vyper sources with the CompilerVersion of a Solidity compiler in metadata.json: the
+    /// CompilerVersion is set to the Vyper version by `--fix-corpus`, which keeps the contract
+    /// out of the analysis like any Vyper contract
+    VyperWithSolidityMetadata,
+}
+
+impl MislabelReason {
+    /// The reason as recorded in the summary
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SolidityInVyFile => "solidity_in_vy_file",
+            Self::VyperWithSolidityMetadata => "vyper_with_solidity_metadata",
+        }
+    }
+}
+
+/// A mislabeled contract met by a run, recorded in the summary
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MislabeledContract {
+    pub bytecode_hash: String,
+    /// Path of the mislabeled file, relative to the corpus root
+    pub source_path: String,
+    pub reason: MislabelReason,
+    /// Whether `--fix-corpus` repaired it in place
+    pub repaired: bool,
+}
+
+/// A mislabeled source of a contract directory
+pub struct Mislabel {
+    pub reason: MislabelReason,
+    pub file: PathBuf,
+    /// The version of a Vyper source, from its version pragma
+    pub vyper_version: Option<String>,
+}
+
+/// Looks for the mislabeled .vy file of a contract directory that has no .sol file nor
+/// contract.json. None when it has no .vy file, or its metadata already names Vyper.
+pub fn detect(contract_dir: &Path, compiler_version: &str) -> Option<Mislabel> {
+    let mut vy_files = WalkDir::new(contract_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "vy"))
+        .collect::<Vec<PathBuf>>();
+    vy_files.sort();
+    let file = vy_files.into_iter().next()?;
+    let source = fs::read_to_string(&file).ok()?;
+    let vyper_version = VYPER_VERSION_REGEX
+        .captures(&source)
+        .map(|captures| captures[1].to_string());
+    if vyper_version.is_none() && SOLIDITY_REGEX.is_match(&source) {
+        return Some(Mislabel {
+            reason: MislabelReason::SolidityInVyFile,
+            file,
+            vyper_version: None,
+        });
+    }
+    if compiler_version.contains("vyper") {
+        return None;
+    }
+    Some(Mislabel {
+        reason: MislabelReason::VyperWithSolidityMetadata,
+        file,
+        vyper_version,
+    })
+}
+
+/// Repairs the mislabel in place: renames the .vy file of Solidity sources, or sets the
+/// CompilerVersion of the metadata.json of Vyper sources
+pub fn repair(contract_dir: &Path, mislabel: &Mislabel) -> Result<(), String> {
+    match mislabel.reason {
+        MislabelReason::SolidityInVyFile => {
+            let renamed = mislabel.file.with_extension("sol");
+            if renamed.exists() {
+                return Err(format!("{} exists already", renamed.display()));
+            }
+            fs::rename(&mislabel.file, &renamed).map_err(|e| e.to_string())
+        }
+        MislabelReason::VyperWithSolidityMetadata => {
+            let metadata_path = contract_dir.join("metadata.json");
+            let contents = fs::read_to_string(&metadata_path).map_err(|e| e.to_string())?;
+            let compiler_version = Value::String(match &mislabel.vyper_version {
+                Some(version) => format!("vyper:{}", version),
+                None => "vyper".to_string(),
+            });
+            // only the value is replaced, the rest of the file is kept byte for byte
+            let value = COMPILER_VERSION_REGEX
+                .captures(&contents)
+                .and_then(|captures| captures.get(1))
+                .ok_or("metadata.json has no CompilerVersion")?;
+            let contents = format!(
+                "{}{}{}",
+                &contents[..value.start()],
+                compiler_version,
+                &contents[value.end()..]
+            );
+            let tmp_path = metadata_path.with_extension("json.tmp");
+            fs::write(&tmp_path, contents)
+                .and_then(|_| fs::rename(&tmp_path, &metadata_path))
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Checks a contract whose sources were not found for a mislabeled .vy file, repairing it with
+/// `fix`. A contract whose Solidity sources were renamed gets its sources collected again, so
+/// it is analyzed.
+pub fn handle(
+    metadata: &mut FiestaMetadata,
+    corpus_root: &Path,
+    fix: bool,
+) -> Option<MislabeledContract> {
+    let contract_dir = PathBuf::from(&metadata.abs_path_to_dir);
+    let mislabel = detect(&contract_dir, &metadata.compiler_version)?;
+    let source_path = mislabel
+        .file
+        .strip_prefix(corpus_root)
+        .unwrap_or(&mislabel.file)
+        .display()
+        .to_string();
+    let repaired = fix
+        && match repair(&contract_dir, &mislabel) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Could not repair {}: {}", source_path, e);
+                false
+            }
+        };
+    if repaired && mislabel.reason == MislabelReason::SolidityInVyFile {
+        if let Err(e) = collect_contract_sources(metadata) {
+            metadata.harness_error = Some(e.to_string());
+        }
+    }
+    Some(MislabeledContract {
+        bytecode_hash: metadata.bytecode_hash.clone(),
+        source_path,
+        reason: mislabel.reason,
+        repaired,
+    })
+}
@@ -0,0 +1,566 @@
+use crate::{report, results, ExitType, FiestaMetadata, ResultsRow};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// Contracts enriched at once
+const RPC_CONCURRENCY: usize = 8;
+/// Addresses of a contract that are looked up. The same bytecode can be deployed at thousands
+/// of addresses (token clones, proxies), the first ones are enough to tell its relevance
+const MAX_ADDRESSES: usize = 20;
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// A request the node or indexer does not answer within these is retried
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Wei in an ether
+const WEI_PER_ETHER: f64 = 1e18;
+/// Failing contracts listed by `print_weighted_failures`
+const LISTED_CONTRACTS: usize = 10;
+
+/// What failures are weighted by in a report, see `report --weight-by`
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum WeightBy {
+    /// Balance in ether of the addresses of the contract
+    Balance,
+    /// Transactions to and from the addresses of the contract, see `enrich --indexer-url`
+    TxCount,
+}
+
+impl WeightBy {
+    fn weight(self, row: &ResultsRow) -> Option<f64> {
+        match self {
+            Self::Balance => row.balance_eth,
+            Self::TxCount => row.tx_count.map(|tx_count| tx_count as f64),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Balance => "balance (ETH)",
+            Self::TxCount => "tx count",
+        }
+    }
+
+    /// Lower bounds of the tiers `print_weighted_failures` splits the contracts into, highest
+    /// first
+    fn tiers(self) -> [f64; 4] {
+        match self {
+            Self::Balance => [1000.0, 100.0, 1.0, 0.0],
+            Self::TxCount => [10000.0, 1000.0, 10.0, 0.0],
+        }
+    }
+}
+
+/// The weight of every contract of an enriched results file, by bytecode hash
+pub fn read_priorities(path: &Path, weight_by: WeightBy) -> Result<HashMap<String, f64>, String> {
+    let priorities = results::read_results_file(path)?
+        .into_iter()
+        .filter_map(|row| {
+            weight_by
+                .weight(&row)
+                .map(|weight| (row.bytecode_hash, weight))
+        })
+        .collect::<HashMap<String, f64>>();
+    if priorities.is_empty() {
+        return Err("no contract has on-chain metadata, see `omniscan enrich`".to_string());
+    }
+    Ok(priorities)
+}
+
+/// Orders the contracts by descending weight, the ones without on-chain metadata last in their
+/// current order. Returns the number of contracts with a weight
+pub fn prioritize(metadatas: &mut [FiestaMetadata], priorities: &HashMap<String, f64>) -> usize {
+    metadatas.sort_by(|a, b| {
+        let weight = |metadata: &FiestaMetadata| priorities.get(&metadata.bytecode_hash).copied();
+        match (weight(a), weight(b)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        }
+    });
+    metadatas
+        .iter()
+        .filter(|metadata| priorities.contains_key(&metadata.bytecode_hash))
+        .count()
+}
+
+/// On-chain metadata of the addresses of one contract
+#[derive(Debug, Default)]
+struct OnchainMetadata {
+    deployed_block: Option<u64>,
+    deployed_at: Option<DateTime<Utc>>,
+    balance_eth: f64,
+    /// None without an indexer
+    tx_count: Option<u64>,
+    /// Why the deployment could not be found, typically a node that does not keep the state of
+    /// old blocks. The other fields are still filled
+    deployment_error: Option<String>,
+}
+
+/// A JSON-RPC node
+#[derive(Clone)]
+struct RpcClient {
+    client: reqwest::Client,
+    url: String,
+}
+
+/// The Blockscout instance transactions are counted with. A node only knows the transactions
+/// an address sent, which for a contract is the number of contracts it created, not the calls
+/// it received
+#[derive(Clone)]
+struct IndexerClient {
+    client: reqwest::Client,
+    /// Root URL, e.g. https://eth.blockscout.com
+    url: String,
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn parse_quantity(value: &Value) -> Result<u128, String> {
+    let quantity = value
+        .as_str()
+        .and_then(|quantity| quantity.strip_prefix("0x"))
+        .ok_or_else(|| format!("expected a hex quantity, got {}", value))?;
+    u128::from_str_radix(quantity, 16).map_err(|e| format!("bad quantity {}: {}", quantity, e))
+}
+
+impl RpcClient {
+    /// Calls `method`, retrying with exponential backoff on network errors, server errors and
+    /// rate limit responses. Returns the `result` of the response.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut backoff = INITIAL_BACKOFF;
+        let mut last_error = String::new();
+        for _attempt in 0..MAX_ATTEMPTS {
+            let response = self.client.post(&self.url).json(&request).send().await;
+            let retry = match response {
+                Ok(response) if response.status().is_success() => {
+                    let body = response.json::<Value>().await.map_err(|e| e.to_string())?;
+                    if let Some(error) = body.get("error") {
+                        return Err(format!(
+                            "{}: {}",
+                            method,
+                            error["message"].as_str().unwrap_or_default()
+                        ));
+                    }
+                    return Ok(body["result"].clone());
+                }
+                Ok(response) if response.status().is_server_error() || response.status() == 429 => {
+                    response.status().to_string()
+                }
+                Ok(response) => return Err(format!("RPC node returned {}", response.status())),
+                Err(e) => e.to_string(),
+            };
+            last_error = retry;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        Err(format!(
+            "giving up after {} attempts: {}",
+            MAX_ATTEMPTS, last_error
+        ))
+    }
+
+    async fn latest_block(&self) -> Result<u64, String> {
+        Ok(parse_quantity(&self.call("eth_blockNumber", json!([])).await?)? as u64)
+    }
+
+    async fn has_code(&self, address: &str, block: u64) -> Result<bool, String> {
+        let code = self
+            .call("eth_getCode", json!([address, format!("{:#x}", block)]))
+            .await?;
+        Ok(code.as_str().is_some_and(|code| code.len() > 2))
+    }
+
+    /// The first block the address has code at, found by bisection, so a contract destroyed
+    /// and deployed again counts from one of its deployments. None when it has no code now.
+    /// Needs a node that keeps the state of old blocks.
+    async fn deployment_block(&self, address: &str, latest: u64) -> Result<Option<u64>, String> {
+        if !self.has_code(address, latest).await? {
+            return Ok(None);
+        }
+        let (mut without_code, mut with_code) = (0, latest);
+        while with_code - without_code > 1 {
+            let middle = without_code + (with_code - without_code) / 2;
+            if self.has_code(address, middle).await? {
+                with_code = middle;
+            } else {
+                without_code = middle;
+            }
+        }
+        Ok(Some(with_code))
+    }
+
+    async fn block_time(&self, block: u64) -> Result<Option<DateTime<Utc>>, String> {
+        let block = self
+            .call(
+                "eth_getBlockByNumber",
+                json!([format!("{:#x}", block), false]),
+            )
+            .await?;
+        Ok(DateTime::from_timestamp(
+            parse_quantity(&block["timestamp"])? as i64,
+            0,
+        ))
+    }
+
+    /// Looks up the addresses, keeping the earliest deployment and summing the balances. The
+    /// deployment is left unknown when it can't be found, the balances are kept
+    async fn fetch(&self, addresses: &[String], latest: u64) -> Result<OnchainMetadata, String> {
+        let mut metadata = OnchainMetadata::default();
+        let latest_tag = format!("{:#x}", latest);
+        for address in addresses.iter().take(MAX_ADDRESSES) {
+            let balance = parse_quantity(
+                &self
+                    .call("eth_getBalance", json!([address, latest_tag]))
+                    .await?,
+            )?;
+            metadata.balance_eth += balance as f64 / WEI_PER_ETHER;
+            // the other addresses would fail the same way
+            if metadata.deployment_error.is_some() {
+                continue;
+            }
+            match self.deployment_block(address, latest).await {
+                Ok(Some(block)) => {
+                    if metadata
+                        .deployed_block
+                        .is_none_or(|deployed| block < deployed)
+                    {
+                        metadata.deployed_block = Some(block);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => metadata.deployment_error = Some(e),
+            }
+        }
+        if metadata.deployment_error.is_some() {
+            metadata.deployed_block = None;
+        }
+        if let Some(block) = metadata.deployed_block {
+            match self.block_time(block).await {
+                Ok(deployed_at) => metadata.deployed_at = deployed_at,
+                Err(e) => {
+                    metadata.deployed_block = None;
+                    metadata.deployment_error = Some(e);
+                }
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+impl IndexerClient {
+    /// Transactions to or from the address, retrying with exponential backoff on network
+    /// errors, server errors and rate limit responses. 0 for an address Blockscout does not know
+    async fn transactions_count(&self, address: &str) -> Result<u64, String> {
+        let url = format!(
+            "{}/api/v2/addresses/{}/counters",
+            self.url.trim_end_matches('/'),
+            address
+        );
+        let mut backoff = INITIAL_BACKOFF;
+        let mut last_error = String::new();
+        for _attempt in 0..MAX_ATTEMPTS {
+            let retry = match self.client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => {
+                    let counters = response.json::<Value>().await.map_err(|e| e.to_string())?;
+                    // the counters are strings, to fit any integer
+                    return counters["transactions_count"]
+                        .as_str()
+                        .and_then(|count| count.parse().ok())
+                        .ok_or(format!("no transactions_count for {}", address));
+                }
+                Ok(response) if response.status() == 404 => return Ok(0),
+                Ok(response) if response.status().is_server_error() || response.status() == 429 => {
+                    response.status().to_string()
+                }
+                Ok(response) => return Err(format!("Blockscout returned {}", response.status())),
+                Err(e) => e.to_string(),
+            };
+            last_error = retry;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        Err(format!(
+            "giving up after {} attempts: {}",
+            MAX_ATTEMPTS, last_error
+        ))
+    }
+
+    /// Transactions to or from the addresses, summed
+    async fn fetch(&self, addresses: &[String]) -> Result<u64, String> {
+        let mut tx_count = 0;
+        for address in addresses.iter().take(MAX_ADDRESSES) {
+            tx_count += self.transactions_count(address).await?;
+        }
+        Ok(tx_count)
+    }
+}
+
+/// Reads an index of the addresses of the contracts, one `address,bytecode_hash` per line (a
+/// `:` or whitespace separates them as well, like the address index of smart-contract-fiesta)
+fn read_address_index(path: &Path) -> Result<HashMap<String, Vec<String>>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut index: HashMap<String, Vec<String>> = HashMap::new();
+    for line in contents.lines() {
+        let mut fields = line
+            .split(|c: char| c == ',' || c == ':' || c.is_whitespace())
+            .filter(|field| !field.is_empty());
+        let (Some(address), Some(bytecode_hash)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        if !address.starts_with("0x") {
+            continue;
+        }
+        index
+            .entry(bytecode_hash.to_string())
+            .or_default()
+            .push(address.to_lowercase());
+    }
+    Ok(index)
+}
+
+/// Fills the on-chain columns of the results from the RPC node at `rpc_url`, and tx_count from
+/// the Blockscout instance at `indexer_url`, for the contracts with known addresses: the
+/// addresses they were fetched from (see `--addresses`), else their addresses in
+/// `address_index`. Contracts enriched before are skipped, so a rerun only retries the ones
+/// that failed. Rewrites the results file in place.
+pub async fn enrich(
+    results_path: &Path,
+    rpc_url: &str,
+    address_index: Option<&Path>,
+    indexer_url: Option<&str>,
+    failures_only: bool,
+) {
+    let mut rows = results::read_results_file(results_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read results file {}: {}",
+            results_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let address_index = address_index
+        .map(|path| {
+            read_address_index(path).unwrap_or_else(|e| {
+                eprintln!("Could not read address index {}: {}", path.display(), e);
+                std::process::exit(1);
+            })
+        })
+        .unwrap_or_default();
+    let mut without_addresses = 0;
+    for row in rows.iter_mut().filter(|row| row.addresses.is_empty()) {
+        match address_index.get(&row.bytecode_hash) {
+            Some(addresses) => row.addresses = addresses.clone(),
+            None => without_addresses += 1,
+        }
+    }
+
+    let client = http_client().unwrap_or_else(|e| {
+        eprintln!("Could not build the HTTP client: {}", e);
+        std::process::exit(1);
+    });
+    let rpc = RpcClient {
+        client: client.clone(),
+        url: rpc_url.to_string(),
+    };
+    let indexer = indexer_url.map(|url| IndexerClient {
+        client,
+        url: url.to_string(),
+    });
+    // every contract is looked up at the same block, so their balances add up
+    let latest = rpc.latest_block().await.unwrap_or_else(|e| {
+        eprintln!("Could not reach the RPC node {}: {}", rpc_url, e);
+        std::process::exit(1);
+    });
+    let semaphore = Arc::new(Semaphore::new(RPC_CONCURRENCY));
+    let mut lookups = JoinSet::new();
+    for (i, row) in rows.iter().enumerate() {
+        let enriched = row.balance_eth.is_some() && (indexer.is_none() || row.tx_count.is_some());
+        if row.addresses.is_empty()
+            || enriched
+            || (failures_only && matches!(row.result, ExitType::Success))
+        {
+            continue;
+        }
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let rpc = rpc.clone();
+        let indexer = indexer.clone();
+        let addresses = row.addresses.clone();
+        lookups.spawn(async move {
+            let metadata = async {
+                let mut metadata = rpc.fetch(&addresses, latest).await?;
+                if let Some(indexer) = &indexer {
+                    metadata.tx_count = Some(indexer.fetch(&addresses).await?);
+                }
+                Ok::<OnchainMetadata, String>(metadata)
+            }
+            .await;
+            drop(permit);
+            (i, metadata)
+        });
+    }
+
+    let total = lookups.len();
+    let (mut enriched, mut failed) = (0, 0);
+    // contracts whose deployment could not be found, with the last error
+    let (mut deployment_unknown, mut deployment_error) = (0, None);
+    while let Some(lookup) = lookups.join_next().await {
+        let Ok((i, metadata)) = lookup else {
+            failed += 1;
+            continue;
+        };
+        match metadata {
+            Ok(metadata) => {
+                let row = &mut rows[i];
+                row.deployed_block = metadata.deployed_block;
+                row.deployed_at = metadata.deployed_at;
+                row.balance_eth = Some(metadata.balance_eth);
+                if metadata.tx_count.is_some() {
+                    row.tx_count = metadata.tx_count;
+                }
+                if metadata.deployment_error.is_some() {
+                    deployment_unknown += 1;
+                    deployment_error = metadata.deployment_error;
+                }
+                enriched += 1;
+            }
+            Err(e) => {
+                eprintln!("{}: {}", rows[i].bytecode_hash, e);
+                failed += 1;
+            }
+        }
+        if (enriched + failed) % 100 == 0 {
+            println!("{}/{} contracts looked up", enriched + failed, total);
+        }
+    }
+    if let Err(e) = report::rewrite_results_file(results_path, &rows) {
+        eprintln!("Could not write the enriched results, {}", e);
+        std::process::exit(1);
+    }
+    println!(
+        "Enriched {} contracts of {} at block {}: {} failed (rerun to retry them), {} without a \
+         known address (see --address-index)",
+        enriched,
+        results_path.display(),
+        latest,
+        failed,
+        without_addresses
+    );
+    if let Some(e) = deployment_error {
+        eprintln!(
+            "The deployment of {} contracts could not be found, their balance was still filled. \
+             Finding it needs a node that keeps the state of old blocks (an archive node): {}",
+            deployment_unknown, e
+        );
+    }
+}
+
+/// Year of the earliest deployment of the contract, "unknown" for results not enriched
+pub fn deployment_year(row: &ResultsRow) -> String {
+    row.deployed_at
+        .map(|deployed_at| deployed_at.format("%Y").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Prints the share of the total weight of the enriched contracts that each exit type holds,
+/// the failure rate per tier of weight, then the failing contracts of most weight
+pub fn print_weighted_failures(rows: &[ResultsRow], weight_by: WeightBy) {
+    let weighted = rows
+        .iter()
+        .filter_map(|row| weight_by.weight(row).map(|weight| (row, weight)))
+        .collect::<Vec<(&ResultsRow, f64)>>();
+    if weighted.is_empty() {
+        println!("No contract has on-chain metadata, see `omniscan enrich`");
+        return;
+    }
+    let total = weighted.iter().map(|(_, weight)| weight).sum::<f64>();
+    let mut by_exit_type: BTreeMap<&str, (usize, f64)> = BTreeMap::new();
+    for (row, weight) in &weighted {
+        let entry = by_exit_type.entry(row.result.name()).or_default();
+        entry.0 += 1;
+        entry.1 += weight;
+    }
+    let mut by_exit_type = by_exit_type.into_iter().collect::<Vec<_>>();
+    by_exit_type.sort_by(|a, b| b.1 .1.total_cmp(&a.1 .1));
+    println!(
+        "Weighted by {}, {} of {} contracts enriched:",
+        weight_by.name(),
+        weighted.len(),
+        rows.len()
+    );
+    println!("| Type | Count | Weight | Percent |");
+    println!("| ----- | ----- | ----- | ------- |");
+    for (name, (count, weight)) in by_exit_type {
+        let percent = if total > 0.0 {
+            weight / total * 100.0
+        } else {
+            0.0
+        };
+        println!("|{} | {} | {:.2} | {:.2} |", name, count, weight, percent);
+    }
+    println!("| Total | {} | {:.2} | 100.00 |", weighted.len(), total);
+
+    // failure rates of the contracts that matter most first
+    let tiers = weight_by.tiers();
+    println!();
+    println!("| {} | Count | Failures | Failure rate |", weight_by.name());
+    println!("| ----- | ----- | ----- | ------- |");
+    for (i, lower) in tiers.iter().enumerate() {
+        let in_tier = weighted
+            .iter()
+            .filter(|(_, weight)| *weight >= *lower && (i == 0 || *weight < tiers[i - 1]))
+            .collect::<Vec<_>>();
+        if in_tier.is_empty() {
+            continue;
+        }
+        let failures = in_tier
+            .iter()
+            .filter(|(row, _)| !matches!(row.result, ExitType::Success))
+            .count();
+        let tier = match i {
+            0 => format!(">= {}", lower),
+            _ => format!("{} - {}", lower, tiers[i - 1]),
+        };
+        println!(
+            "|{} | {} | {} | {:.2} |",
+            tier,
+            in_tier.len(),
+            failures,
+            failures as f64 / in_tier.len() as f64 * 100.0
+        );
+    }
+
+    let mut failures = weighted
+        .into_iter()
+        .filter(|(row, _)| !matches!(row.result, ExitType::Success))
+        .collect::<Vec<(&ResultsRow, f64)>>();
+    if failures.is_empty() {
+        return;
+    }
+    failures.sort_by(|a, b| b.1.total_cmp(&a.1));
+    println!();
+    println!("Failing contracts of most {}:", weight_by.name());
+    for (row, weight) in failures.iter().take(LISTED_CONTRACTS) {
+        println!(
+            "  {}: {:.2} {}",
+            row.bytecode_hash,
+            weight,
+            row.result.name()
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    /// `DONE <PHASE> IN: <duration>`, e.g. pyrometer's `DONE ANALYZING IN: 12ms`
+    static ref PHASE_MARKER: Regex =
+        Regex::new(r"DONE (PARSING|ANALYZING|WRITING) IN: (\d+(?:\.\d+)?)(ns|us|µs|ms|s)\b")
+            .unwrap();
+}
+
+/// Durations (secs) of the analyzer's phases, None for phases it printed no marker for
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PhaseTimes {
+    pub parse: Option<f64>,
+    pub analysis: Option<f64>,
+    pub output: Option<f64>,
+}
+
+/// The phase of the last marker in the analyzer's stdout, e.g. "ANALYZING" when the analyzer
+/// was last reported done analyzing. None when it printed no marker.
+pub fn last_phase(stdout: &str) -> Option<String> {
+    PHASE_MARKER
+        .captures_iter(stdout)
+        .last()
+        .map(|captures| captures[1].to_string())
+}
+
+/// Reads the phase markers out of the analyzer's stdout. A phase reported several times keeps
+/// the sum of its durations.
+pub fn parse_phase_times(stdout: &str) -> PhaseTimes {
+    let mut phase_times = PhaseTimes::default();
+    for captures in PHASE_MARKER.captures_iter(stdout) {
+        let Ok(value) = captures[2].parse::<f64>() else {
+            continue;
+        };
+        let secs = match &captures[3] {
+            "ns" => value / 1e9,
+            "us" | "µs" => value / 1e6,
+            "ms" => value / 1e3,
+            _ => value,
+        };
+        let phase = match &captures[1] {
+            "PARSING" => &mut phase_times.parse,
+            "ANALYZING" => &mut phase_times.analysis,
+            _ => &mut phase_times.output,
+        };
+        *phase = Some(phase.unwrap_or(0.0) + secs);
+    }
+    phase_times
+}
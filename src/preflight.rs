@@ -0,0 +1,290 @@
+use crate::{
+    project::{CorpusKind, Framework},
+    FiestaMetadata, SourcePreparation,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Rough upper bounds of what a run writes per contract, used to estimate its disk usage
+const RESULTS_ROW_BYTES: u64 = 1024;
+const REPORT_ENTRY_BYTES: u64 = 1024;
+const JOURNAL_ENTRY_BYTES: u64 = 512;
+/// Captured stdout and stderr of a failing contract, pyrometer's `--debug` output included,
+/// when there are no artifacts of an earlier run to measure. Output sizes vary too much for
+/// this guess to fail a run, it only warns.
+const ARTIFACT_BYTES: u64 = 256 * 1024;
+/// Contracts of the artifacts directory measured for the size of the artifacts of a contract
+const ARTIFACT_SAMPLE: usize = 200;
+/// crytic-compile writes its combined export and the standard-json input next to it
+const CRYTIC_SOURCE_COPIES: u64 = 4;
+
+/// Checks that the corpus root has the layout `--corpus` expects, so a wrong path fails now
+/// rather than with an empty run
+pub fn check_corpus_layout(corpus: CorpusKind, corpus_root: &Path) -> Result<(), String> {
+    let framework = match corpus {
+        CorpusKind::Fiesta => return check_fiesta_layout(corpus_root),
+        CorpusKind::Foundry => Framework::Foundry,
+        CorpusKind::Hardhat => Framework::Hardhat,
+        CorpusKind::Plain => return check_plain_layout(corpus_root),
+    };
+    let has_project = fs::read_dir(corpus_root)
+        .map_err(|e| format!("could not read {}: {}", corpus_root.display(), e))?
+        .flatten()
+        .any(|entry| framework.is_project_root(&entry.path()));
+    if has_project {
+        Ok(())
+    } else {
+        Err(format!(
+            "no subdirectory of {} holds a {} project ({})",
+            corpus_root.display(),
+            framework,
+            framework.config_files().join(" or ")
+        ))
+    }
+}
+
+/// A plain corpus only needs a .sol file somewhere
+fn check_plain_layout(corpus_root: &Path) -> Result<(), String> {
+    let has_source = WalkDir::new(corpus_root)
+        .into_iter()
+        .flatten()
+        .any(|entry| {
+            entry.file_type().is_file() && entry.path().extension() == Some("sol".as_ref())
+        });
+    if has_source {
+        Ok(())
+    } else {
+        Err(format!("{} holds no .sol file", corpus_root.display()))
+    }
+}
+
+/// smart-contract-fiesta keeps every contract in organized_contracts/XX/bytecodehash/
+fn check_fiesta_layout(corpus_root: &Path) -> Result<(), String> {
+    let organized_contracts = corpus_root.join("organized_contracts");
+    let prefix_dirs = fs::read_dir(&organized_contracts).map_err(|_| {
+        format!(
+            "{} has no organized_contracts directory, is it a smart-contract-fiesta checkout? \
+             Use --corpus foundry or --corpus hardhat for project repositories, --corpus plain \
+             for any directory of .sol files",
+            corpus_root.display()
+        )
+    })?;
+    let has_contract = prefix_dirs
+        .flatten()
+        .filter(|prefix_dir| prefix_dir.path().is_dir())
+        .find_map(|prefix_dir| fs::read_dir(prefix_dir.path()).ok()?.flatten().next())
+        .is_some_and(|contract_dir| contract_dir.path().join("metadata.json").is_file());
+    if has_contract {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} holds no XX/<bytecode_hash>/metadata.json, the corpus looks empty or incomplete",
+            organized_contracts.display()
+        ))
+    }
+}
+
+/// Where a run writes and what it will write there
+pub struct RunOutputs<'a> {
+    pub results: &'a Path,
+    pub reports: Vec<&'a Path>,
+    pub journal: Option<&'a Path>,
+    pub artifacts_dir: Option<&'a Path>,
+    pub work_dir: &'a Path,
+    pub preparation: Option<SourcePreparation>,
+}
+
+/// Size of the files next to the contract's entrypoint, a stand-in for its sources
+fn source_bytes(metadata: &FiestaMetadata) -> u64 {
+    fs::read_dir(&metadata.abs_path_to_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Mean size of the artifacts of a contract in the artifacts directory of earlier runs, over
+/// up to `ARTIFACT_SAMPLE` contracts. None when it holds none yet.
+fn measured_artifact_bytes(artifacts_dir: &Path) -> Option<u64> {
+    let contract_dirs = fs::read_dir(artifacts_dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .take(ARTIFACT_SAMPLE)
+        .map(|entry| {
+            WalkDir::new(entry.path())
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len())
+                .sum::<u64>()
+        })
+        .collect::<Vec<u64>>();
+    (!contract_dirs.is_empty())
+        .then(|| contract_dirs.iter().sum::<u64>() / contract_dirs.len() as u64)
+}
+
+/// Closest ancestor of `path` that exists, outputs are checked before their directories are
+/// created
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().ok()?.join(path)
+    };
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .map(Path::to_path_buf)
+}
+
+/// Identifier of the filesystem holding `path`
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+fn available_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only writes the struct passed to it, path is a valid C string
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(not(unix))]
+fn available_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        bytes if bytes >= 1 << 30 => format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64),
+        bytes if bytes >= 1 << 20 => format!("{:.1} MiB", bytes as f64 / (1u64 << 20) as f64),
+        bytes => format!("{:.1} KiB", bytes as f64 / 1024.0),
+    }
+}
+
+/// What a filesystem is estimated to receive: bytes the run needs, bytes guessed without
+/// anything to measure them by, and what is written there
+#[derive(Default)]
+struct VolumeEstimate<'a> {
+    needed: u64,
+    guessed: u64,
+    written: Vec<&'a str>,
+}
+
+/// Estimates what the run writes to each filesystem and checks it fits in the space left.
+/// Outputs sharing a filesystem are added up. Filesystems whose free space cannot be read are
+/// not checked. Estimates that are guesses only warn when they don't fit.
+pub fn check_disk_space(outputs: &RunOutputs, metadatas: &[FiestaMetadata]) -> Result<(), String> {
+    let contracts = metadatas.len() as u64;
+    // (path, what, bytes, whether the bytes are a guess)
+    let mut estimates = vec![(
+        outputs.results,
+        "results",
+        contracts * RESULTS_ROW_BYTES,
+        false,
+    )];
+    for report in &outputs.reports {
+        estimates.push((report, "reports", contracts * REPORT_ENTRY_BYTES, false));
+    }
+    if let Some(journal) = outputs.journal {
+        estimates.push((
+            journal,
+            "queue journal",
+            contracts * JOURNAL_ENTRY_BYTES,
+            false,
+        ));
+    }
+    if let Some(artifacts_dir) = outputs.artifacts_dir {
+        let (bytes, guessed) = match measured_artifact_bytes(artifacts_dir) {
+            Some(bytes) => (bytes, false),
+            None => (ARTIFACT_BYTES, true),
+        };
+        estimates.push((artifacts_dir, "artifacts", contracts * bytes, guessed));
+    }
+    if let Some(preparation) = outputs.preparation {
+        let copies = match preparation {
+            SourcePreparation::CryticCompile => CRYTIC_SOURCE_COPIES,
+            SourcePreparation::Flatten(_) => 1,
+        };
+        let sources = metadatas.iter().map(source_bytes).sum::<u64>();
+        estimates.push((
+            outputs.work_dir,
+            "prepared sources",
+            sources * copies,
+            false,
+        ));
+    }
+
+    // filesystem -> (a path on it, its estimate)
+    let mut volumes: BTreeMap<u64, (PathBuf, VolumeEstimate)> = BTreeMap::new();
+    for (path, what, bytes, guessed) in estimates {
+        let Some(existing) = existing_ancestor(path) else {
+            continue;
+        };
+        let Some(device) = device_id(&existing) else {
+            continue;
+        };
+        let (_, volume) = volumes
+            .entry(device)
+            .or_insert_with(|| (existing, VolumeEstimate::default()));
+        if guessed {
+            volume.guessed += bytes;
+        } else {
+            volume.needed += bytes;
+        }
+        if !volume.written.contains(&what) {
+            volume.written.push(what);
+        }
+    }
+
+    for (path, volume) in volumes.values() {
+        let Some(available) = available_bytes(path) else {
+            continue;
+        };
+        let message = |estimate: u64| {
+            format!(
+                "the filesystem holding {} has {} free, the {} of {} contracts need an estimated {}",
+                path.display(),
+                format_bytes(available),
+                volume.written.join(", "),
+                contracts,
+                format_bytes(estimate)
+            )
+        };
+        if available < volume.needed {
+            return Err(message(volume.needed));
+        }
+        if available < volume.needed + volume.guessed {
+            eprintln!(
+                "WARNING: {} (at {} of artifacts per contract, which earlier runs will tell better)",
+                message(volume.needed + volume.guessed),
+                format_bytes(ARTIFACT_BYTES)
+            );
+        }
+    }
+    Ok(())
+}
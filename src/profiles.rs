@@ -0,0 +1,117 @@
+use crate::rules::Rules;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// How the output of a range of pyrometer versions is classified: what its success marker
+/// and its error messages look like
+pub struct ClassificationProfile {
+    pub name: &'static str,
+    /// Oldest version the profile applies to
+    first_version: (u64, u64, u64),
+    /// First version the profile no longer applies to
+    end_version: (u64, u64, u64),
+    pub success: Regex,
+    /// Captures the error message
+    pub error: Regex,
+}
+
+lazy_static! {
+    /// Every known profile, oldest versions first
+    static ref PROFILES: Vec<ClassificationProfile> = vec![
+        ClassificationProfile {
+            name: "0.1",
+            first_version: (0, 0, 0),
+            end_version: (0, 2, 0),
+            // the marker is the last line, always in milliseconds
+            success: Regex::new(r"DONE ANALYZING IN: \d+ms\. Writing to cli\.\.\.\n$").unwrap(),
+            // the message is printed in red after `Error:`
+            error: Regex::new(r"(?s)Error:.*?31m([a-zA-Z0-9` .]{5,})").unwrap(),
+        },
+        ClassificationProfile {
+            name: "0.2",
+            first_version: (0, 2, 0),
+            end_version: (0, 3, 0),
+            // the duration comes in any unit, and the report may follow the marker
+            success: Regex::new(r"DONE ANALYZING IN: \d+(?:\.\d+)?(?:ns|us|µs|ms|s)\b").unwrap(),
+            // colors are left out when stdout is not a terminal
+            error: Regex::new(r"(?m)^Error:(?:\s|\x1b\[[0-9;]*m)*([a-zA-Z0-9` .]{5,})").unwrap(),
+        },
+    ];
+    static ref VERSION_REGEX: Regex = Regex::new(r"(\d+)\.(\d+)\.(\d+)").unwrap();
+}
+
+/// Names of the known profiles, for `--classification-profile`
+pub fn profile_names() -> Vec<&'static str> {
+    PROFILES.iter().map(|profile| profile.name).collect()
+}
+
+pub fn by_name(name: &str) -> Option<&'static ClassificationProfile> {
+    PROFILES.iter().find(|profile| profile.name == name)
+}
+
+/// The profile of the original success and error regexes, used when the analyzer version is
+/// not known so that the default classification does not change
+pub fn baseline() -> &'static ClassificationProfile {
+    PROFILES.first().unwrap()
+}
+
+/// The first `x.y.z` in `version`, e.g. in `pyrometer 0.2.1`
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let captures = VERSION_REGEX.captures(version)?;
+    Some((
+        captures[1].parse().ok()?,
+        captures[2].parse().ok()?,
+        captures[3].parse().ok()?,
+    ))
+}
+
+/// The profile of the analyzer reporting `version` (see `environment::analyzer_version`),
+/// None when the version could not be read or no profile covers it
+pub fn for_version(version: Option<&str>) -> Option<&'static ClassificationProfile> {
+    let version = parse_version(version?)?;
+    PROFILES
+        .iter()
+        .find(|profile| profile.first_version <= version && version < profile.end_version)
+}
+
+/// The profile named by `--classification-profile`, or the one of the analyzer's version. A
+/// version no profile covers falls back to the baseline profile, with a warning to print: the
+/// output of that version may not be understood, which shows up as NonInterpreted results
+/// rather than as an error.
+pub fn select(
+    forced: Option<&str>,
+    analyzer_version: Option<&str>,
+) -> Result<(&'static ClassificationProfile, Option<String>), String> {
+    if let Some(name) = forced {
+        return by_name(name).map(|profile| (profile, None)).ok_or_else(|| {
+            format!(
+                "unknown classification profile {}, known profiles: {}",
+                name,
+                profile_names().join(", ")
+            )
+        });
+    }
+    if let Some(profile) = for_version(analyzer_version) {
+        return Ok((profile, None));
+    }
+    let profile = baseline();
+    let version = match analyzer_version {
+        Some(version) => format!("pyrometer version `{}`", version),
+        None => "the pyrometer version (`pyrometer --version` failed)".to_string(),
+    };
+    let warning = format!(
+        "No classification profile is known for {}, classifying its output with the baseline \
+         profile ({}). Success and error messages that changed format will be counted as \
+         NonInterpreted: check the NonInterpreted results, and pass --classification-profile \
+         to pick a profile",
+        version, profile.name
+    );
+    Ok((profile, Some(warning)))
+}
+
+/// Everything the analyzer's output is classified with: the profile of its version, then the
+/// rules of `classify-unknowns` for the outputs the profile does not recognize
+pub struct Classifier {
+    pub profile: &'static ClassificationProfile,
+    pub rules: Rules,
+}
@@ -0,0 +1,177 @@
+use crate::status::RunStatus;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Window the current rate of `progress.json` is measured over
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Contents of the progress file, see `--progress-file`
+#[derive(Serialize)]
+struct Progress {
+    /// "running", then "finished" or "aborted" once the results are recorded
+    state: &'static str,
+    pid: u32,
+    results: String,
+    started_at: String,
+    updated_at: String,
+    total: usize,
+    completed: usize,
+    in_flight: usize,
+    queued: usize,
+    paused: bool,
+    /// Number of completed contracts per result type
+    counts: BTreeMap<String, usize>,
+    success_rate: f64,
+    /// Seconds since the analysis began
+    elapsed: f64,
+    /// Contracts completed per second over the last minute, and since the start of the run
+    current_rate: f64,
+    overall_rate: f64,
+    /// Seconds left at the current rate, None before any contract completed or once the run
+    /// is over
+    eta: Option<f64>,
+    eta_at: Option<String>,
+}
+
+struct ProgressState {
+    /// (when, completed) of the recent writes, oldest first
+    samples: VecDeque<(Instant, usize)>,
+    /// Set once the final state is written, the writer thread then stops
+    done: bool,
+}
+
+/// Progress of the run written to a file for orchestrators, replaced atomically every interval
+/// so a reader never sees a partial file
+pub struct ProgressFile {
+    path: PathBuf,
+    results: String,
+    /// When the analysis began, after the contracts were discovered
+    started: Instant,
+    started_at: DateTime<Utc>,
+    status: Arc<RunStatus>,
+    state: Mutex<ProgressState>,
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl ProgressFile {
+    /// Writes the progress file, then rewrites it every `interval` from a background thread
+    /// until `finish`
+    pub fn start(
+        path: PathBuf,
+        results: &Path,
+        status: Arc<RunStatus>,
+        interval: Duration,
+    ) -> io::Result<Arc<Self>> {
+        let progress = Arc::new(Self {
+            path,
+            results: results.display().to_string(),
+            started: Instant::now(),
+            started_at: Utc::now(),
+            status,
+            state: Mutex::new(ProgressState {
+                samples: VecDeque::new(),
+                done: false,
+            }),
+        });
+        progress.write("running")?;
+        let writer = progress.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            if writer.state.lock().unwrap().done {
+                return;
+            }
+            if let Err(e) = writer.write("running") {
+                eprintln!(
+                    "Could not write the progress file {}: {}",
+                    writer.path.display(),
+                    e
+                );
+            }
+        });
+        Ok(progress)
+    }
+
+    /// Writes the final state, "finished" or "aborted", and stops the updates
+    pub fn finish(&self, state: &'static str) {
+        if let Err(e) = self.write(state) {
+            eprintln!(
+                "Could not write the progress file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    fn write(&self, state: &'static str) -> io::Result<()> {
+        let mut progress_state = self.state.lock().unwrap();
+        if progress_state.done {
+            return Ok(());
+        }
+        let snapshot = self.status.snapshot();
+        let now = Instant::now();
+        progress_state.samples.push_back((now, snapshot.completed));
+        while progress_state
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > RATE_WINDOW)
+        {
+            progress_state.samples.pop_front();
+        }
+        let elapsed = now.duration_since(self.started).as_secs_f64();
+        let overall_rate = if elapsed > 0.0 {
+            snapshot.completed as f64 / elapsed
+        } else {
+            0.0
+        };
+        // the oldest sample of the window, or the start of the run while the window fills
+        let current_rate = match progress_state.samples.front() {
+            Some((at, completed)) if now.duration_since(*at) >= RATE_WINDOW / 2 => {
+                (snapshot.completed - completed) as f64 / now.duration_since(*at).as_secs_f64()
+            }
+            _ => overall_rate,
+        };
+        let remaining = snapshot.total.saturating_sub(snapshot.completed);
+        let eta =
+            (state == "running" && current_rate > 0.0).then(|| remaining as f64 / current_rate);
+        let updated_at = Utc::now();
+
+        let progress = Progress {
+            state,
+            pid: std::process::id(),
+            results: self.results.clone(),
+            started_at: timestamp(self.started_at),
+            updated_at: timestamp(updated_at),
+            total: snapshot.total,
+            completed: snapshot.completed,
+            in_flight: snapshot.in_flight,
+            queued: snapshot.queued,
+            paused: snapshot.paused,
+            counts: snapshot.counts,
+            success_rate: snapshot.success_rate,
+            elapsed,
+            current_rate,
+            overall_rate,
+            eta,
+            eta_at: eta.map(|eta| {
+                timestamp(updated_at + chrono::Duration::milliseconds((eta * 1000.0) as i64))
+            }),
+        };
+        // a final state that fails to be written still stops the updates
+        progress_state.done = state != "running";
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+        fs::write(&tmp_path, serde_json::to_string_pretty(&progress).unwrap())?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
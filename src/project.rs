@@ -0,0 +1,604 @@
+use crate::{error::OmniscanError, solc, FiestaMetadata, SourceType};
+use clap::ValueEnum;
+use ethers::utils::{hex, keccak256};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+use walkdir::WalkDir;
+
+/// `forge remappings` taking longer than this is killed, the remappings are then gathered by hand
+const FORGE_REMAPPINGS_TIMEOUT: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref IMPORT_PATH: Regex =
+        Regex::new(r#"import\s+(?:[^'";]*\s+from\s+)?["']([^"']+)["']"#).unwrap();
+    static ref HARDHAT_SOURCES: Regex = Regex::new(r#"sources\s*:\s*["'`]([^"'`]+)["'`]"#).unwrap();
+    static ref HARDHAT_VERSION: Regex =
+        Regex::new(r#"(?:version|solidity)\s*:\s*["'](\d+\.\d+\.\d+)["']"#).unwrap();
+    static ref HARDHAT_OPTIMIZER: Regex = Regex::new(r"enabled\s*:\s*(true|false)").unwrap();
+    static ref HARDHAT_RUNS: Regex = Regex::new(r"runs\s*:\s*(\d+)").unwrap();
+    static ref HARDHAT_EVM_VERSION: Regex =
+        Regex::new(r#"evmVersion\s*:\s*["'](\w+)["']"#).unwrap();
+}
+
+/// Layout of the corpus directory handed to omniscan
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CorpusKind {
+    /// smart-contract-fiesta: organized_contracts/<XX>/<bytecode_hash>/metadata.json
+    Fiesta,
+    /// A directory of forge projects, analyzing the contracts in each project's src/
+    Foundry,
+    /// A directory of hardhat projects, analyzing the contracts in each project's contracts/
+    Hardhat,
+    /// Any directory tree, analyzing every .sol file in it on its own
+    Plain,
+}
+
+/// Build framework of a repository in a project corpus
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framework {
+    Foundry,
+    Hardhat,
+    /// No framework, a .sol file of a plain directory corpus
+    Plain,
+}
+
+impl fmt::Display for Framework {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Framework::Foundry => write!(f, "Foundry"),
+            Framework::Hardhat => write!(f, "Hardhat"),
+            Framework::Plain => write!(f, "Plain"),
+        }
+    }
+}
+
+impl Framework {
+    /// Config files marking a directory as a project of this framework
+    pub fn config_files(&self) -> &'static [&'static str] {
+        match self {
+            Framework::Foundry => &["foundry.toml"],
+            Framework::Hardhat => &["hardhat.config.js", "hardhat.config.ts"],
+            Framework::Plain => &[],
+        }
+    }
+
+    pub fn is_project_root(&self, path: &Path) -> bool {
+        self.config_files()
+            .iter()
+            .any(|config_file| path.join(config_file).is_file())
+    }
+
+    fn read_config(&self, project_root: &Path) -> Result<ProjectConfig, String> {
+        match self {
+            Framework::Foundry => read_foundry_config(project_root),
+            Framework::Hardhat => read_hardhat_config(project_root),
+            Framework::Plain => Err("plain directories have no config".to_string()),
+        }
+    }
+}
+
+/// A source file of a framework project, analyzed with the project's remappings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectSource {
+    pub framework: Framework,
+    /// File name of the contract inside its directory
+    pub entrypoint: String,
+    /// Remappings file with absolute targets, None when the project has no remappings
+    pub remappings: Option<PathBuf>,
+}
+
+impl ProjectSource {
+    /// The `prefix=target` remapping lines, for tools that take them as arguments
+    pub fn remapping_lines(&self) -> Vec<String> {
+        self.remappings
+            .as_ref()
+            .and_then(|remappings| fs::read_to_string(remappings).ok())
+            .map(|contents| contents.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Settings of a project, read from its framework's config file
+struct ProjectConfig {
+    /// Directory of the project's contracts, relative to the project root
+    src: String,
+    solc_version: Option<String>,
+    optimizer: bool,
+    optimizer_runs: i64,
+    evm_version: Option<String>,
+    /// `prefix=target` lines with targets relative to the project root or absolute
+    remappings: Vec<String>,
+}
+
+/// Reads foundry.toml's `[profile.default]`
+fn read_foundry_config(project_root: &Path) -> Result<ProjectConfig, String> {
+    let contents =
+        fs::read_to_string(project_root.join("foundry.toml")).map_err(|e| e.to_string())?;
+    let config: toml::Value = toml::from_str(&contents).map_err(|e| e.to_string())?;
+    let profile = config
+        .get("profile")
+        .and_then(|profile| profile.get("default"));
+    let setting = |key: &str| profile.and_then(|profile| profile.get(key));
+    let string = |key: &str| setting(key).and_then(|value| value.as_str());
+
+    let mut config = ProjectConfig {
+        src: string("src").unwrap_or("src").to_string(),
+        solc_version: string("solc_version")
+            .or_else(|| string("solc"))
+            .map(|version| format!("v{}", version.trim_start_matches('v'))),
+        optimizer: setting("optimizer")
+            .and_then(|optimizer| optimizer.as_bool())
+            .unwrap_or(false),
+        optimizer_runs: setting("optimizer_runs")
+            .and_then(|runs| runs.as_integer())
+            .unwrap_or(200),
+        evm_version: string("evm_version").map(str::to_string),
+        remappings: setting("remappings")
+            .and_then(|remappings| remappings.as_array())
+            .map(|remappings| {
+                remappings
+                    .iter()
+                    .filter_map(|remapping| remapping.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    config.remappings = foundry_remappings(project_root, &config.remappings);
+    Ok(config)
+}
+
+/// hardhat configs are javascript, so the settings are picked out of the source text. With
+/// several compilers configured, the first one is used.
+fn read_hardhat_config(project_root: &Path) -> Result<ProjectConfig, String> {
+    let contents = Framework::Hardhat
+        .config_files()
+        .iter()
+        .find_map(|config_file| fs::read_to_string(project_root.join(config_file)).ok())
+        .ok_or("no readable hardhat config")?;
+    let capture = |regex: &Regex| {
+        regex
+            .captures(&contents)
+            .map(|captures| captures[1].to_string())
+    };
+
+    let src = capture(&HARDHAT_SOURCES)
+        .map(|sources| sources.trim_start_matches("./").to_string())
+        .unwrap_or_else(|| "contracts".to_string());
+    let remappings = node_modules_remappings(project_root, &project_root.join(&src));
+    Ok(ProjectConfig {
+        solc_version: capture(&HARDHAT_VERSION).map(|version| format!("v{}", version)),
+        optimizer: capture(&HARDHAT_OPTIMIZER).as_deref() == Some("true"),
+        optimizer_runs: capture(&HARDHAT_RUNS)
+            .and_then(|runs| runs.parse().ok())
+            .unwrap_or(200),
+        evm_version: capture(&HARDHAT_EVM_VERSION),
+        src,
+        remappings,
+    })
+}
+
+/// The npm package of a non-relative import, e.g. "@openzeppelin/contracts" for
+/// "@openzeppelin/contracts/token/ERC20/ERC20.sol"
+fn import_package(import_path: &str) -> Option<String> {
+    if import_path.starts_with('.') || import_path.starts_with('/') {
+        return None;
+    }
+    let mut segments = import_path.split('/');
+    let first = segments.next()?;
+    if first.starts_with('@') {
+        Some(format!("{}/{}", first, segments.next()?))
+    } else {
+        Some(first.to_string())
+    }
+}
+
+/// Packages imported by the solidity files under `dir`
+fn imported_packages(dir: &Path) -> BTreeSet<String> {
+    WalkDir::new(dir)
+        .into_iter()
+        .flatten()
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension == "sol")
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .flat_map(|contents| {
+            IMPORT_PATH
+                .captures_iter(&contents)
+                .filter_map(|captures| import_package(&captures[1]))
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+/// Resolves the packages imported from `sources_dir`, and the packages those import in turn,
+/// the way node does: in the closest node_modules walking up from the project root, so hoisted
+/// monorepo dependencies are found too
+fn node_modules_remappings(project_root: &Path, sources_dir: &Path) -> Vec<String> {
+    let mut resolved: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut queue: VecDeque<String> = imported_packages(sources_dir).into_iter().collect();
+    while let Some(package) = queue.pop_front() {
+        if resolved.contains_key(&package) {
+            continue;
+        }
+        let Some(package_dir) = project_root
+            .ancestors()
+            .map(|dir| dir.join("node_modules").join(&package))
+            .find(|package_dir| package_dir.is_dir())
+        else {
+            continue;
+        };
+        queue.extend(imported_packages(&package_dir));
+        resolved.insert(package, package_dir);
+    }
+
+    resolved
+        .into_iter()
+        .filter_map(|(package, package_dir)| {
+            Some(format!("{}/={}/", package, package_dir.to_str()?))
+        })
+        .collect()
+}
+
+/// The project's remappings as `prefix=target` lines with targets relative to the project
+/// root. `forge remappings` is the source of truth when forge is installed, otherwise they are
+/// gathered like forge does: remappings.txt, foundry.toml, then one per library in lib/.
+fn foundry_remappings(project_root: &Path, config_remappings: &[String]) -> Vec<String> {
+    let forge = Command::new("forge")
+        .arg("remappings")
+        .current_dir(project_root)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Some((true, stdout, _stderr)) = forge
+        .ok()
+        .and_then(|child| solc::wait_with_timeout(child, FORGE_REMAPPINGS_TIMEOUT))
+    {
+        return stdout.lines().map(str::to_string).collect();
+    }
+
+    let mut remappings: Vec<String> = fs::read_to_string(project_root.join("remappings.txt"))
+        .map(|contents| contents.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    remappings.extend(config_remappings.iter().cloned());
+    if let Ok(libs) = fs::read_dir(project_root.join("lib")) {
+        let mut libs = libs
+            .flatten()
+            .filter(|lib| lib.path().is_dir())
+            .map(|lib| lib.file_name().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        libs.sort();
+        for lib in libs {
+            let prefix = format!("{}/", lib);
+            if remappings
+                .iter()
+                .any(|remapping| remapping.starts_with(&prefix))
+            {
+                continue;
+            }
+            let target = if project_root.join("lib").join(&lib).join("src").is_dir() {
+                format!("lib/{}/src/", lib)
+            } else {
+                format!("lib/{}/", lib)
+            };
+            remappings.push(format!("{}={}", prefix, target));
+        }
+    }
+    remappings
+}
+
+/// Rewrites the remapping targets as absolute paths, so the analyzer can run from any directory,
+/// and writes them to `<remappings_dir>/<project name>.txt`
+fn write_remappings(
+    project_root: &Path,
+    remappings: &[String],
+    remappings_dir: &Path,
+) -> Result<Option<PathBuf>, OmniscanError> {
+    let lines = remappings
+        .iter()
+        .map(|remapping| remapping.trim())
+        .filter(|remapping| !remapping.is_empty())
+        .filter_map(|remapping| {
+            let (prefix, target) = remapping.split_once('=')?;
+            let target = project_root.join(target);
+            Some(format!("{}={}", prefix, target.to_str()?))
+        })
+        .collect::<Vec<String>>();
+    let Some(name) = project_root.file_name() else {
+        return Ok(None);
+    };
+    if lines.is_empty() {
+        return Ok(None);
+    }
+
+    let path = remappings_dir.join(format!("{}.txt", name.to_string_lossy()));
+    fs::create_dir_all(remappings_dir)
+        .and_then(|()| fs::write(&path, format!("{}\n", lines.join("\n"))))
+        .map_err(|source| OmniscanError::Write {
+            path: path.clone(),
+            source,
+        })?;
+    Ok(Some(path))
+}
+
+/// Metadata of a contract of a project corpus, with the settings of its project's `config`, or
+/// of a plain corpus without, in the directory `source_dir` of the corpus `corpus_root`
+fn file_metadata(
+    (source_dir, corpus_root): (&Path, &Path),
+    contract_name: String,
+    bytecode_hash: String,
+    config: Option<&ProjectConfig>,
+    project_source: ProjectSource,
+) -> Result<FiestaMetadata, OmniscanError> {
+    let mut metadata = FiestaMetadata {
+        contract_name,
+        compiler_version: config
+            .and_then(|config| config.solc_version.clone())
+            .unwrap_or_default(),
+        runs: config.map_or(0, |config| config.optimizer_runs),
+        optimization_used: config.is_some_and(|config| config.optimizer),
+        bytecode_hash,
+        evm_version: config.and_then(|config| config.evm_version.clone()),
+        chain: None,
+        abs_path_to_dir: String::new(),
+        rel_path_to_dir: String::new(),
+        source_type: None,
+        import_graph: None,
+        complexity: None,
+        addresses: Vec::new(),
+        harness_error: None,
+        compiler_available: None,
+        source_hash: None,
+    };
+    metadata.update_path_to_dir(source_dir, corpus_root)?;
+    metadata.update_source_type(SourceType::Project(project_source));
+    Ok(metadata)
+}
+
+/// The metadata a run gave the contract of the file `source` of a project or plain corpus,
+/// which have no metadata.json to read it back from, e.g. for the re-runs of `omniscan triage`.
+/// The file belongs to the closest project above it, and to a plain corpus without one.
+pub fn source_metadata(
+    corpus_root: &Path,
+    source: &Path,
+    remappings_dir: &Path,
+) -> Result<FiestaMetadata, OmniscanError> {
+    let read_error = |message: &str| OmniscanError::Read {
+        path: source.to_path_buf(),
+        source: std::io::Error::other(message.to_string()),
+    };
+    let (Some(source_dir), Some(entrypoint), Some(contract_name)) =
+        (source.parent(), source.file_name(), source.file_stem())
+    else {
+        return Err(read_error("not a file"));
+    };
+    let (entrypoint, contract_name) = (
+        entrypoint.to_string_lossy().to_string(),
+        contract_name.to_string_lossy().to_string(),
+    );
+    let project = source
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(corpus_root))
+        .find_map(|dir| {
+            [Framework::Foundry, Framework::Hardhat]
+                .into_iter()
+                .find(|framework| framework.is_project_root(dir))
+                .map(|framework| (framework, dir))
+        });
+    let Some((framework, project_root)) = project else {
+        let contents = fs::read(source).map_err(|e| OmniscanError::Read {
+            path: source.to_path_buf(),
+            source: e,
+        })?;
+        return file_metadata(
+            (source_dir, corpus_root),
+            contract_name,
+            hex::encode(keccak256(&contents)),
+            None,
+            ProjectSource {
+                framework: Framework::Plain,
+                entrypoint,
+                remappings: None,
+            },
+        );
+    };
+    let config = framework
+        .read_config(project_root)
+        .map_err(|e| read_error(&format!("invalid {} config: {}", framework, e)))?;
+    let remappings = write_remappings(project_root, &config.remappings, remappings_dir)?;
+    file_metadata(
+        (source_dir, corpus_root),
+        contract_name,
+        source_id(source.strip_prefix(corpus_root).unwrap_or(source)),
+        Some(&config),
+        ProjectSource {
+            framework,
+            entrypoint,
+            remappings,
+        },
+    )
+}
+
+/// Contracts have no deployed bytecode, so they are keyed by the hash of their path instead
+pub fn source_id(rel_path: &Path) -> String {
+    hex::encode(keccak256(rel_path.to_string_lossy().as_bytes()))
+}
+
+/// Treats every subdirectory of `corpus_root` holding the framework's config file as a project
+/// and collects the contracts in its sources directory, scripts and tests excluded
+pub fn discover_project_contracts(
+    framework: Framework,
+    corpus_root: &Path,
+    remappings_dir: &Path,
+    num_contracts: usize,
+    skip_contracts: usize,
+) -> Result<Vec<FiestaMetadata>, OmniscanError> {
+    let mut projects = fs::read_dir(corpus_root)
+        .map_err(|source| OmniscanError::Read {
+            path: corpus_root.to_path_buf(),
+            source,
+        })?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| framework.is_project_root(path))
+        .collect::<Vec<PathBuf>>();
+    projects.sort();
+
+    let mut metadatas = Vec::new();
+    let mut skipped_count = 0;
+    for project_root in projects {
+        let config = match framework.read_config(&project_root) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!(
+                    "Skipping {}: invalid {} config: {}",
+                    project_root.display(),
+                    framework,
+                    e
+                );
+                continue;
+            }
+        };
+        let remappings = write_remappings(&project_root, &config.remappings, remappings_dir)?;
+
+        let mut sources = WalkDir::new(project_root.join(&config.src))
+            .into_iter()
+            .flatten()
+            .map(|entry| entry.into_path())
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                path.is_file()
+                    && name.ends_with(".sol")
+                    && !name.ends_with(".t.sol")
+                    && !name.ends_with(".s.sol")
+            })
+            .collect::<Vec<PathBuf>>();
+        sources.sort();
+
+        for source in sources {
+            if skipped_count < skip_contracts {
+                skipped_count += 1;
+                continue;
+            }
+            let rel_path = source.strip_prefix(corpus_root).unwrap_or(&source);
+            // the walk only yields files under the project
+            let (Some(source_dir), Some(entrypoint), Some(contract_name)) =
+                (source.parent(), source.file_name(), source.file_stem())
+            else {
+                continue;
+            };
+            let metadata = file_metadata(
+                (source_dir, corpus_root),
+                contract_name.to_string_lossy().to_string(),
+                source_id(rel_path),
+                Some(&config),
+                ProjectSource {
+                    framework,
+                    entrypoint: entrypoint.to_string_lossy().to_string(),
+                    remappings: remappings.clone(),
+                },
+            )?;
+            metadatas.push(metadata);
+            if metadatas.len() == num_contracts {
+                return Ok(metadatas);
+            }
+        }
+    }
+    Ok(metadatas)
+}
+
+/// Collects every .sol file under `corpus_root`, hidden directories excluded, as a contract of
+/// its own. Files have no metadata, so a contract is identified by the hash of its contents and
+/// named after its file, and copies of a file are analyzed once.
+pub fn discover_plain_contracts(
+    corpus_root: &Path,
+    num_contracts: usize,
+    skip_contracts: usize,
+) -> Vec<FiestaMetadata> {
+    let walk = WalkDir::new(corpus_root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        });
+
+    let mut metadatas = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut duplicates = 0;
+    let mut skipped_count = 0;
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Could not read the corpus: {}", e);
+                continue;
+            }
+        };
+        let source = entry.path();
+        if !entry.file_type().is_file() || source.extension().is_none_or(|ext| ext != "sol") {
+            continue;
+        }
+        let (Some(source_dir), Some(entrypoint), Some(contract_name)) =
+            (source.parent(), source.file_name(), source.file_stem())
+        else {
+            continue;
+        };
+        let contents = match fs::read(source) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Could not read the corpus: {}: {}", source.display(), e);
+                continue;
+            }
+        };
+        let bytecode_hash = hex::encode(keccak256(&contents));
+        if !seen.insert(bytecode_hash.clone()) {
+            duplicates += 1;
+            continue;
+        }
+        if skipped_count < skip_contracts {
+            skipped_count += 1;
+            continue;
+        }
+
+        let metadata = file_metadata(
+            (source_dir, corpus_root),
+            contract_name.to_string_lossy().to_string(),
+            bytecode_hash,
+            None,
+            ProjectSource {
+                framework: Framework::Plain,
+                entrypoint: entrypoint.to_string_lossy().to_string(),
+                remappings: None,
+            },
+        );
+        match metadata {
+            Ok(metadata) => metadatas.push(metadata),
+            Err(e) => {
+                eprintln!("Could not read the corpus: {}", e);
+                continue;
+            }
+        }
+        if metadatas.len() == num_contracts {
+            break;
+        }
+    }
+    if duplicates > 0 {
+        println!(
+            "Skipped {} files with the same contents as another file of the corpus",
+            duplicates
+        );
+    }
+    metadatas
+}
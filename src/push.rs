@@ -0,0 +1,346 @@
+use crate::ResultsRow;
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+const MAX_ATTEMPTS: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// A collector that does not answer within these is unavailable and the batch is spooled
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where and how often the results are pushed, see `--push-results`
+pub struct PushConfig {
+    pub url: String,
+    /// Directory keeping the batches the collector did not take, see `--push-spool`
+    pub spool_dir: PathBuf,
+    pub batch_size: usize,
+    pub interval: Duration,
+}
+
+/// Hands the results to the task pushing them, cheap to clone into the loops recording results
+#[derive(Clone)]
+pub struct ResultPusher {
+    tx: mpsc::UnboundedSender<String>,
+}
+
+impl ResultPusher {
+    pub fn push(&self, row: &ResultsRow) {
+        // the task only stops once every sender is gone
+        let _ = self
+            .tx
+            .send(row.convert_to_json_line().trim_end().to_string());
+    }
+}
+
+#[derive(Default)]
+struct PushStats {
+    results: usize,
+    batches: usize,
+    /// Batches spooled by this or an earlier run that were pushed later
+    resent: usize,
+    /// Batches left in the spool once the run is over
+    spooled: usize,
+    /// Batches the collector refused, set aside in the spool as `.rejected`
+    rejected: usize,
+    last_error: Option<String>,
+}
+
+/// Why a batch was not pushed
+enum PushError {
+    /// The collector may take the batch later: network errors, server errors and rate limits
+    Unavailable(String),
+    /// The collector refused the batch itself with a client error, sending it again won't help
+    Rejected(String),
+}
+
+/// The task pushing the results, see `start`
+pub struct PushTask {
+    url: String,
+    spool_dir: PathBuf,
+    handle: JoinHandle<PushStats>,
+}
+
+/// A batch of results as JSON lines, with what the collector tells the batches apart by
+struct Batch {
+    /// Unique per run, so a collector can tell runs apart and drop a batch sent twice
+    run: String,
+    sequence: u64,
+    body: String,
+}
+
+impl Batch {
+    fn file_name(&self) -> String {
+        format!("{}-{:06}.jsonl", self.run, self.sequence)
+    }
+
+    /// Reads a spooled batch back, its run and sequence are in its file name
+    fn read(path: &Path) -> Option<Self> {
+        let stem = path.file_stem()?.to_str()?;
+        let (run, sequence) = stem.rsplit_once('-')?;
+        Some(Self {
+            run: run.to_string(),
+            sequence: sequence.parse().ok()?,
+            body: fs::read_to_string(path).ok()?,
+        })
+    }
+}
+
+struct Collector {
+    client: reqwest::Client,
+    url: String,
+    host: String,
+    spool_dir: PathBuf,
+    stats: PushStats,
+}
+
+impl Collector {
+    /// POSTs the batch, retrying with exponential backoff on network errors, server errors and
+    /// rate limit responses
+    async fn post(&self, batch: &Batch) -> Result<(), PushError> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut last_error = String::new();
+        for attempt in 0..MAX_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            let mut request = self
+                .client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/x-ndjson")
+                .header("X-Omniscan-Run", &batch.run)
+                .header("X-Omniscan-Batch", batch.sequence.to_string())
+                .header("X-Omniscan-Host", &self.host)
+                .body(batch.body.clone());
+            if let Ok(token) = std::env::var("OMNISCAN_PUSH_TOKEN") {
+                request = request.bearer_auth(token);
+            }
+            match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if response.status().is_server_error() || response.status() == 429 => {
+                    last_error = format!("the collector returned {}", response.status());
+                }
+                Ok(response) => {
+                    return Err(PushError::Rejected(format!(
+                        "the collector returned {}",
+                        response.status()
+                    )));
+                }
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        Err(PushError::Unavailable(last_error))
+    }
+
+    fn spool(&mut self, batch: &Batch) {
+        self.write_spool(batch.file_name(), batch);
+    }
+
+    /// Keeps a batch the collector refused in the spool for inspection, out of the batches
+    /// pushed again
+    fn reject(&mut self, batch: &Batch, error: String) {
+        eprintln!(
+            "The collector refused batch {} of run {}, it is kept in {} ({})",
+            batch.sequence,
+            batch.run,
+            self.spool_dir.display(),
+            error
+        );
+        self.write_spool(format!("{}.rejected", batch.file_name()), batch);
+        self.stats.rejected += 1;
+    }
+
+    fn write_spool(&mut self, file_name: String, batch: &Batch) {
+        let path = self.spool_dir.join(file_name);
+        if let Err(e) = fs::write(&path, &batch.body) {
+            eprintln!(
+                "Could not spool a batch of results to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// Spooled batches, oldest first
+    fn spooled(&self) -> Vec<PathBuf> {
+        let mut paths = fs::read_dir(&self.spool_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        paths
+    }
+
+    /// Pushes the spooled batches, then `batch`. Once a push fails the collector is taken to be
+    /// down, and `batch` is spooled without trying it. Batches the collector refuses are set
+    /// aside, and the next ones are still pushed
+    async fn flush(&mut self, batch: Option<Batch>) {
+        for path in self.spooled() {
+            let Some(spooled) = Batch::read(&path) else {
+                continue;
+            };
+            match self.post(&spooled).await {
+                Ok(()) => {
+                    let _ = fs::remove_file(&path);
+                    self.stats.resent += 1;
+                }
+                Err(PushError::Rejected(e)) => {
+                    let mut rejected = path.clone().into_os_string();
+                    rejected.push(".rejected");
+                    let _ = fs::rename(&path, rejected);
+                    self.stats.rejected += 1;
+                    eprintln!(
+                        "The collector refused the spooled batch {}, it is set aside ({})",
+                        path.display(),
+                        e
+                    );
+                }
+                Err(PushError::Unavailable(e)) => {
+                    self.stats.last_error = Some(e);
+                    if let Some(batch) = batch {
+                        self.spool(&batch);
+                    }
+                    return;
+                }
+            }
+        }
+        let Some(batch) = batch else {
+            return;
+        };
+        let results = batch.body.lines().count();
+        match self.post(&batch).await {
+            Ok(()) => {
+                self.stats.results += results;
+                self.stats.batches += 1;
+            }
+            Err(PushError::Rejected(e)) => self.reject(&batch, e),
+            Err(PushError::Unavailable(e)) => {
+                self.stats.last_error = Some(e);
+                self.spool(&batch);
+            }
+        }
+    }
+}
+
+/// Starts the task POSTing the results to the collector as JSON lines, `batch_size` at a time
+/// or every `interval`. Batches the collector does not take are spooled and pushed again
+/// before the next one, by this run or the next one with the same spool
+pub fn start(config: PushConfig) -> Result<(ResultPusher, PushTask), String> {
+    fs::create_dir_all(&config.spool_dir).map_err(|e| {
+        format!(
+            "could not create the spool {}: {}",
+            config.spool_dir.display(),
+            e
+        )
+    })?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let run = format!(
+        "{}-{:08x}",
+        Utc::now().format("%Y%m%dT%H%M%S"),
+        fastrand::u32(..)
+    );
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("could not build the client pushing the results: {}", e))?;
+    let mut collector = Collector {
+        client,
+        url: config.url.clone(),
+        host: std::env::var("HOSTNAME").unwrap_or_default(),
+        spool_dir: config.spool_dir.clone(),
+        stats: PushStats::default(),
+    };
+    let batch_size = config.batch_size.max(1);
+    let interval = config.interval;
+    let handle = tokio::spawn(async move {
+        let mut sequence = 0;
+        let mut pending = Vec::new();
+        let mut ticker = tokio::time::interval(interval);
+        let mut open = true;
+        while open {
+            let full = tokio::select! {
+                line = rx.recv() => match line {
+                    Some(line) => {
+                        pending.push(line);
+                        pending.len() >= batch_size
+                    }
+                    None => {
+                        open = false;
+                        true
+                    }
+                },
+                _ = ticker.tick() => true,
+            };
+            if !full {
+                continue;
+            }
+            let batch = (!pending.is_empty()).then(|| {
+                sequence += 1;
+                Batch {
+                    run: run.clone(),
+                    sequence,
+                    body: std::mem::take(&mut pending).join("\n") + "\n",
+                }
+            });
+            collector.flush(batch).await;
+            ticker.reset();
+        }
+        collector.stats.spooled = collector.spooled().len();
+        collector.stats
+    });
+    Ok((
+        ResultPusher { tx },
+        PushTask {
+            url: config.url,
+            spool_dir: config.spool_dir,
+            handle,
+        },
+    ))
+}
+
+impl PushTask {
+    /// Waits for the last results to be pushed, once every `ResultPusher` is dropped
+    pub async fn finish(self) {
+        let Ok(stats) = self.handle.await else {
+            eprintln!("Pushing the results to {} failed", self.url);
+            return;
+        };
+        println!(
+            "Pushed {} results in {} batches to {}",
+            stats.results, stats.batches, self.url
+        );
+        if stats.resent > 0 {
+            println!("Pushed {} batches spooled earlier", stats.resent);
+        }
+        if stats.rejected > 0 {
+            eprintln!(
+                "The collector refused {} batches of results, they are kept as .rejected files in \
+                 {} and not pushed again",
+                stats.rejected,
+                self.spool_dir.display()
+            );
+        }
+        if stats.spooled > 0 {
+            eprintln!(
+                "{} batches of results could not be pushed ({}), they are kept in {} and pushed \
+                 by the next run with the same --push-spool",
+                stats.spooled,
+                stats.last_error.unwrap_or_default(),
+                self.spool_dir.display()
+            );
+        } else {
+            let _ = fs::remove_dir(&self.spool_dir);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{error::OmniscanError, results, FiestaMetadata};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// One line of the queue journal
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueEvent {
+    /// First line of the journal, the results file the run appends to
+    Run { output: String },
+    /// A contract selected for the run, with the absolute path to its directory
+    Queued { bytecode_hash: String, dir: String },
+    /// The contract's analyzer process was spawned
+    Dispatched { bytecode_hash: String },
+    /// The contract's result was written to the results file
+    Completed { bytecode_hash: String },
+}
+
+/// What is left to do when resuming from an existing journal
+pub struct ResumeState {
+    pub output_path: PathBuf,
+    /// Directories of the contracts that were queued but never completed, in queue order.
+    /// Includes contracts that were in flight when the previous run died.
+    pub pending_dirs: Vec<PathBuf>,
+    /// Bytecode hashes of the pending contracts
+    pub pending_hashes: HashSet<String>,
+    pub completed: usize,
+    pub in_flight: usize,
+}
+
+/// Append-only journal of the work queue, so a run that crashes can resume where it left off
+pub struct QueueJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl QueueJournal {
+    fn open(path: &Path) -> Result<Self, OmniscanError> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|source| OmniscanError::Write {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    fn record(&self, event: &QueueEvent) -> Result<(), OmniscanError> {
+        let line = format!("{}\n", serde_json::to_string(event).unwrap());
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+            .map_err(|source| OmniscanError::Write {
+                path: self.path.clone(),
+                source,
+            })
+    }
+
+    /// Starts a new journal holding the full, ordered queue of the run
+    pub fn create(
+        path: &Path,
+        output_path: &Path,
+        metadatas: &[FiestaMetadata],
+    ) -> Result<Self, OmniscanError> {
+        let output = output_path
+            .to_str()
+            .ok_or_else(|| OmniscanError::NonUtf8Path(output_path.to_path_buf()))?;
+        // truncate anything left over, the queue is recorded from scratch
+        File::create(path).map_err(|source| OmniscanError::Write {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let journal = Self::open(path)?;
+        journal.record(&QueueEvent::Run {
+            output: output.to_string(),
+        })?;
+        for metadata in metadatas {
+            journal.record(&QueueEvent::Queued {
+                bytecode_hash: metadata.bytecode_hash.clone(),
+                dir: metadata.abs_path_to_dir.clone(),
+            })?;
+        }
+        Ok(journal)
+    }
+
+    /// Reopens an existing journal to keep recording events while resuming
+    pub fn reopen(path: &Path) -> Result<Self, OmniscanError> {
+        Self::open(path)
+    }
+
+    pub fn record_dispatched(&self, bytecode_hash: &str) -> Result<(), OmniscanError> {
+        self.record(&QueueEvent::Dispatched {
+            bytecode_hash: bytecode_hash.to_string(),
+        })
+    }
+
+    pub fn record_completed(&self, bytecode_hash: &str) -> Result<(), OmniscanError> {
+        self.record(&QueueEvent::Completed {
+            bytecode_hash: bytecode_hash.to_string(),
+        })
+    }
+}
+
+/// Replays a journal to find the contracts that still have to be analyzed.
+/// A partially written last line (from a crash mid-write) is ignored.
+pub fn load_resume_state(path: &Path) -> Result<ResumeState, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+
+    let mut output_path = None;
+    let mut queued: Vec<(String, String)> = Vec::new();
+    let mut dispatched: HashSet<String> = HashSet::new();
+    let mut completed: HashSet<String> = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let Ok(event) = serde_json::from_str::<QueueEvent>(&line) else {
+            continue;
+        };
+        match event {
+            QueueEvent::Run { output } => output_path = Some(PathBuf::from(output)),
+            QueueEvent::Queued { bytecode_hash, dir } => queued.push((bytecode_hash, dir)),
+            QueueEvent::Dispatched { bytecode_hash } => {
+                dispatched.insert(bytecode_hash);
+            }
+            QueueEvent::Completed { bytecode_hash } => {
+                completed.insert(bytecode_hash);
+            }
+        }
+    }
+
+    let output_path = output_path.ok_or("queue journal does not start with a run record")?;
+    // a crash between writing a result and journaling its completion leaves the contract
+    // recorded in the results file only, it is not analyzed again and written twice
+    if output_path.exists() {
+        completed.extend(
+            results::analyzed_hashes(&output_path)
+                .map_err(|e| format!("{}: {}", output_path.display(), e))?,
+        );
+    }
+    let in_flight = dispatched.difference(&completed).count();
+    let pending = queued
+        .into_iter()
+        .filter(|(bytecode_hash, _dir)| !completed.contains(bytecode_hash))
+        .collect::<Vec<(String, String)>>();
+    let pending_hashes = pending
+        .iter()
+        .map(|(bytecode_hash, _dir)| bytecode_hash.clone())
+        .collect();
+    let pending_dirs = pending
+        .into_iter()
+        .map(|(_bytecode_hash, dir)| PathBuf::from(dir))
+        .collect();
+
+    Ok(ResumeState {
+        output_path,
+        pending_dirs,
+        pending_hashes,
+        completed: completed.len(),
+        in_flight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{row, test_dir, ResultsWriter};
+
+    #[test]
+    fn resume_skips_contracts_completed_in_the_journal_or_the_results() {
+        let dir = test_dir("queue");
+        let journal_path = dir.join("queue.jsonl");
+        let output_path = dir.join("results.csv");
+
+        let journal = QueueJournal::open(&journal_path).unwrap();
+        journal
+            .record(&QueueEvent::Run {
+                output: output_path.to_str().unwrap().to_string(),
+            })
+            .unwrap();
+        for hash in ["0x1", "0x2", "0x3", "0x4"] {
+            journal
+                .record(&QueueEvent::Queued {
+                    bytecode_hash: hash.to_string(),
+                    dir: format!("/corpus/{}", hash),
+                })
+                .unwrap();
+        }
+        for hash in ["0x1", "0x2", "0x3"] {
+            journal.record_dispatched(hash).unwrap();
+        }
+        journal.record_completed("0x1").unwrap();
+        // the run died after writing the result of 0x2, before journaling it
+        let mut writer = ResultsWriter::new(output_path);
+        writer.initiate_headers_for_results_csv().unwrap();
+        for hash in ["0x1", "0x2"] {
+            writer
+                .append_to_results_file(&row(hash, "Success"))
+                .unwrap();
+        }
+        // and in the middle of a journal line
+        let mut file = OpenOptions::new().append(true).open(&journal_path).unwrap();
+        file.write_all(br#"{"completed":{"bytecode_ha"#).unwrap();
+
+        let state = load_resume_state(&journal_path).unwrap();
+        assert_eq!(
+            state.pending_dirs,
+            [PathBuf::from("/corpus/0x3"), PathBuf::from("/corpus/0x4")]
+        );
+        assert_eq!(
+            state.pending_hashes,
+            HashSet::from(["0x3".to_string(), "0x4".to_string()])
+        );
+        assert_eq!((state.completed, state.in_flight), (2, 1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
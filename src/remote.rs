@@ -0,0 +1,832 @@
+use crate::{archive::split_contract_path, junit, ContractSelection, FiestaMetadata};
+use ethers::utils::hex;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{io::AsyncReadExt, sync::Semaphore, task::JoinSet};
+
+/// Objects downloaded at once
+const FETCH_CONCURRENCY: usize = 32;
+/// A bucket that does not answer within these fails the run rather than hanging it
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+/// Files larger than this are uploaded in parts of this size, a single PUT takes at most 5 GiB
+/// on S3. Only a part is held in memory at a time
+const MULTIPART_PART_SIZE: u64 = 64 * 1024 * 1024;
+/// Slowest upload an upload request is given time for, on top of `REQUEST_TIMEOUT`
+const MIN_UPLOAD_BYTES_PER_SEC: u64 = 256 * 1024;
+/// Directory of the cache root keeping the ETag of every cached object, mirroring the layout
+/// of the objects. Hidden, so it is never taken for part of the corpus
+const ETAGS_DIR: &str = ".omniscan-etags";
+
+lazy_static! {
+    static ref LISTED_OBJECT: Regex = Regex::new(r"(?s)<Contents>(.*?)</Contents>").unwrap();
+    static ref OBJECT_KEY: Regex = Regex::new(r"(?s)<Key>(.*?)</Key>").unwrap();
+    static ref OBJECT_SIZE: Regex = Regex::new(r"<Size>\s*(\d+)\s*</Size>").unwrap();
+    static ref OBJECT_ETAG: Regex = Regex::new(r"(?s)<ETag>(.*?)</ETag>").unwrap();
+    static ref CONTINUATION_TOKEN: Regex =
+        Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").unwrap();
+    static ref UPLOAD_ID: Regex = Regex::new(r"<UploadId>(.*?)</UploadId>").unwrap();
+}
+
+/// An object of a bucket listing
+struct ListedObject {
+    key: String,
+    size: u64,
+    /// Changes whenever the object does, None when the store does not list it
+    etag: Option<String>,
+}
+
+/// Parses the objects of a ListObjectsV2 response. The elements of an object are looked up
+/// one by one, so their order does not matter
+fn parse_listed_objects(body: &str) -> Vec<ListedObject> {
+    LISTED_OBJECT
+        .captures_iter(body)
+        .filter_map(|contents| {
+            let contents = &contents[1];
+            Some(ListedObject {
+                key: xml_unescape(&OBJECT_KEY.captures(contents)?[1]),
+                size: OBJECT_SIZE.captures(contents)?[1].parse().ok()?,
+                etag: OBJECT_ETAG
+                    .captures(contents)
+                    .map(|etag| xml_unescape(&etag[1]).trim_matches('"').to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Client for bucket requests, with timeouts
+pub fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Time an upload of `length` bytes gets, uploads can take longer than other requests
+pub fn upload_timeout(length: u64) -> Duration {
+    REQUEST_TIMEOUT + Duration::from_secs(length / MIN_UPLOAD_BYTES_PER_SEC)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Provider {
+    S3,
+    Gcs,
+}
+
+/// A smart-contract-fiesta corpus in an S3 or GCS bucket, e.g. `s3://bucket/fiesta`, or
+/// where `--upload` puts the files of a run
+///
+/// S3 requests are signed with AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY (plus
+/// AWS_SESSION_TOKEN) when they are set, in AWS_REGION. AWS_ENDPOINT_URL points them at an
+/// S3-compatible store instead. GCS requests carry GOOGLE_OAUTH_ACCESS_TOKEN when it is set.
+/// Public buckets need neither.
+#[derive(Clone, Debug)]
+pub struct RemoteCorpus {
+    provider: Provider,
+    bucket: String,
+    /// Key prefix of the corpus root, without the trailing slash
+    prefix: String,
+}
+
+/// Percent-encodes everything but the unreserved characters, and `/` when `keep_slash` is set
+pub fn uri_encode(value: &str, keep_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Where omniscan keeps what it caches across runs: "$XDG_CACHE_HOME/omniscan"
+pub fn cache_home() -> PathBuf {
+    std::env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(|_| std::env::temp_dir())
+        .join("omniscan")
+}
+
+impl RemoteCorpus {
+    /// Parses `s3://bucket/prefix` and `gs://bucket/prefix`. None when `url` is not a bucket URL.
+    pub fn parse(url: &str) -> Option<Result<Self, String>> {
+        let (provider, rest) = if let Some(rest) = url.strip_prefix("s3://") {
+            (Provider::S3, rest)
+        } else if let Some(rest) = url.strip_prefix("gs://") {
+            (Provider::Gcs, rest)
+        } else {
+            return None;
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Some(Err(format!("{} names no bucket", url)));
+        }
+        Some(Ok(Self {
+            provider,
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        }))
+    }
+
+    /// Where fetched objects are kept between runs: `$XDG_CACHE_HOME/omniscan/<bucket>/<prefix>`
+    pub fn default_cache_dir(&self) -> PathBuf {
+        cache_home().join(&self.bucket).join(&self.prefix)
+    }
+
+    fn region() -> String {
+        std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string())
+    }
+
+    /// Scheme, host and path of an object, `key` empty for the bucket itself
+    fn location(&self, key: &str) -> (String, String, String) {
+        let key = uri_encode(key, true);
+        let path_style = if key.is_empty() {
+            format!("/{}", self.bucket)
+        } else {
+            format!("/{}/{}", self.bucket, key)
+        };
+        match self.provider {
+            Provider::S3 => match std::env::var("AWS_ENDPOINT_URL") {
+                // S3-compatible stores are addressed path style
+                Ok(endpoint) => {
+                    let (scheme, host) = endpoint
+                        .trim_end_matches('/')
+                        .split_once("://")
+                        .map(|(scheme, host)| (scheme.to_string(), host.to_string()))
+                        .unwrap_or_else(|| ("https".to_string(), endpoint.clone()));
+                    (scheme, host, path_style)
+                }
+                Err(_) => (
+                    "https".to_string(),
+                    format!("{}.s3.{}.amazonaws.com", self.bucket, Self::region()),
+                    format!("/{}", key),
+                ),
+            },
+            Provider::Gcs => (
+                "https".to_string(),
+                "storage.googleapis.com".to_string(),
+                path_style,
+            ),
+        }
+    }
+
+    /// GET request for `key` with the given query, authenticated when credentials are set
+    fn get(
+        &self,
+        client: &reqwest::Client,
+        key: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::RequestBuilder {
+        self.request(client, reqwest::Method::GET, key, query)
+    }
+
+    /// Request for `key` with the given method and query, authenticated when credentials are
+    /// set
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        key: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::RequestBuilder {
+        let (scheme, host, path) = self.location(key);
+        let mut query = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+            .collect::<Vec<(String, String)>>();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>()
+            .join("&");
+        let url = if query.is_empty() {
+            format!("{}://{}{}", scheme, host, path)
+        } else {
+            format!("{}://{}{}?{}", scheme, host, path, query)
+        };
+        let request = client.request(method.clone(), url);
+
+        match self.provider {
+            Provider::S3 => match (
+                std::env::var("AWS_ACCESS_KEY_ID"),
+                std::env::var("AWS_SECRET_ACCESS_KEY"),
+            ) {
+                (Ok(access_key), Ok(secret_key)) => sign_v4(
+                    request,
+                    &SignedRequest {
+                        method: method.as_str(),
+                        host: &host,
+                        path: &path,
+                        query: &query,
+                        service: "s3",
+                        region: &Self::region(),
+                        payload: None,
+                    },
+                    &access_key,
+                    &secret_key,
+                ),
+                _ => request,
+            },
+            Provider::Gcs => match std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+                Ok(token) => request.bearer_auth(token),
+                Err(_) => request,
+            },
+        }
+    }
+
+    /// One page of the objects under the corpus root, and the token of the next page
+    async fn list_page(
+        &self,
+        client: &reqwest::Client,
+        continuation_token: Option<&str>,
+    ) -> Result<(Vec<ListedObject>, Option<String>), String> {
+        let prefix = if self.prefix.is_empty() {
+            "organized_contracts/".to_string()
+        } else {
+            format!("{}/organized_contracts/", self.prefix)
+        };
+        let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+        if let Some(continuation_token) = continuation_token {
+            query.push(("continuation-token", continuation_token));
+        }
+        let response = self
+            .get(client, "", &query)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!(
+                "listing {}{} failed with {}",
+                self.scheme(),
+                self.bucket,
+                status
+            ));
+        }
+
+        let objects = parse_listed_objects(&body);
+        let next = CONTINUATION_TOKEN
+            .captures(&body)
+            .map(|captures| xml_unescape(&captures[1]));
+        Ok((objects, next))
+    }
+
+    /// Uploads the file at `path` as the object `key`, relative to the prefix of the URL. The
+    /// file is streamed in one PUT, or in parts when larger than `MULTIPART_PART_SIZE`
+    pub async fn upload_file(
+        &self,
+        client: &reqwest::Client,
+        key: &str,
+        path: &Path,
+    ) -> Result<(), String> {
+        let key = if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        };
+        let length = fs::metadata(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?
+            .len();
+        if length > MULTIPART_PART_SIZE {
+            return self.upload_multipart(client, &key, path).await;
+        }
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let response = self
+            .request(client, reqwest::Method::PUT, &key, &[])
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .timeout(upload_timeout(length))
+            .body(file)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        self.check_upload(&key, response).await.map(|_| ())
+    }
+
+    /// Uploads the file in parts with a multipart upload, aborted when a part fails so the
+    /// store does not keep the parts
+    async fn upload_multipart(
+        &self,
+        client: &reqwest::Client,
+        key: &str,
+        path: &Path,
+    ) -> Result<(), String> {
+        let response = self
+            .request(client, reqwest::Method::POST, key, &[("uploads", "")])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let body = self.check_upload(key, response).await?;
+        let upload_id = UPLOAD_ID
+            .captures(&body)
+            .map(|captures| xml_unescape(&captures[1]))
+            .ok_or_else(|| format!("starting the upload of {} returned no upload id", key))?;
+        let uploaded = match self.upload_parts(client, key, path, &upload_id).await {
+            Ok(etags) => {
+                self.complete_multipart(client, key, &upload_id, &etags)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if uploaded.is_err() {
+            let _ = self
+                .request(
+                    client,
+                    reqwest::Method::DELETE,
+                    key,
+                    &[("uploadId", &upload_id)],
+                )
+                .send()
+                .await;
+        }
+        uploaded
+    }
+
+    /// Uploads the parts of the file in order, returning their ETags
+    async fn upload_parts(
+        &self,
+        client: &reqwest::Client,
+        key: &str,
+        path: &Path,
+        upload_id: &str,
+    ) -> Result<Vec<String>, String> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let mut etags = Vec::new();
+        loop {
+            let mut part = Vec::new();
+            (&mut file)
+                .take(MULTIPART_PART_SIZE)
+                .read_to_end(&mut part)
+                .await
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            if part.is_empty() {
+                return Ok(etags);
+            }
+            let part_number = (etags.len() + 1).to_string();
+            let length = part.len() as u64;
+            let response = self
+                .request(
+                    client,
+                    reqwest::Method::PUT,
+                    key,
+                    &[("partNumber", &part_number), ("uploadId", upload_id)],
+                )
+                .timeout(upload_timeout(length))
+                .body(part)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            let etag = response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_string);
+            self.check_upload(key, response).await?;
+            etags.push(etag.ok_or_else(|| {
+                format!(
+                    "part {} of {} was uploaded without an ETag",
+                    part_number, key
+                )
+            })?);
+        }
+    }
+
+    /// Assembles the uploaded parts into the object
+    async fn complete_multipart(
+        &self,
+        client: &reqwest::Client,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> Result<(), String> {
+        let parts = etags
+            .iter()
+            .enumerate()
+            .map(|(idx, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    idx + 1,
+                    junit::escape_xml(etag)
+                )
+            })
+            .collect::<String>();
+        let response = self
+            .request(
+                client,
+                reqwest::Method::POST,
+                key,
+                &[("uploadId", upload_id)],
+            )
+            .body(format!(
+                "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+                parts
+            ))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let body = self.check_upload(key, response).await?;
+        // S3 can answer 200 and report the error in the body
+        if body.contains("<Error>") {
+            return Err(format!(
+                "completing the upload of {}{}/{} failed: {}",
+                self.scheme(),
+                self.bucket,
+                key,
+                body.chars().take(200).collect::<String>()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The body of the response to an upload request, an error unless it succeeded
+    async fn check_upload(&self, key: &str, response: reqwest::Response) -> Result<String, String> {
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "uploading {}{}/{} failed with {}",
+                self.scheme(),
+                self.bucket,
+                key,
+                status
+            ));
+        }
+        response.text().await.map_err(|e| e.to_string())
+    }
+
+    fn scheme(&self) -> &'static str {
+        match self.provider {
+            Provider::S3 => "s3://",
+            Provider::Gcs => "gs://",
+        }
+    }
+
+    /// Downloads `key` to `path` unless the cache already holds it: with the listed size, and
+    /// the listed ETag when the store lists one, so an object rewritten with the same size is
+    /// fetched again. `etag_path` keeps the ETag of the cached copy
+    async fn fetch_to_cache(
+        &self,
+        client: &reqwest::Client,
+        object: &CachedObject,
+    ) -> Result<(), String> {
+        let CachedObject {
+            key,
+            size,
+            etag,
+            path,
+            etag_path,
+        } = object;
+        let cached_etag = || fs::read_to_string(etag_path).ok();
+        if fs::metadata(path).is_ok_and(|metadata| metadata.len() == *size)
+            && (etag.is_none() || cached_etag().as_ref() == etag.as_ref())
+        {
+            return Ok(());
+        }
+        let response = self
+            .get(client, key, &[])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "fetching {}{}/{} failed with {}",
+                self.scheme(),
+                self.bucket,
+                key,
+                response.status()
+            ));
+        }
+        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        for dir in [path.parent(), etag_path.parent()].into_iter().flatten() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        // the stale ETag goes first, an interrupted write then leaves the object to fetch again
+        let _ = fs::remove_file(etag_path);
+        fs::write(path, bytes).map_err(|e| e.to_string())?;
+        if let Some(etag) = etag {
+            fs::write(etag_path, etag).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// The parts of a request AWS Signature Version 4 signs
+pub struct SignedRequest<'a> {
+    pub method: &'a str,
+    pub host: &'a str,
+    pub path: &'a str,
+    /// Canonical query string, the parameters encoded and sorted
+    pub query: &'a str,
+    /// AWS service and region the request is for, e.g. "s3" and "us-east-1"
+    pub service: &'a str,
+    pub region: &'a str,
+    /// Body of the request, None to sign it as UNSIGNED-PAYLOAD, which only S3 accepts
+    pub payload: Option<&'a [u8]>,
+}
+
+/// Signs the request with AWS Signature Version 4, signing the payload when there is one and
+/// leaving it unsigned otherwise
+pub fn sign_v4(
+    request: reqwest::RequestBuilder,
+    signed: &SignedRequest,
+    access_key: &str,
+    secret_key: &str,
+) -> reqwest::RequestBuilder {
+    let SignedRequest {
+        method,
+        host,
+        path,
+        query,
+        service,
+        region,
+        payload,
+    } = *signed;
+    let payload_hash = match payload {
+        Some(payload) => hex::encode(Sha256::digest(payload)),
+        None => "UNSIGNED-PAYLOAD".to_string(),
+    };
+    let now = chrono::Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+
+    let mut headers = vec![
+        ("host", host.to_string()),
+        ("x-amz-content-sha256", payload_hash.clone()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(session_token) = &session_token {
+        headers.push(("x-amz-security-token", session_token.clone()));
+    }
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect::<String>();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _value)| *name)
+        .collect::<Vec<&str>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, path, query, canonical_headers, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signing_key = [service, "aws4_request"].iter().fold(
+        hmac_sha256(
+            &hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), &date),
+            region,
+        ),
+        |key, part| hmac_sha256(&key, part),
+    );
+    let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+
+    let mut request = request
+        .header("x-amz-content-sha256", payload_hash)
+        .header("x-amz-date", amz_date)
+        .header(
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                access_key, scope, signed_headers, signature
+            ),
+        );
+    if let Some(session_token) = session_token {
+        request = request.header("x-amz-security-token", session_token);
+    }
+    request
+}
+
+/// Objects of one contract directory (`organized_contracts/XX/bytecodehash`)
+struct ListedContract {
+    dir: String,
+    /// File inside the directory, and its object
+    files: Vec<(String, ListedObject)>,
+}
+
+/// An object to download into the cache
+struct CachedObject {
+    key: String,
+    size: u64,
+    etag: Option<String>,
+    path: PathBuf,
+    etag_path: PathBuf,
+}
+
+impl CachedObject {
+    /// The object of the file `file` of the contract directory `dir`
+    fn new(cache_root: &Path, dir: &str, file: &str, object: &ListedObject) -> Self {
+        Self {
+            key: object.key.clone(),
+            size: object.size,
+            etag: object.etag.clone(),
+            path: cache_root.join(dir).join(file),
+            etag_path: cache_root.join(ETAGS_DIR).join(dir).join(file),
+        }
+    }
+}
+
+/// Downloads the given objects into the cache, `FETCH_CONCURRENCY` at a time
+async fn fetch_all(
+    corpus: &RemoteCorpus,
+    client: &reqwest::Client,
+    objects: Vec<CachedObject>,
+) -> Result<(), String> {
+    let semaphore = Arc::new(Semaphore::new(FETCH_CONCURRENCY));
+    let mut fetches = JoinSet::new();
+    for object in objects {
+        let corpus = corpus.clone();
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+        fetches.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            corpus.fetch_to_cache(&client, &object).await
+        });
+    }
+    while let Some(fetch) = fetches.join_next().await {
+        fetch.map_err(|e| e.to_string())??;
+    }
+    Ok(())
+}
+
+/// Lists the corpus in the bucket and fetches the metadata.json and sources of the selected
+/// contracts into `cache_root`, in the smart-contract-fiesta layout. Objects already in the
+/// cache are not fetched again, and listing stops once the selection is complete.
+pub async fn fetch_contracts(
+    corpus: &RemoteCorpus,
+    cache_root: &Path,
+    selection: ContractSelection<'_>,
+) -> Result<Vec<FiestaMetadata>, String> {
+    let client = http_client()?;
+    let mut metadatas = Vec::new();
+    let mut skipped_count = 0;
+    let mut continuation_token = None;
+    // objects of a contract directory split across two pages
+    let mut carried = Vec::new();
+    loop {
+        let (objects, next) = corpus
+            .list_page(&client, continuation_token.as_deref())
+            .await?;
+        carried.extend(objects);
+
+        let mut contracts: Vec<ListedContract> = Vec::new();
+        for object in carried.drain(..) {
+            let Some((dir, file)) = split_contract_path(&object.key) else {
+                continue;
+            };
+            match contracts.last_mut() {
+                Some(contract) if contract.dir == dir => contract.files.push((file, object)),
+                _ => contracts.push(ListedContract {
+                    dir,
+                    files: vec![(file, object)],
+                }),
+            }
+        }
+        // the last contract of the page may continue on the next one
+        if next.is_some() {
+            if let Some(contract) = contracts.pop() {
+                carried = contract
+                    .files
+                    .into_iter()
+                    .map(|(_file, object)| object)
+                    .collect();
+            }
+        }
+
+        let metadata_objects = contracts
+            .iter()
+            .flat_map(|contract| {
+                contract
+                    .files
+                    .iter()
+                    .filter(|(file, _object)| file == "metadata.json")
+                    .map(|(file, object)| {
+                        CachedObject::new(cache_root, &contract.dir, file, object)
+                    })
+            })
+            .collect();
+        fetch_all(corpus, &client, metadata_objects).await?;
+
+        let mut source_objects = Vec::new();
+        for contract in &contracts {
+            if selection.is_complete(metadatas.len()) {
+                break;
+            }
+            let path_to_dir = cache_root.join(&contract.dir);
+            let Ok(metadata_json) = fs::read(path_to_dir.join("metadata.json")) else {
+                continue;
+            };
+            let Ok(mut metadata) = serde_json::from_slice::<FiestaMetadata>(&metadata_json) else {
+                continue;
+            };
+            if !selection.select(&metadata, &mut skipped_count) {
+                continue;
+            }
+            source_objects.extend(
+                contract
+                    .files
+                    .iter()
+                    .filter(|(file, _object)| file != "metadata.json")
+                    .map(|(file, object)| {
+                        CachedObject::new(cache_root, &contract.dir, file, object)
+                    }),
+            );
+            metadata
+                .update_path_to_dir(&path_to_dir, cache_root)
+                .map_err(|e| e.to_string())?;
+            metadatas.push(metadata);
+        }
+        fetch_all(corpus, &client, source_objects).await?;
+        println!(
+            "Total of {} contracts fetched from {}{}",
+            metadatas.len(),
+            corpus.scheme(),
+            corpus.bucket
+        );
+
+        match next {
+            Some(next) if !selection.is_complete(metadatas.len()) => {
+                continuation_token = Some(next)
+            }
+            _ => break,
+        }
+    }
+    Ok(metadatas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_listed_objects_in_any_element_order() {
+        let body = r#"<ListBucketResult>
+            <Contents>
+                <Key>fiesta/a&amp;b/main.sol</Key><Size>12</Size><ETag>&quot;e1&quot;</ETag>
+            </Contents>
+            <Contents><Size> 7 </Size><ETag>"e2"</ETag><Key>fiesta/c/metadata.json</Key></Contents>
+            <Contents><Key>fiesta/d/main.sol</Key><Size>3</Size></Contents>
+            <Contents><Key>fiesta/no-size</Key></Contents>
+        </ListBucketResult>"#;
+        let objects = parse_listed_objects(body)
+            .into_iter()
+            .map(|object| (object.key, object.size, object.etag))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            objects,
+            [
+                (
+                    "fiesta/a&b/main.sol".to_string(),
+                    12,
+                    Some("e1".to_string())
+                ),
+                (
+                    "fiesta/c/metadata.json".to_string(),
+                    7,
+                    Some("e2".to_string())
+                ),
+                ("fiesta/d/main.sol".to_string(), 3, None),
+            ]
+        );
+    }
+}
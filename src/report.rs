@@ -0,0 +1,330 @@
+use crate::{
+    clusters, correlation, error::OmniscanError, flakes, junit, manifest, mislabel, onchain,
+    results, rotation, sarif, score, summary, tags, triage, ResultsRow, ResultsWriter,
+};
+use clap::ValueEnum;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Optional end-of-run reports written in addition to the results file
+#[derive(Clone)]
+pub struct ReportPaths {
+    pub junit: Option<PathBuf>,
+    pub sarif: Option<PathBuf>,
+    pub summary: Option<PathBuf>,
+    /// Root of the corpus, used to resolve the relative source paths in the reports
+    pub corpus_root: PathBuf,
+    /// Version of the analyzed dataset, recorded in the summary
+    pub corpus_fingerprint: Option<String>,
+    /// Contracts left out by `--skip-boilerplate`, recorded in the summary
+    pub skipped_boilerplate: Option<BTreeMap<String, usize>>,
+    /// Contracts left out by the ignore file, recorded in the summary
+    pub ignored: Option<usize>,
+    /// Contracts with a mislabeled .vy file as sources, recorded in the summary
+    pub mislabeled: Option<Vec<mislabel::MislabeledContract>>,
+    /// Weights the score recorded in the summary is computed with
+    pub score_weights: score::ScoreWeights,
+    /// Tags and labels of the run, recorded in the summary
+    pub run_tags: tags::RunTags,
+    /// Flake database the known flaky contracts of the results are listed from, see
+    /// `--flakes-db`
+    pub flakes_db: Option<PathBuf>,
+    /// What the failures are also weighted by, see `onchain::print_weighted_failures`
+    pub weight_by: Option<onchain::WeightBy>,
+    /// Whether the run covered the whole corpus, its summary then has no confidence interval
+    pub full_corpus: bool,
+}
+
+/// Converts a user supplied report path into a PathBuf, creating any missing parent directories
+pub fn prepare_report_path(path: String) -> PathBuf {
+    let path = std::path::PathBuf::from(path);
+    if let Some(path_parent) = path.parent() {
+        create_dir_or_exit(path_parent);
+    }
+    path
+}
+
+/// Creates `dir` and its parents when missing, exiting when it can't be
+pub fn create_dir_or_exit(dir: &Path) {
+    if dir.as_os_str().is_empty() || dir.exists() {
+        return;
+    }
+    if let Err(source) = std::fs::create_dir_all(dir) {
+        eprintln!(
+            "{}",
+            OmniscanError::Write {
+                path: dir.to_path_buf(),
+                source
+            }
+        );
+        std::process::exit(1);
+    }
+}
+
+pub fn write_reports(report_paths: &ReportPaths, result_rows: &[ResultsRow]) {
+    if let Some(junit_path) = &report_paths.junit {
+        println!("Writing JUnit report to: {:?}", junit_path);
+        junit::write_junit_report(junit_path, result_rows);
+    }
+    if let Some(sarif_path) = &report_paths.sarif {
+        println!("Writing SARIF log to: {:?}", sarif_path);
+        sarif::write_sarif_log(sarif_path, result_rows, &report_paths.corpus_root);
+    }
+    if let Some(summary_path) = &report_paths.summary {
+        println!("Writing summary to: {:?}", summary_path);
+        run_summary(report_paths, result_rows).write(summary_path);
+    }
+}
+
+/// The summary written with `--summary`
+pub fn run_summary(report_paths: &ReportPaths, result_rows: &[ResultsRow]) -> summary::RunSummary {
+    let summary =
+        summary::RunSummary::from_rows(result_rows).with_full_corpus(report_paths.full_corpus);
+    summary::RunSummary {
+        corpus_fingerprint: report_paths.corpus_fingerprint.clone(),
+        skipped_boilerplate: report_paths.skipped_boilerplate.clone(),
+        ignored: report_paths.ignored,
+        mislabeled: report_paths.mislabeled.clone(),
+        score: Some(report_paths.score_weights.score(&summary)),
+        score_weights: Some(report_paths.score_weights.weights().clone()),
+        run_tags: report_paths.run_tags.clone(),
+        ..summary
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum GroupBy {
+    /// Success, PerformanceTimeout, Error, ThreadPanic, NonInterpreted, Nondeterministic,
+    /// HarnessError
+    ExitType,
+    /// The full result, including the error/panic message
+    Result,
+    /// SingleFile, MultipleFiles, JSON
+    SourceType,
+    /// The source type with its detail (number of files, standard-json or not), see the
+    /// source_layout column
+    SourceLayout,
+    /// Year the contract was deployed, for results enriched with `omniscan enrich`
+    DeploymentYear,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SortBy {
+    /// Ascending bytecode hash, the order to compare the results of two runs
+    Hash,
+    /// Slowest contracts first, ties by bytecode hash. The contracts of the warmup (see
+    /// `--warmup`) come last, their times are not comparable
+    Time,
+}
+
+pub fn sort_rows(rows: &mut [ResultsRow], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Hash => rows.sort_by(|a, b| a.bytecode_hash.cmp(&b.bytecode_hash)),
+        SortBy::Time => rows.sort_by(|a, b| {
+            (a.warmup == Some(true))
+                .cmp(&(b.warmup == Some(true)))
+                .then_with(|| b.time.total_cmp(&a.time))
+                .then_with(|| a.bytecode_hash.cmp(&b.bytecode_hash))
+        }),
+    }
+}
+
+/// Rewrites the results file with its rows in this order, in the form this omniscan writes
+/// them: current column order, number formats and JSON key order. Files of two runs rewritten
+/// this way can be compared line by line with plain `diff`. Columns this omniscan does not
+/// know are kept, after the known ones. The file is replaced only once the rewrite is
+/// complete. Rotated results are rewritten into parts of the same maximum size.
+pub fn rewrite_results_file(path: &Path, rows: &[ResultsRow]) -> Result<(), OmniscanError> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".sorting");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut extra_columns: Vec<String> = Vec::new();
+    for (name, _) in rows.iter().flat_map(|row| &row.extra_columns) {
+        if !extra_columns.contains(name) {
+            extra_columns.push(name.clone());
+        }
+    }
+    // the extension of the temporary file would hide the format of the results
+    let mut writer =
+        ResultsWriter::with_format(tmp_path.clone(), results::ResultsFormat::from_path(path))
+            .with_extra_columns(extra_columns);
+    let rotation = rotation::read_parts_manifest(path).and_then(Result::ok);
+    if let Some(rotation) = &rotation {
+        writer = writer.rotating(rotation.max_part_bytes);
+    }
+    writer.initiate_headers_for_results_csv()?;
+    for row in rows {
+        writer.append_to_results_file(row)?;
+    }
+    let replaced = match rotation {
+        Some(_) => rotation::replace_parts(path, &tmp_path),
+        None => fs::rename(&tmp_path, path),
+    };
+    replaced.map_err(|source| OmniscanError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Counts the rows per group, sorted by descending count
+pub fn breakdown(rows: &[ResultsRow], group_by: GroupBy) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        let key = match group_by {
+            GroupBy::ExitType => row.result.name().to_string(),
+            GroupBy::Result => row.result.to_string(),
+            GroupBy::SourceType => row.source_type.clone(),
+            GroupBy::SourceLayout => row.source_layout_group(),
+            GroupBy::DeploymentYear => onchain::deployment_year(row),
+        };
+        *counts.entry(key).or_insert(0) += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<(String, usize)>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Prints the breakdown as a markdown table, the same layout as the README snapshots
+pub fn print_breakdown(rows: &[ResultsRow], group_by: GroupBy, full_corpus: bool) {
+    println!("| Type | Count | Percent |");
+    println!("| ----- | ----- | ------- |");
+    for (key, count) in breakdown(rows, group_by) {
+        println!(
+            "|{} | {} | {:.2} |",
+            key,
+            count,
+            count as f64 / rows.len() as f64 * 100.0
+        );
+    }
+    println!("| Total | {} | 100.00 |", rows.len());
+    println!();
+    println!(
+        "Success rate: {}",
+        summary::RunSummary::from_rows(rows)
+            .with_full_corpus(full_corpus)
+            .format_success_rate()
+    );
+    let timings = summary::timing_stats(rows);
+    if !timings.is_empty() {
+        println!();
+        println!("| Time (sec) | Count | p50 | p90 | p99 | Max |");
+        println!("| ----- | ----- | ----- | ----- | ----- | ----- |");
+        let (successes, failures): (Vec<_>, Vec<_>) =
+            timings.iter().partition(|(name, _)| *name == "Success");
+        for (name, stats) in successes.into_iter().chain(failures) {
+            println!(
+                "|{} | {} | {:.3} | {:.3} | {:.3} | {:.3} |",
+                name, stats.count, stats.p50, stats.p90, stats.p99, stats.max
+            );
+        }
+    }
+    if let Some((compiling, success_rate)) = summary::solc_success_rate(rows) {
+        println!();
+        println!(
+            "Success rate on the {} contracts that compile with solc: {:.2}%",
+            compiling, success_rate
+        );
+    }
+}
+
+/// Entry point of the `report` subcommand
+pub fn run_report(
+    results_path: &Path,
+    group_by: GroupBy,
+    failure_correlation: bool,
+    cluster_options: Option<clusters::ClusterOptions>,
+    sort_by: Option<SortBy>,
+    exclude_unavailable_compilers: bool,
+    mut report_paths: ReportPaths,
+) {
+    report_paths.corpus_fingerprint = manifest::results_fingerprint(results_path);
+    report_paths.full_corpus = manifest::results_full_corpus(results_path);
+    let mut rows = results::read_results_file(results_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read results file {}: {}",
+            results_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    if let Ok(run_manifest) = manifest::read_manifest(&manifest::manifest_path(results_path)) {
+        if run_manifest.record == results::RecordMode::Failures {
+            println!(
+                "Only the failures of the run were recorded (--record failures): the other {} \
+                 contracts of its manifest succeeded or were not analyzed, the rates below \
+                 cover the failures only",
+                run_manifest.contracts.len().saturating_sub(rows.len())
+            );
+        }
+    }
+    if rows.is_empty() {
+        println!("No results in {}", results_path.display());
+        return;
+    }
+    if let Some(sort_by) = sort_by {
+        sort_rows(&mut rows, sort_by);
+        if let Err(e) = rewrite_results_file(results_path, &rows) {
+            eprintln!("Could not sort the results, {}", e);
+            std::process::exit(1);
+        }
+        println!(
+            "Sorted the {} results of {}",
+            rows.len(),
+            results_path.display()
+        );
+    }
+    // after sorting, which rewrites every row of the file
+    if exclude_unavailable_compilers {
+        let before = rows.len();
+        rows.retain(|row| row.compiler_available != Some(false));
+        println!(
+            "Leaving out {} contracts whose compiler can't be installed",
+            before - rows.len()
+        );
+        if !rows.iter().any(|row| row.compiler_available.is_some()) {
+            println!("The run did not check the compilers, see --check-compiler");
+        }
+    }
+
+    print_breakdown(&rows, group_by, report_paths.full_corpus);
+    println!();
+    println!(
+        "{}",
+        report_paths
+            .score_weights
+            .format_score(&summary::RunSummary::from_rows(&rows), None)
+    );
+    if let Some(weight_by) = report_paths.weight_by {
+        println!();
+        onchain::print_weighted_failures(&rows, weight_by);
+    }
+    if failure_correlation {
+        correlation::print_failure_correlation(&rows);
+    }
+    if let Some(cluster_options) = &cluster_options {
+        clusters::print_clusters(&rows, cluster_options);
+    }
+    if let Some(triage) = triage::load_for_results(results_path) {
+        println!();
+        triage::print_triage_counts("Triage", &rows, &triage);
+    }
+    if let Some(flakes_db) = &report_paths.flakes_db {
+        match flakes::FlakeDb::load(flakes_db) {
+            Ok(flake_db) => {
+                // the flakes of the pyrometer that produced the results, of any when unknown
+                let analyzer = flakes::results_analyzer_key(results_path);
+                println!();
+                flakes::print_known_flakes(&rows, &flake_db.flaky(analyzer.as_deref()));
+            }
+            Err(e) => eprintln!(
+                "Could not read flake database {}: {}",
+                flakes_db.display(),
+                e
+            ),
+        }
+    }
+    write_reports(&report_paths, &rows);
+}
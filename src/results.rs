@@ -0,0 +1,1246 @@
+use crate::{
+    error::OmniscanError,
+    phases::PhaseTimes,
+    rotation::{self, PartsManifest},
+    ExitType, FiestaMetadata, ResultMessage, OLDEST_READABLE_SCHEMA_VERSION,
+    RESULTS_SCHEMA_VERSION,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Columns of the results csv, in the order they are written
+pub const CSV_COLUMNS: [&str; 42] = [
+    "bytecode_hash",
+    "result",
+    "time (sec)",
+    "source_type",
+    "source_size",
+    "source_path",
+    "schema_version",
+    "compiler_version",
+    "optimization_used",
+    "solc_compiles",
+    "flattened",
+    "import_files",
+    "import_depth",
+    "unresolved_imports",
+    "lines_of_code",
+    "contract_count",
+    "function_count",
+    "assembly_blocks",
+    "parse_time",
+    "analysis_time",
+    "output_time",
+    "addresses",
+    "chain",
+    "source_layout",
+    "spawn_time",
+    "queued_at",
+    "started_at",
+    "compiler_available",
+    "batch_size",
+    "cpu_time",
+    "source_hash",
+    "spawn_retries",
+    "warmup",
+    "core_dump",
+    "crash_location",
+    "unhonored_settings",
+    "target_contract_analyzed",
+    "last_phase",
+    "deployed_block",
+    "deployed_at",
+    "balance_eth",
+    "tx_count",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultsFormat {
+    Csv,
+    /// One JSON object per line, see `omniscan schema results`
+    Jsonl,
+}
+
+/// Which results are written to the results file, see `--record`
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordMode {
+    /// Every result
+    #[default]
+    All,
+    /// Only the results that are not Success. Successes are still counted in the summary and
+    /// the end of run table
+    Failures,
+}
+
+impl ResultsFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl") => ResultsFormat::Jsonl,
+            _ => ResultsFormat::Csv,
+        }
+    }
+}
+
+pub struct ResultsWriter {
+    pub output_path: PathBuf,
+    pub format: ResultsFormat,
+    /// Parts written so far when the results are rotated, see `--rotate-results`
+    rotation: Option<PartsManifest>,
+    /// Bytes in the file being written, only tracked when rotating
+    part_bytes: u64,
+    record: RecordMode,
+    /// Columns written after the known ones, see `ResultsRow::extra_columns`
+    extra_columns: Vec<String>,
+}
+
+impl ResultsWriter {
+    pub fn new(output_path: PathBuf) -> Self {
+        let format = ResultsFormat::from_path(&output_path);
+        Self::with_format(output_path, format)
+    }
+
+    pub fn with_format(output_path: PathBuf, format: ResultsFormat) -> Self {
+        Self {
+            output_path,
+            format,
+            rotation: None,
+            part_bytes: 0,
+            record: RecordMode::All,
+            extra_columns: Vec::new(),
+        }
+    }
+
+    /// Writes these columns of the rows' `extra_columns` after the known ones, to rewrite a
+    /// results file without losing the columns this omniscan does not know. Json lines write
+    /// every extra column of a row regardless.
+    pub fn with_extra_columns(mut self, extra_columns: Vec<String>) -> Self {
+        self.extra_columns = extra_columns;
+        self
+    }
+
+    /// Writes only the results `record` selects, the others are dropped
+    pub fn recording(mut self, record: RecordMode) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Writes the results in parts of at most `max_part_bytes` (a part holds at least one
+    /// result), listed in the parts manifest next to the output path
+    pub fn rotating(mut self, max_part_bytes: u64) -> Self {
+        self.rotation = Some(PartsManifest {
+            max_part_bytes,
+            parts: Vec::new(),
+        });
+        self
+    }
+
+    /// Appends to the results of an earlier run, to its last part when it was rotated
+    pub fn resuming(mut self) -> Result<Self, OmniscanError> {
+        let Some(manifest) = rotation::read_parts_manifest(&self.output_path) else {
+            return Ok(self);
+        };
+        let manifest = manifest.map_err(|e| {
+            self.write_error(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })?;
+        self.part_bytes = manifest
+            .parts
+            .last()
+            .and_then(|part| fs::metadata(self.output_path.with_file_name(part)).ok())
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        self.rotation = Some(manifest);
+        Ok(self)
+    }
+
+    pub fn convert_fields_to_header(&self) -> String {
+        let mut columns = CSV_COLUMNS.map(str::to_string).to_vec();
+        columns.extend(self.extra_columns.iter().cloned().map(escape_csv_field));
+        format!("{}\n", columns.join(","))
+    }
+
+    /// The file results are appended to, the latest part when rotating
+    fn current_path(&self) -> PathBuf {
+        match self
+            .rotation
+            .as_ref()
+            .and_then(|manifest| manifest.parts.last())
+        {
+            Some(part) => self.output_path.with_file_name(part),
+            None => self.output_path.clone(),
+        }
+    }
+
+    fn header_bytes(&self) -> u64 {
+        match self.format {
+            ResultsFormat::Csv => self.convert_fields_to_header().len() as u64,
+            ResultsFormat::Jsonl => 0,
+        }
+    }
+
+    fn write_error(&self, source: std::io::Error) -> OmniscanError {
+        OmniscanError::Write {
+            path: self.current_path(),
+            source,
+        }
+    }
+
+    /// Starts the next part and lists it in the parts manifest
+    fn start_part(&mut self) -> Result<(), OmniscanError> {
+        let manifest = self.rotation.as_mut().unwrap();
+        let part = rotation::part_file_name(&self.output_path, manifest.parts.len() + 1);
+        manifest.parts.push(part);
+        rotation::write_parts_manifest(&self.output_path, manifest).map_err(|source| {
+            OmniscanError::Write {
+                path: rotation::parts_manifest_path(&self.output_path),
+                source,
+            }
+        })?;
+        self.truncate_with_header()
+    }
+
+    fn truncate_with_header(&mut self) -> Result<(), OmniscanError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.current_path())
+            .map_err(|e| self.write_error(e))?;
+
+        // json lines are self describing, so they only need the file to be truncated
+        if self.format == ResultsFormat::Csv {
+            let header_string = self.convert_fields_to_header();
+            file.write_all(header_string.as_bytes())
+                .map_err(|e| self.write_error(e))?;
+        }
+        self.part_bytes = self.header_bytes();
+        Ok(())
+    }
+
+    pub fn initiate_headers_for_results_csv(&mut self) -> Result<(), OmniscanError> {
+        println!("Initiating headers for results at: {:?}", &self.output_path);
+        // the parts of an earlier run to the same output would linger next to the new ones, and
+        // without rotation its manifest would make readers take them for this run's results
+        if let Some(Ok(previous)) = rotation::read_parts_manifest(&self.output_path) {
+            for (idx, part) in previous.parts.iter().enumerate() {
+                if *part == rotation::part_file_name(&self.output_path, idx + 1) {
+                    let _ = fs::remove_file(self.output_path.with_file_name(part));
+                }
+            }
+        }
+        if self.rotation.is_none() {
+            let manifest_path = rotation::parts_manifest_path(&self.output_path);
+            match fs::remove_file(&manifest_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(OmniscanError::Write {
+                        path: manifest_path,
+                        source: e,
+                    })
+                }
+                _ => {}
+            }
+            return self.truncate_with_header();
+        }
+        self.start_part()
+    }
+
+    pub fn append_to_results_file(&mut self, result_row: &ResultsRow) -> Result<(), OmniscanError> {
+        if self.record == RecordMode::Failures && matches!(result_row.result, ExitType::Success) {
+            return Ok(());
+        }
+        let row_string = match self.format {
+            ResultsFormat::Csv if self.extra_columns.is_empty() => {
+                result_row.convert_to_csv_string()
+            }
+            ResultsFormat::Csv => {
+                let mut fields = result_row.csv_fields().to_vec();
+                fields.extend(self.extra_columns.iter().map(|column| {
+                    result_row
+                        .extra_columns
+                        .iter()
+                        .find(|(name, _)| name == column)
+                        .map(|(_, value)| match value {
+                            Value::String(value) => value.clone(),
+                            value => value.to_string(),
+                        })
+                        .unwrap_or_default()
+                }));
+                let fields = fields.into_iter().map(escape_csv_field).collect::<Vec<_>>();
+                format!("{}\n", fields.join(","))
+            }
+            ResultsFormat::Jsonl => result_row.convert_to_json_line(),
+        };
+        if let Some(manifest) = &self.rotation {
+            let row_bytes = row_string.len() as u64;
+            let part_has_rows = self.part_bytes > self.header_bytes();
+            if manifest.parts.is_empty()
+                || (part_has_rows && self.part_bytes + row_bytes > manifest.max_part_bytes)
+            {
+                self.start_part()?;
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.current_path())
+            .map_err(|e| self.write_error(e))?;
+
+        file.write_all(row_string.as_bytes())
+            .map_err(|e| self.write_error(e))?;
+        self.part_bytes += row_string.len() as u64;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResultsRow {
+    pub bytecode_hash: String,
+    pub result: ExitType,
+    pub time: f64,
+    /// Display label of the contract's `SourceType`
+    pub source_type: String,
+    pub size: u64,
+    /// Path of the file handed to pyrometer, relative to the corpus root
+    pub source_path: String,
+    /// Compiler version from the contract's metadata.json, e.g. "v0.8.17+commit.8df45f5f"
+    pub compiler_version: String,
+    /// None when read from a results file that predates the column
+    pub optimization_used: Option<bool>,
+    /// Whether the contract compiles with its own solc version, None when not checked
+    pub solc_compiles: Option<bool>,
+    /// Whether flattening the contract's sources succeeded, None when not flattened
+    pub flattened: Option<bool>,
+    /// Number of source files reachable from the entrypoint, None when unknown
+    pub import_files: Option<usize>,
+    /// Longest shortest import chain from the entrypoint, None when unknown
+    pub import_depth: Option<usize>,
+    /// Number of imports that could not be resolved, None when unknown
+    pub unresolved_imports: Option<usize>,
+    /// Complexity of the sources reachable from the entrypoint, None when unknown
+    pub lines_of_code: Option<usize>,
+    pub contract_count: Option<usize>,
+    pub function_count: Option<usize>,
+    pub assembly_blocks: Option<usize>,
+    /// Durations of the analyzer's phases (secs) from the markers in its output, None when it
+    /// printed no marker for the phase
+    pub parse_time: Option<f64>,
+    pub analysis_time: Option<f64>,
+    pub output_time: Option<f64>,
+    /// On-chain addresses the contract was fetched from with `--addresses` or given to
+    /// `enrich --address-index`, empty otherwise
+    pub addresses: Vec<String>,
+    /// Chain the contract was fetched from, None for smart-contract-fiesta and projects
+    pub chain: Option<String>,
+    /// `FiestaMetadata::source_layout`, empty when read from a results file that predates the
+    /// column
+    pub source_layout: String,
+    /// Time taken to start the analyzer process (secs), not included in `time`. None when
+    /// read from a results file that predates the column
+    pub spawn_time: Option<f64>,
+    /// When the contract came next in line for a job and when it was given a job slot, before
+    /// its sources were prepared. started_at - queued_at is the time it waited for a job. None
+    /// when read from a results file that predates the columns
+    pub queued_at: Option<DateTime<Utc>>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// Whether the compiler of the metadata can be installed, None when not checked (see
+    /// `--check-compiler`) or unknown
+    pub compiler_available: Option<bool>,
+    /// Number of contracts analyzed by the same pyrometer invocation, None when the contract
+    /// was analyzed on its own (see `--batch-size`)
+    pub batch_size: Option<usize>,
+    /// User and system CPU time (secs) of the analyzer process and the processes it waited
+    /// for, None when it ran on a worker (see `--worker-pool`) or when read from a results
+    /// file that predates the column
+    pub cpu_time: Option<f64>,
+    /// sha256 of the sources reachable from the entrypoint, see `fingerprint::sources_hash`.
+    /// None when the sources could not be read, or when read from a results file that
+    /// predates the column
+    pub source_hash: Option<String>,
+    /// Times pyrometer was started again after transient failures (see `--spawn-retries`),
+    /// None when it started at once
+    pub spawn_retries: Option<u32>,
+    /// Whether the contract was among the first ones dispatched, left out of the timing
+    /// statistics (see `--warmup`). None for the other contracts
+    pub warmup: Option<bool>,
+    /// Path of the core of the crashed analyzer relative to the artifacts directory, None when
+    /// it did not crash or no core was collected (see `--core-dumps`)
+    pub core_dump: Option<String>,
+    /// Innermost analyzer frames of the backtrace of a panic, see `backtrace::crash_location`.
+    /// None when the analyzer did not panic or printed no backtrace
+    pub crash_location: Option<String>,
+    /// Compiler settings of the metadata the analyzer or solc could not be given, e.g.
+    /// "pyrometer:evm_version" (see `--compiler-settings`). Empty when every setting was
+    /// honored, or without `--compiler-settings`
+    pub unhonored_settings: Vec<String>,
+    /// Whether the analyzer analyzed the contract named by the metadata's ContractName, see
+    /// `target::target_analyzed`. None without `--target-contract-from-metadata`
+    pub target_contract_analyzed: Option<bool>,
+    /// Phase of the last marker the analyzer printed before it was killed at its timeout, see
+    /// `phases::last_phase`. None for contracts that did not time out
+    pub last_phase: Option<String>,
+    /// On-chain metadata of the addresses of the contract, see `onchain::enrich`: the block
+    /// and time of the earliest deployment, and the balance (ether) and transaction count
+    /// summed over the addresses. None until the results are enriched
+    pub deployed_block: Option<u64>,
+    pub deployed_at: Option<DateTime<Utc>>,
+    pub balance_eth: Option<f64>,
+    pub tx_count: Option<u64>,
+    /// Columns of the file the row was read from that this omniscan does not know, e.g. added
+    /// by a newer version or by hand: in file order for csv, where values are strings, and by
+    /// name for json lines. Kept so that rewriting the file (`report --sort`) does not drop
+    /// them
+    pub extra_columns: Vec<(String, Value)>,
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+impl ResultsRow {
+    pub fn from(result: ExitType, metadata: &FiestaMetadata, time: f64, size: u64) -> Self {
+        Self {
+            bytecode_hash: metadata.bytecode_hash.clone(),
+            result,
+            time,
+            // contracts whose sources could not be collected have no source type
+            source_type: metadata
+                .source_type
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            size,
+            source_path: metadata.source_path(),
+            compiler_version: metadata.compiler_version.clone(),
+            optimization_used: Some(metadata.optimization_used),
+            solc_compiles: None,
+            flattened: None,
+            import_files: metadata.import_graph.map(|import_graph| import_graph.files),
+            import_depth: metadata.import_graph.map(|import_graph| import_graph.depth),
+            unresolved_imports: metadata
+                .import_graph
+                .map(|import_graph| import_graph.unresolved),
+            lines_of_code: metadata
+                .complexity
+                .map(|complexity| complexity.lines_of_code),
+            contract_count: metadata.complexity.map(|complexity| complexity.contracts),
+            function_count: metadata.complexity.map(|complexity| complexity.functions),
+            assembly_blocks: metadata
+                .complexity
+                .map(|complexity| complexity.assembly_blocks),
+            parse_time: None,
+            analysis_time: None,
+            output_time: None,
+            addresses: metadata.addresses.clone(),
+            chain: metadata.chain.clone(),
+            source_layout: metadata.source_layout(),
+            spawn_time: None,
+            queued_at: None,
+            started_at: None,
+            compiler_available: metadata.compiler_available,
+            batch_size: None,
+            cpu_time: None,
+            source_hash: metadata.source_hash.clone(),
+            spawn_retries: None,
+            warmup: None,
+            core_dump: None,
+            crash_location: None,
+            unhonored_settings: Vec::new(),
+            target_contract_analyzed: None,
+            last_phase: None,
+            deployed_block: None,
+            deployed_at: None,
+            balance_eth: None,
+            tx_count: None,
+            extra_columns: Vec::new(),
+        }
+    }
+
+    pub fn from_result_message(result_message: &ResultMessage, exit_type: ExitType) -> Self {
+        Self {
+            solc_compiles: result_message.solc_compiles,
+            flattened: result_message.flattened,
+            spawn_time: Some(result_message.spawn_time),
+            queued_at: Some(result_message.queued_at),
+            started_at: Some(result_message.started_at),
+            batch_size: result_message.batch_size,
+            cpu_time: result_message.cpu_time,
+            spawn_retries: (result_message.spawn_retries > 0)
+                .then_some(result_message.spawn_retries),
+            warmup: result_message.warmup.then_some(true),
+            core_dump: result_message.core_dump.clone(),
+            unhonored_settings: result_message.unhonored_settings.clone(),
+            ..Self::from(
+                exit_type,
+                &result_message.metadata,
+                result_message.time,
+                result_message.size,
+            )
+        }
+    }
+
+    pub fn with_phase_times(self, phase_times: PhaseTimes) -> Self {
+        Self {
+            parse_time: phase_times.parse,
+            analysis_time: phase_times.analysis,
+            output_time: phase_times.output,
+            ..self
+        }
+    }
+
+    pub fn with_target_contract_analyzed(self, target_contract_analyzed: Option<bool>) -> Self {
+        Self {
+            target_contract_analyzed,
+            ..self
+        }
+    }
+
+    pub fn with_last_phase(self, last_phase: Option<String>) -> Self {
+        Self { last_phase, ..self }
+    }
+
+    pub fn with_crash_location(self, crash_location: Option<String>) -> Self {
+        Self {
+            crash_location,
+            ..self
+        }
+    }
+
+    /// The values of `CSV_COLUMNS` for this row
+    pub fn csv_fields(&self) -> [String; CSV_COLUMNS.len()] {
+        [
+            self.bytecode_hash.clone(),
+            self.result.to_string(),
+            format!("{:.3}", self.time),
+            self.source_type.clone(),
+            self.size.to_string(),
+            self.source_path.clone(),
+            RESULTS_SCHEMA_VERSION.to_string(),
+            self.compiler_version.replace(',', ":"),
+            self.optimization_used
+                .map(|optimization_used| optimization_used.to_string())
+                .unwrap_or_default(),
+            self.solc_compiles
+                .map(|solc_compiles| solc_compiles.to_string())
+                .unwrap_or_default(),
+            self.flattened
+                .map(|flattened| flattened.to_string())
+                .unwrap_or_default(),
+            self.import_files
+                .map(|import_files| import_files.to_string())
+                .unwrap_or_default(),
+            self.import_depth
+                .map(|import_depth| import_depth.to_string())
+                .unwrap_or_default(),
+            self.unresolved_imports
+                .map(|unresolved_imports| unresolved_imports.to_string())
+                .unwrap_or_default(),
+            self.lines_of_code
+                .map(|lines_of_code| lines_of_code.to_string())
+                .unwrap_or_default(),
+            self.contract_count
+                .map(|contract_count| contract_count.to_string())
+                .unwrap_or_default(),
+            self.function_count
+                .map(|function_count| function_count.to_string())
+                .unwrap_or_default(),
+            self.assembly_blocks
+                .map(|assembly_blocks| assembly_blocks.to_string())
+                .unwrap_or_default(),
+            self.parse_time
+                .map(|parse_time| format!("{:.3}", parse_time))
+                .unwrap_or_default(),
+            self.analysis_time
+                .map(|analysis_time| format!("{:.3}", analysis_time))
+                .unwrap_or_default(),
+            self.output_time
+                .map(|output_time| format!("{:.3}", output_time))
+                .unwrap_or_default(),
+            self.addresses.join(" "),
+            self.chain.clone().unwrap_or_default(),
+            self.source_layout.clone(),
+            self.spawn_time
+                .map(|spawn_time| format!("{:.4}", spawn_time))
+                .unwrap_or_default(),
+            self.queued_at
+                .as_ref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            self.started_at
+                .as_ref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            self.compiler_available
+                .map(|compiler_available| compiler_available.to_string())
+                .unwrap_or_default(),
+            self.batch_size
+                .map(|batch_size| batch_size.to_string())
+                .unwrap_or_default(),
+            self.cpu_time
+                .map(|cpu_time| format!("{:.3}", cpu_time))
+                .unwrap_or_default(),
+            self.source_hash.clone().unwrap_or_default(),
+            self.spawn_retries
+                .map(|spawn_retries| spawn_retries.to_string())
+                .unwrap_or_default(),
+            self.warmup
+                .map(|warmup| warmup.to_string())
+                .unwrap_or_default(),
+            self.core_dump.clone().unwrap_or_default(),
+            self.crash_location.clone().unwrap_or_default(),
+            self.unhonored_settings.join(" "),
+            self.target_contract_analyzed
+                .map(|analyzed| analyzed.to_string())
+                .unwrap_or_default(),
+            self.last_phase.clone().unwrap_or_default(),
+            self.deployed_block
+                .map(|deployed_block| deployed_block.to_string())
+                .unwrap_or_default(),
+            self.deployed_at
+                .as_ref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            self.balance_eth
+                .map(|balance_eth| balance_eth.to_string())
+                .unwrap_or_default(),
+            self.tx_count
+                .map(|tx_count| tx_count.to_string())
+                .unwrap_or_default(),
+        ]
+    }
+
+    pub fn convert_to_csv_string(&self) -> String {
+        let fields = self.csv_fields().map(escape_csv_field);
+        format!("{}\n", fields.join(","))
+    }
+
+    /// Whether the analyzer was handed sources with imports that cannot be resolved, in which
+    /// case a failure is expected rather than a finding
+    pub fn has_unresolved_imports(&self) -> bool {
+        self.unresolved_imports.unwrap_or(0) > 0
+    }
+
+    /// Source layout with the file counts bucketed, so contracts of similar shape are grouped
+    /// together. Falls back to the source type for results without a layout.
+    pub fn source_layout_group(&self) -> String {
+        let Some((shape, count)) = self.source_layout.split_once(':') else {
+            return if self.source_layout.is_empty() {
+                self.source_type.clone()
+            } else {
+                self.source_layout.clone()
+            };
+        };
+        let bucket = match count.parse::<usize>() {
+            Ok(0..=2) => count,
+            Ok(3..=5) => "3-5",
+            Ok(6..=10) => "6-10",
+            Ok(11..=20) => "11-20",
+            Ok(_) => "21+",
+            Err(_) => count,
+        };
+        format!("{}:{}", shape, bucket)
+    }
+
+    /// The error/panic text of the result, if any
+    pub fn message(&self) -> Option<&str> {
+        match &self.result {
+            ExitType::Error(message)
+            | ExitType::ThreadPanic(message)
+            | ExitType::Nondeterministic(message)
+            | ExitType::HarnessError(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    pub fn convert_to_json_line(&self) -> String {
+        let value = serde_json::json!({
+            "schema_version": RESULTS_SCHEMA_VERSION,
+            "bytecode_hash": self.bytecode_hash,
+            "result": self.result.name(),
+            "message": self.message(),
+            "time": (self.time * 1000.0).round() / 1000.0,
+            "source_type": self.source_type,
+            "source_size": self.size,
+            "source_path": self.source_path,
+            "compiler_version": self.compiler_version,
+            "optimization_used": self.optimization_used,
+            "solc_compiles": self.solc_compiles,
+            "flattened": self.flattened,
+            "import_files": self.import_files,
+            "import_depth": self.import_depth,
+            "unresolved_imports": self.unresolved_imports,
+            "lines_of_code": self.lines_of_code,
+            "contract_count": self.contract_count,
+            "function_count": self.function_count,
+            "assembly_blocks": self.assembly_blocks,
+            "parse_time": self.parse_time.map(|parse_time| (parse_time * 1000.0).round() / 1000.0),
+            "analysis_time": self.analysis_time.map(|analysis_time| (analysis_time * 1000.0).round() / 1000.0),
+            "output_time": self.output_time.map(|output_time| (output_time * 1000.0).round() / 1000.0),
+            "addresses": self.addresses,
+            "chain": self.chain,
+            "source_layout": self.source_layout,
+            "spawn_time": self.spawn_time.map(|spawn_time| (spawn_time * 10000.0).round() / 10000.0),
+            "queued_at": self.queued_at.as_ref().map(format_timestamp),
+            "started_at": self.started_at.as_ref().map(format_timestamp),
+            "compiler_available": self.compiler_available,
+            "batch_size": self.batch_size,
+            "cpu_time": self.cpu_time.map(|cpu_time| (cpu_time * 1000.0).round() / 1000.0),
+            "source_hash": self.source_hash,
+            "spawn_retries": self.spawn_retries,
+            "warmup": self.warmup,
+            "core_dump": self.core_dump,
+            "crash_location": self.crash_location,
+            "unhonored_settings": self.unhonored_settings,
+            "target_contract_analyzed": self.target_contract_analyzed,
+            "last_phase": self.last_phase,
+            "deployed_block": self.deployed_block,
+            "deployed_at": self.deployed_at.as_ref().map(format_timestamp),
+            "balance_eth": self.balance_eth,
+            "tx_count": self.tx_count,
+        });
+        let mut value = value;
+        if let Value::Object(object) = &mut value {
+            for (name, extra) in &self.extra_columns {
+                object.insert(name.clone(), extra.clone());
+            }
+        }
+        format!("{}\n", value)
+    }
+
+    /// Builds a row from named fields, shared by the csv and json lines readers.
+    /// Only bytecode_hash and result are required, other missing columns get defaults.
+    pub fn from_fields<'a>(field: impl Fn(&str) -> Option<&'a str>) -> Result<Self, String> {
+        let bytecode_hash = field("bytecode_hash").ok_or("missing bytecode_hash")?;
+        let result_label = field("result").ok_or("missing result")?;
+        let result = ExitType::from_label(result_label)
+            .ok_or(format!("unknown result `{}`", result_label))?;
+
+        Ok(Self {
+            bytecode_hash: bytecode_hash.to_string(),
+            result,
+            time: field("time (sec)")
+                .and_then(|time| time.parse().ok())
+                .unwrap_or(0.0),
+            source_type: field("source_type").unwrap_or_default().to_string(),
+            size: field("source_size")
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            source_path: field("source_path").unwrap_or_default().to_string(),
+            compiler_version: field("compiler_version").unwrap_or_default().to_string(),
+            optimization_used: field("optimization_used")
+                .and_then(|optimization_used| optimization_used.parse().ok()),
+            solc_compiles: field("solc_compiles")
+                .and_then(|solc_compiles| solc_compiles.parse().ok()),
+            flattened: field("flattened").and_then(|flattened| flattened.parse().ok()),
+            import_files: field("import_files").and_then(|import_files| import_files.parse().ok()),
+            import_depth: field("import_depth").and_then(|import_depth| import_depth.parse().ok()),
+            unresolved_imports: field("unresolved_imports")
+                .and_then(|unresolved_imports| unresolved_imports.parse().ok()),
+            lines_of_code: field("lines_of_code")
+                .and_then(|lines_of_code| lines_of_code.parse().ok()),
+            contract_count: field("contract_count")
+                .and_then(|contract_count| contract_count.parse().ok()),
+            function_count: field("function_count")
+                .and_then(|function_count| function_count.parse().ok()),
+            assembly_blocks: field("assembly_blocks")
+                .and_then(|assembly_blocks| assembly_blocks.parse().ok()),
+            parse_time: field("parse_time").and_then(|parse_time| parse_time.parse().ok()),
+            analysis_time: field("analysis_time")
+                .and_then(|analysis_time| analysis_time.parse().ok()),
+            output_time: field("output_time").and_then(|output_time| output_time.parse().ok()),
+            addresses: field("addresses")
+                .map(|addresses| addresses.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            chain: field("chain").map(str::to_string),
+            source_layout: field("source_layout").unwrap_or_default().to_string(),
+            spawn_time: field("spawn_time").and_then(|spawn_time| spawn_time.parse().ok()),
+            queued_at: field("queued_at").and_then(parse_timestamp),
+            started_at: field("started_at").and_then(parse_timestamp),
+            compiler_available: field("compiler_available")
+                .and_then(|compiler_available| compiler_available.parse().ok()),
+            batch_size: field("batch_size").and_then(|batch_size| batch_size.parse().ok()),
+            cpu_time: field("cpu_time").and_then(|cpu_time| cpu_time.parse().ok()),
+            source_hash: field("source_hash").map(str::to_string),
+            spawn_retries: field("spawn_retries")
+                .and_then(|spawn_retries| spawn_retries.parse().ok()),
+            warmup: field("warmup").and_then(|warmup| warmup.parse().ok()),
+            core_dump: field("core_dump").map(str::to_string),
+            crash_location: field("crash_location").map(str::to_string),
+            unhonored_settings: field("unhonored_settings")
+                .map(|settings| settings.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            target_contract_analyzed: field("target_contract_analyzed")
+                .and_then(|analyzed| analyzed.parse().ok()),
+            last_phase: field("last_phase").map(str::to_string),
+            deployed_block: field("deployed_block")
+                .and_then(|deployed_block| deployed_block.parse().ok()),
+            deployed_at: field("deployed_at").and_then(parse_timestamp),
+            balance_eth: field("balance_eth").and_then(|balance_eth| balance_eth.parse().ok()),
+            tx_count: field("tx_count").and_then(|tx_count| tx_count.parse().ok()),
+            extra_columns: Vec::new(),
+        })
+    }
+}
+
+/// Reads a results file previously written by `ResultsWriter`, either csv or JSON lines.
+/// Files written with a different schema version are rejected, so that results from old
+/// omniscan versions are never silently compared against new ones.
+/// Results rotated into parts (see `--rotate-results`) are read from all their parts.
+pub fn read_results_file(path: &Path) -> Result<Vec<ResultsRow>, String> {
+    let Some(parts) = rotation::part_paths(path) else {
+        return read_results_part(path);
+    };
+    let mut rows = Vec::new();
+    for part in parts? {
+        // a part is created empty when json lines are rotated
+        if fs::metadata(&part)
+            .map(|metadata| metadata.len() == 0)
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        rows.extend(read_results_part(&part).map_err(|e| format!("{}: {}", part.display(), e))?);
+    }
+    Ok(rows)
+}
+
+/// Bytecode hashes of the contracts a results file has a result for, to resume the run that
+/// wrote it with `--resume`. A last line cut short by the run being killed is dropped from the
+/// file, so the results appended after it stay readable. The file is only cut once the rest of
+/// it was read, a file that can't be read is left as it is.
+pub fn analyzed_hashes(path: &Path) -> Result<HashSet<String>, String> {
+    let rotated = rotation::part_paths(path);
+    let parts = match &rotated {
+        Some(Ok(parts)) => parts.clone(),
+        Some(Err(e)) => return Err(e.clone()),
+        None => vec![path.to_path_buf()],
+    };
+    let Some((current_part, earlier_parts)) = parts.split_last() else {
+        return Ok(HashSet::new());
+    };
+    let mut rows = Vec::new();
+    for part in earlier_parts {
+        // a part is created empty when json lines are rotated
+        if fs::metadata(part).is_ok_and(|metadata| metadata.len() == 0) {
+            continue;
+        }
+        rows.extend(read_results_part(part).map_err(|e| format!("{}: {}", part.display(), e))?);
+    }
+    let contents = fs::read(current_part).map_err(|e| e.to_string())?;
+    let complete = contents
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let complete_lines = std::str::from_utf8(&contents[..complete])
+        .map_err(|e| format!("{}: {}", current_part.display(), e))?;
+    if rotated.is_none() || !complete_lines.trim().is_empty() {
+        rows.extend(
+            parse_results_part(complete_lines)
+                .map_err(|e| format!("{}: {}", current_part.display(), e))?,
+        );
+    }
+    if complete < contents.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(current_part)
+            .and_then(|file| file.set_len(complete as u64))
+            .map_err(|e| e.to_string())?;
+        println!(
+            "Dropped the incomplete last line of {}",
+            current_part.display()
+        );
+    }
+    Ok(rows.into_iter().map(|row| row.bytecode_hash).collect())
+}
+
+fn read_results_part(path: &Path) -> Result<Vec<ResultsRow>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_results_part(&contents)
+}
+
+fn parse_results_part(contents: &str) -> Result<Vec<ResultsRow>, String> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_line_idx, line)| !line.trim().is_empty());
+    let (_, first_line) = lines.next().ok_or("results file is empty")?;
+    let parser = ResultsLineParser::for_first_line(first_line)?;
+    // the first json line is a result too, the csv header is not
+    let first_row = match parser {
+        ResultsLineParser::Csv(_) => None,
+        ResultsLineParser::Jsonl => Some((0, first_line)),
+    };
+
+    first_row
+        .into_iter()
+        .chain(lines)
+        .map(|(line_idx, line)| parser.parse_line(line, line_idx + 1))
+        .collect()
+}
+
+/// Parses the lines of a results file one at a time, so that a file still being written can
+/// be read as it grows
+pub enum ResultsLineParser {
+    /// Index of each column of the csv header, by name. Columns are looked up by name, so
+    /// files with extra or reordered columns can be read.
+    Csv(HashMap<String, usize>),
+    Jsonl,
+}
+
+fn check_schema_version(version: Option<u32>, line_number: usize) -> Result<(), String> {
+    match version {
+        Some(version)
+            if (OLDEST_READABLE_SCHEMA_VERSION..=RESULTS_SCHEMA_VERSION).contains(&version) =>
+        {
+            Ok(())
+        }
+        Some(version) => Err(format!(
+            "line {}: written with results schema version {}, but this omniscan reads versions \
+             {} to {}",
+            line_number, version, OLDEST_READABLE_SCHEMA_VERSION, RESULTS_SCHEMA_VERSION
+        )),
+        None => Err(format!(
+            "line {}: missing schema_version, the file was written by an older omniscan \
+             (schema version 1) and must be regenerated to be compared with schema version {}",
+            line_number, RESULTS_SCHEMA_VERSION
+        )),
+    }
+}
+
+impl ResultsLineParser {
+    /// Detects the format from the first non-empty line of the file, the csv header or the
+    /// first json line
+    pub fn for_first_line(first_line: &str) -> Result<Self, String> {
+        if first_line.trim_start().starts_with('{') {
+            return Ok(ResultsLineParser::Jsonl);
+        }
+        let columns: HashMap<String, usize> = first_line
+            .split(',')
+            .enumerate()
+            .map(|(idx, name)| (name.trim().to_string(), idx))
+            .collect();
+        if !columns.contains_key("schema_version") {
+            return Err(format!(
+                "results file has no schema_version column, it was written by an older omniscan \
+                 (schema version 1) and must be regenerated to be compared with schema version {}",
+                RESULTS_SCHEMA_VERSION
+            ));
+        }
+        Ok(ResultsLineParser::Csv(columns))
+    }
+
+    /// Parses a non-empty line following the first one (for csv) or including it (for json
+    /// lines), `line_number` counting from 1 for the errors
+    pub fn parse_line(&self, line: &str, line_number: usize) -> Result<ResultsRow, String> {
+        match self {
+            ResultsLineParser::Csv(columns) => parse_csv_line(columns, line, line_number),
+            ResultsLineParser::Jsonl => parse_json_line(line, line_number),
+        }
+    }
+}
+
+/// Quotes a csv field holding a comma or a quote, such as a path or the demangled frames of a
+/// crash location (`<alloc::vec::Vec<T,A> as ...>`), doubling its quotes
+fn escape_csv_field(field: String) -> String {
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Splits a line of a results csv into its fields, unquoting the fields `escape_csv_field`
+/// quoted
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn parse_csv_line(
+    columns: &HashMap<String, usize>,
+    line: &str,
+    line_number: usize,
+) -> Result<ResultsRow, String> {
+    let fields = split_csv_line(line);
+    let field = |name: &str| {
+        columns
+            .get(name)
+            .and_then(|idx| fields.get(*idx))
+            .map(|field| field.trim())
+            .filter(|field| !field.is_empty())
+    };
+
+    check_schema_version(
+        field("schema_version").and_then(|version| version.parse().ok()),
+        line_number,
+    )?;
+    let mut row =
+        ResultsRow::from_fields(field).map_err(|e| format!("line {}: {}", line_number, e))?;
+    let mut extra_columns = columns
+        .iter()
+        .filter(|(name, _)| !CSV_COLUMNS.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+    extra_columns.sort_by_key(|(_, idx)| **idx);
+    row.extra_columns = extra_columns
+        .into_iter()
+        .map(|(name, idx)| {
+            let value = fields
+                .get(*idx)
+                .map(|field| field.trim())
+                .unwrap_or_default();
+            (name.clone(), Value::String(value.to_string()))
+        })
+        .collect();
+    Ok(row)
+}
+
+fn parse_json_line(line: &str, line_number: usize) -> Result<ResultsRow, String> {
+    let value: Value = serde_json::from_str(line)
+        .map_err(|e| format!("line {}: invalid json: {}", line_number, e))?;
+
+    check_schema_version(
+        value["schema_version"]
+            .as_u64()
+            .map(|version| version as u32),
+        line_number,
+    )?;
+
+    // convert the json values into the same string fields as the csv columns
+    let mut fields: HashMap<&str, String> = HashMap::new();
+    if let Value::Object(object) = &value {
+        for (key, field_value) in object {
+            let field_string = match field_value {
+                Value::Null => continue,
+                Value::String(string) => string.clone(),
+                // addresses, space separated like in the csv
+                Value::Array(items) => items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+                other => other.to_string(),
+            };
+            let column = if key == "time" { "time (sec)" } else { key };
+            fields.insert(column, field_string);
+        }
+    }
+    // json lines keep the message separate from the result name
+    if let Some(result_name) = fields.get("result").cloned() {
+        let label = match (result_name.as_str(), value["message"].as_str()) {
+            ("Error" | "ThreadPanic" | "Nondeterministic" | "HarnessError", message) => {
+                format!("{}: {}", result_name, message.unwrap_or_default())
+            }
+            ("NonInterpreted", _) => "NonInterpreted Error".to_string(),
+            _ => result_name,
+        };
+        fields.insert("result", label);
+    }
+
+    let mut row = ResultsRow::from_fields(|name| fields.get(name).map(|field| field.as_str()))
+        .map_err(|e| format!("line {}: {}", line_number, e))?;
+    if let Value::Object(object) = value {
+        row.extra_columns = object
+            .into_iter()
+            // the keys of `convert_to_json_line`
+            .filter(|(key, _)| {
+                !(CSV_COLUMNS.contains(&key.as_str()) || key == "time" || key == "message")
+            })
+            .collect();
+    }
+    Ok(row)
+}
+
+/// A row with only the bytecode hash and the result set, for tests
+#[cfg(test)]
+pub(crate) fn row(bytecode_hash: &str, result: &str) -> ResultsRow {
+    ResultsRow::from_fields(|name| match name {
+        "bytecode_hash" => Some(bytecode_hash),
+        "result" => Some(result),
+        _ => None,
+    })
+    .unwrap()
+}
+
+/// Empty directory for the files of one test
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("omniscan-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csv_parser() -> ResultsLineParser {
+        let header = ResultsWriter::new(PathBuf::from("results.csv")).convert_fields_to_header();
+        ResultsLineParser::for_first_line(header.trim_end()).unwrap()
+    }
+
+    #[test]
+    fn split_csv_line_unquotes_fields() {
+        assert_eq!(split_csv_line("a,,b"), ["a", "", "b"]);
+        assert_eq!(
+            split_csv_line(r#"a,"b,c","say ""hi""",d"#),
+            ["a", "b,c", r#"say "hi""#, "d"]
+        );
+    }
+
+    #[test]
+    fn csv_round_trips_fields_with_commas_and_quotes() {
+        let mut written = row("0xabc", "Error: expected \"}\"");
+        written.crash_location = Some("<alloc::vec::Vec<T,A> as Drop>::drop".to_string());
+        let read = csv_parser()
+            .parse_line(written.convert_to_csv_string().trim_end(), 2)
+            .unwrap();
+        assert_eq!(read.bytecode_hash, "0xabc");
+        assert_eq!(read.message(), Some("expected \"}\""));
+        assert_eq!(read.crash_location, written.crash_location);
+    }
+
+    #[test]
+    fn unknown_columns_are_kept() {
+        let header = format!("{},note", CSV_COLUMNS.join(","));
+        let parser = ResultsLineParser::for_first_line(&header).unwrap();
+        let mut fields = row("0xabc", "Success").csv_fields().to_vec();
+        fields.push("flaky".to_string());
+        let read = parser.parse_line(&fields.join(","), 2).unwrap();
+        assert_eq!(
+            read.extra_columns,
+            [("note".to_string(), Value::String("flaky".to_string()))]
+        );
+
+        let writer = ResultsWriter::new(PathBuf::from("results.csv"))
+            .with_extra_columns(vec!["note".to_string()]);
+        assert_eq!(writer.convert_fields_to_header().trim_end(), header);
+
+        let line = read.convert_to_json_line();
+        let read = ResultsLineParser::Jsonl.parse_line(&line, 1).unwrap();
+        assert_eq!(
+            read.extra_columns,
+            [("note".to_string(), Value::String("flaky".to_string()))]
+        );
+    }
+
+    #[test]
+    fn older_readable_schema_versions_are_accepted() {
+        assert!(check_schema_version(Some(RESULTS_SCHEMA_VERSION), 1).is_ok());
+        assert!(check_schema_version(Some(OLDEST_READABLE_SCHEMA_VERSION), 1).is_ok());
+        assert!(check_schema_version(Some(OLDEST_READABLE_SCHEMA_VERSION - 1), 1).is_err());
+        assert!(check_schema_version(Some(RESULTS_SCHEMA_VERSION + 1), 1).is_err());
+        assert!(check_schema_version(None, 1).is_err());
+
+        // columns added after the file's version are left empty
+        let line = format!(
+            r#"{{"schema_version":{},"bytecode_hash":"0xabc","result":"Success"}}"#,
+            OLDEST_READABLE_SCHEMA_VERSION
+        );
+        let read = ResultsLineParser::Jsonl.parse_line(&line, 1).unwrap();
+        assert_eq!(read.bytecode_hash, "0xabc");
+        assert_eq!(read.source_hash, None);
+        assert!(read.extra_columns.is_empty());
+    }
+
+    #[test]
+    fn rotated_results_are_read_from_all_parts() {
+        let dir = test_dir("rotation");
+        let output_path = dir.join("results.csv");
+        let mut writer = ResultsWriter::new(output_path.clone()).rotating(1);
+        writer.initiate_headers_for_results_csv().unwrap();
+        for hash in ["0x1", "0x2", "0x3"] {
+            writer
+                .append_to_results_file(&row(hash, "Success"))
+                .unwrap();
+        }
+        let manifest = rotation::read_parts_manifest(&output_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            manifest.parts,
+            ["results.0001.csv", "results.0002.csv", "results.0003.csv"]
+        );
+        let hashes = read_results_file(&output_path)
+            .unwrap()
+            .into_iter()
+            .map(|row| row.bytecode_hash)
+            .collect::<Vec<_>>();
+        assert_eq!(hashes, ["0x1", "0x2", "0x3"]);
+
+        // writing without rotation removes the parts of the earlier run
+        let mut writer = ResultsWriter::new(output_path.clone());
+        writer.initiate_headers_for_results_csv().unwrap();
+        writer
+            .append_to_results_file(&row("0x4", "Success"))
+            .unwrap();
+        assert!(rotation::read_parts_manifest(&output_path).is_none());
+        assert!(!dir.join("results.0001.csv").exists());
+        assert_eq!(read_results_file(&output_path).unwrap().len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn analyzed_hashes_drops_an_incomplete_last_line() {
+        let dir = test_dir("resume");
+        let output_path = dir.join("results.jsonl");
+        let mut writer = ResultsWriter::new(output_path.clone());
+        writer.initiate_headers_for_results_csv().unwrap();
+        writer
+            .append_to_results_file(&row("0x1", "Success"))
+            .unwrap();
+        writer
+            .append_to_results_file(&row("0x2", "Success"))
+            .unwrap();
+        let complete_len = fs::metadata(&output_path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&output_path).unwrap();
+        file.write_all(br#"{"schema_version":3,"bytecode_h"#)
+            .unwrap();
+
+        let hashes = analyzed_hashes(&output_path).unwrap();
+        assert_eq!(
+            hashes,
+            HashSet::from(["0x1".to_string(), "0x2".to_string()])
+        );
+        assert_eq!(fs::metadata(&output_path).unwrap().len(), complete_len);
+
+        // results appended after the cut stay readable
+        let mut writer = ResultsWriter::new(output_path.clone()).resuming().unwrap();
+        writer
+            .append_to_results_file(&row("0x3", "Success"))
+            .unwrap();
+        assert_eq!(read_results_file(&output_path).unwrap().len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
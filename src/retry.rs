@@ -0,0 +1,57 @@
+use crate::error::OmniscanError;
+use std::time::Duration;
+
+/// Errors starting a process that come from a loaded machine rather than from the analyzer or
+/// the contract: out of processes or memory, out of file descriptors for the pipes
+const TRANSIENT_ERRNOS: [i32; 4] = [libc::EAGAIN, libc::ENOMEM, libc::EMFILE, libc::ENFILE];
+
+/// Whether the analyzer could not be started for lack of resources, which starting it again
+/// a little later may fix
+pub fn is_transient(error: &OmniscanError) -> bool {
+    match error {
+        OmniscanError::Spawn(e) => e
+            .raw_os_error()
+            .is_some_and(|errno| TRANSIENT_ERRNOS.contains(&errno)),
+        _ => false,
+    }
+}
+
+/// How starting the analyzer is retried after transient failures, see `--spawn-retries`
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnRetry {
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each next one. Each wait is jittered by up
+    /// to half of it, so jobs that failed together on a loaded machine don't retry together
+    pub backoff: Duration,
+}
+
+impl SpawnRetry {
+    /// Calls `spawn` until it succeeds, fails for another reason than a transient one, or
+    /// was retried `retries` times. Returns its last outcome with the number of retries; a
+    /// transient failure left after the retries names them, so it is told apart from the
+    /// analyzer's own failures.
+    pub async fn spawn<T>(
+        &self,
+        mut spawn: impl FnMut() -> Result<T, OmniscanError>,
+    ) -> (Result<T, OmniscanError>, u32) {
+        let mut retries = 0;
+        let mut backoff = self.backoff;
+        loop {
+            match spawn() {
+                Err(e) if is_transient(&e) && retries < self.retries => {
+                    tokio::time::sleep(backoff.mul_f64(0.5 + fastrand::f64())).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Err(e) if is_transient(&e) => {
+                    let e = OmniscanError::TransientSpawn {
+                        retries,
+                        source: Box::new(e),
+                    };
+                    return (Err(e), retries);
+                }
+                spawned => return (spawned, retries),
+            }
+        }
+    }
+}
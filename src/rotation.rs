@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Parts a results file was rotated into, see `--rotate-results`. Written next to the results
+/// as `<stem>.parts.json`, whenever a part is started.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PartsManifest {
+    /// Size (bytes) past which a new part is started
+    pub max_part_bytes: u64,
+    /// File names of the parts, in the order they were written, next to the manifest
+    pub parts: Vec<String>,
+}
+
+pub fn parts_manifest_path(output_path: &Path) -> PathBuf {
+    output_path.with_extension("parts.json")
+}
+
+/// File name of the part `index` (from 1) of the results, e.g. results.0001.jsonl
+pub fn part_file_name(output_path: &Path, index: usize) -> String {
+    let stem = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    match output_path.extension() {
+        Some(extension) => format!("{}.{:04}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}.{:04}", stem, index),
+    }
+}
+
+/// The parts manifest of the results, None when they were not rotated
+pub fn read_parts_manifest(output_path: &Path) -> Option<Result<PartsManifest, String>> {
+    let manifest_path = parts_manifest_path(output_path);
+    let contents = fs::read(&manifest_path).ok()?;
+    Some(
+        serde_json::from_slice(&contents)
+            .map_err(|e| format!("invalid parts manifest {}: {}", manifest_path.display(), e)),
+    )
+}
+
+/// Paths of the parts of the results in order, None when they were not rotated
+pub fn part_paths(output_path: &Path) -> Option<Result<Vec<PathBuf>, String>> {
+    Some(read_parts_manifest(output_path)?.map(|manifest| {
+        manifest
+            .parts
+            .iter()
+            .map(|part| output_path.with_file_name(part))
+            .collect()
+    }))
+}
+
+pub fn write_parts_manifest(output_path: &Path, manifest: &PartsManifest) -> std::io::Result<()> {
+    fs::write(
+        parts_manifest_path(output_path),
+        serde_json::to_string_pretty(manifest).unwrap(),
+    )
+}
+
+/// Replaces the parts of the results at `output_path` with the parts written at `new_output_path`
+pub fn replace_parts(output_path: &Path, new_output_path: &Path) -> std::io::Result<()> {
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+    let new_manifest = read_parts_manifest(new_output_path)
+        .unwrap_or_else(|| Err("no parts written".to_string()))
+        .map_err(invalid)?;
+    let old_parts = part_paths(output_path)
+        .transpose()
+        .map_err(invalid)?
+        .unwrap_or_default();
+    let mut parts = Vec::new();
+    for (idx, new_part) in new_manifest.parts.iter().enumerate() {
+        let part = part_file_name(output_path, idx + 1);
+        fs::rename(
+            new_output_path.with_file_name(new_part),
+            output_path.with_file_name(&part),
+        )?;
+        parts.push(part);
+    }
+    for old_part in old_parts.iter().skip(parts.len()) {
+        let _ = fs::remove_file(old_part);
+    }
+    write_parts_manifest(
+        output_path,
+        &PartsManifest {
+            max_part_bytes: new_manifest.max_part_bytes,
+            parts,
+        },
+    )?;
+    fs::remove_file(parts_manifest_path(new_output_path))
+}
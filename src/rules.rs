@@ -0,0 +1,168 @@
+use crate::{fingerprint, manifest, ExitType};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Rules file read by runs when `--rules-file` is not given, and written by
+/// `omniscan classify-unknowns`
+pub const DEFAULT_RULES_FILE: &str = "omniscan.rules.json";
+
+/// What an output matched by a rule counts as
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleResult {
+    Success,
+    Error,
+    ThreadPanic,
+}
+
+/// Which of the analyzer's outputs a rule is matched against
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleStream {
+    #[default]
+    Either,
+    Stdout,
+    Stderr,
+}
+
+/// Classifies the outputs the classification profile does not recognize (see `profiles`)
+/// whose stdout or stderr match `pattern`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClassificationRule {
+    pub pattern: String,
+    pub result: RuleResult,
+    #[serde(default)]
+    pub stream: RuleStream,
+    /// RFC 3339 time the rule was added
+    pub added_at: String,
+}
+
+/// Rules tried in order on the outputs nothing else classified
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RulesFile {
+    pub rules: Vec<ClassificationRule>,
+}
+
+impl RulesFile {
+    /// Reads the rules file, empty when it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Writes the rules file, replacing it only once it is complete
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        fs::write(&tmp_path, serde_json::to_string_pretty(self).unwrap())?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// The rules of a rules file with their patterns compiled
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<(ClassificationRule, Regex)>,
+}
+
+impl Rules {
+    pub fn compile(rules_file: &RulesFile) -> Result<Self, String> {
+        let rules = rules_file
+            .rules
+            .iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .map(|regex| (rule.clone(), regex))
+                    .map_err(|e| format!("invalid pattern `{}`: {}", rule.pattern, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::compile(&RulesFile::load(path)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// The result of the first rule matching the output, None when none does. The message of
+    /// an Error or ThreadPanic is the first group of the pattern, or else all it matched.
+    pub fn classify(&self, stdout: &str, stderr: &str) -> Option<ExitType> {
+        self.rules.iter().find_map(|(rule, regex)| {
+            let captures = match rule.stream {
+                RuleStream::Either => regex.captures(stdout).or_else(|| regex.captures(stderr))?,
+                RuleStream::Stdout => regex.captures(stdout)?,
+                RuleStream::Stderr => regex.captures(stderr)?,
+            };
+            let message = || {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|matched| matched.as_str().trim().to_string())
+                    .unwrap_or_default()
+            };
+            Some(match rule.result {
+                RuleResult::Success => ExitType::Success,
+                RuleResult::Error => ExitType::Error(message()),
+                RuleResult::ThreadPanic => ExitType::ThreadPanic(message()),
+            })
+        })
+    }
+}
+
+/// Rules file of a run: the given `--rules-file`, which has to exist, else the default one
+/// when there is one in the current directory
+pub fn run_rules_file(rules_file: Option<&str>) -> Option<PathBuf> {
+    match rules_file {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            if !path.is_file() {
+                eprintln!("The rules file {} does not exist", path.display());
+                std::process::exit(1);
+            }
+            Some(path)
+        }
+        None => Some(PathBuf::from(DEFAULT_RULES_FILE)).filter(|path| path.is_file()),
+    }
+}
+
+/// Rules file of a triage re-run: the given `--rules-file`, else the one the run of the
+/// results recorded in its manifest, else the default one
+pub fn triage_rules_file(results_path: &Path, rules_file: Option<&str>) -> Option<PathBuf> {
+    if rules_file.is_some() {
+        return run_rules_file(rules_file);
+    }
+    let Some(run_manifest) = manifest::read_manifest(&manifest::manifest_path(results_path))
+        .ok()
+        .filter(|run_manifest| run_manifest.rules_file.is_some())
+    else {
+        return run_rules_file(None);
+    };
+    let path = PathBuf::from(run_manifest.rules_file.unwrap_or_default());
+    match fingerprint::file_hash(&path) {
+        None => {
+            eprintln!(
+                "WARNING: the rules file {} of the run no longer exists, re-runs are classified \
+                 without it",
+                path.display()
+            );
+            return run_rules_file(None);
+        }
+        Some(sha256) if Some(&sha256) != run_manifest.rules_sha256.as_ref() => eprintln!(
+            "WARNING: the rules file {} changed since the run, re-runs may be classified \
+             differently",
+            path.display()
+        ),
+        Some(_) => {}
+    }
+    Some(path)
+}
@@ -0,0 +1,181 @@
+use crate::{
+    boilerplate, catch_harness_panic, collect_contract_sources, grep, FiestaMetadata, SourceType,
+};
+use regex::Regex;
+use std::{collections::HashSet, fs, path::Path};
+
+/// Sources of a contract as found on disk, before they are collected: the entrypoint of a
+/// project contract, every file of a fiesta contract's directory but its metadata.json
+fn read_sources(metadata: &FiestaMetadata) -> Option<Vec<String>> {
+    let dir = Path::new(&metadata.abs_path_to_dir);
+    if let Some(SourceType::Project(project_source)) = &metadata.source_type {
+        return fs::read_to_string(dir.join(&project_source.entrypoint))
+            .ok()
+            .map(|source| vec![source]);
+    }
+    let mut paths = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file() && path.file_name().is_some_and(|name| name != "metadata.json")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| fs::read_to_string(path).ok())
+        .collect()
+}
+
+/// Keeps the first contract of each group with the same sources, up to comments and
+/// whitespace. Contracts whose sources can't be read are kept, the run reports them.
+fn retain_unique(metadatas: &mut Vec<FiestaMetadata>, seen: &mut HashSet<[u8; 32]>) -> usize {
+    let before = metadatas.len();
+    metadatas.retain(|metadata| match read_sources(metadata) {
+        Some(sources) => seen.insert(boilerplate::sources_key(&sources)),
+        None => true,
+    });
+    before - metadatas.len()
+}
+
+/// Keeps the contracts with a line of their sources matching the pattern, see
+/// `--source-contains`. Their sources are collected on the way, so the run does not read them
+/// again. Contracts whose sources can't be collected can't be told to match and are left out.
+fn retain_matching(metadatas: &mut Vec<FiestaMetadata>, pattern: &Regex) -> usize {
+    let before = metadatas.len();
+    metadatas.retain_mut(|metadata| {
+        if metadata.source_type.is_none()
+            && !matches!(
+                catch_harness_panic("collecting the sources", || {
+                    collect_contract_sources(metadata)
+                }),
+                Ok(Ok(()))
+            )
+        {
+            return false;
+        }
+        grep::sources_match(metadata, pattern).unwrap_or(false)
+    });
+    before - metadatas.len()
+}
+
+/// Selects `num_contracts` contracts in corpus order with `discover_next(num_contracts,
+/// skip_contracts)`, leaving out the contracts `retain` removes from each batch. The selection
+/// is topped up with `discover_next(wanted, 0)`, which continues the walk where the previous
+/// batch stopped, until it is full or the corpus is exhausted.
+fn top_up(
+    mut discover_next: impl FnMut(usize, usize) -> Vec<FiestaMetadata>,
+    num_contracts: usize,
+    skip_contracts: usize,
+    mut retain: impl FnMut(&mut Vec<FiestaMetadata>),
+) -> Vec<FiestaMetadata> {
+    let mut sample = Vec::new();
+    let mut skip = skip_contracts;
+    while sample.len() < num_contracts {
+        let wanted = num_contracts - sample.len();
+        let mut batch = discover_next(wanted, std::mem::take(&mut skip));
+        let exhausted = batch.len() < wanted;
+        retain(&mut batch);
+        sample.append(&mut batch);
+        if exhausted {
+            break;
+        }
+    }
+    sample
+}
+
+/// Samples `num_contracts` contracts with sources matching `source_pattern`, see
+/// `--source-contains`, and with distinct sources when `unique`, see `--unique-sample`. The
+/// contracts left out are made up for with contracts from further into the corpus.
+pub fn filtered_sample(
+    discover_next: impl FnMut(usize, usize) -> Vec<FiestaMetadata>,
+    num_contracts: usize,
+    skip_contracts: usize,
+    unique: bool,
+    source_pattern: Option<&Regex>,
+) -> Vec<FiestaMetadata> {
+    let mut seen = HashSet::new();
+    let (mut not_matching, mut left_out) = (0, 0);
+    let sample = top_up(discover_next, num_contracts, skip_contracts, |batch| {
+        if let Some(source_pattern) = source_pattern {
+            not_matching += retain_matching(batch, source_pattern);
+        }
+        if unique {
+            left_out += retain_unique(batch, &mut seen);
+        }
+    });
+    if let Some(source_pattern) = source_pattern {
+        println!(
+            "Selected {} contracts with sources matching `{}`, {} walked past did not match",
+            sample.len(),
+            source_pattern,
+            not_matching
+        );
+    }
+    print_left_out(left_out);
+    sample
+}
+
+/// Leaves out the copies of a sample that can't be topped up, as a fetched corpus
+pub fn dedup_sample(metadatas: &mut Vec<FiestaMetadata>) {
+    let left_out = retain_unique(metadatas, &mut HashSet::new());
+    print_left_out(left_out);
+}
+
+fn print_left_out(left_out: usize) {
+    if left_out > 0 {
+        println!(
+            "Left out {} contracts with the same sources as another contract of the sample",
+            left_out
+        );
+    }
+}
+
+/// Shuffles the contracts for `--time-budget` and `--deterministic-schedule`, the same seed
+/// giving the same order
+pub fn shuffle(metadatas: &mut [FiestaMetadata], seed: u64) {
+    fastrand::Rng::with_seed(seed).shuffle(metadatas);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus(count: usize) -> Vec<FiestaMetadata> {
+        (0..count)
+            .map(|idx| FiestaMetadata::for_test(&idx.to_string()))
+            .collect()
+    }
+
+    /// `discover_next` walking `corpus`, each call continuing where the previous one stopped
+    fn walk(corpus: Vec<FiestaMetadata>) -> impl FnMut(usize, usize) -> Vec<FiestaMetadata> {
+        let mut next = 0;
+        move |num_contracts, skip_contracts| {
+            let start = (next + skip_contracts).min(corpus.len());
+            let end = (start + num_contracts).min(corpus.len());
+            next = end;
+            corpus[start..end].to_vec()
+        }
+    }
+
+    fn hashes(metadatas: &[FiestaMetadata]) -> Vec<String> {
+        metadatas
+            .iter()
+            .map(|metadata| metadata.bytecode_hash.clone())
+            .collect()
+    }
+
+    #[test]
+    fn top_up_replaces_the_contracts_left_out() {
+        let keep_even = |batch: &mut Vec<FiestaMetadata>| {
+            batch.retain(|metadata| metadata.bytecode_hash.parse::<usize>().unwrap() % 2 == 0)
+        };
+        let sample = top_up(walk(corpus(20)), 4, 3, keep_even);
+        assert_eq!(hashes(&sample), ["4", "6", "8", "10"]);
+
+        // an exhausted corpus ends the selection short
+        let sample = top_up(walk(corpus(7)), 5, 0, keep_even);
+        assert_eq!(hashes(&sample), ["0", "2", "4", "6"]);
+    }
+}
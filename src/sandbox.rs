@@ -0,0 +1,94 @@
+use std::{
+    io,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+};
+
+/// Cuts the analyzer processes off from the network, by starting each of them in a network
+/// namespace of its own that only has a loopback interface, brought up so that local servers
+/// (e.g. a language server over TCP) keep working. Analyzers fetching compilers or
+/// imports would otherwise make timings depend on the network, and let untrusted sources
+/// reach it.
+#[derive(Clone, Copy, Debug)]
+pub struct NetworkIsolation {
+    /// Namespaces the child unshares before it execs
+    flags: libc::c_int,
+}
+
+impl NetworkIsolation {
+    /// Finds how this host lets omniscan create network namespaces: directly with
+    /// CAP_SYS_ADMIN (e.g. as root), otherwise inside an unprivileged user namespace
+    pub fn detect() -> Result<Self, String> {
+        let candidates = [libc::CLONE_NEWNET, libc::CLONE_NEWUSER | libc::CLONE_NEWNET];
+        let mut last_error = String::new();
+        for flags in candidates {
+            let isolation = Self { flags };
+            match isolation.probe() {
+                Ok(()) => return Ok(isolation),
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Runs omniscan's own `--version` isolated, as unshare only fails in the child
+    fn probe(&self) -> io::Result<()> {
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        self.apply(&mut command);
+        let status = command.status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "the probe exited with {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+
+    /// Makes the command's process enter a new network namespace before it execs
+    pub fn apply(&self, command: &mut Command) {
+        let flags = self.flags;
+        // SAFETY: unshare(2) is a plain syscall, safe between fork and exec. The child is
+        // single threaded there, as creating a user namespace requires.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::unshare(flags) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                bring_up_loopback()
+            });
+        }
+    }
+}
+
+/// Sets the IFF_UP flag of `lo`, which a new network namespace starts with down. Only makes
+/// syscalls, so it can run between fork and exec.
+fn bring_up_loopback() -> io::Result<()> {
+    // SAFETY: the socket is closed before returning, and the ifreq outlives the ioctls that
+    // read and write it
+    unsafe {
+        let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if socket < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut request: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in request.ifr_name.iter_mut().zip(b"lo") {
+            *dst = *src as libc::c_char;
+        }
+        let mut result = libc::ioctl(socket, libc::SIOCGIFFLAGS as _, &mut request);
+        if result == 0 {
+            request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            result = libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &mut request);
+        }
+        let error = io::Error::last_os_error();
+        libc::close(socket);
+        if result != 0 {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use crate::{ExitType, ResultsRow};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+lazy_static! {
+    /// pyrometer error locations look like `rel_path_to_file:line_number:col`
+    static ref LOCATION_REGEX: Regex = Regex::new(r":(\d+):(\d+)").unwrap();
+}
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const CORPUS_URI_BASE_ID: &str = "CORPUS";
+
+/// SARIF rule id and level for each failing exit type. Successful runs produce no finding.
+fn rule_for_exit_type(exit_type: &ExitType) -> Option<(&'static str, &'static str)> {
+    match exit_type {
+        ExitType::Success => None,
+        ExitType::PerformanceTimeout => Some(("pyrometer/performance-timeout", "warning")),
+        ExitType::Error(_) => Some(("pyrometer/error", "error")),
+        ExitType::ThreadPanic(_) => Some(("pyrometer/thread-panic", "error")),
+        ExitType::NonInterpreted(_, _) => Some(("pyrometer/non-interpreted", "note")),
+        ExitType::Nondeterministic(_) => Some(("pyrometer/nondeterministic", "warning")),
+        ExitType::HarnessError(_) => Some(("omniscan/harness-error", "note")),
+    }
+}
+
+fn rules() -> Value {
+    json!([
+        {
+            "id": "pyrometer/performance-timeout",
+            "shortDescription": { "text": "pyrometer did not finish within the timeout" }
+        },
+        {
+            "id": "pyrometer/error",
+            "shortDescription": { "text": "pyrometer reported an error" }
+        },
+        {
+            "id": "pyrometer/thread-panic",
+            "shortDescription": { "text": "pyrometer panicked" }
+        },
+        {
+            "id": "pyrometer/non-interpreted",
+            "shortDescription": { "text": "pyrometer output could not be interpreted" }
+        },
+        {
+            "id": "pyrometer/nondeterministic",
+            "shortDescription": { "text": "two runs of pyrometer on the contract differed" }
+        },
+        {
+            "id": "omniscan/harness-error",
+            "shortDescription": { "text": "omniscan could not run pyrometer on the contract" }
+        }
+    ])
+}
+
+fn message_for_row(row: &ResultsRow) -> String {
+    match &row.result {
+        ExitType::Success => "Success".to_string(),
+        ExitType::PerformanceTimeout => format!("Timed out after {:.3}s", row.time),
+        ExitType::Error(message) => message.clone(),
+        ExitType::ThreadPanic(message) => format!("Thread panic: {}", message),
+        ExitType::NonInterpreted(_stdout, stderr) => {
+            format!(
+                "Could not interpret pyrometer output. stderr: {}",
+                stderr.trim()
+            )
+        }
+        ExitType::Nondeterministic(difference) => format!("Nondeterministic: {}", difference),
+        ExitType::HarnessError(message) => format!("Harness error: {}", message),
+    }
+}
+
+fn location_for_row(row: &ResultsRow) -> Value {
+    let mut physical_location = json!({
+        "artifactLocation": {
+            "uri": row.source_path,
+            "uriBaseId": CORPUS_URI_BASE_ID,
+        }
+    });
+
+    if let ExitType::Error(message) = &row.result {
+        if let Some(captures) = LOCATION_REGEX.captures(message) {
+            let line: u64 = captures[1].parse().unwrap_or(0);
+            let column: u64 = captures[2].parse().unwrap_or(0);
+            // SARIF lines and columns are 1-based
+            if line > 0 && column > 0 {
+                physical_location["region"] = json!({
+                    "startLine": line,
+                    "startColumn": column,
+                });
+            }
+        }
+    }
+
+    json!([{ "physicalLocation": physical_location }])
+}
+
+/// Builds a SARIF 2.1.0 log with one result per failing contract. Failures of contracts with
+/// unresolved imports are expected and downgraded to notes.
+pub fn convert_rows_to_sarif(rows: &[ResultsRow], corpus_root: &Path) -> Value {
+    let results = rows
+        .iter()
+        .filter_map(|row| {
+            let (rule_id, mut level) = rule_for_exit_type(&row.result)?;
+            if row.has_unresolved_imports() {
+                level = "note";
+            }
+            Some(json!({
+                "ruleId": rule_id,
+                "level": level,
+                "message": { "text": message_for_row(row) },
+                "locations": location_for_row(row),
+                "partialFingerprints": { "bytecodeHash": row.bytecode_hash },
+                "properties": {
+                    "sourceType": row.source_type,
+                    "time": row.time,
+                    "unresolvedImports": row.unresolved_imports,
+                },
+            }))
+        })
+        .collect::<Vec<Value>>();
+
+    // the base uri must end with a slash for relative uris to resolve against it
+    let corpus_root = fs::canonicalize(corpus_root).unwrap_or_else(|_| corpus_root.to_path_buf());
+    let corpus_uri = format!(
+        "file://{}/",
+        corpus_root.to_str().unwrap().trim_end_matches('/')
+    );
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "pyrometer",
+                    "informationUri": "https://github.com/nascentxyz/pyrometer",
+                    "rules": rules(),
+                }
+            },
+            "originalUriBaseIds": {
+                CORPUS_URI_BASE_ID: { "uri": corpus_uri }
+            },
+            "results": results,
+        }]
+    })
+}
+
+pub fn write_sarif_log(path: &Path, rows: &[ResultsRow], corpus_root: &Path) {
+    let sarif = convert_rows_to_sarif(rows, corpus_root);
+    fs::write(path, serde_json::to_string_pretty(&sarif).unwrap()).unwrap();
+}
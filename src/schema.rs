@@ -0,0 +1,323 @@
+use crate::{summary, RESULTS_SCHEMA_VERSION};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SchemaKind {
+    /// One line of a `.jsonl` results file
+    Results,
+    /// The `--summary` output
+    Summary,
+}
+
+pub const EXIT_TYPE_NAMES: [&str; 7] = [
+    "Success",
+    "PerformanceTimeout",
+    "Error",
+    "ThreadPanic",
+    "NonInterpreted",
+    "Nondeterministic",
+    "HarnessError",
+];
+
+pub fn results_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("https://github.com/nascentxyz/omniscan/schema/results/v{}", RESULTS_SCHEMA_VERSION),
+        "title": "omniscan results row",
+        "type": "object",
+        "required": ["schema_version", "bytecode_hash", "result", "time", "source_type", "source_size"],
+        "properties": {
+            "schema_version": { "const": RESULTS_SCHEMA_VERSION },
+            "bytecode_hash": {
+                "type": "string",
+                "description": "Bytecode hash of the contract, identifiable key for smart-contract-fiesta"
+            },
+            "result": { "enum": EXIT_TYPE_NAMES },
+            "message": {
+                "type": ["string", "null"],
+                "description": "Error or panic text, null for other results"
+            },
+            "time": { "type": "number", "description": "Time taken to analyze the contract (secs)" },
+            "source_type": { "enum": ["SingleFile", "MultipleFiles", "JSON"] },
+            "source_size": { "type": "integer", "minimum": 0 },
+            "source_path": {
+                "type": "string",
+                "description": "Path of the analyzed file, relative to the corpus root"
+            },
+            "compiler_version": {
+                "type": "string",
+                "description": "CompilerVersion from the contract's metadata.json"
+            },
+            "optimization_used": {
+                "type": ["boolean", "null"],
+                "description": "OptimizationUsed from the contract's metadata.json"
+            },
+            "solc_compiles": {
+                "type": ["boolean", "null"],
+                "description": "Whether the contract compiles with the solc version from its metadata.json, null when not checked or the matching solc is not installed"
+            },
+            "flattened": {
+                "type": ["boolean", "null"],
+                "description": "Whether flattening the contract's sources with --flatten succeeded, null when not flattened"
+            },
+            "import_files": {
+                "type": ["integer", "null"],
+                "minimum": 1,
+                "description": "Number of source files reachable through imports from the entrypoint, the entrypoint included"
+            },
+            "import_depth": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Number of imports between the entrypoint and the furthest reachable file"
+            },
+            "unresolved_imports": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Number of distinct imports that could not be resolved to a source"
+            },
+            "lines_of_code": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Non-blank, non-comment lines across the sources reachable from the entrypoint"
+            },
+            "contract_count": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Contracts, interfaces and libraries across the sources reachable from the entrypoint"
+            },
+            "function_count": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Functions, constructors, fallback and receive functions across the sources reachable from the entrypoint"
+            },
+            "assembly_blocks": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Inline assembly blocks across the sources reachable from the entrypoint"
+            },
+            "parse_time": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "description": "Seconds the analyzer reported spending on parsing (DONE PARSING IN marker), null without a marker"
+            },
+            "analysis_time": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "description": "Seconds the analyzer reported spending on analysis (DONE ANALYZING IN marker), null without a marker"
+            },
+            "output_time": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "description": "Seconds the analyzer reported spending on writing its output (DONE WRITING IN marker), null without a marker"
+            },
+            "addresses": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "On-chain addresses the contract was fetched from with --addresses, empty for other corpora"
+            },
+            "source_layout": {
+                "type": "string",
+                "description": "source_type with its detail: SingleFile, MultipleFiles:<files>, StandardJson:<sources>, SourcesJson:<sources> (contract.json without settings), SingleSourceJson, Foundry or Hardhat"
+            },
+            "chain": {
+                "type": ["string", "null"],
+                "description": "Chain the contract was fetched from with fetch-bulk or --addresses (ethereum, arbitrum, optimism, polygon, base, bsc, gnosis, zora), null for smart-contract-fiesta and projects"
+            },
+            "spawn_time": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "description": "Seconds taken to start the analyzer process, not included in time"
+            },
+            "queued_at": {
+                "type": ["string", "null"],
+                "format": "date-time",
+                "description": "When the contract came next in line for a job, after the contracts before it were given theirs"
+            },
+            "started_at": {
+                "type": ["string", "null"],
+                "format": "date-time",
+                "description": "When the contract was given a job slot, before its sources were prepared and the analyzer started. started_at - queued_at is the time it waited for a job"
+            },
+            "compiler_available": {
+                "type": ["boolean", "null"],
+                "description": "Whether the exact compiler version of the metadata is a solc release svm can install, null when the run did not use --check-compiler or it could not be told"
+            },
+            "batch_size": {
+                "type": ["integer", "null"],
+                "minimum": 2,
+                "description": "Number of contracts analyzed by the same pyrometer invocation, whose time and spawn_time are split evenly among them, null when the contract was analyzed on its own (see --batch-size)"
+            },
+            "cpu_time": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "description": "Seconds of user and system CPU time of the analyzer process and the processes it waited for, null when it ran on a worker (see --worker-pool)"
+            },
+            "source_hash": {
+                "type": ["string", "null"],
+                "pattern": "^[0-9a-f]{64}$",
+                "description": "sha256 of the sources reachable from the entrypoint, sorted by contents and each followed by a NUL byte, independent of the corpus' bytecode hash. null when the sources could not be read"
+            },
+            "spawn_retries": {
+                "type": ["integer", "null"],
+                "minimum": 1,
+                "description": "Times pyrometer was started again after failing to start for lack of resources (EAGAIN, ENOMEM, out of file descriptors), null when it started at once (see --spawn-retries)"
+            },
+            "warmup": {
+                "type": ["boolean", "null"],
+                "description": "true for the first contracts dispatched, whose times are left out of the timing statistics (see --warmup), null for the others"
+            },
+            "core_dump": {
+                "type": ["string", "null"],
+                "description": "Path of the core of the analyzer killed by a signal, relative to the artifacts directory, with the pyrometer binary next to it. null when it did not crash or no core was found (see --core-dumps)"
+            },
+            "crash_location": {
+                "type": ["string", "null"],
+                "description": "Innermost frames of the backtrace of a pyrometer panic that belong to pyrometer, without symbol hashes, files or lines, joined by ' <- '. null when pyrometer did not panic or printed no backtrace (pyrometer runs with RUST_BACKTRACE=1 unless --analyzer-env sets it)"
+            },
+            "unhonored_settings": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Compiler settings of the contract's metadata that could not be passed on with --compiler-settings, as <tool>:<setting>: pyrometer:optimizer and pyrometer:evm_version without the matching --analyzer-*-flag, solc:evm_version when the solc of --solc-check rejected the EVM version. Empty when every setting was honored, for standard-json contracts, whose settings travel with the sources, and without --compiler-settings"
+            },
+            "target_contract_analyzed": {
+                "type": ["boolean", "null"],
+                "description": "With --target-contract-from-metadata, whether pyrometer analyzed the contract named by the metadata's ContractName: false when the sources do not declare it, when pyrometer rejected --analyzer-contract-flag or reported the contract not found. null without --target-contract-from-metadata, and when pyrometer left no output (timeouts) or never ran"
+            },
+            "last_phase": {
+                "type": ["string", "null"],
+                "description": "For a PerformanceTimeout, the phase of the last DONE <PHASE> IN marker pyrometer printed before it was killed: PARSING, ANALYZING or WRITING. null when it printed none, and for contracts that did not time out"
+            },
+            "deployed_block": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Earliest block any address of the contract has code at, found by bisecting eth_getCode (see omniscan enrich). null until the results are enriched, or when the RPC node has no state that old"
+            },
+            "deployed_at": {
+                "type": ["string", "null"],
+                "format": "date-time",
+                "description": "Time of deployed_block (RFC 3339). null until the results are enriched"
+            },
+            "balance_eth": {
+                "type": ["number", "null"],
+                "description": "Balance in ether at the latest block, summed over the addresses of the contract (see omniscan enrich). null until the results are enriched"
+            },
+            "tx_count": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Transactions to or from the addresses of the contract as counted by the Blockscout indexer given to omniscan enrich, summed over the addresses. null until the results are enriched with --indexer-url"
+            }
+        }
+    })
+}
+
+pub fn summary_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("https://github.com/nascentxyz/omniscan/schema/summary/v{}", RESULTS_SCHEMA_VERSION),
+        "title": "omniscan run summary",
+        "type": "object",
+        "required": ["schema_version", "total", "counts", "success_rate", "total_time"],
+        "properties": {
+            "schema_version": { "const": RESULTS_SCHEMA_VERSION },
+            "total": { "type": "integer", "minimum": 0 },
+            "counts": {
+                "type": "object",
+                "propertyNames": { "enum": EXIT_TYPE_NAMES },
+                "additionalProperties": { "type": "integer", "minimum": 0 }
+            },
+            "success_rate": { "type": "number", "minimum": 0, "maximum": 100 },
+            "success_rate_interval": {
+                "type": ["array", "null"],
+                "description": "95% confidence interval (Wilson score) of the success rate, in percent. Null for runs over the whole corpus",
+                "items": { "type": "number", "minimum": 0, "maximum": 100 },
+                "minItems": 2,
+                "maxItems": 2
+            },
+            "total_time": { "type": "number", "minimum": 0 },
+            "solc_compiles": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Number of contracts that compile with their own solc version, null if the run did not check with solc"
+            },
+            "solc_success_rate": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "maximum": 100,
+                "description": "Percentage of the contracts that compile with solc that were parsed successfully"
+            },
+            "corpus_fingerprint": {
+                "type": ["string", "null"],
+                "description": "Version of the analyzed dataset: release:<tag> when the corpus has a VERSION file or a git tag, else sha256:<hash> of the smart-contract-fiesta metadata index or of the archive, null when it cannot be computed"
+            },
+            "skipped_boilerplate": {
+                "type": ["object", "null"],
+                "additionalProperties": { "type": "integer", "minimum": 0 },
+                "description": "Number of contracts left out by --skip-boilerplate per kind of boilerplate (MinimalProxy, Copy), null if the run did not skip boilerplate"
+            },
+            "ignored": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "description": "Number of contracts left out by the ignore file (see omniscan ignore), null if the run had no ignore file"
+            },
+            "mislabeled": {
+                "type": ["array", "null"],
+                "items": {
+                    "type": "object",
+                    "required": ["bytecode_hash", "source_path", "reason", "repaired"],
+                    "properties": {
+                        "bytecode_hash": { "type": "string" },
+                        "source_path": { "type": "string", "description": "Path of the mislabeled file, relative to the corpus root" },
+                        "reason": { "enum": ["solidity_in_vy_file", "vyper_with_solidity_metadata"] },
+                        "repaired": { "type": "boolean", "description": "Whether --fix-corpus repaired the contract in place" }
+                    }
+                },
+                "description": "Contracts whose sources are a mislabeled .vy file: Solidity in a .vy file, or Vyper with the CompilerVersion of a Solidity compiler. null in summaries of reports"
+            },
+            "timings": {
+                "type": "object",
+                "propertyNames": { "enum": summary::TIMED_EXIT_TYPES },
+                "additionalProperties": {
+                    "type": "object",
+                    "required": ["count", "p50", "p90", "p99", "max"],
+                    "properties": {
+                        "count": { "type": "integer", "minimum": 1 },
+                        "p50": { "type": "number", "minimum": 0 },
+                        "p90": { "type": "number", "minimum": 0 },
+                        "p99": { "type": "number", "minimum": 0 },
+                        "max": { "type": "number", "minimum": 0 }
+                    }
+                },
+                "description": "Nearest-rank percentiles and maximum of the analysis times (secs) per exit type, for Success, Error and ThreadPanic. Timeouts are left out"
+            },
+            "score": {
+                "type": ["number", "null"],
+                "description": "Mean weight of the contracts of the run, with the weights of score_weights (see --score-weights)"
+            },
+            "score_weights": {
+                "type": ["object", "null"],
+                "propertyNames": { "enum": EXIT_TYPE_NAMES },
+                "additionalProperties": { "type": "number" },
+                "description": "Weight of each exit type in the score, exit types left out weigh 0"
+            },
+            "tags": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Tags of the run (see --tag)"
+            },
+            "labels": {
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+                "description": "Labels of the run as key/value pairs (see --label)"
+            }
+        }
+    })
+}
+
+pub fn print_schema(kind: SchemaKind) {
+    let schema = match kind {
+        SchemaKind::Results => results_schema(),
+        SchemaKind::Summary => summary_schema(),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
@@ -0,0 +1,93 @@
+use crate::{schema::EXIT_TYPE_NAMES, summary::RunSummary};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Weights of `--score-weights` when not given: a panic costs five clean errors
+pub const DEFAULT_SCORE_WEIGHTS: &str = "Success=1,Error=-1,NonInterpreted=-1,\
+     PerformanceTimeout=-2,Nondeterministic=-2,ThreadPanic=-5,HarnessError=0";
+
+/// Weight of each exit type in the score of a run, see `--score-weights`. Exit types left out
+/// weigh 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoreWeights(BTreeMap<String, f64>);
+
+impl FromStr for ScoreWeights {
+    type Err = String;
+
+    /// Parses comma separated `ExitType=weight` pairs, e.g. "Success=1,ThreadPanic=-5". Exit
+    /// types are matched case-insensitively.
+    fn from_str(weights: &str) -> Result<Self, Self::Err> {
+        let mut parsed = BTreeMap::new();
+        for pair in weights.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (name, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected ExitType=weight, got `{}`", pair.trim()))?;
+            let name = EXIT_TYPE_NAMES
+                .iter()
+                .find(|exit_type| exit_type.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| {
+                    format!(
+                        "unknown exit type `{}`, expected one of {}",
+                        name.trim(),
+                        EXIT_TYPE_NAMES.join(", ")
+                    )
+                })?;
+            let weight = weight
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|weight| weight.is_finite())
+                .ok_or_else(|| format!("invalid weight `{}` for {}", weight.trim(), name))?;
+            parsed.insert(name.to_string(), weight);
+        }
+        Ok(Self(parsed))
+    }
+}
+
+impl fmt::Display for ScoreWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs = self
+            .0
+            .iter()
+            .map(|(name, weight)| format!("{}={}", name, weight))
+            .collect::<Vec<String>>();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+impl ScoreWeights {
+    pub fn weights(&self) -> &BTreeMap<String, f64> {
+        &self.0
+    }
+
+    /// Score of a run: the mean weight of its contracts, so runs over corpora of different
+    /// sizes compare. 0 for a run without results.
+    pub fn score(&self, summary: &RunSummary) -> f64 {
+        if summary.total == 0 {
+            return 0.0;
+        }
+        let weighted = summary
+            .counts
+            .iter()
+            .map(|(name, count)| self.0.get(name).copied().unwrap_or(0.0) * *count as f64)
+            .sum::<f64>();
+        weighted / summary.total as f64
+    }
+
+    /// The score of a run as a line to print, with its change against the baseline run
+    pub fn format_score(&self, summary: &RunSummary, baseline: Option<&RunSummary>) -> String {
+        let score = self.score(summary);
+        match baseline {
+            Some(baseline) => {
+                let baseline_score = self.score(baseline);
+                format!(
+                    "Score: {:.3} (baseline {:.3}, {:+.3}) with weights {}",
+                    score,
+                    baseline_score,
+                    score - baseline_score,
+                    self
+                )
+            }
+            None => format!("Score: {:.3} with weights {}", score, self),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use clap::ValueEnum;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Where the analyzer processes keep their caches and configuration, see `--analyzer-home`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnalyzerHome {
+    /// A fresh HOME per contract: every analysis starts with cold caches and none of them can
+    /// affect another
+    Cold,
+    /// One scratch HOME shared by the analyzer processes of the run, so caches warm up across
+    /// contracts as they would on a developer's machine
+    Warm,
+    /// The HOME of the user running omniscan, caches persist across runs
+    Inherit,
+}
+
+/// Directories one contract's analyzer runs with: a working directory and temp dir of its own,
+/// and a scratch HOME unless it inherits the user's. Removed once dropped, the shared HOME of
+/// `warm` goes with the run's work directory.
+pub struct JobScratch {
+    dir: PathBuf,
+    cwd: PathBuf,
+    home: Option<PathBuf>,
+}
+
+impl JobScratch {
+    /// Creates the directories of a contract under `scratch_root`. The analyzer runs in
+    /// `run_dir` instead when its outputs are collected, see `--collect-outputs`.
+    pub fn create(
+        scratch_root: &Path,
+        bytecode_hash: &str,
+        analyzer_home: AnalyzerHome,
+        run_dir: Option<&Path>,
+    ) -> io::Result<Self> {
+        let dir = scratch_root.join(bytecode_hash);
+        let cwd = match run_dir {
+            Some(run_dir) => run_dir.to_path_buf(),
+            None => dir.join("cwd"),
+        };
+        let home = match analyzer_home {
+            AnalyzerHome::Cold => Some(dir.join("home")),
+            AnalyzerHome::Warm => Some(scratch_root.join("home")),
+            AnalyzerHome::Inherit => None,
+        };
+        fs::create_dir_all(&cwd)?;
+        if let Some(home) = &home {
+            fs::create_dir_all(home)?;
+        }
+        Ok(Self { dir, cwd, home })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Working directory of the analyzer
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Makes the command run in the contract's directories, with the XDG base directories
+    /// under its HOME so caches following them stay there too
+    pub fn apply(&self, command: &mut Command) {
+        command.current_dir(&self.cwd).env("TMPDIR", &self.cwd);
+        if let Some(home) = &self.home {
+            command
+                .env("HOME", home)
+                .env("XDG_CACHE_HOME", home.join(".cache"))
+                .env("XDG_CONFIG_HOME", home.join(".config"))
+                .env("XDG_DATA_HOME", home.join(".local/share"))
+                .env("XDG_STATE_HOME", home.join(".local/state"));
+        }
+    }
+}
+
+impl Drop for JobScratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
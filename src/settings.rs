@@ -0,0 +1,138 @@
+use crate::{FiestaMetadata, SourceType};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    /// The `--evm-version` option as a whole token, as solc names it when rejecting it: e.g.
+    /// "unrecognised option '--evm-version'", "Invalid option for --evm-version: cancun"
+    static ref EVM_VERSION_OPTION: Regex =
+        Regex::new(r#"(?:^|[\s'"`])--evm-version(?:$|[\s'"`:=])"#).unwrap();
+}
+
+/// Flags the analyzer takes the compiler settings of a contract's metadata with, see
+/// `--compiler-settings`. Settings without a flag can't be passed on, and are recorded as not
+/// honored by the analyzer.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct AnalyzerSettingsFlags {
+    /// Takes the optimizer runs, e.g. `--optimizer-runs`
+    pub optimizer_runs: Option<String>,
+    /// Takes the target EVM version, e.g. `--evm-version`
+    pub evm_version: Option<String>,
+}
+
+/// Whether the compiler settings travel with the sources, as in a standard-json input
+fn settings_in_sources(metadata: &FiestaMetadata) -> bool {
+    matches!(metadata.source_type, Some(SourceType::EtherscanMetadata(_)))
+}
+
+impl AnalyzerSettingsFlags {
+    /// The flags passing the settings of `metadata` to the analyzer, and the settings that
+    /// were left out for lack of a flag, e.g. "pyrometer:evm_version"
+    pub fn flags(&self, metadata: &FiestaMetadata) -> (Vec<String>, Vec<String>) {
+        let mut flags = Vec::new();
+        let mut unhonored = Vec::new();
+        if settings_in_sources(metadata) {
+            return (flags, unhonored);
+        }
+        if metadata.optimization_used {
+            match &self.optimizer_runs {
+                Some(flag) => flags.extend([flag.clone(), metadata.runs.to_string()]),
+                None => unhonored.push("pyrometer:optimizer".to_string()),
+            }
+        }
+        if let Some(evm_version) = metadata.target_evm_version() {
+            match &self.evm_version {
+                Some(flag) => flags.extend([flag.clone(), evm_version.to_string()]),
+                None => unhonored.push("pyrometer:evm_version".to_string()),
+            }
+        }
+        (flags, unhonored)
+    }
+}
+
+/// solc flags compiling the contract with the settings of its metadata. The EVM version is
+/// left out when `evm_version` is false, for a solc that rejects it.
+pub fn solc_flags(metadata: &FiestaMetadata, evm_version: bool) -> Vec<String> {
+    let mut flags = Vec::new();
+    if settings_in_sources(metadata) {
+        return flags;
+    }
+    if metadata.optimization_used {
+        flags.extend([
+            "--optimize".to_string(),
+            "--optimize-runs".to_string(),
+            metadata.runs.to_string(),
+        ]);
+    }
+    if let Some(target) = metadata.target_evm_version().filter(|_| evm_version) {
+        flags.extend(["--evm-version".to_string(), target.to_string()]);
+    }
+    flags
+}
+
+/// Whether solc failed on its `--evm-version` flag rather than on the sources: a version it
+/// does not know, or a solc older than the flag
+pub fn solc_rejected_evm_version(stderr: &str) -> bool {
+    EVM_VERSION_OPTION.is_match(stderr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(evm_version: Option<&str>) -> FiestaMetadata {
+        let mut metadata = FiestaMetadata::for_test("0x1");
+        metadata.evm_version = evm_version.map(str::to_string);
+        metadata.update_source_type(SourceType::SingleMain("contract Token {}".to_string()));
+        metadata
+    }
+
+    #[test]
+    fn flags_record_the_settings_without_a_flag() {
+        let analyzer = AnalyzerSettingsFlags {
+            optimizer_runs: Some("--optimizer-runs".to_string()),
+            evm_version: None,
+        };
+        assert_eq!(
+            analyzer.flags(&metadata(Some("london"))),
+            (
+                vec!["--optimizer-runs".to_string(), "200".to_string()],
+                vec!["pyrometer:evm_version".to_string()]
+            )
+        );
+        assert_eq!(
+            solc_flags(&metadata(Some("london")), true),
+            [
+                "--optimize",
+                "--optimize-runs",
+                "200",
+                "--evm-version",
+                "london"
+            ]
+        );
+        assert_eq!(
+            solc_flags(&metadata(Some("london")), false),
+            ["--optimize", "--optimize-runs", "200"]
+        );
+    }
+
+    #[test]
+    fn solc_rejected_evm_version_needs_the_whole_option() {
+        assert!(solc_rejected_evm_version(
+            "Error: unrecognised option '--evm-version'"
+        ));
+        assert!(solc_rejected_evm_version(
+            "Invalid option for --evm-version: cancun"
+        ));
+        assert!(solc_rejected_evm_version(
+            "--evm-version=cancun is not supported"
+        ));
+        assert!(!solc_rejected_evm_version(
+            "Warning: --evm-versions is deprecated"
+        ));
+        assert!(!solc_rejected_evm_version(
+            "Error: Expected ';' but got identifier"
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{correlation::normalize_compiler_version, settings, FiestaMetadata, SourceType};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    env,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Compilation taking longer than this is treated as unknown rather than a failure
+const SOLC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the pipes of an exited process are still read for, as processes it started may
+/// keep them open
+const PIPE_GRACE: Duration = Duration::from_secs(1);
+
+/// Where svm downloads solc releases from, `<platform>/list.json` lists them
+const SOLC_BINARIES_URL: &str = "https://binaries.soliditylang.org";
+
+/// soliditylang.org has no linux aarch64 builds, svm installs these instead
+const SOLC_LINUX_AARCH64_LIST_URL: &str =
+    "https://raw.githubusercontent.com/nikitastupin/solc/main/linux/aarch64/list.json";
+
+/// The release list does not answer within these, only the svm cache is checked
+const RELEASES_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const RELEASES_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The list of the solc releases svm installs on this machine, None on a platform svm has no
+/// builds for. macOS builds are universal binaries, so Apple silicon uses the amd64 list too.
+fn solc_releases_url() -> Option<String> {
+    let platform = match (env::consts::OS, env::consts::ARCH) {
+        ("linux", "x86_64") => "linux-amd64",
+        ("linux", "aarch64") => return Some(SOLC_LINUX_AARCH64_LIST_URL.to_string()),
+        ("macos", "x86_64" | "aarch64") => "macosx-amd64",
+        ("windows", "x86_64") => "windows-amd64",
+        _ => return None,
+    };
+    Some(format!("{}/{}/list.json", SOLC_BINARIES_URL, platform))
+}
+
+/// Long versions (e.g. "0.8.17+commit.8df45f5f") of the solc releases svm can install
+pub async fn solc_releases() -> Result<HashSet<String>, String> {
+    let url = solc_releases_url().ok_or(format!(
+        "svm has no solc builds for {}-{}",
+        env::consts::OS,
+        env::consts::ARCH
+    ))?;
+    let client = reqwest::Client::builder()
+        .connect_timeout(RELEASES_CONNECT_TIMEOUT)
+        .timeout(RELEASES_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let list: Value = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    let builds = list["builds"]
+        .as_array()
+        .ok_or(format!("{} lists no builds", url))?;
+    Ok(builds
+        .iter()
+        .filter_map(|build| {
+            // lists without longVersion name the binaries after it, e.g. solc-v0.8.17+commit...
+            build["longVersion"].as_str().or_else(|| {
+                build["path"]
+                    .as_str()
+                    .and_then(|path| path.rsplit_once("-v"))
+                    .map(|(_, long_version)| long_version)
+            })
+        })
+        .map(str::to_string)
+        .collect())
+}
+
+/// Whether the exact compiler of a metadata CompilerVersion can be obtained: it is one of the
+/// `releases` svm installs, commit included. Without the release list, a version in the svm
+/// cache counts as available. None when unknown: no release list and nothing cached, a
+/// contract without a compiler version, or a Vyper contract, which no solc release compiles.
+pub fn compiler_available(
+    compiler_version: &str,
+    releases: Option<&HashSet<String>>,
+) -> Option<bool> {
+    let version = compiler_version.trim();
+    if version.is_empty() {
+        return None;
+    }
+    if version.contains("vyper") {
+        return None;
+    }
+    match releases {
+        Some(releases) => Some(releases.contains(version.strip_prefix('v').unwrap_or(version))),
+        None => cached_solc(version).map(|_| true),
+    }
+}
+
+/// Directories svm installs solc binaries into, newer svm versions use the XDG data dir
+fn svm_dirs() -> Vec<PathBuf> {
+    let Ok(home) = env::var("HOME") else {
+        return Vec::new();
+    };
+    let home = PathBuf::from(home);
+    let data_dir = env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home.join(".local").join("share"));
+    vec![home.join(".svm"), data_dir.join("svm")]
+}
+
+/// The cached svm binary for a metadata CompilerVersion like "v0.8.17+commit.8df45f5f",
+/// None if that version was never installed with `svm install`
+pub fn cached_solc(compiler_version: &str) -> Option<PathBuf> {
+    let version = normalize_compiler_version(compiler_version);
+    let version = version.strip_prefix('v')?;
+    svm_dirs()
+        .into_iter()
+        .map(|svm_dir| svm_dir.join(version).join(format!("solc-{}", version)))
+        .find(|solc| solc.is_file())
+}
+
+/// Waits for a compiler, killing it after `timeout`, None then. stdout and stderr are drained
+/// on their own threads so large standard-json outputs cannot fill the pipe and stall it. A
+/// pipe still open `PIPE_GRACE` after the process exited is given up on with what was read
+/// from it, its reader is left to finish on its own.
+pub fn wait_with_timeout(mut child: Child, timeout: Duration) -> Option<(bool, String, String)> {
+    struct Drain {
+        contents: Arc<Mutex<Vec<u8>>>,
+        reader: thread::JoinHandle<()>,
+    }
+    fn drain(mut pipe: impl Read + Send + 'static) -> Drain {
+        let contents = Arc::new(Mutex::new(Vec::new()));
+        let thread_contents = contents.clone();
+        let reader = thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            loop {
+                match pipe.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => thread_contents.lock().unwrap().extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+        });
+        Drain { contents, reader }
+    }
+    let stdout_reader = child.stdout.take().map(drain);
+    let stderr_reader = child.stderr.take().map(drain);
+
+    let start_time = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if start_time.elapsed() < timeout => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+
+    let deadline = Instant::now() + PIPE_GRACE;
+    while Instant::now() < deadline
+        && [&stdout_reader, &stderr_reader].iter().any(|drain| {
+            drain
+                .as_ref()
+                .is_some_and(|drain| !drain.reader.is_finished())
+        })
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let collect = |drain: Option<Drain>| {
+        drain
+            .map(|drain| String::from_utf8_lossy(&drain.contents.lock().unwrap()).into_owned())
+            .unwrap_or_default()
+    };
+    let stdout = collect(stdout_reader);
+    let stderr = collect(stderr_reader);
+    status.map(|status| (status.success(), stdout, stderr))
+}
+
+/// Whether a standard-json output contains any error (warnings are fine)
+fn standard_json_has_errors(output: &str) -> Option<bool> {
+    let output: Value = serde_json::from_str(output).ok()?;
+    Some(
+        output["errors"]
+            .as_array()
+            .map(|errors| {
+                errors
+                    .iter()
+                    .any(|error| error["severity"].as_str() == Some("error"))
+            })
+            .unwrap_or(false),
+    )
+}
+
+/// Compiles the contract with the solc version from its metadata, as ground truth for whether
+/// it is valid solidity at all. None when the outcome is unknown: the matching solc is not in
+/// the svm cache, or solc could not be run or timed out. With `honor_settings`, solc also
+/// gets the optimizer settings and EVM version of the metadata (see `--compiler-settings`);
+/// an EVM version solc rejects is dropped, and returned as not honored.
+pub fn solc_compiles(
+    metadata: &FiestaMetadata,
+    honor_settings: bool,
+) -> (Option<bool>, Vec<String>) {
+    let compile = |evm_version: bool| {
+        let settings = match honor_settings {
+            true => settings::solc_flags(metadata, evm_version),
+            false => Vec::new(),
+        };
+        compile_with(metadata, &settings)
+    };
+    match compile(true) {
+        Some((false, stderr))
+            if honor_settings
+                && metadata.target_evm_version().is_some()
+                && settings::solc_rejected_evm_version(&stderr) =>
+        {
+            let compiles = compile(false).map(|(success, _stderr)| success);
+            (compiles, vec!["solc:evm_version".to_string()])
+        }
+        outcome => (outcome.map(|(success, _stderr)| success), Vec::new()),
+    }
+}
+
+/// Compiles the contract with solc and the extra `flags`, returning whether it compiled and
+/// what solc printed to stderr
+fn compile_with(metadata: &FiestaMetadata, flags: &[String]) -> Option<(bool, String)> {
+    let solc = cached_solc(&metadata.compiler_version)?;
+    let dir = Path::new(&metadata.abs_path_to_dir);
+
+    match metadata.source_type.as_ref()? {
+        SourceType::SingleMain(_) | SourceType::Multiple(_) => {
+            // without output flags solc only parses and type checks, which is all we need
+            let child = Command::new(solc)
+                .arg(metadata.entrypoint_file_name().ok()?)
+                .args(flags)
+                .current_dir(dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .ok()?;
+            wait_with_timeout(child, SOLC_TIMEOUT)
+                .map(|(success, _stdout, stderr)| (success, stderr))
+        }
+        SourceType::Project(project_source) => {
+            let child = Command::new(solc)
+                .args(project_source.remapping_lines())
+                .arg(&project_source.entrypoint)
+                .args(["--allow-paths", "/"])
+                .args(flags)
+                .current_dir(dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .ok()?;
+            wait_with_timeout(child, SOLC_TIMEOUT)
+                .map(|(success, _stdout, stderr)| (success, stderr))
+        }
+        SourceType::EtherscanMetadata(_) => {
+            let input = File::open(dir.join("contract.json")).ok()?;
+            let child = Command::new(solc)
+                .arg("--standard-json")
+                .stdin(input)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let (_success, stdout, _stderr) = wait_with_timeout(child, SOLC_TIMEOUT)?;
+            standard_json_has_errors(&stdout).map(|has_errors| (!has_errors, String::new()))
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{ExitType, ResultsRow};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Number of failures kept for the dashboard's failure feed
+const RECENT_FAILURES: usize = 50;
+
+/// Bearer token POST on /pause and /resume needs, without it only local clients may pause
+const STATUS_TOKEN_VAR: &str = "OMNISCAN_STATUS_TOKEN";
+
+/// A client that sends no request or reads no response for this long is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Static page served on `/`, polling `/status`
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// A contract currently being analyzed by a worker
+struct ActiveJob {
+    bytecode_hash: String,
+    started: Instant,
+}
+
+struct StatusState {
+    total: usize,
+    completed: usize,
+    successes: usize,
+    counts: BTreeMap<String, usize>,
+    /// One slot per concurrent process, `None` when the worker is idle
+    workers: Vec<Option<ActiveJob>>,
+    /// Most recent failures first
+    recent_failures: VecDeque<FailureSnapshot>,
+}
+
+/// Live progress of a run, shared between the tx loop, the rx loop and the status server
+pub struct RunStatus {
+    started: Instant,
+    state: Mutex<StatusState>,
+    /// Whether dispatching new contracts is paused, see `RunStatus::set_paused`
+    paused: watch::Sender<bool>,
+}
+
+#[derive(Serialize)]
+pub struct WorkerSnapshot {
+    pub worker: usize,
+    /// "busy" or "idle"
+    pub state: &'static str,
+    pub bytecode_hash: Option<String>,
+    /// Seconds spent on the current contract
+    pub elapsed: Option<f64>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FailureSnapshot {
+    pub bytecode_hash: String,
+    pub result: String,
+    pub source_path: String,
+    /// Seconds since the run started when the failure was recorded
+    pub at: f64,
+}
+
+/// Point-in-time view of a run, as served on `/status`
+#[derive(Serialize)]
+pub struct StatusSnapshot {
+    pub total: usize,
+    /// Contracts not yet handed to a worker
+    pub queued: usize,
+    pub in_flight: usize,
+    pub completed: usize,
+    /// Number of completed contracts per result type
+    pub counts: BTreeMap<String, usize>,
+    /// Successes over completed contracts, 0 when nothing completed yet
+    pub success_rate: f64,
+    /// Seconds since the run started
+    pub elapsed: f64,
+    pub workers: Vec<WorkerSnapshot>,
+    pub recent_failures: Vec<FailureSnapshot>,
+    /// No new contract is started while the run is paused
+    pub paused: bool,
+}
+
+impl RunStatus {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            state: Mutex::new(StatusState {
+                total: 0,
+                completed: 0,
+                successes: 0,
+                counts: BTreeMap::new(),
+                workers: (0..workers).map(|_| None).collect(),
+                recent_failures: VecDeque::with_capacity(RECENT_FAILURES),
+            }),
+            paused: watch::Sender::new(false),
+        }
+    }
+
+    /// Pauses or resumes dispatching contracts. Jobs already running finish while the run is
+    /// paused. Returns false when the run already was in that state.
+    pub fn set_paused(&self, paused: bool) -> bool {
+        let changed = self.paused.send_if_modified(|current| {
+            let changed = *current != paused;
+            *current = paused;
+            changed
+        });
+        if changed && paused {
+            println!("Paused: no new contract is started until the run is resumed");
+        } else if changed {
+            println!("Resumed");
+        }
+        changed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Returns once the run is not paused
+    pub async fn wait_while_paused(&self) {
+        let mut paused = self.paused.subscribe();
+        // the sender lives as long as self, so waiting can't fail
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+
+    /// Sets the number of contracts the run will analyze, once they are discovered
+    pub fn set_total(&self, total: usize) {
+        self.state.lock().unwrap().total = total;
+    }
+
+    /// Marks a contract as picked up, returning the worker slot it occupies
+    pub fn start_job(&self, bytecode_hash: &str) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let job = ActiveJob {
+            bytecode_hash: bytecode_hash.to_string(),
+            started: Instant::now(),
+        };
+        match state.workers.iter().position(|worker| worker.is_none()) {
+            Some(slot) => {
+                state.workers[slot] = Some(job);
+                slot
+            }
+            None => {
+                state.workers.push(Some(job));
+                state.workers.len() - 1
+            }
+        }
+    }
+
+    pub fn finish_job(&self, slot: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(worker) = state.workers.get_mut(slot) {
+            *worker = None;
+        }
+    }
+
+    /// Frees the worker slot of a job that panicked before finishing
+    pub fn abandon_job(&self, bytecode_hash: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(worker) = state.workers.iter_mut().find(|worker| {
+            worker
+                .as_ref()
+                .is_some_and(|job| job.bytecode_hash == bytecode_hash)
+        }) {
+            *worker = None;
+        }
+    }
+
+    /// Records a result written by the rx loop
+    pub fn record_result(&self, row: &ResultsRow) {
+        let mut state = self.state.lock().unwrap();
+        state.completed += 1;
+        if matches!(row.result, ExitType::Success) {
+            state.successes += 1;
+        } else {
+            if state.recent_failures.len() == RECENT_FAILURES {
+                state.recent_failures.pop_back();
+            }
+            state.recent_failures.push_front(FailureSnapshot {
+                bytecode_hash: row.bytecode_hash.clone(),
+                result: row.result.to_string(),
+                source_path: row.source_path.clone(),
+                at: self.started.elapsed().as_secs_f64(),
+            });
+        }
+        *state
+            .counts
+            .entry(row.result.name().to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn snapshot(&self) -> StatusSnapshot {
+        let state = self.state.lock().unwrap();
+        let workers = state
+            .workers
+            .iter()
+            .enumerate()
+            .map(|(worker, job)| match job {
+                Some(job) => WorkerSnapshot {
+                    worker,
+                    state: "busy",
+                    bytecode_hash: Some(job.bytecode_hash.clone()),
+                    elapsed: Some(job.started.elapsed().as_secs_f64()),
+                },
+                None => WorkerSnapshot {
+                    worker,
+                    state: "idle",
+                    bytecode_hash: None,
+                    elapsed: None,
+                },
+            })
+            .collect::<Vec<WorkerSnapshot>>();
+        let in_flight = workers
+            .iter()
+            .filter(|worker| worker.state == "busy")
+            .count();
+
+        StatusSnapshot {
+            total: state.total,
+            queued: state.total.saturating_sub(state.completed + in_flight),
+            in_flight,
+            completed: state.completed,
+            counts: state.counts.clone(),
+            success_rate: if state.completed == 0 {
+                0.0
+            } else {
+                state.successes as f64 / state.completed as f64
+            },
+            elapsed: self.started.elapsed().as_secs_f64(),
+            workers,
+            recent_failures: state.recent_failures.iter().cloned().collect(),
+            paused: self.is_paused(),
+        }
+    }
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    // the observer going away mid-response is not our problem
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Whether the client may pause and resume the run: with the bearer token of
+/// `OMNISCAN_STATUS_TOKEN` when it is set, else only from the machine itself
+fn may_pause(stream: &TcpStream, authorization: Option<&str>) -> bool {
+    match std::env::var(STATUS_TOKEN_VAR) {
+        Ok(token) => authorization
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .is_some_and(|given| given.trim() == token),
+        Err(_) => stream.peer_addr().is_ok_and(|peer| peer.ip().is_loopback()),
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, status: &RunStatus) {
+    if stream.set_read_timeout(Some(CLIENT_TIMEOUT)).is_err()
+        || stream.set_write_timeout(Some(CLIENT_TIMEOUT)).is_err()
+    {
+        return;
+    }
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if header.trim().is_empty() => break,
+            Ok(_) => {
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("authorization") {
+                        authorization = Some(value.trim().to_string());
+                    }
+                }
+            }
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+
+    match (method, path) {
+        (Some("GET"), Some("/")) => {
+            write_response(&mut stream, "200 OK", "text/html", DASHBOARD_HTML)
+        }
+        (Some("GET"), Some("/status")) => {
+            let body = serde_json::to_string_pretty(&status.snapshot()).unwrap();
+            write_response(&mut stream, "200 OK", "application/json", &body);
+        }
+        (Some("GET"), _) => write_response(&mut stream, "404 Not Found", "text/plain", "not found"),
+        (Some("POST"), Some("/pause" | "/resume"))
+            if !may_pause(&stream, authorization.as_deref()) =>
+        {
+            write_response(
+                &mut stream,
+                "403 Forbidden",
+                "text/plain",
+                "pausing needs the OMNISCAN_STATUS_TOKEN bearer token, or a local client\n",
+            )
+        }
+        (Some("POST"), Some(path @ ("/pause" | "/resume"))) => {
+            let body = match status.set_paused(path == "/pause") {
+                true => format!("{}d\n", &path[1..]),
+                false => format!("already {}d\n", &path[1..]),
+            };
+            write_response(&mut stream, "200 OK", "text/plain", &body);
+        }
+        (Some("POST"), _) => {
+            write_response(&mut stream, "404 Not Found", "text/plain", "not found")
+        }
+        _ => write_response(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET, and POST on /pause and /resume",
+        ),
+    }
+}
+
+/// Serves the `/status` endpoint and the dashboard on `/` on `addr` from a background thread
+/// for the rest of the process' life, each connection on its own thread so a client that
+/// stalls holds up no other. POST on `/pause` and `/resume` pauses and resumes the run, see
+/// `may_pause` for who may.
+pub fn serve_status(addr: &str, status: Arc<RunStatus>) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).map_err(|e| e.to_string())?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let status = status.clone();
+            std::thread::spawn(move || handle_connection(stream, &status));
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use crate::{diff::RunDiff, mislabel, tags::RunTags, ExitType, ResultsRow, RESULTS_SCHEMA_VERSION};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Exit types whose times are summarized, each on its own: panics usually happen fast while
+/// successes make up the tail. Timeouts are left out, their time is the timeout.
+pub const TIMED_EXIT_TYPES: [&str; 3] = ["Success", "Error", "ThreadPanic"];
+
+/// Distribution of the analysis times (secs) of the contracts of one exit type
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TimingStats {
+    pub count: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Nearest-rank percentile of sorted times
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Timing statistics per exit type of `TIMED_EXIT_TYPES`, for those with results. The
+/// contracts of the warmup are left out, see `--warmup`.
+pub fn timing_stats(rows: &[ResultsRow]) -> BTreeMap<String, TimingStats> {
+    let mut times: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for row in rows.iter().filter(|row| row.warmup != Some(true)) {
+        let name = row.result.name();
+        if TIMED_EXIT_TYPES.contains(&name) {
+            times.entry(name.to_string()).or_default().push(row.time);
+        }
+    }
+    times
+        .into_iter()
+        .map(|(name, mut times)| {
+            times.sort_by(f64::total_cmp);
+            let stats = TimingStats {
+                count: times.len(),
+                p50: percentile(&times, 50.0),
+                p90: percentile(&times, 90.0),
+                p99: percentile(&times, 99.0),
+                max: times[times.len() - 1],
+            };
+            (name, stats)
+        })
+        .collect()
+}
+
+/// Aggregate view of a run, written as JSON with `--summary`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunSummary {
+    pub schema_version: u32,
+    /// Number of contracts with a recorded result
+    pub total: usize,
+    /// Number of contracts per exit type name (Success, Error, ...)
+    pub counts: BTreeMap<String, usize>,
+    /// Percentage of contracts that were parsed successfully
+    pub success_rate: f64,
+    /// 95% confidence interval of the success rate (percentages), see `wilson_interval`. None
+    /// for runs over the whole corpus, whose rate is exact, and in summaries that predate it
+    #[serde(default)]
+    pub success_rate_interval: Option<(f64, f64)>,
+    /// Sum of the per contract analysis times (secs), without the warmup (see `--warmup`)
+    pub total_time: f64,
+    /// Number of contracts that compile with their own solc version, None if the run did not
+    /// check with solc
+    #[serde(default)]
+    pub solc_compiles: Option<usize>,
+    /// Percentage of the contracts that compile with solc that were parsed successfully
+    #[serde(default)]
+    pub solc_success_rate: Option<f64>,
+    /// Version of the analyzed dataset, see `fingerprint::corpus_fingerprint`
+    #[serde(default)]
+    pub corpus_fingerprint: Option<String>,
+    /// Number of contracts left out per kind of boilerplate (MinimalProxy, Copy), None if the
+    /// run did not skip boilerplate
+    #[serde(default)]
+    pub skipped_boilerplate: Option<BTreeMap<String, usize>>,
+    /// Number of contracts left out by the ignore file, None if the run had no ignore file
+    #[serde(default)]
+    pub ignored: Option<usize>,
+    /// Contracts whose sources are a mislabeled .vy file, with the reason and whether
+    /// `--fix-corpus` repaired them. None in summaries of reports and of older runs
+    #[serde(default)]
+    pub mislabeled: Option<Vec<mislabel::MislabeledContract>>,
+    /// Analysis time percentiles per exit type, see `TIMED_EXIT_TYPES`. Empty in summaries
+    /// that predate them
+    #[serde(default)]
+    pub timings: BTreeMap<String, TimingStats>,
+    /// Mean weight of the contracts, see `--score-weights`
+    #[serde(default)]
+    pub score: Option<f64>,
+    /// Weight of each exit type the score was computed with
+    #[serde(default)]
+    pub score_weights: Option<BTreeMap<String, f64>>,
+    /// See `--tag` and `--label`
+    #[serde(flatten)]
+    pub run_tags: RunTags,
+}
+
+/// z-score of a two-sided 95% confidence interval
+const Z_95: f64 = 1.959964;
+
+/// Wilson score interval (percentages) of a success rate measured on `total` contracts drawn at
+/// random: the success rate of the whole corpus lies in it with 95% confidence. Runs over the
+/// first `-n` contracts are not random samples, the interval is then only a rough guide to
+/// how much the rate would move on other contracts. Unlike the normal approximation the
+/// interval stays within 0-100% for small samples and rates close to the ends.
+pub fn wilson_interval(successes: usize, total: usize) -> (f64, f64) {
+    if total == 0 {
+        return (0.0, 100.0);
+    }
+    let n = total as f64;
+    let p = successes as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let half_width = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+    (
+        (center - half_width).max(0.0) * 100.0,
+        (center + half_width).min(1.0) * 100.0,
+    )
+}
+
+/// Two-sided p-value of McNemar's exact test on the contracts of two runs over the same
+/// corpus whose result changed: without a real change, a contract is as likely to regress as
+/// to be fixed, so the regressions follow a binomial distribution with p = 1/2. Paired runs
+/// call for it rather than comparing the confidence intervals of the two rates.
+pub fn mcnemar_p_value(regressions: usize, fixes: usize) -> f64 {
+    let changed = regressions + fixes;
+    if changed == 0 {
+        return 1.0;
+    }
+    // sum of the binomial probabilities of the tail, in log space so 2^-n does not underflow
+    let log_half_pow = changed as f64 * 0.5f64.ln();
+    let mut log_choose = 0.0;
+    let mut tail = 0.0;
+    for k in 0..=regressions.min(fixes) {
+        if k > 0 {
+            log_choose += ((changed - k + 1) as f64).ln() - (k as f64).ln();
+        }
+        tail += (log_choose + log_half_pow).exp();
+    }
+    (2.0 * tail).min(1.0)
+}
+
+/// Success rate normalized against the contracts that are valid solidity, as the number of
+/// contracts that compile with solc and the percentage of them parsed successfully
+pub fn solc_success_rate(rows: &[ResultsRow]) -> Option<(usize, f64)> {
+    let compiling = rows
+        .iter()
+        .filter(|row| row.solc_compiles == Some(true))
+        .collect::<Vec<&ResultsRow>>();
+    if !rows.iter().any(|row| row.solc_compiles.is_some()) {
+        return None;
+    }
+    let success_count = compiling
+        .iter()
+        .filter(|row| matches!(row.result, ExitType::Success))
+        .count();
+    let success_rate = if compiling.is_empty() {
+        0.0
+    } else {
+        success_count as f64 / compiling.len() as f64 * 100.0
+    };
+    Some((compiling.len(), success_rate))
+}
+
+impl RunSummary {
+    pub fn from_rows(rows: &[ResultsRow]) -> Self {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for row in rows {
+            *counts.entry(row.result.name().to_string()).or_insert(0) += 1;
+        }
+
+        let success_count = rows
+            .iter()
+            .filter(|row| matches!(row.result, ExitType::Success))
+            .count();
+        let success_rate = if rows.is_empty() {
+            0.0
+        } else {
+            success_count as f64 / rows.len() as f64 * 100.0
+        };
+
+        let solc_success_rate = solc_success_rate(rows);
+
+        Self {
+            schema_version: RESULTS_SCHEMA_VERSION,
+            total: rows.len(),
+            counts,
+            success_rate,
+            success_rate_interval: Some(wilson_interval(success_count, rows.len())),
+            total_time: rows
+                .iter()
+                .filter(|row| row.warmup != Some(true))
+                .map(|row| row.time)
+                .sum(),
+            solc_compiles: solc_success_rate.map(|(compiling, _)| compiling),
+            solc_success_rate: solc_success_rate.map(|(_, success_rate)| success_rate),
+            corpus_fingerprint: None,
+            skipped_boilerplate: None,
+            ignored: None,
+            mislabeled: None,
+            timings: timing_stats(rows),
+            score: None,
+            score_weights: None,
+            run_tags: RunTags::default(),
+        }
+    }
+
+    /// Drops the confidence interval of a run over the whole corpus: its success rate is the
+    /// rate of the corpus, not an estimate of it
+    pub fn with_full_corpus(self, full_corpus: bool) -> Self {
+        match full_corpus {
+            true => Self {
+                success_rate_interval: None,
+                ..self
+            },
+            false => self,
+        }
+    }
+
+    /// The success rate with its confidence interval, e.g. "80.00% (95% CI 37.55% to 96.38%)"
+    pub fn format_success_rate(&self) -> String {
+        match self.success_rate_interval {
+            Some((low, high)) => format!(
+                "{:.2}% (95% CI {:.2}% to {:.2}%)",
+                self.success_rate, low, high
+            ),
+            None => format!("{:.2}%", self.success_rate),
+        }
+    }
+
+    /// The success rate against the one of an older run over the same contracts, noting when
+    /// its regressions and fixes could be chance, see `mcnemar_p_value`
+    pub fn format_success_rate_change(&self, old: &RunSummary, diff: &RunDiff) -> String {
+        let mut line = format!(
+            "Success rate: {} -> {}, {:+.2} points",
+            old.format_success_rate(),
+            self.format_success_rate(),
+            self.success_rate - old.success_rate
+        );
+        let (regressions, fixes) = (diff.regressions.len(), diff.fixes.len());
+        if regressions + fixes > 0 {
+            let p_value = mcnemar_p_value(regressions, fixes);
+            if p_value >= 0.05 {
+                line.push_str(&format!(
+                    ", within noise ({} regressions against {} fixes, McNemar p = {:.2})",
+                    regressions, fixes, p_value
+                ));
+            }
+        }
+        line
+    }
+
+    pub fn write(&self, path: &Path) {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mcnemar_p_value_is_the_two_sided_binomial_tail() {
+        assert_eq!(mcnemar_p_value(0, 0), 1.0);
+        assert_eq!(mcnemar_p_value(3, 3), 1.0);
+        assert!((mcnemar_p_value(5, 0) - 0.0625).abs() < 1e-12);
+        assert!((mcnemar_p_value(2, 10) - 2.0 * 79.0 / 4096.0).abs() < 1e-12);
+        assert_eq!(mcnemar_p_value(10, 2), mcnemar_p_value(2, 10));
+        // 2^-2000 underflows, the tail summed in log space does not
+        let p_value = mcnemar_p_value(1100, 900);
+        assert!(p_value > 0.0 && p_value < 1e-4);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{diff::RunDiff, summary::RunSummary};
+use comfy_table::{presets::UTF8_FULL_CONDENSED, Cell, CellAlignment, Color, Row, Table};
+use std::collections::BTreeSet;
+
+/// Whether the end of run table is colored: not with `--no-color`, nor when the NO_COLOR
+/// environment variable is set to anything (https://no-color.org). Colors are also left out
+/// when stdout is not a terminal.
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+fn count_cell(count: usize) -> Cell {
+    Cell::new(count).set_alignment(CellAlignment::Right)
+}
+
+/// Change of a count against the baseline, red when the run got worse and green when it
+/// improved. More successes are an improvement, more of any failure a regression.
+fn change_cell(name: &str, count: usize, baseline_count: usize) -> Cell {
+    let change = count as i64 - baseline_count as i64;
+    if change == 0 {
+        return Cell::new("0").set_alignment(CellAlignment::Right);
+    }
+    let cell = Cell::new(format!("{:+}", change)).set_alignment(CellAlignment::Right);
+    if (change > 0) == (name == "Success") {
+        cell.fg(Color::Green)
+    } else {
+        cell.fg(Color::Red)
+    }
+}
+
+fn seconds_cell(seconds: f64) -> Cell {
+    Cell::new(format!("{:.3}", seconds)).set_alignment(CellAlignment::Right)
+}
+
+/// Renders the analysis time percentiles of the summary as a table with a row per exit type,
+/// Success first. None when no contract succeeded or failed before its timeout.
+pub fn format_timings_table(summary: &RunSummary, color: bool) -> Option<String> {
+    if summary.timings.is_empty() {
+        return None;
+    }
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL_CONDENSED);
+    if !color {
+        table.force_no_tty();
+    }
+    table.set_header(vec!["Time (sec)", "Count", "p50", "p90", "p99", "Max"]);
+    let (successes, failures): (Vec<_>, Vec<_>) = summary
+        .timings
+        .iter()
+        .partition(|(name, _)| *name == "Success");
+    for (name, stats) in successes.into_iter().chain(failures) {
+        table.add_row(vec![
+            Cell::new(name),
+            count_cell(stats.count),
+            seconds_cell(stats.p50),
+            seconds_cell(stats.p90),
+            seconds_cell(stats.p99),
+            seconds_cell(stats.max),
+        ]);
+    }
+    Some(table.to_string())
+}
+
+/// Renders the end of run summary as a table with a row per result type, Success first and in
+/// green. With a baseline, its counts and the change of each count are added, as well as the
+/// number of regressions.
+pub fn format_summary_table(
+    summary: &RunSummary,
+    baseline: Option<(&RunSummary, &RunDiff)>,
+    color: bool,
+) -> String {
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL_CONDENSED);
+    if !color {
+        table.force_no_tty();
+    }
+
+    let mut header = vec!["Result", "Count", "Percent"];
+    if baseline.is_some() {
+        header.extend(["Baseline", "Change"]);
+    }
+    table.set_header(header);
+
+    // results that disappeared since the baseline are listed too, with a count of 0
+    let mut names = summary.counts.keys().collect::<BTreeSet<&String>>();
+    if let Some((baseline_summary, _)) = baseline {
+        names.extend(baseline_summary.counts.keys());
+    }
+    let (successes, failures): (Vec<&String>, Vec<&String>) =
+        names.into_iter().partition(|name| *name == "Success");
+
+    for name in successes.into_iter().chain(failures) {
+        let count = summary.counts.get(name).copied().unwrap_or(0);
+        let percent = if summary.total == 0 {
+            0.0
+        } else {
+            count as f64 / summary.total as f64 * 100.0
+        };
+        let name_cell = Cell::new(name);
+        let name_cell = if name == "Success" {
+            name_cell.fg(Color::Green)
+        } else {
+            name_cell
+        };
+        let mut row = Row::from(vec![
+            name_cell,
+            count_cell(count),
+            Cell::new(format!("{:.2}", percent)).set_alignment(CellAlignment::Right),
+        ]);
+        if let Some((baseline_summary, _)) = baseline {
+            let baseline_count = baseline_summary.counts.get(name).copied().unwrap_or(0);
+            row.add_cell(count_cell(baseline_count));
+            row.add_cell(change_cell(name, count, baseline_count));
+        }
+        table.add_row(row);
+    }
+
+    let mut total_row = Row::from(vec![
+        Cell::new("Total"),
+        count_cell(summary.total),
+        Cell::new("100.00").set_alignment(CellAlignment::Right),
+    ]);
+    if let Some((baseline_summary, diff)) = baseline {
+        total_row.add_cell(count_cell(baseline_summary.total));
+        total_row.add_cell(Cell::new(""));
+        table.add_row(total_row);
+
+        let regressions = Cell::new(diff.regressions.len()).set_alignment(CellAlignment::Right);
+        let regressions = if diff.regressions.is_empty() {
+            regressions
+        } else {
+            regressions.fg(Color::Red)
+        };
+        table.add_row(vec![
+            Cell::new("Regressions"),
+            regressions,
+            Cell::new(""),
+            Cell::new(""),
+            Cell::new(""),
+        ]);
+    } else {
+        table.add_row(total_row);
+    }
+
+    table.to_string()
+}
@@ -0,0 +1,122 @@
+use crate::summary::RunSummary;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Experiment a run belongs to, see `--tag` and `--label`. Recorded in the manifest and the
+/// summary of the run
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunTags {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl RunTags {
+    pub fn new(tags: &[String], labels: &[(String, String)]) -> Self {
+        Self {
+            tags: tags.to_vec(),
+            labels: labels.iter().cloned().collect(),
+        }
+    }
+
+    /// Whether the run has every tag of `tags` and every label of `labels`
+    pub fn matches(&self, tags: &[String], labels: &[(String, String)]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+            && labels
+                .iter()
+                .all(|(key, value)| self.labels.get(key) == Some(value))
+    }
+
+    /// The tags then the labels as `key=value`, space separated
+    pub fn describe(&self) -> String {
+        self.tags
+            .iter()
+            .cloned()
+            .chain(
+                self.labels
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            )
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+fn read_summary(path: &Path) -> Result<RunSummary, String> {
+    let contents = fs::read(path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&contents).map_err(|e| e.to_string())
+}
+
+/// Lists the runs of the summaries (see `--summary`) that have the tags and labels, one per
+/// line, or with `group_by` one line per value of that label: the number of runs, their mean
+/// success rate and mean score
+pub fn run_runs(
+    summary_paths: &[String],
+    tags: &[String],
+    labels: &[(String, String)],
+    group_by: Option<&str>,
+) {
+    let mut runs = Vec::new();
+    for path in summary_paths {
+        match read_summary(Path::new(path)) {
+            Ok(summary) if summary.run_tags.matches(tags, labels) => runs.push((path, summary)),
+            Ok(_) => {}
+            Err(e) => eprintln!("Skipping {}: {}", path, e),
+        }
+    }
+    if runs.is_empty() {
+        println!("No run has these tags and labels");
+        return;
+    }
+    let Some(label) = group_by else {
+        let width = runs.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+        for (path, summary) in &runs {
+            println!(
+                "{:<width$}  {:>8} contracts  {:>7.2}% success  {}",
+                path,
+                summary.total,
+                summary.success_rate,
+                summary.run_tags.describe(),
+                width = width
+            );
+        }
+        return;
+    };
+    let mut groups: BTreeMap<String, Vec<&RunSummary>> = BTreeMap::new();
+    for (_, summary) in &runs {
+        let value = summary
+            .run_tags
+            .labels
+            .get(label)
+            .cloned()
+            .unwrap_or_else(|| "(none)".to_string());
+        groups.entry(value).or_default().push(summary);
+    }
+    let width = groups.keys().map(|value| value.len()).max().unwrap_or(0) + label.len() + 1;
+    for (value, summaries) in &groups {
+        let mean = |values: Vec<f64>| values.iter().sum::<f64>() / values.len() as f64;
+        let scores = summaries
+            .iter()
+            .filter_map(|summary| summary.score)
+            .collect::<Vec<f64>>();
+        let score = if scores.is_empty() {
+            String::new()
+        } else {
+            format!("  mean score {:.3}", mean(scores))
+        };
+        println!(
+            "{:<width$}  {:>3} runs  {:>7.2}% mean success{}",
+            format!("{}={}", label, value),
+            summaries.len(),
+            mean(
+                summaries
+                    .iter()
+                    .map(|summary| summary.success_rate)
+                    .collect()
+            ),
+            score,
+            width = width
+        );
+    }
+}
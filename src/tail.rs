@@ -0,0 +1,294 @@
+use crate::{
+    results::{ResultsLineParser, ResultsRow},
+    rotation, ExitType,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+/// Longest message shown per failure, so a failure fits on a line
+const MESSAGE_WIDTH: usize = 100;
+
+/// Statistics over the results read so far
+struct TailStats {
+    total: usize,
+    successes: usize,
+    /// Whether each of the latest results is a success, and when it completed if known
+    recent: VecDeque<(bool, Option<DateTime<Utc>>)>,
+    window: usize,
+    /// Most recent failures last
+    failures: VecDeque<ResultsRow>,
+    max_failures: usize,
+}
+
+impl TailStats {
+    fn new(window: usize, max_failures: usize) -> Self {
+        Self {
+            total: 0,
+            successes: 0,
+            recent: VecDeque::with_capacity(window),
+            window: window.max(1),
+            failures: VecDeque::with_capacity(max_failures),
+            max_failures,
+        }
+    }
+
+    fn record(&mut self, row: ResultsRow) {
+        let success = matches!(row.result, ExitType::Success);
+        self.total += 1;
+        self.successes += success as usize;
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        // rows written by an older omniscan have no start time
+        let completed_at = row.started_at.map(|started_at| {
+            started_at + ChronoDuration::milliseconds((row.time * 1000.0) as i64)
+        });
+        self.recent.push_back((success, completed_at));
+        if !success && self.max_failures > 0 {
+            if self.failures.len() == self.max_failures {
+                self.failures.pop_front();
+            }
+            self.failures.push_back(row);
+        }
+    }
+
+    /// Contracts completed per second over the latest results, None without start times
+    fn throughput(&self) -> Option<f64> {
+        let completed = self
+            .recent
+            .iter()
+            .filter_map(|(_success, completed_at)| *completed_at)
+            .collect::<Vec<DateTime<Utc>>>();
+        let first = completed.iter().min()?;
+        let last = completed.iter().max()?;
+        let span = (*last - *first).num_milliseconds() as f64 / 1000.0;
+        (span > 0.0).then(|| (completed.len() - 1) as f64 / span)
+    }
+
+    fn print(&self, path: &Path) {
+        let rate = |successes: usize, total: usize| match total {
+            0 => 0.0,
+            total => successes as f64 / total as f64 * 100.0,
+        };
+        let recent_successes = self.recent.iter().filter(|(success, _)| *success).count();
+        println!(
+            "{}: {} results, {:.2}% success, last {}: {:.2}%, {}",
+            path.display(),
+            self.total,
+            rate(self.successes, self.total),
+            self.recent.len(),
+            rate(recent_successes, self.recent.len()),
+            match self.throughput() {
+                Some(throughput) => format!("{:.2} contracts/s", throughput),
+                None => "throughput unknown".to_string(),
+            }
+        );
+        if self.failures.is_empty() {
+            return;
+        }
+        println!("Most recent failures:");
+        for row in self.failures.iter().rev() {
+            let mut message = row.message().unwrap_or_default().replace('\n', " ");
+            if message.chars().count() > MESSAGE_WIDTH {
+                message = message.chars().take(MESSAGE_WIDTH).collect::<String>() + "...";
+            }
+            println!(
+                "  {} {} {} {}",
+                row.bytecode_hash,
+                row.result.name(),
+                row.source_path,
+                message
+            );
+        }
+    }
+}
+
+/// Reads the lines appended to a results file since the last read
+struct ResultsFollower {
+    offset: u64,
+    /// Bytes of the trailing line still being written, which may end in the middle of a
+    /// UTF-8 character
+    partial: Vec<u8>,
+    parser: Option<ResultsLineParser>,
+    line_number: usize,
+}
+
+impl ResultsFollower {
+    fn new() -> Self {
+        Self {
+            offset: 0,
+            partial: Vec::new(),
+            parser: None,
+            line_number: 0,
+        }
+    }
+
+    /// The rows of the complete lines appended since the last call, a line being written is
+    /// kept for the next call. Lines that can't be parsed are reported and skipped. Returns
+    /// None when the file was truncated or replaced, it is then read again from the start.
+    fn read_new_rows(&mut self, path: &Path) -> Result<Option<Vec<ResultsRow>>, String> {
+        let mut file = File::open(path).map_err(|e| e.to_string())?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        if len < self.offset {
+            *self = Self::new();
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(self.offset))
+            .map_err(|e| e.to_string())?;
+        let appended = file
+            .read_to_end(&mut self.partial)
+            .map_err(|e| e.to_string())?;
+        self.offset += appended as u64;
+
+        // decoded up to the last newline only, a character split across reads stays whole
+        let Some(last_newline) = self.partial.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(Some(Vec::new()));
+        };
+        let complete = String::from_utf8_lossy(&self.partial[..last_newline]).into_owned();
+        self.partial.drain(..=last_newline);
+
+        let mut rows = Vec::new();
+        for line in complete.lines() {
+            self.line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parser = match &self.parser {
+                Some(parser) => parser,
+                None => {
+                    let parser = ResultsLineParser::for_first_line(line).unwrap_or_else(|e| {
+                        eprintln!("Could not follow {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    });
+                    let is_header = matches!(parser, ResultsLineParser::Csv(_));
+                    self.parser = Some(parser);
+                    if is_header {
+                        continue;
+                    }
+                    self.parser.as_ref().unwrap()
+                }
+            };
+            match parser.parse_line(line, self.line_number) {
+                Ok(row) => rows.push(row),
+                Err(e) => eprintln!("Skipping a result, {}", e),
+            }
+        }
+        Ok(Some(rows))
+    }
+}
+
+/// Follows a results file written by another omniscan, printing the success rate, the
+/// throughput and the latest failures whenever results are added. Rotated results are followed
+/// part after part. With `once`, prints them for the file as it is and returns.
+pub fn run_tail(path: &Path, window: usize, max_failures: usize, interval: f64, once: bool) {
+    if !(interval > 0.0 && interval.is_finite()) {
+        eprintln!("--interval must be a positive number of secs");
+        std::process::exit(1);
+    }
+    let mut follower = ResultsFollower::new();
+    let mut stats = TailStats::new(window, max_failures);
+    let mut waiting_printed = false;
+    // index of the part being read when the results are rotated
+    let mut part = 0;
+    loop {
+        let (current_path, more_parts) = match rotation::part_paths(path) {
+            Some(Ok(parts)) if !parts.is_empty() => {
+                if part >= parts.len() {
+                    println!(
+                        "The parts of {} were replaced, reading them from the start",
+                        path.display()
+                    );
+                    part = 0;
+                    follower = ResultsFollower::new();
+                    stats = TailStats::new(window, max_failures);
+                }
+                (parts[part].clone(), part + 1 < parts.len())
+            }
+            _ => (path.to_path_buf(), false),
+        };
+        match follower.read_new_rows(&current_path) {
+            Ok(Some(rows)) => {
+                let read_any = !rows.is_empty();
+                rows.into_iter().for_each(|row| stats.record(row));
+                // a part is complete once the next one is started
+                if more_parts {
+                    part += 1;
+                    follower = ResultsFollower::new();
+                    continue;
+                }
+                if read_any {
+                    stats.print(path);
+                    println!();
+                } else if once {
+                    stats.print(path);
+                }
+            }
+            Ok(None) => {
+                println!(
+                    "{} was truncated, reading it from the start",
+                    path.display()
+                );
+                stats = TailStats::new(window, max_failures);
+                continue;
+            }
+            Err(e) if !once => {
+                if !waiting_printed {
+                    println!("Waiting for {}: {}", path.display(), e);
+                    waiting_printed = true;
+                }
+            }
+            Err(e) => {
+                eprintln!("Could not read {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        if once {
+            return;
+        }
+        thread::sleep(Duration::from_secs_f64(interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::test_dir;
+    use std::{fs, io::Write};
+
+    #[test]
+    fn follower_keeps_characters_split_across_reads_whole() {
+        let dir = test_dir("tail");
+        let path = dir.join("results.jsonl");
+        let line = format!(
+            "{}\n",
+            serde_json::json!({
+                "schema_version": crate::RESULTS_SCHEMA_VERSION,
+                "bytecode_hash": "0x1",
+                "result": "Error",
+                "message": "unexpected `é`",
+            })
+        );
+        let split = line.find('é').unwrap() + 1;
+        fs::write(&path, &line.as_bytes()[..split]).unwrap();
+
+        let mut follower = ResultsFollower::new();
+        assert!(follower.read_new_rows(&path).unwrap().unwrap().is_empty());
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&line.as_bytes()[split..]).unwrap();
+        let rows = follower.read_new_rows(&path).unwrap().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].message(), Some("unexpected `é`"));
+
+        // a truncated file is read again from the start
+        fs::write(&path, "").unwrap();
+        assert!(follower.read_new_rows(&path).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{FiestaMetadata, SourceType};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs;
+
+lazy_static! {
+    /// What analyzers print when the selected contract is not in the compilation unit
+    static ref NOT_FOUND_REGEX: Regex = Regex::new(
+        r"(?i)no contract named|contract .{0,80}not found|could not find contract|unknown contract"
+    )
+    .unwrap();
+    /// How an argument parser like clap rejects a flag it does not know
+    static ref REJECTED_FLAG_REGEX: Regex =
+        Regex::new(r"(?i)unexpected argument|unrecognized (option|argument)|unknown (option|flag|argument)")
+            .unwrap();
+}
+
+/// The flag and value selecting the contract named by the metadata's ContractName, see
+/// `--target-contract-from-metadata`. None for contracts without a name.
+pub fn target_flags(flag: &str, metadata: &FiestaMetadata) -> Vec<String> {
+    if metadata.contract_name.trim().is_empty() {
+        return Vec::new();
+    }
+    vec![flag.to_string(), metadata.contract_name.clone()]
+}
+
+/// Whether the sources handed to the analyzer declare the contract, None when they could not
+/// be read or the analyzer reads them from the metadata file itself. A library or interface
+/// counts, the ContractName of a deployed library names it.
+fn sources_declare(metadata: &FiestaMetadata) -> Option<bool> {
+    let declaration = Regex::new(&format!(
+        r"\b(contract|library|interface)\s+{}\b",
+        regex::escape(&metadata.contract_name)
+    ))
+    .ok()?;
+    Some(match metadata.source_type.as_ref()? {
+        SourceType::SingleMain(source) => declaration.is_match(source),
+        SourceType::Multiple(files) => files.iter().any(|(_, source)| declaration.is_match(source)),
+        SourceType::EtherscanMetadata(source_metadata) => {
+            let sources = source_metadata.sources();
+            if sources.is_empty() {
+                return None;
+            }
+            sources
+                .into_values()
+                .any(|entry| declaration.is_match(&entry.content))
+        }
+        SourceType::Project(project_source) => {
+            let path =
+                std::path::Path::new(&metadata.abs_path_to_dir).join(&project_source.entrypoint);
+            declaration.is_match(&fs::read_to_string(path).ok()?)
+        }
+    })
+}
+
+/// Whether the analyzer analyzed the contract named by the metadata when asked to with `flag`:
+/// not when the sources don't declare it, when the analyzer rejected the flag, or when it
+/// could not find the contract. `output` is None when the analyzer left none, e.g. on timeout,
+/// and the answer is then None unless the sources rule the contract out.
+pub fn target_analyzed(
+    flag: &str,
+    metadata: &FiestaMetadata,
+    output: Option<(&str, &str)>,
+) -> Option<bool> {
+    if metadata.contract_name.trim().is_empty() || sources_declare(metadata) == Some(false) {
+        return Some(false);
+    }
+    let (stdout, stderr) = output?;
+    let rejected_flag = stderr
+        .lines()
+        .chain(stdout.lines())
+        .any(|line| line.contains(flag) && REJECTED_FLAG_REGEX.is_match(line));
+    let not_found = NOT_FOUND_REGEX.is_match(stderr) || NOT_FOUND_REGEX.is_match(stdout);
+    Some(!rejected_flag && !not_found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(contract_name: &str, source: &str) -> FiestaMetadata {
+        let mut metadata = FiestaMetadata::for_test("0x1");
+        metadata.contract_name = contract_name.to_string();
+        metadata.update_source_type(SourceType::SingleMain(source.to_string()));
+        metadata
+    }
+
+    #[test]
+    fn sources_declare_matches_whole_declarations() {
+        let source = "interface IToken {}\nlibrary SafeMath {}\ncontract TokenSale is IToken {}";
+        assert_eq!(sources_declare(&metadata("SafeMath", source)), Some(true));
+        assert_eq!(sources_declare(&metadata("IToken", source)), Some(true));
+        assert_eq!(sources_declare(&metadata("TokenSale", source)), Some(true));
+        // a declared name only starting with it is not the contract
+        assert_eq!(sources_declare(&metadata("Token", source)), Some(false));
+    }
+
+    #[test]
+    fn target_analyzed_reads_the_analyzer_output() {
+        let metadata = metadata("Token", "contract Token {}");
+        assert_eq!(target_analyzed("--contract", &metadata, None), None);
+        assert_eq!(
+            target_analyzed("--contract", &metadata, Some(("done", ""))),
+            Some(true)
+        );
+        assert_eq!(
+            target_analyzed(
+                "--contract",
+                &metadata,
+                Some(("", "error: unexpected argument '--contract' found"))
+            ),
+            Some(false)
+        );
+        assert_eq!(
+            target_analyzed(
+                "--contract",
+                &metadata,
+                Some(("", "No contract named Token"))
+            ),
+            Some(false)
+        );
+    }
+}
@@ -0,0 +1,573 @@
+use crate::{
+    analyze_with_pyrometer, artifacts, collect_contract_sources,
+    convert_pyrometer_output_to_exit_type, diff, load_contract_metadata, profiles, project,
+    results, wait_for_analyzer, AnalyzerConfig, AnalyzerRun, ExitType, ResultsRow,
+};
+use chrono::{SecondsFormat, Utc};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    style::Print,
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+/// Lines of captured output shown per failure
+const OUTPUT_LINES: usize = 12;
+
+/// What a failure turned out to be
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Classification {
+    /// The contract itself is broken or incomplete, e.g. missing sources
+    CorpusIssue,
+    /// pyrometer is wrong, the failure needs a fix
+    AnalyzerBug,
+    /// A failure already tracked or accepted
+    Known,
+}
+
+impl Classification {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Classification::CorpusIssue => "corpus-issue",
+            Classification::AnalyzerBug => "analyzer-bug",
+            Classification::Known => "known",
+        }
+    }
+}
+
+/// Classification of one contract's failure
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TriageDecision {
+    pub classification: Classification,
+    /// Result type of the failure when it was triaged, e.g. "ThreadPanic"
+    pub result: String,
+    /// Error or panic message of the failure when it was triaged
+    pub message: Option<String>,
+    /// RFC 3339 time of the decision
+    pub decided_at: String,
+}
+
+impl TriageDecision {
+    fn new(classification: Classification, row: &ResultsRow) -> Self {
+        Self {
+            classification,
+            result: row.result.name().to_string(),
+            message: row.message().map(str::to_string),
+            decided_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+
+    /// Whether the decision still holds for the result: the same failure, up to line numbers
+    /// and hashes in its message
+    pub fn applies_to(&self, row: &ResultsRow) -> bool {
+        self.result == row.result.name()
+            && self.message.as_deref().map(diff::normalize_message)
+                == row.message().map(diff::normalize_message)
+    }
+}
+
+/// Decisions taken with `omniscan triage`, by bytecode hash. Written next to the results as
+/// `<stem>.triage.json`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct TriageFile {
+    pub decisions: BTreeMap<String, TriageDecision>,
+}
+
+pub fn triage_path(results_path: &Path) -> PathBuf {
+    results_path.with_extension("triage.json")
+}
+
+impl TriageFile {
+    /// Reads the triage file, empty when it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Writes the triage file, replacing it only once it is complete
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        fs::write(&tmp_path, serde_json::to_string_pretty(self).unwrap())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// The decision on the contract's failure, None when it was not triaged or failed
+    /// differently since
+    pub fn decision_for(&self, row: &ResultsRow) -> Option<&TriageDecision> {
+        self.decisions
+            .get(&row.bytecode_hash)
+            .filter(|decision| decision.applies_to(row))
+    }
+}
+
+/// Prints how many of the failures of `rows` were classified each way in `triage`
+pub fn print_triage_counts(title: &str, rows: &[ResultsRow], triage: &TriageFile) {
+    let mut counts: BTreeMap<Classification, usize> = BTreeMap::new();
+    let mut untriaged = 0;
+    for row in rows
+        .iter()
+        .filter(|row| !matches!(row.result, ExitType::Success))
+    {
+        match triage.decision_for(row) {
+            Some(decision) => *counts.entry(decision.classification).or_default() += 1,
+            None => untriaged += 1,
+        }
+    }
+    let counts = counts
+        .iter()
+        .map(|(classification, count)| format!("{} {}", count, classification.label()))
+        .chain((untriaged > 0).then(|| format!("{} untriaged", untriaged)))
+        .collect::<Vec<String>>();
+    if !counts.is_empty() {
+        println!("{}: {}", title, counts.join(", "));
+    }
+}
+
+/// Loads the triage file of a results file for reports, None when there is none
+pub fn load_for_results(results_path: &Path) -> Option<TriageFile> {
+    let path = triage_path(results_path);
+    if !path.exists() {
+        return None;
+    }
+    TriageFile::load(&path)
+        .map_err(|e| eprintln!("Ignoring the triage file {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Everything `omniscan triage` needs besides the results
+pub struct TriageOptions {
+    pub triage_path: PathBuf,
+    pub corpus_root: PathBuf,
+    pub artifacts_dir: Option<PathBuf>,
+    /// Results of an earlier run whose triage decisions carry over, see `--baseline`
+    pub baseline: Option<PathBuf>,
+    /// Also go through the failures that are already triaged
+    pub all: bool,
+    pub timeout: f64,
+    /// How the output of a re-run is classified, see `--classification-profile` and the rules
+    /// file of `classify-unknowns`
+    pub classifier: profiles::Classifier,
+    pub analyzer: AnalyzerConfig,
+}
+
+/// Puts the terminal back the way it was, also when the triage panics
+struct TerminalGuard;
+
+/// Puts the terminal back and exits, which would skip the guard's destructor
+fn exit_restoring(guard: TerminalGuard, message: &str) -> ! {
+    drop(guard);
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// What the screen shows of the current failure
+struct Screen<'a> {
+    row: &'a ResultsRow,
+    position: (usize, usize),
+    decision: Option<&'a TriageDecision>,
+    source: &'a [String],
+    scroll: usize,
+    output: &'a [String],
+    rerun: Option<&'a str>,
+    status: &'a str,
+}
+
+fn fit(line: &str, width: usize) -> String {
+    line.replace('\t', "    ").chars().take(width).collect()
+}
+
+fn draw(screen: &Screen) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    let row = screen.row;
+    let mut lines = vec![
+        format!(
+            "omniscan triage [{}/{}]  {}",
+            screen.position.0 + 1,
+            screen.position.1,
+            screen.status
+        ),
+        format!(
+            "{}  {}  {:.2}s  {}  decision: {}",
+            row.bytecode_hash,
+            row.result.name(),
+            row.time,
+            row.source_type,
+            screen
+                .decision
+                .map(|decision| decision.classification.label())
+                .unwrap_or("none")
+        ),
+        row.source_path.clone(),
+        format!(
+            "Message: {}",
+            row.message().unwrap_or("-").replace('\n', " ")
+        ),
+    ];
+    if let Some(rerun) = screen.rerun {
+        lines.push(format!("Re-run: {}", rerun));
+    }
+
+    let footer = "c corpus-issue  b analyzer-bug  k known  u clear  e edit  r re-run  \
+                  <-/-> previous/next  up/down scroll  q quit";
+    let output_height = OUTPUT_LINES.min(height.saturating_sub(lines.len() + 4) / 2);
+    let source_height = height.saturating_sub(lines.len() + output_height + 3);
+    let source_end = lines.len() + 1 + source_height;
+    lines.push(format!(
+        "-- Source (lines {}-{} of {}) --",
+        (screen.scroll + 1).min(screen.source.len()),
+        (screen.scroll + source_height).min(screen.source.len()),
+        screen.source.len()
+    ));
+    lines.extend(
+        screen
+            .source
+            .iter()
+            .enumerate()
+            .skip(screen.scroll)
+            .take(source_height)
+            .map(|(idx, line)| format!("{:>5} | {}", idx + 1, line)),
+    );
+    // the captured output stays in place when the source is short
+    lines.resize(source_end, String::new());
+    lines.push("-- Captured output --".to_string());
+    let output_start = screen.output.len().saturating_sub(output_height);
+    lines.extend(screen.output[output_start..].iter().cloned());
+
+    let mut stdout = io::stdout();
+    queue!(stdout, Clear(ClearType::All))?;
+    for (idx, line) in lines.iter().take(height.saturating_sub(1)).enumerate() {
+        queue!(stdout, MoveTo(0, idx as u16), Print(fit(line, width)))?;
+    }
+    queue!(
+        stdout,
+        MoveTo(0, height.saturating_sub(1) as u16),
+        Print(fit(footer, width))
+    )?;
+    stdout.flush()
+}
+
+/// Lines of the contract's entrypoint
+fn read_source(corpus_root: &Path, row: &ResultsRow) -> Vec<String> {
+    match fs::read_to_string(corpus_root.join(&row.source_path)) {
+        Ok(source) => source.lines().map(str::to_string).collect(),
+        Err(e) => vec![format!("Could not read the source: {}", e)],
+    }
+}
+
+/// Lines pyrometer printed for the contract, stderr last
+fn read_output(artifacts_dir: Option<&Path>, row: &ResultsRow) -> Vec<String> {
+    let Some(artifacts_dir) = artifacts_dir else {
+        return vec!["(no --artifacts directory given)".to_string()];
+    };
+    let contract_dir = artifacts::contract_artifacts_dir(artifacts_dir, &row.bytecode_hash);
+    let mut lines = Vec::new();
+    for file in ["stdout.txt", "stderr.txt"] {
+        if let Ok(contents) = fs::read_to_string(contract_dir.join(file)) {
+            lines.extend(
+                contents
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| format!("{}: {}", file.trim_end_matches(".txt"), line)),
+            );
+        }
+    }
+    if lines.is_empty() {
+        lines.push("(no captured output)".to_string());
+    }
+    lines
+}
+
+/// Opens the contract's entrypoint in $VISUAL or $EDITOR, vi when neither is set
+fn open_in_editor(corpus_root: &Path, row: &ResultsRow) -> Result<(), String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = shlex::split(&editor).ok_or(format!("could not parse `{}`", editor))?;
+    if words.is_empty() {
+        return Err("the editor is empty".to_string());
+    }
+    let program = words.remove(0);
+    let status = Command::new(&program)
+        .args(words)
+        .arg(corpus_root.join(&row.source_path))
+        .status()
+        .map_err(|e| format!("could not start {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+/// Analyzes the contract again, describing the new result against the recorded one
+async fn rerun(row: &ResultsRow, options: &TriageOptions) -> String {
+    let contract_dir = Path::new(&row.source_path)
+        .parent()
+        .map(|dir| options.corpus_root.join(dir))
+        .unwrap_or_else(|| options.corpus_root.clone());
+    // project and plain corpora have no metadata.json, their contracts are files
+    let metadata = match contract_dir.join("metadata.json").is_file() {
+        true => load_contract_metadata(&contract_dir, &options.corpus_root)
+            .and_then(|mut metadata| collect_contract_sources(&mut metadata).map(|()| metadata)),
+        false => project::source_metadata(
+            &options.corpus_root,
+            &options.corpus_root.join(&row.source_path),
+            &std::env::temp_dir().join("omniscan-triage-remappings"),
+        ),
+    };
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(e) => return format!("could not load the contract: {}", e),
+    };
+    let child = match analyze_with_pyrometer(&metadata, None, &options.analyzer, None, None) {
+        Ok((child, _)) => child,
+        Err(e) => return e.to_string(),
+    };
+    let result = match wait_for_analyzer(child, options.timeout, Duration::from_millis(50)).await {
+        Some(AnalyzerRun {
+            output: Some((stdout, stderr)),
+            time,
+            ..
+        }) => (
+            convert_pyrometer_output_to_exit_type(&options.classifier, stdout, stderr),
+            time,
+        ),
+        Some(AnalyzerRun { time, .. }) => (ExitType::PerformanceTimeout, time),
+        None => return "could not wait for pyrometer".to_string(),
+    };
+    let (result, time) = result;
+    let comparison = if result.to_string() == row.result.to_string() {
+        "same as recorded".to_string()
+    } else {
+        format!("recorded: {}", row.result)
+    };
+    format!("{} in {:.2}s ({})", result, time, comparison)
+}
+
+/// Copies the baseline's decisions that still hold to the failures without one
+fn carry_over_baseline(triage: &mut TriageFile, failures: &[ResultsRow], baseline: &Path) {
+    let baseline_path = triage_path(baseline);
+    let baseline_triage = TriageFile::load(&baseline_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read the baseline triage file {}: {}",
+            baseline_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let mut carried = 0;
+    for row in failures {
+        if triage.decision_for(row).is_some() {
+            continue;
+        }
+        if let Some(decision) = baseline_triage.decision_for(row) {
+            triage
+                .decisions
+                .insert(row.bytecode_hash.clone(), decision.clone());
+            carried += 1;
+        }
+    }
+    println!(
+        "Carried over {} decisions from {}",
+        carried,
+        baseline_path.display()
+    );
+}
+
+/// Entry point of the `triage` subcommand: presents the failures of a results file one by one
+/// and records what each of them is in the triage file
+pub async fn run_triage(results_path: &Path, options: TriageOptions) {
+    let rows = results::read_results_file(results_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read results file {}: {}",
+            results_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let failures = rows
+        .into_iter()
+        .filter(|row| !matches!(row.result, ExitType::Success))
+        .collect::<Vec<ResultsRow>>();
+    let mut triage = TriageFile::load(&options.triage_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read the triage file {}: {}",
+            options.triage_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let save = |triage: &TriageFile| {
+        triage
+            .save(&options.triage_path)
+            .map_err(|e| format!("Could not save {}: {}", options.triage_path.display(), e))
+    };
+    if let Some(baseline) = &options.baseline {
+        carry_over_baseline(&mut triage, &failures, baseline);
+        if let Err(e) = save(&triage) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let queue = failures
+        .iter()
+        .filter(|row| options.all || triage.decision_for(row).is_none())
+        .collect::<Vec<&ResultsRow>>();
+    if queue.is_empty() {
+        println!("All {} failures are triaged", failures.len());
+        print_triage_counts("Triage", &failures, &triage);
+        return;
+    }
+
+    let guard = TerminalGuard::enter().unwrap_or_else(|e| {
+        eprintln!("omniscan triage needs an interactive terminal: {}", e);
+        std::process::exit(1);
+    });
+    let mut position = 0;
+    let mut decided = 0;
+    let mut rerun_result: Option<String> = None;
+    let mut status = String::new();
+    let mut scroll = 0;
+    let mut source = read_source(&options.corpus_root, queue[0]);
+    let mut output = read_output(options.artifacts_dir.as_deref(), queue[0]);
+    loop {
+        let row = queue[position];
+        let screen_status = if status.is_empty() {
+            format!("{} decided this session", decided)
+        } else {
+            status.clone()
+        };
+        let drawn = draw(&Screen {
+            row,
+            position: (position, queue.len()),
+            decision: triage.decision_for(row),
+            source: &source,
+            scroll,
+            output: &output,
+            rerun: rerun_result.as_deref(),
+            status: &screen_status,
+        });
+        if let Err(e) = drawn {
+            exit_restoring(guard, &format!("Could not draw the triage screen: {}", e));
+        }
+        let Ok(Event::Key(key)) = event::read() else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        status.clear();
+        let mut moved_to = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            KeyCode::Char(key @ ('c' | 'b' | 'k')) => {
+                let classification = match key {
+                    'c' => Classification::CorpusIssue,
+                    'b' => Classification::AnalyzerBug,
+                    _ => Classification::Known,
+                };
+                triage.decisions.insert(
+                    row.bytecode_hash.clone(),
+                    TriageDecision::new(classification, row),
+                );
+                if let Err(e) = save(&triage) {
+                    exit_restoring(guard, &e);
+                }
+                decided += 1;
+                moved_to = Some((position + 1).min(queue.len() - 1));
+            }
+            KeyCode::Char('u') => {
+                triage.decisions.remove(&row.bytecode_hash);
+                if let Err(e) = save(&triage) {
+                    exit_restoring(guard, &e);
+                }
+            }
+            KeyCode::Char('e') => {
+                let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+                let _ = terminal::disable_raw_mode();
+                let edited = open_in_editor(&options.corpus_root, row);
+                let _ = terminal::enable_raw_mode();
+                let _ = execute!(io::stdout(), EnterAlternateScreen, Hide);
+                match edited {
+                    Ok(()) => source = read_source(&options.corpus_root, row),
+                    Err(e) => status = format!("Could not open the editor: {}", e),
+                }
+            }
+            KeyCode::Char('r') => {
+                status = "Re-running...".to_string();
+                rerun_result = None;
+                let _ = draw(&Screen {
+                    row,
+                    position: (position, queue.len()),
+                    decision: triage.decision_for(row),
+                    source: &source,
+                    scroll,
+                    output: &output,
+                    rerun: rerun_result.as_deref(),
+                    status: &status,
+                });
+                rerun_result = Some(rerun(row, &options).await);
+                status.clear();
+            }
+            KeyCode::Right | KeyCode::Char('n') | KeyCode::Enter | KeyCode::Char(' ') => {
+                moved_to = Some((position + 1).min(queue.len() - 1));
+            }
+            KeyCode::Left | KeyCode::Char('p') => moved_to = Some(position.saturating_sub(1)),
+            KeyCode::Down => scroll = (scroll + 1).min(source.len().saturating_sub(1)),
+            KeyCode::Up => scroll = scroll.saturating_sub(1),
+            KeyCode::PageDown => scroll = (scroll + 20).min(source.len().saturating_sub(1)),
+            KeyCode::PageUp => scroll = scroll.saturating_sub(20),
+            _ => {}
+        }
+        if let Some(new_position) = moved_to {
+            if new_position == position && position == queue.len() - 1 {
+                status = "Last failure, q to quit".to_string();
+            } else if new_position != position {
+                position = new_position;
+                scroll = 0;
+                rerun_result = None;
+                source = read_source(&options.corpus_root, queue[position]);
+                output = read_output(options.artifacts_dir.as_deref(), queue[position]);
+            }
+        }
+    }
+    drop(guard);
+    println!(
+        "Recorded {} decisions in {}",
+        decided,
+        options.triage_path.display()
+    );
+    print_triage_counts("Triage", &failures, &triage);
+}
@@ -0,0 +1,290 @@
+use crate::{
+    artifacts, backtrace, clusters, report, results,
+    rules::{ClassificationRule, RuleResult, RuleStream, Rules, RulesFile},
+    ExitType, ResultsRow,
+};
+use chrono::{SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+/// Last lines of the output shown for a cluster
+const SHOWN_LINES: usize = 12;
+
+lazy_static! {
+    static ref NUMBER_REGEX: Regex = Regex::new(r"\d+").unwrap();
+}
+
+/// Where `classify-unknowns` reads the outputs from and writes the rules to
+pub struct UnknownsOptions {
+    pub artifacts_dir: PathBuf,
+    pub rules_path: PathBuf,
+    /// See `ClusterOptions::min_similarity`
+    pub min_similarity: f64,
+}
+
+/// Captured stdout and stderr of a contract, None when neither was captured
+fn read_output(artifacts_dir: &Path, bytecode_hash: &str) -> Option<(String, String)> {
+    let contract_dir = artifacts::contract_artifacts_dir(artifacts_dir, bytecode_hash);
+    let stdout = fs::read_to_string(contract_dir.join("stdout.txt"));
+    let stderr = fs::read_to_string(contract_dir.join("stderr.txt"));
+    if stdout.is_err() && stderr.is_err() {
+        return None;
+    }
+    Some((stdout.unwrap_or_default(), stderr.unwrap_or_default()))
+}
+
+/// Asks on stdin, None at the end of the input
+fn prompt(question: &str) -> Option<String> {
+    print!("{}", question);
+    io::stdout().flush().ok()?;
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_string()),
+    }
+}
+
+/// Pattern matching `line` with any numbers in it, offered as the default pattern
+fn suggest_pattern(line: &str) -> String {
+    NUMBER_REGEX
+        .replace_all(&regex::escape(line), r"\d+")
+        .into_owned()
+}
+
+/// Reclassifies the NonInterpreted rows whose captured output a rule matches, returning how
+/// many were
+fn apply_rules(
+    rows: &mut [ResultsRow],
+    outputs: &HashMap<String, (String, String)>,
+    rules: &Rules,
+) -> usize {
+    let mut reclassified = 0;
+    for row in rows.iter_mut() {
+        if !matches!(row.result, ExitType::NonInterpreted(_, _)) {
+            continue;
+        }
+        let Some((stdout, stderr)) = outputs.get(&row.bytecode_hash) else {
+            continue;
+        };
+        if let Some(exit_type) = rules.classify(stdout, stderr) {
+            if matches!(exit_type, ExitType::ThreadPanic(_)) {
+                row.crash_location = backtrace::crash_location(stderr);
+            }
+            row.result = exit_type;
+            reclassified += 1;
+        }
+    }
+    reclassified
+}
+
+/// Prompts for a rule matching the output of `sample` until one is given, None when the user
+/// gives up on the cluster
+fn write_rule(
+    representative: &str,
+    sample: &(String, String),
+    unknown: &[(String, String)],
+) -> Option<ClassificationRule> {
+    let suggestion = suggest_pattern(representative);
+    loop {
+        let pattern = prompt(&format!("Pattern (empty for `{}`): ", suggestion))?;
+        let pattern = if pattern.is_empty() {
+            suggestion.clone()
+        } else {
+            pattern
+        };
+        let result = match prompt("Counts as [e]rror, [p]anic or [s]uccess: ")?.as_str() {
+            "e" => RuleResult::Error,
+            "p" => RuleResult::ThreadPanic,
+            "s" => RuleResult::Success,
+            _ => {
+                println!("Unknown result, starting over");
+                continue;
+            }
+        };
+        let rule = ClassificationRule {
+            pattern,
+            result,
+            stream: RuleStream::Either,
+            added_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        let rules = match Rules::compile(&RulesFile {
+            rules: vec![rule.clone()],
+        }) {
+            Ok(rules) => rules,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let Some(sample_result) = rules.classify(&sample.0, &sample.1) else {
+            println!("The pattern does not match the output above");
+            continue;
+        };
+        let matched = unknown
+            .iter()
+            .filter(|(stdout, stderr)| rules.classify(stdout, stderr).is_some())
+            .count();
+        println!(
+            "The rule classifies {} of the {} unknown outputs, the one above as {}",
+            matched,
+            unknown.len(),
+            sample_result
+        );
+        match prompt("Add the rule? [y]es, [n]o and start over, [c]ancel: ")?.as_str() {
+            "y" => return Some(rule),
+            "c" => return None,
+            _ => continue,
+        }
+    }
+}
+
+/// Goes through the NonInterpreted results of a results file, largest cluster of similar
+/// outputs first, asking for a rule classifying each. Every rule is appended to the rules file
+/// and applied to the results file at once, which is rewritten with the new classifications.
+pub fn run_classify_unknowns(results_path: &Path, options: UnknownsOptions) {
+    let mut rows = results::read_results_file(results_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read results file {}: {}",
+            results_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let mut rules_file = RulesFile::load(&options.rules_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read the rules file {}: {}",
+            options.rules_path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let mut outputs = HashMap::new();
+    let mut without_output = 0;
+    for row in &rows {
+        if matches!(row.result, ExitType::NonInterpreted(_, _)) {
+            match read_output(&options.artifacts_dir, &row.bytecode_hash) {
+                Some(output) => {
+                    outputs.insert(row.bytecode_hash.clone(), output);
+                }
+                None => without_output += 1,
+            }
+        }
+    }
+    if without_output > 0 {
+        println!(
+            "{} NonInterpreted results have no captured output in {} and are left out",
+            without_output,
+            options.artifacts_dir.display()
+        );
+    }
+    let rewrite = |rows: &[ResultsRow]| {
+        if let Err(e) = report::rewrite_results_file(results_path, rows) {
+            eprintln!("Could not rewrite {}: {}", results_path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let compile = |rules_file: &RulesFile| {
+        Rules::compile(rules_file).unwrap_or_else(|e| {
+            eprintln!("{}: {}", options.rules_path.display(), e);
+            std::process::exit(1);
+        })
+    };
+
+    // rules added since the run apply to it too
+    let reclassified = apply_rules(&mut rows, &outputs, &compile(&rules_file));
+    if reclassified > 0 {
+        rewrite(&rows);
+        println!(
+            "Reclassified {} results with the {} rules of {}",
+            reclassified,
+            rules_file.rules.len(),
+            options.rules_path.display()
+        );
+    }
+
+    let mut skipped = HashSet::new();
+    loop {
+        let unknown = rows
+            .iter()
+            .filter(|row| {
+                matches!(row.result, ExitType::NonInterpreted(_, _))
+                    && outputs.contains_key(&row.bytecode_hash)
+            })
+            .cloned()
+            .collect::<Vec<ResultsRow>>();
+        if unknown.is_empty() {
+            println!("No NonInterpreted results left");
+            return;
+        }
+        let clusters = clusters::cluster_failures(
+            &unknown,
+            Some(&options.artifacts_dir),
+            options.min_similarity,
+        );
+        let Some((idx, cluster)) = clusters
+            .iter()
+            .enumerate()
+            .find(|(_, cluster)| !skipped.contains(&cluster.representative))
+        else {
+            println!(
+                "{} NonInterpreted results left, every cluster was skipped",
+                unknown.len()
+            );
+            return;
+        };
+        let sample = &outputs[&cluster.rows[0].bytecode_hash];
+        println!();
+        println!(
+            "Cluster {} of {}: {} of the {} NonInterpreted results, e.g. {}",
+            idx + 1,
+            clusters.len(),
+            cluster.rows.len(),
+            unknown.len(),
+            cluster.rows[0].source_path
+        );
+        for (stream, output) in [("stdout", &sample.0), ("stderr", &sample.1)] {
+            let lines = output
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect::<Vec<&str>>();
+            for line in &lines[lines.len().saturating_sub(SHOWN_LINES)..] {
+                println!("  {}: {}", stream, line);
+            }
+        }
+        match prompt("[r]ule, [s]kip, [q]uit: ").as_deref() {
+            Some("r") => {
+                let unknown_outputs = unknown
+                    .iter()
+                    .map(|row| outputs[&row.bytecode_hash].clone())
+                    .collect::<Vec<(String, String)>>();
+                let Some(rule) = write_rule(&cluster.representative, sample, &unknown_outputs)
+                else {
+                    continue;
+                };
+                rules_file.rules.push(rule);
+                if let Err(e) = rules_file.save(&options.rules_path) {
+                    eprintln!("Could not save {}: {}", options.rules_path.display(), e);
+                    std::process::exit(1);
+                }
+                let reclassified = apply_rules(&mut rows, &outputs, &compile(&rules_file));
+                rewrite(&rows);
+                println!(
+                    "Added the rule to {}, reclassified {} results",
+                    options.rules_path.display(),
+                    reclassified
+                );
+            }
+            Some("s") => {
+                skipped.insert(cluster.representative.clone());
+            }
+            Some("q") | None => return,
+            Some(_) => println!("Unknown answer"),
+        }
+    }
+}
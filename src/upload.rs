@@ -0,0 +1,220 @@
+use crate::{
+    export, manifest,
+    remote::{self, RemoteCorpus},
+    rotation,
+    tags::RunTags,
+    ResultsRow,
+};
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Key of every uploaded file when `--upload-key` is not given
+pub const DEFAULT_UPLOAD_KEY: &str = "{date}/{tag}/{pyrometer_rev}/{time}/{file}";
+
+/// Where `--upload` puts the files of a run
+pub enum UploadDestination {
+    /// `s3://bucket/prefix` or `gs://bucket/prefix`, see `RemoteCorpus` for the credentials
+    Bucket(RemoteCorpus),
+    /// An HTTP endpoint every file is PUT under, with OMNISCAN_UPLOAD_TOKEN as bearer token
+    /// when it is set
+    Http(String),
+}
+
+impl UploadDestination {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        if let Some(bucket) = RemoteCorpus::parse(url) {
+            return bucket.map(Self::Bucket);
+        }
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Ok(Self::Http(url.trim_end_matches('/').to_string()));
+        }
+        Err(format!(
+            "{} is neither a bucket URL (s3://, gs://) nor an HTTP endpoint",
+            url
+        ))
+    }
+
+    /// Uploads the file at `path` as `key`, streamed from the file
+    async fn put(&self, client: &reqwest::Client, key: &str, path: &Path) -> Result<(), String> {
+        match self {
+            Self::Bucket(bucket) => bucket.upload_file(client, key, path).await,
+            Self::Http(endpoint) => {
+                let url = format!("{}/{}", endpoint, key);
+                let length = fs::metadata(path)
+                    .map_err(|e| format!("could not read {}: {}", path.display(), e))?
+                    .len();
+                let file = tokio::fs::File::open(path)
+                    .await
+                    .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+                let mut request = client
+                    .put(&url)
+                    .header(reqwest::header::CONTENT_LENGTH, length)
+                    .timeout(remote::upload_timeout(length))
+                    .body(file);
+                if let Ok(token) = std::env::var("OMNISCAN_UPLOAD_TOKEN") {
+                    request = request.bearer_auth(token);
+                }
+                let response = request.send().await.map_err(|e| e.to_string())?;
+                if !response.status().is_success() {
+                    return Err(format!(
+                        "uploading {} failed with {}",
+                        url,
+                        response.status()
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What the placeholders of the key template stand for
+pub struct UploadKey {
+    pub template: String,
+    pub started_at: DateTime<Utc>,
+    pub run_tags: RunTags,
+    /// See `environment::analyzer_version`
+    pub analyzer_version: Option<String>,
+}
+
+/// Keeps `value` usable as a key segment: runs of other characters than letters, digits, `.`,
+/// `_` and `-` become a single `-`
+fn key_segment(value: &str) -> String {
+    let mut segment = String::new();
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+            segment.push(c);
+        } else if !segment.ends_with('-') {
+            segment.push('-');
+        }
+    }
+    segment.trim_matches('-').to_string()
+}
+
+impl UploadKey {
+    /// The key of `file`: the template with `{date}` (YYYY-MM-DD) and `{time}` (HHMMSS) of the
+    /// start of the run, `{tag}` (the tags joined by `+`, "untagged" without any),
+    /// `{pyrometer_rev}` (the pyrometer version, "unknown" when it could not be read) and
+    /// `{file}` replaced
+    pub fn render(&self, file: &str) -> String {
+        let tag = if self.run_tags.tags.is_empty() {
+            "untagged".to_string()
+        } else {
+            self.run_tags
+                .tags
+                .iter()
+                .map(|tag| key_segment(tag))
+                .collect::<Vec<String>>()
+                .join("+")
+        };
+        let pyrometer_rev = self
+            .analyzer_version
+            .as_deref()
+            .map(|version| key_segment(version.trim_start_matches("pyrometer").trim()))
+            .filter(|version| !version.is_empty())
+            .unwrap_or_else(|| "unknown".to_string());
+        self.template
+            .replace("{date}", &self.started_at.format("%Y-%m-%d").to_string())
+            .replace("{time}", &self.started_at.format("%H%M%S").to_string())
+            .replace("{tag}", &tag)
+            .replace("{pyrometer_rev}", &pyrometer_rev)
+            .replace("{file}", file)
+    }
+}
+
+/// Everything of the run that is uploaded
+pub struct UploadedRun<'a> {
+    pub output_path: &'a Path,
+    /// The summary of the run as written with `--summary`
+    pub summary_json: String,
+    pub result_rows: &'a [ResultsRow],
+    pub corpus_root: &'a Path,
+    pub artifacts_dir: Option<&'a Path>,
+}
+
+/// Writes the failing contracts of the run as a `failures.tar.gz` repro corpus into `dir`, see
+/// `export-failures`, returning its path. None when every contract succeeded.
+fn failures_archive(run: &UploadedRun, dir: &Path) -> Result<Option<PathBuf>, String> {
+    if run
+        .result_rows
+        .iter()
+        .all(|row| matches!(row.result, crate::ExitType::Success))
+    {
+        return Ok(None);
+    }
+    let out = dir.join("failures");
+    let archive_path = dir.join("failures.tar.gz");
+    let archive =
+        export::export_failure_rows(run.result_rows, run.corpus_root, &out, run.artifacts_dir)
+            .and_then(|_| {
+                let file = fs::File::create(&archive_path).map_err(|e| e.to_string())?;
+                let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+                builder
+                    .append_dir_all("failures", &out)
+                    .and_then(|_| builder.into_inner())
+                    .and_then(|encoder| encoder.finish())
+                    .map_err(|e| e.to_string())
+            });
+    let _ = fs::remove_dir_all(&out);
+    archive.map(|_| Some(archive_path))
+}
+
+/// Files of the results: the parts of rotated results with their list, else the results file
+fn results_files(output_path: &Path) -> Result<Vec<PathBuf>, String> {
+    match rotation::part_paths(output_path) {
+        Some(parts) => {
+            let mut files = parts?;
+            files.push(rotation::parts_manifest_path(output_path));
+            Ok(files)
+        }
+        None => Ok(vec![output_path.to_path_buf()]),
+    }
+}
+
+/// Uploads the results, manifest and summary of the run and the archive of its failures,
+/// returning the keys of the uploaded files. Stops at the first file that fails to upload.
+pub async fn upload_run(
+    destination: &UploadDestination,
+    key: &UploadKey,
+    run: &UploadedRun<'_>,
+) -> Result<Vec<String>, String> {
+    let mut files = results_files(run.output_path)?
+        .into_iter()
+        .chain([manifest::manifest_path(run.output_path)])
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            (name.into_owned(), path)
+        })
+        .collect::<Vec<(String, PathBuf)>>();
+    // the summary and the archive are written next to each other, and streamed like the results
+    let dir = std::env::temp_dir().join(format!("omniscan-upload-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    let uploaded = async {
+        let summary_path = dir.join("summary.json");
+        fs::write(&summary_path, &run.summary_json)
+            .map_err(|e| format!("could not write {}: {}", summary_path.display(), e))?;
+        files.push(("summary.json".to_string(), summary_path));
+        if let Some(archive) = failures_archive(run, &dir)
+            .map_err(|e| format!("could not archive the failures: {}", e))?
+        {
+            files.push(("failures.tar.gz".to_string(), archive));
+        }
+
+        let client = remote::http_client()?;
+        let mut uploaded = Vec::new();
+        for (name, path) in &files {
+            let object_key = key.render(name);
+            destination.put(&client, &object_key, path).await?;
+            uploaded.push(object_key);
+        }
+        Ok(uploaded)
+    }
+    .await;
+    let _ = fs::remove_dir_all(&dir);
+    uploaded
+}
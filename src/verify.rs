@@ -0,0 +1,377 @@
+use crate::{mislabel, read_metadata_file};
+use ethers::etherscan::contract::SourceCodeMetadata;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+};
+
+/// Problems listed on the terminal, the report holds all of them
+const LISTED_PROBLEMS: usize = 20;
+
+/// What is wrong with a part of the corpus
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// A directory could not be listed
+    UnreadableDir,
+    /// Something else than a bucket directory (two hex digits) in organized_contracts, or than
+    /// a contract directory in a bucket
+    StrayEntry,
+    MissingMetadata,
+    /// metadata.json could not be read, e.g. a truncated copy or an IO error
+    UnreadableMetadata,
+    /// metadata.json is not the JSON of a contract's metadata
+    InvalidMetadata,
+    /// The BytecodeHash of metadata.json is not 64 lowercase hex digits
+    MalformedHash,
+    /// The BytecodeHash is not the name of the contract directory, or its first two digits not
+    /// the bucket
+    MisplacedContract,
+    /// Neither a contract.json nor a .sol or .vy file
+    MissingSources,
+    /// A .vy file holding Solidity, or Vyper with a Solidity compiler in metadata.json, see
+    /// `--fix-corpus`. Not a corruption: the run leaves the contract out
+    Mislabeled,
+    /// A source file could not be read, or is not UTF-8
+    UnreadableSource,
+    /// contract.json is not the JSON of Etherscan's source code metadata
+    InvalidContractJson,
+}
+
+impl ProblemKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnreadableDir => "unreadable directory",
+            Self::StrayEntry => "stray entry",
+            Self::MissingMetadata => "missing metadata.json",
+            Self::UnreadableMetadata => "unreadable metadata.json",
+            Self::InvalidMetadata => "invalid metadata.json",
+            Self::MalformedHash => "malformed bytecode hash",
+            Self::MisplacedContract => "misplaced contract",
+            Self::MissingSources => "missing sources",
+            Self::Mislabeled => "mislabeled sources",
+            Self::UnreadableSource => "unreadable source",
+            Self::InvalidContractJson => "invalid contract.json",
+        }
+    }
+
+    /// Whether the problem makes the contract corrupted, as opposed to reported only
+    pub fn is_corruption(self) -> bool {
+        self != Self::Mislabeled
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    /// Path relative to the corpus root
+    pub path: String,
+    pub detail: String,
+}
+
+/// Corruption report of `omniscan verify-corpus`
+#[derive(Serialize, Debug, Default)]
+pub struct CorpusReport {
+    pub corpus: String,
+    pub buckets: usize,
+    pub contracts: usize,
+    /// Contracts without any corruption
+    pub intact: usize,
+    pub problems: Vec<Problem>,
+}
+
+fn is_hex(value: &str) -> bool {
+    value
+        .chars()
+        .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+struct Verifier<'a> {
+    corpus_root: &'a Path,
+    problems: Vec<Problem>,
+}
+
+impl Verifier<'_> {
+    fn record(&mut self, kind: ProblemKind, path: &Path, detail: impl Into<String>) {
+        self.problems.push(Problem {
+            kind,
+            path: path
+                .strip_prefix(self.corpus_root)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            detail: detail.into(),
+        });
+    }
+
+    fn list_dir(&mut self, dir: &Path) -> Vec<PathBuf> {
+        match fs::read_dir(dir) {
+            Ok(entries) => {
+                let mut paths = Vec::new();
+                for entry in entries {
+                    match entry {
+                        Ok(entry) => paths.push(entry.path()),
+                        Err(e) => self.record(ProblemKind::UnreadableDir, dir, e.to_string()),
+                    }
+                }
+                paths.sort();
+                paths
+            }
+            Err(e) => {
+                self.record(ProblemKind::UnreadableDir, dir, e.to_string());
+                Vec::new()
+            }
+        }
+    }
+
+    /// Checks the metadata and sources of a contract directory, returning whether it is intact,
+    /// i.e. has no corruption
+    fn verify_contract(&mut self, bucket: &str, contract_dir: &Path) -> bool {
+        let problems = self.problems.len();
+        let dir_name = contract_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let metadata_path = contract_dir.join("metadata.json");
+        if !metadata_path.is_file() {
+            self.record(ProblemKind::MissingMetadata, contract_dir, "");
+        } else {
+            match read_metadata_file(&metadata_path) {
+                Ok(metadata) => {
+                    let hash = metadata.bytecode_hash.as_str();
+                    if hash.len() != 64 || !is_hex(hash) {
+                        self.record(ProblemKind::MalformedHash, &metadata_path, hash);
+                    } else if hash != dir_name || !hash.starts_with(bucket) {
+                        self.record(
+                            ProblemKind::MisplacedContract,
+                            &metadata_path,
+                            format!("BytecodeHash is {}", hash),
+                        );
+                    }
+                }
+                Err(crate::error::OmniscanError::Json { source, .. }) => {
+                    self.record(
+                        ProblemKind::InvalidMetadata,
+                        &metadata_path,
+                        source.to_string(),
+                    );
+                }
+                Err(e) => {
+                    self.record(
+                        ProblemKind::UnreadableMetadata,
+                        &metadata_path,
+                        e.to_string(),
+                    );
+                }
+            }
+        }
+
+        let (mut has_sources, mut has_vy) = (false, false);
+        for entry in walkdir::WalkDir::new(contract_dir) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(contract_dir).to_path_buf();
+                    self.record(ProblemKind::UnreadableDir, &path, e.to_string());
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let is_contract_json = path.file_name().is_some_and(|name| name == "contract.json");
+            let is_sol = path.extension().is_some_and(|extension| extension == "sol");
+            let is_vy = path.extension().is_some_and(|extension| extension == "vy");
+            if !entry.file_type().is_file() || !(is_contract_json || is_sol || is_vy) {
+                continue;
+            }
+            has_sources |= !is_vy;
+            has_vy |= is_vy;
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    self.record(ProblemKind::UnreadableSource, path, e.to_string());
+                    continue;
+                }
+            };
+            if is_contract_json {
+                if let Err(e) = serde_json::from_str::<SourceCodeMetadata>(&contents) {
+                    self.record(ProblemKind::InvalidContractJson, path, e.to_string());
+                }
+            }
+        }
+        // a main.vy is the source of a Vyper contract, unless mislabeled
+        if !has_sources && has_vy {
+            let compiler_version = read_metadata_file(&metadata_path)
+                .map(|metadata| metadata.compiler_version)
+                .unwrap_or_default();
+            if let Some(mislabel) = mislabel::detect(contract_dir, &compiler_version) {
+                self.record(
+                    ProblemKind::Mislabeled,
+                    &mislabel.file,
+                    format!("{}, see --fix-corpus", mislabel.reason.name()),
+                );
+            }
+        } else if !has_sources {
+            self.record(ProblemKind::MissingSources, contract_dir, "");
+        }
+        self.problems[problems..]
+            .iter()
+            .all(|problem| !problem.kind.is_corruption())
+    }
+
+    /// Checks the contracts of a bucket, returning how many there are and how many are intact
+    fn verify_bucket(&mut self, bucket_dir: &Path) -> (usize, usize) {
+        let bucket = bucket_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let (mut contracts, mut intact) = (0, 0);
+        for path in self.list_dir(bucket_dir) {
+            if !path.is_dir() {
+                self.record(ProblemKind::StrayEntry, &path, "not a contract directory");
+                continue;
+            }
+            contracts += 1;
+            intact += self.verify_contract(&bucket, &path) as usize;
+        }
+        (contracts, intact)
+    }
+}
+
+/// Checks every contract of a smart-contract-fiesta corpus: its metadata.json parses, its
+/// bytecode hash is well-formed and matches its place in the corpus, and its sources are
+/// present and readable. The buckets are checked in parallel, as many at once as there are
+/// cores.
+pub fn verify_corpus(corpus_root: &Path) -> Result<CorpusReport, String> {
+    let organized_contracts = corpus_root.join("organized_contracts");
+    if !organized_contracts.is_dir() {
+        return Err(format!(
+            "{} is not a smart-contract-fiesta corpus, it has no organized_contracts directory",
+            corpus_root.display()
+        ));
+    }
+    let mut verifier = Verifier {
+        corpus_root,
+        problems: Vec::new(),
+    };
+    let mut buckets = Vec::new();
+    for path in verifier.list_dir(&organized_contracts) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() && name.len() == 2 && is_hex(&name) {
+            buckets.push(path);
+        } else {
+            verifier.record(ProblemKind::StrayEntry, &path, "not a bucket directory");
+        }
+    }
+
+    let mut report = CorpusReport {
+        corpus: corpus_root.display().to_string(),
+        buckets: buckets.len(),
+        ..CorpusReport::default()
+    };
+    let mut verified = 0;
+    for (i, wave) in buckets.chunks(num_cpus::get().max(1)).enumerate() {
+        let scans = thread::scope(|scope| {
+            let handles = wave
+                .iter()
+                .map(|bucket_dir| {
+                    scope.spawn(move || {
+                        let mut verifier = Verifier {
+                            corpus_root,
+                            problems: Vec::new(),
+                        };
+                        let (contracts, intact) = verifier.verify_bucket(bucket_dir);
+                        (contracts, intact, verifier.problems)
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for (contracts, intact, problems) in scans {
+            report.contracts += contracts;
+            report.intact += intact;
+            verifier.problems.extend(problems);
+        }
+        verified += wave.len();
+        if (i + 1) % 16 == 0 {
+            println!(
+                "{} of {} buckets verified, {} contracts",
+                verified,
+                buckets.len(),
+                report.contracts
+            );
+        }
+    }
+    report.problems = verifier.problems;
+    Ok(report)
+}
+
+/// Prints the number of problems of each kind, then the first problems
+pub fn print_report(report: &CorpusReport) {
+    println!(
+        "{} contracts in {} buckets of {}: {} intact, {} corrupted",
+        report.contracts,
+        report.buckets,
+        report.corpus,
+        report.intact,
+        report.contracts - report.intact
+    );
+    if report.problems.is_empty() {
+        return;
+    }
+    let mut counts: BTreeMap<ProblemKind, usize> = BTreeMap::new();
+    for problem in &report.problems {
+        *counts.entry(problem.kind).or_default() += 1;
+    }
+    println!("| Problem | Count |");
+    println!("| ----- | ----- |");
+    for (kind, count) in counts {
+        println!("|{} | {} |", kind.name(), count);
+    }
+    println!();
+    for problem in report.problems.iter().take(LISTED_PROBLEMS) {
+        match problem.detail.as_str() {
+            "" => println!("  {}: {}", problem.path, problem.kind.name()),
+            detail => println!("  {}: {}: {}", problem.path, problem.kind.name(), detail),
+        }
+    }
+    if report.problems.len() > LISTED_PROBLEMS {
+        println!("  ... and {} more", report.problems.len() - LISTED_PROBLEMS);
+    }
+}
+
+/// Runs `omniscan verify-corpus`, exiting with status 1 when the corpus has corrupted parts
+pub fn run_verify_corpus(corpus_root: &Path, report_path: Option<&Path>) {
+    let report = verify_corpus(corpus_root).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    print_report(&report);
+    if let Some(report_path) = report_path {
+        match fs::write(report_path, serde_json::to_string_pretty(&report).unwrap()) {
+            Ok(()) => println!("Wrote the corruption report to {}", report_path.display()),
+            Err(e) => {
+                eprintln!(
+                    "Could not write the corruption report {}: {}",
+                    report_path.display(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    if report
+        .problems
+        .iter()
+        .any(|problem| problem.kind.is_corruption())
+    {
+        std::process::exit(1);
+    }
+}
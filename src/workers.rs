@@ -0,0 +1,247 @@
+use crate::{error::OmniscanError, scratch, AnalyzerConfig, AnalyzerRun};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// A contract handed to a worker: the arguments pyrometer would be invoked with on it
+#[derive(Serialize)]
+struct WorkerRequest<'a> {
+    id: u64,
+    args: &'a [String],
+}
+
+/// What a worker replies once it analyzed a contract, the output pyrometer would have printed
+#[derive(Deserialize)]
+struct WorkerResponse {
+    id: u64,
+    #[serde(default)]
+    stdout: String,
+    #[serde(default)]
+    stderr: String,
+}
+
+/// A long-lived analyzer process taking one contract at a time over its stdin
+pub struct Worker {
+    /// Shared with the timeout of the contract being analyzed, which kills the worker while
+    /// the exchange waits on it. Only reaped under the lock, so it is never killed once reaped
+    child: Arc<Mutex<Child>>,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// Where the worker's own stderr goes, read back when it dies on a contract
+    stderr_path: PathBuf,
+    /// Contracts analyzed so far
+    served: usize,
+}
+
+impl Worker {
+    /// Sends the request and waits for its response. Fails when the worker exits or answers
+    /// something else.
+    fn exchange(&mut self, request: &WorkerRequest) -> io::Result<WorkerResponse> {
+        let line = format!("{}\n", serde_json::to_string(request).unwrap());
+        self.stdin.write_all(line.as_bytes())?;
+        self.stdin.flush()?;
+        let mut response = String::new();
+        if self.stdout.read_line(&mut response)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let response: WorkerResponse = serde_json::from_str(&response)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if response.id != request.id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "response to request {} instead of {}",
+                    response.id, request.id
+                ),
+            ));
+        }
+        Ok(response)
+    }
+
+    /// What the worker wrote to its stderr from `offset` on
+    fn stderr_since(&self, offset: u64) -> String {
+        let mut stderr = String::new();
+        if let Ok(mut file) = File::open(&self.stderr_path) {
+            let _ = file.seek(SeekFrom::Start(offset));
+            let _ = file.read_to_string(&mut stderr);
+        }
+        stderr
+    }
+
+    fn stderr_len(&self) -> u64 {
+        fs::metadata(&self.stderr_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+        let _ = fs::remove_file(&self.stderr_path);
+    }
+}
+
+/// Analyzer processes kept running across contracts, see `--worker-pool`. Workers are started
+/// as jobs need them, so there are at most as many as jobs, and are replaced after
+/// `recycle_after` contracts, or once they time out or die.
+pub struct WorkerPool {
+    server_args: Vec<String>,
+    recycle_after: usize,
+    analyzer: Arc<AnalyzerConfig>,
+    /// Working directory and HOME shared by the workers, removed with the pool
+    scratch: scratch::JobScratch,
+    idle: Mutex<Vec<Worker>>,
+    next_id: AtomicU64,
+}
+
+impl WorkerPool {
+    pub fn new(
+        server_args: Vec<String>,
+        recycle_after: usize,
+        analyzer: Arc<AnalyzerConfig>,
+        scratch_root: &Path,
+        analyzer_home: scratch::AnalyzerHome,
+    ) -> io::Result<Self> {
+        // a HOME per contract is not possible with processes that outlive the contract
+        let analyzer_home = match analyzer_home {
+            scratch::AnalyzerHome::Cold => scratch::AnalyzerHome::Warm,
+            analyzer_home => analyzer_home,
+        };
+        Ok(Self {
+            server_args,
+            recycle_after,
+            analyzer,
+            scratch: scratch::JobScratch::create(scratch_root, "workers", analyzer_home, None)?,
+            idle: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    fn spawn(&self) -> Result<Worker, OmniscanError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let stderr_path = self.scratch.dir().join(format!("worker-{}.stderr", id));
+        let stderr = File::create(&stderr_path).map_err(OmniscanError::Spawn)?;
+        let mut command = Command::new("pyrometer");
+        command
+            .args(&self.server_args)
+            .envs(self.analyzer.env.iter().map(|(key, value)| (key, value)));
+        self.scratch.apply(&mut command);
+        if let Some(network_isolation) = &self.analyzer.network_isolation {
+            network_isolation.apply(&mut command);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()
+            .map_err(OmniscanError::Spawn)?;
+        Ok(Worker {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child: Arc::new(Mutex::new(child)),
+            stderr_path,
+            served: 0,
+        })
+    }
+
+    /// An idle worker, or a new one when none is idle
+    pub fn checkout(&self) -> Result<Worker, OmniscanError> {
+        match self.idle.lock().unwrap().pop() {
+            Some(worker) => Ok(worker),
+            None => self.spawn(),
+        }
+    }
+
+    /// Analyzes a contract with `args` on the worker, then gives the worker back to the pool
+    /// unless it is due for recycling. A worker that does not answer within `timeout` (secs)
+    /// is killed and the contract recorded as a timeout; one that dies on the contract is
+    /// replaced, its stderr from the contract on becomes the contract's output.
+    pub async fn analyze(
+        self: &Arc<Self>,
+        worker: Worker,
+        args: Vec<String>,
+        timeout: f64,
+    ) -> AnalyzerRun {
+        let mut worker = worker;
+        let child = worker.child.clone();
+        let request_id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let pool = self.clone();
+        // set under the idle lock, so a worker that answers as it times out is not reused
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let exchange_timed_out = timed_out.clone();
+        let start_time = Instant::now();
+        let exchange = tokio::task::spawn_blocking(move || {
+            let stderr_offset = worker.stderr_len();
+            let response = worker.exchange(&WorkerRequest {
+                id: request_id,
+                args: &args,
+            });
+            match response {
+                Ok(response) => {
+                    worker.served += 1;
+                    let mut idle = pool.idle.lock().unwrap();
+                    if worker.served < pool.recycle_after
+                        && !exchange_timed_out.load(Ordering::SeqCst)
+                    {
+                        idle.push(worker);
+                    }
+                    (response.stdout, response.stderr)
+                }
+                // the worker is dropped, which kills and reaps it
+                Err(e) => {
+                    let mut stderr = worker.stderr_since(stderr_offset);
+                    if stderr.trim().is_empty() {
+                        stderr = format!("the analyzer worker failed on the contract: {}", e);
+                    }
+                    (String::new(), stderr)
+                }
+            }
+        });
+        match tokio::time::timeout(Duration::from_secs_f64(timeout), exchange).await {
+            Ok(output) => AnalyzerRun {
+                output: Some(output.unwrap()),
+                partial_output: None,
+                time: start_time.elapsed().as_secs_f64(),
+                cpu_time: None,
+                harness_error: None,
+                signal: None,
+            },
+            Err(_) => {
+                let mut idle = self.idle.lock().unwrap();
+                timed_out.store(true, Ordering::SeqCst);
+                match idle
+                    .iter()
+                    .position(|worker| Arc::ptr_eq(&worker.child, &child))
+                {
+                    // answered right as it timed out, dropping it kills it
+                    Some(position) => drop(idle.swap_remove(position)),
+                    // the exchange then fails and drops the worker. A worker the exchange
+                    // dropped already was reaped, and killing it does nothing
+                    None => {
+                        let _ = child.lock().unwrap().kill();
+                    }
+                }
+                AnalyzerRun {
+                    output: None,
+                    partial_output: None,
+                    time: timeout,
+                    cpu_time: None,
+                    harness_error: None,
+                    signal: None,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{error::OmniscanError, FiestaMetadata, SourceType};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Directory holding the workspace copy of a single contract
+pub fn contract_copy_dir(workspace_dir: &Path, bytecode_hash: &str) -> PathBuf {
+    workspace_dir.join("contracts").join(bytecode_hash)
+}
+
+/// Copies the contract's directory into the workspace and returns its metadata pointing at the
+/// copy. Project contracts import files outside of their directory, so they are analyzed in
+/// place and returned as is.
+pub fn copy_contract(
+    metadata: &FiestaMetadata,
+    workspace_dir: &Path,
+) -> Result<FiestaMetadata, OmniscanError> {
+    if let Some(SourceType::Project(_)) = metadata.source_type {
+        return Ok(metadata.clone());
+    }
+    let source_dir = Path::new(&metadata.abs_path_to_dir);
+    let copy_dir = contract_copy_dir(workspace_dir, &metadata.bytecode_hash);
+    // the analyzer is given the path as a string
+    let abs_path_to_dir = copy_dir
+        .to_str()
+        .ok_or_else(|| OmniscanError::NonUtf8Path(copy_dir.clone()))?
+        .to_string();
+    for entry in WalkDir::new(source_dir) {
+        let entry = entry?;
+        let target = copy_dir.join(entry.path().strip_prefix(source_dir).unwrap());
+        let copied = if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)
+        } else {
+            fs::copy(entry.path(), &target).map(|_| ())
+        };
+        copied.map_err(|source| OmniscanError::Write {
+            path: target,
+            source,
+        })?;
+    }
+
+    let mut copy = metadata.clone();
+    copy.abs_path_to_dir = abs_path_to_dir;
+    Ok(copy)
+}